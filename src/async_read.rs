@@ -0,0 +1,180 @@
+use crate::{Error, JsonPushParser, OwnedToken, TokenSink, TokenizeOptions};
+use futures03::{io::AsyncRead, ready, Stream};
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The size of the buffer an [`AsyncReadTokens`] reads into.
+///
+/// [`AsyncReadTokens`]: struct.AsyncReadTokens.html
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// A format adapter for [`tokenize_async_read`], which incrementally parses chunks of a format's bytes into owned `Token`s.
+///
+/// *Requires the `futures03` and `json` features.*
+///
+/// [`tokenize_async_read`]: fn.tokenize_async_read.html
+pub trait PushParser {
+    /// Parses the next chunk of input, appending every token it completes.
+    fn feed(&mut self, chunk: &[u8], tokens: &mut VecDeque<OwnedToken>) -> Result<(), Error>;
+
+    /// Appends any tokens completed by the end of the input, failing if it ended mid-value.
+    fn finish(&mut self, tokens: &mut VecDeque<OwnedToken>) -> Result<(), Error>;
+}
+
+/// The [`PushParser`] for JSON, backed by a [`JsonPushParser`].
+///
+/// *Requires the `futures03` and `json` features.*
+///
+/// [`PushParser`]: trait.PushParser.html
+/// [`JsonPushParser`]: struct.JsonPushParser.html
+pub struct JsonFormat(JsonPushParser<Queue>);
+
+impl JsonFormat {
+    /// Creates a JSON adapter that writes the plain token stream.
+    pub fn new() -> Self {
+        JsonFormat(JsonPushParser::new(Queue(VecDeque::new())))
+    }
+
+    /// Configures the tokens the adapter writes with the given options.
+    pub fn with_options(self, options: &TokenizeOptions) -> Self {
+        JsonFormat(self.0.with_options(options))
+    }
+}
+
+impl Default for JsonFormat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PushParser for JsonFormat {
+    fn feed(&mut self, chunk: &[u8], tokens: &mut VecDeque<OwnedToken>) -> Result<(), Error> {
+        let result = self.0.feed(chunk);
+        tokens.append(&mut self.0.get_mut().0);
+        result
+    }
+
+    fn finish(&mut self, tokens: &mut VecDeque<OwnedToken>) -> Result<(), Error> {
+        let result = self.0.end();
+        tokens.append(&mut self.0.get_mut().0);
+        result
+    }
+}
+
+/// Buffers the tokens a `JsonPushParser` writes until they're taken by a `JsonFormat`.
+struct Queue(VecDeque<OwnedToken>);
+
+impl TokenSink<'static> for Queue {
+    type Error = Error;
+
+    fn write(&mut self, token: OwnedToken) -> Result<(), Error> {
+        self.0.push_back(token);
+        Ok(())
+    }
+}
+
+/// Reads a futures 0.3 [`AsyncRead`] through a [`PushParser`] (such as a [`JsonFormat`]), returning an [`AsyncReadTokens`] stream of the owned `Token`s it parses.
+///
+/// Input is read in chunks of 8 KiB as the stream is polled, so only as much is read (and buffered) as the consumer has asked for, and reading never blocks the executor. The stream ends with the first error, whether from reading or parsing, once the tokens completed before it have been yielded.
+///
+/// *Requires the `futures03` and `json` features.*
+///
+/// # Example:
+///
+/// ```
+/// use futures03::{executor::block_on, io::Cursor, TryStreamExt};
+/// use serde_token::{tokenize_async_read, JsonFormat, Token};
+///
+/// let reader = Cursor::new(br#"{"a": [true]}"#.to_vec());
+/// let tokens = block_on(tokenize_async_read(reader, JsonFormat::new()).try_collect::<Vec<_>>());
+///
+/// assert_eq!(tokens.unwrap(), vec![
+///     Token::Map { len: None },
+///     Token::String("a".to_string()),
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ]);
+/// ```
+///
+/// [`AsyncRead`]: https://docs.rs/futures/0.3/futures/io/trait.AsyncRead.html
+/// [`PushParser`]: trait.PushParser.html
+/// [`JsonFormat`]: struct.JsonFormat.html
+/// [`AsyncReadTokens`]: struct.AsyncReadTokens.html
+pub fn tokenize_async_read<R, P>(reader: R, parser: P) -> AsyncReadTokens<R, P>
+where
+    R: AsyncRead + Unpin,
+    P: PushParser,
+{
+    AsyncReadTokens {
+        reader,
+        parser,
+        buf: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+        tokens: VecDeque::new(),
+        error: None,
+        done: false,
+    }
+}
+
+/// A stream of the owned `Token`s parsed from an `AsyncRead`, created by [`tokenize_async_read`].
+///
+/// *Requires the `futures03` and `json` features.*
+///
+/// [`tokenize_async_read`]: fn.tokenize_async_read.html
+pub struct AsyncReadTokens<R, P> {
+    reader: R,
+    parser: P,
+    buf: Box<[u8]>,
+    tokens: VecDeque<OwnedToken>,
+    error: Option<Error>,
+    done: bool,
+}
+
+impl<R, P> AsyncReadTokens<R, P> {
+    /// Consumes the stream, returning the reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R, P> Stream for AsyncReadTokens<R, P>
+where
+    R: AsyncRead + Unpin,
+    P: PushParser + Unpin,
+{
+    type Item = Result<OwnedToken, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(token) = this.tokens.pop_front() {
+                return Poll::Ready(Some(Ok(token)));
+            }
+            // the error follows any tokens completed before it
+            if let Some(err) = this.error.take() {
+                return Poll::Ready(Some(Err(err)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let result = match ready!(Pin::new(&mut this.reader).poll_read(cx, &mut this.buf)) {
+                Ok(0) => {
+                    this.done = true;
+                    this.parser.finish(&mut this.tokens)
+                }
+                Ok(n) => this.parser.feed(&this.buf[..n], &mut this.tokens),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(Error::IoError(err)),
+            };
+            if let Err(err) = result {
+                this.error = Some(err);
+                this.done = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{to_tokens, tokenize_owned, Error, Token, TokenSink};
+use futures03::{
+    channel::mpsc::{channel, Sender},
+    executor::block_on,
+    Sink, SinkExt, StreamExt,
+};
+use serde::de;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+};
+
+/// Transcodes a deserializer into a futures 0.3 [`Sink`] of `Token`s, awaiting the sink's readiness before sending each token and flushing it once done.
+///
+/// Since a deserializer can't be suspended mid-value, it is first tokenized into a buffer; to only tokenize as far ahead as the consumer, use a [`TokenStream`] instead.
+///
+/// *Requires the `futures03` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures03::{channel::mpsc::channel, executor::block_on, future::join, StreamExt};
+/// use serde_token::{tokenize_async, Token};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// let (token_sink, token_stream) = channel::<Token>(0);
+///
+/// let (result, tokens) = block_on(join(
+///     tokenize_async(&mut de, token_sink),
+///     token_stream.collect::<Vec<_>>(),
+/// ));
+///
+/// result.unwrap();
+/// assert_eq!(tokens, vec![Token::Seq { len: None }, Token::Bool(true), Token::SeqEnd]);
+/// ```
+///
+/// [`Sink`]: https://docs.rs/futures/0.3/futures/sink/trait.Sink.html
+/// [`TokenStream`]: struct.TokenStream.html
+pub async fn tokenize_async<'de, D, S>(deserializer: D, mut sink: S) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: Sink<Token<'de>> + Unpin,
+    S::Error: fmt::Debug,
+{
+    for token in to_tokens(deserializer)? {
+        sink.feed(token).await.map_err(Error::sink_debug)?;
+    }
+    sink.flush().await.map_err(Error::sink_debug)
+}
+
+/// Transcodes a deserializer into a futures 0.3 [`Sink`] of `Token`s like [`tokenize_async`], but yields back to the executor after every `every` tokens sent, so that tokenizing a huge document into a sink that is always ready (such as an unbounded channel) doesn't starve the executor's other tasks.
+///
+/// Since a deserializer can't be suspended mid-value, it is driven on its own thread (like a [`TokenStream`]), which blocks once 1024 tokens are waiting to be sent; the returned future only receives and sends the owned tokens, awaiting the worker whenever it falls behind. Dropping the future stops the tokenization at its next token.
+///
+/// *Requires the `futures03` feature.*
+///
+/// # Panics
+///
+/// Panics if `every` is zero.
+///
+/// # Example:
+///
+/// ```
+/// use futures03::{channel::mpsc::unbounded, executor::block_on, StreamExt};
+/// use serde_token::{tokenize_async_yielding, Token};
+///
+/// let de = serde_json::Deserializer::from_reader(r#" [true, false] "#.as_bytes());
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// block_on(tokenize_async_yielding(de, token_sink, 2)).unwrap();
+///
+/// let tokens = block_on(token_stream.collect::<Vec<_>>());
+/// assert_eq!(tokens, vec![
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::Bool(false),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`Sink`]: https://docs.rs/futures/0.3/futures/sink/trait.Sink.html
+/// [`tokenize_async`]: fn.tokenize_async.html
+/// [`TokenStream`]: struct.TokenStream.html
+pub async fn tokenize_async_yielding<D, S>(
+    mut deserializer: D,
+    mut sink: S,
+    every: usize,
+) -> Result<(), Error>
+where
+    D: Send + 'static,
+    for<'a> &'a mut D: de::Deserializer<'a>,
+    S: Sink<Token<'static>> + Unpin,
+    S::Error: fmt::Debug,
+{
+    assert!(every > 0, "yield interval must be non-zero");
+    let (tx, mut tokens) = channel(1023);
+    let worker = thread::spawn(move || {
+        let mut errors = tx.clone();
+        if let Err(err) = tokenize_owned(&mut deserializer, WorkerSink(tx)) {
+            // Fails only if the future has been dropped.
+            let _ = block_on(errors.send(Err(err)));
+        }
+    });
+
+    let mut sent = 0;
+    while let Some(token) = tokens.next().await {
+        if sent > 0 && sent % every == 0 {
+            YieldNow(false).await;
+        }
+        sink.feed(token?).await.map_err(Error::sink_debug)?;
+        sent += 1;
+    }
+    // The worker has dropped its sender, so is about to exit.
+    if worker.join().is_err() {
+        return Err(Error::TokenizerError(
+            "tokenizer thread panicked".to_string(),
+        ));
+    }
+    sink.flush().await.map_err(Error::sink_debug)
+}
+
+/// The sink a `tokenize_async_yielding` worker tokenizes into, which blocks the worker until the channel has room for each token.
+struct WorkerSink(Sender<Result<Token<'static>, Error>>);
+
+impl TokenSink<'static> for WorkerSink {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'static>) -> Result<(), Error> {
+        block_on(self.0.send(Ok(token))).map_err(Error::sink_debug)
+    }
+}
+
+/// A future that is pending (having scheduled itself to be polled again) the first time it is polled.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// A [`TokenSink`] that blocks the current thread on a futures 0.3 [`Sink`] of `Token`s, so it can be passed to [`tokenize`].
+///
+/// *Requires the `futures03` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures03::{channel::mpsc::unbounded, executor::block_on, StreamExt};
+/// use serde_token::{tokenize, BlockingSink, Token};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize(&mut de, BlockingSink::new(token_sink)).unwrap();
+///
+/// let expected = block_on(token_stream.collect::<Vec<_>>());
+/// assert_eq!(expected, vec![Token::Seq { len: None }, Token::Bool(true), Token::SeqEnd]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`Sink`]: https://docs.rs/futures/0.3/futures/sink/trait.Sink.html
+/// [`tokenize`]: fn.tokenize.html
+#[derive(Debug)]
+pub struct BlockingSink<S>(S);
+
+impl<S> BlockingSink<S> {
+    /// Wraps the given sink.
+    pub fn new(sink: S) -> Self {
+        BlockingSink(sink)
+    }
+
+    /// Consumes the `BlockingSink`, returning the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<'de, S> TokenSink<'de> for BlockingSink<S>
+where
+    S: Sink<Token<'de>> + Unpin,
+    S::Error: fmt::Debug,
+{
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        block_on(self.0.feed(token)).map_err(Error::sink_debug)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        block_on(self.0.flush()).map_err(Error::sink_debug)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        block_on(self.0.close()).map_err(Error::sink_debug)
+    }
+}
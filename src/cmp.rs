@@ -0,0 +1,189 @@
+use crate::Token;
+#[cfg(feature = "std")]
+use crate::{tree::Node, Error};
+use core::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
+
+/// Tokens are equal if they are the same variant with equal payloads, where `F32` and `F64` payloads are compared by their bits.
+///
+/// Comparing floats by their bits makes equality reflexive (so that `Token` can implement `Eq` and `Hash`, and be used as a `HashMap` key), at the cost of diverging from IEEE 754: each `NaN` is equal to any `NaN` with the same bits, and `0.0` and `-0.0` are unequal.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::Token;
+///
+/// assert_eq!(Token::F64(f64::NAN), Token::F64(f64::NAN));
+/// assert_ne!(Token::F64(0.0), Token::F64(-0.0));
+/// assert_ne!(Token::Str("a".into()), Token::String("a".to_string()));
+/// ```
+impl<'a> PartialEq for Token<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind() && payload(self) == payload(other)
+    }
+}
+
+impl<'a> Eq for Token<'a> {}
+
+/// Tokens are ordered first by variant, in the order they are declared in [`Token`] (and [`TokenKind`]), then by payload:
+///
+/// - integers and chars numerically, and `bool`s with `false` first,
+/// - floats by the IEEE 754 `totalOrder` predicate, i.e. numerically, with `-0.0` before `0.0` and negative and positive `NaN`s at either end,
+/// - strings (including `Number`s and `Field`s) and bytes lexicographically by their bytes,
+/// - length hints with `None` first, then by length (as are `Tuple` and `Truncated` lengths),
+/// - named headers by name, then by variant index, variant name and length,
+/// - `Extension` headers by tag.
+///
+/// Sequences of tokens (such as `Vec<Token>`) are then ordered lexicographically, which gives a deterministic ordering for canonical encodings, such as of the keys sorted by [`sort_maps`].
+///
+/// [`Token`]: enum.Token.html
+/// [`TokenKind`]: enum.TokenKind.html
+/// [`sort_maps`]: fn.sort_maps.html
+impl<'a> Ord for Token<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.kind()
+            .cmp(&other.kind())
+            .then_with(|| payload(self).cmp(&payload(other)))
+    }
+}
+
+impl<'a> PartialOrd for Token<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Hashes a token consistently with its `Eq` implementation.
+impl<'a> Hash for Token<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.kind().hash(state);
+        payload(self).hash(state);
+    }
+}
+
+// The data carried by a token, with floats' bits mapped to integers with the same `totalOrder` ordering (by flipping all but the sign bit of negative floats); tokens of the same `TokenKind` always have the same kind of `Payload`.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord)]
+enum Payload<'t> {
+    Empty,
+    Bool(bool),
+    Int(i128),
+    Uint(u128),
+    Float(i64),
+    Char(char),
+    Str(&'t str),
+    Bytes(&'t [u8]),
+    Len(Option<usize>),
+    Name(&'static str, usize),
+    Variant(&'static str, u32, &'static str, usize),
+    Tag(u64),
+}
+
+fn payload<'t>(token: &'t Token) -> Payload<'t> {
+    match *token {
+        Token::Bool(v) => Payload::Bool(v),
+        Token::I8(v) => Payload::Int(v.into()),
+        Token::I16(v) => Payload::Int(v.into()),
+        Token::I32(v) => Payload::Int(v.into()),
+        Token::I64(v) => Payload::Int(v.into()),
+        Token::I128(v) => Payload::Int(v),
+        Token::U8(v) => Payload::Uint(v.into()),
+        Token::U16(v) => Payload::Uint(v.into()),
+        Token::U32(v) => Payload::Uint(v.into()),
+        Token::U64(v) => Payload::Uint(v.into()),
+        Token::U128(v) => Payload::Uint(v),
+        Token::F32(v) => {
+            let bits = v.to_bits() as i32;
+            Payload::Float((bits ^ (((bits >> 31) as u32) >> 1) as i32).into())
+        }
+        Token::F64(v) => {
+            let bits = v.to_bits() as i64;
+            Payload::Float(bits ^ (((bits >> 63) as u64) >> 1) as i64)
+        }
+        Token::Char(v) => Payload::Char(v),
+        Token::Number(ref v) | Token::Str(ref v) | Token::StrChunk(ref v) => Payload::Str(v),
+        Token::String(ref v) => Payload::Str(v),
+        Token::Interned(ref v) => Payload::Str(v),
+        Token::Inline(ref v) => Payload::Str(v),
+        Token::Field(v) => Payload::Str(v),
+        Token::Bytes(ref v) | Token::BytesChunk(ref v) => Payload::Bytes(v),
+        Token::ByteBuf(ref v) => Payload::Bytes(v),
+        Token::Seq { len } | Token::Map { len } => Payload::Len(len),
+        Token::Tuple { len } | Token::Truncated { len } => Payload::Len(Some(len)),
+        Token::UnitStruct { name } | Token::NewtypeStruct { name } | Token::Enum { name } => {
+            Payload::Name(name, 0)
+        }
+        Token::TupleStruct { name, len } | Token::Struct { name, len } => Payload::Name(name, len),
+        Token::UnitVariant {
+            name,
+            variant_index,
+            variant,
+        }
+        | Token::NewtypeVariant {
+            name,
+            variant_index,
+            variant,
+        } => Payload::Variant(name, variant_index, variant, 0),
+        Token::TupleVariant {
+            name,
+            variant_index,
+            variant,
+            len,
+        }
+        | Token::StructVariant {
+            name,
+            variant_index,
+            variant,
+            len,
+        } => Payload::Variant(name, variant_index, variant, len),
+        Token::Extension { tag } => Payload::Tag(tag),
+        Token::StrEnd
+        | Token::BytesEnd
+        | Token::None
+        | Token::Some
+        | Token::Unit
+        | Token::SeqEnd
+        | Token::TupleEnd
+        | Token::TupleStructEnd
+        | Token::TupleVariantEnd
+        | Token::MapEnd
+        | Token::MapKey
+        | Token::MapValue
+        | Token::StructEnd
+        | Token::StructVariantEnd
+        | Token::DocumentStart
+        | Token::DocumentEnd => Payload::Empty,
+    }
+}
+
+/// Sorts the entries of every map in the given tokens by their keys' tokens, using the [`Ord`] implementation of [`Token`], so that equal maps produce identical token streams.
+///
+/// Structs' fields are left in their declared order. Values nested more than 128 levels deep fail with `Error::DepthLimitExceeded`.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{sort_maps, to_tokens};
+///
+/// let a = to_tokens(&mut serde_json::Deserializer::from_str(r#" {"b": 1, "a": {"d": 2, "c": 3}} "#)).unwrap();
+/// let b = to_tokens(&mut serde_json::Deserializer::from_str(r#" {"a": {"c": 3, "d": 2}, "b": 1} "#)).unwrap();
+///
+/// assert_ne!(a, b);
+/// assert_eq!(sort_maps(a).unwrap(), sort_maps(b).unwrap());
+/// ```
+///
+/// [`Ord`]: enum.Token.html#impl-Ord
+/// [`Token`]: enum.Token.html
+#[cfg(feature = "std")]
+pub fn sort_maps<'a, I>(tokens: I) -> Result<Vec<Token<'a>>, Error>
+where
+    I: IntoIterator<Item = Token<'a>>,
+{
+    let mut sorted = Vec::new();
+    for mut node in Node::parse_all(tokens)? {
+        node.sort_maps();
+        node.emit(&mut sorted);
+    }
+    Ok(sorted)
+}
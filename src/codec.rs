@@ -0,0 +1,112 @@
+use crate::{
+    wire::{Decoder as WireDecoder, Encoder as WireEncoder},
+    Error, Token,
+};
+use bytes::{Buf, BytesMut};
+use std::collections::VecDeque;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A `tokio-util` codec that frames `Token`s in the [`wire`] format, for sending token streams over sockets with `FramedWrite` and `FramedRead`.
+///
+/// The encoder writes the stream header before its first token, and the decoder reads the header before its first frame, decoding whichever protocol version the stream was encoded with.
+///
+/// *Requires the `codec` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use bytes::BytesMut;
+/// use serde_token::{Token, TokenCodec};
+/// use tokio_util::codec::{Decoder, Encoder};
+///
+/// let mut buf = BytesMut::new();
+/// let mut codec = TokenCodec::new();
+/// codec.encode(Token::Seq { len: None }, &mut buf).unwrap();
+/// codec.encode(Token::Str("a".into()), &mut buf).unwrap();
+///
+/// let mut codec = TokenCodec::new();
+/// assert_eq!(codec.decode(&mut buf).unwrap(), Some(Token::Seq { len: None }));
+/// assert_eq!(codec.decode(&mut buf).unwrap(), Some(Token::String("a".to_string())));
+/// assert_eq!(codec.decode(&mut buf).unwrap(), None);
+/// ```
+///
+/// [`wire`]: wire/index.html
+#[derive(Debug)]
+pub struct TokenCodec {
+    encoder: WireEncoder,
+    header_sent: bool,
+    decoder: Option<WireDecoder>,
+    decoded: VecDeque<Token<'static>>,
+}
+
+impl Default for TokenCodec {
+    fn default() -> Self {
+        Self::with_encoder(WireEncoder::default())
+    }
+}
+
+impl TokenCodec {
+    /// Creates a codec that encodes the newest protocol version.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a codec that encodes with the given (e.g. negotiated) [`wire::Encoder`].
+    ///
+    /// [`wire::Encoder`]: wire/struct.Encoder.html
+    pub fn with_encoder(encoder: WireEncoder) -> Self {
+        TokenCodec {
+            encoder,
+            header_sent: false,
+            decoder: None,
+            decoded: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a> Encoder<Token<'a>> for TokenCodec {
+    type Error = Error;
+
+    fn encode(&mut self, token: Token<'a>, dst: &mut BytesMut) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        if !self.header_sent {
+            self.encoder.encode_header(&mut buf);
+            self.header_sent = true;
+        }
+        self.encoder.encode_frame(&[token], &mut buf);
+        dst.extend_from_slice(&buf);
+        Ok(())
+    }
+}
+
+impl Decoder for TokenCodec {
+    type Item = Token<'static>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Token<'static>>, Error> {
+        loop {
+            if let Some(token) = self.decoded.pop_front() {
+                return Ok(Some(token));
+            }
+
+            let decoder = match self.decoder {
+                Some(ref mut decoder) => decoder,
+                None => match WireDecoder::from_header(src)? {
+                    Some((decoder, len)) => {
+                        src.advance(len);
+                        self.decoder.get_or_insert(decoder)
+                    }
+                    None => return Ok(None),
+                },
+            };
+            match decoder.decode_frame(src)? {
+                Some((tokens, len)) => {
+                    self.decoded
+                        .extend(tokens.into_iter().map(Token::into_owned));
+                    src.advance(len);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
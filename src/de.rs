@@ -0,0 +1,781 @@
+//! Deserializing typed values from `Token`s.
+
+use crate::{
+    path::{role, Role},
+    transcode::MAX_DEPTH,
+    tree::malformed,
+    Error, Token, NUMBER_TOKEN,
+};
+#[cfg(feature = "futures01")]
+use futures::{
+    stream::Wait,
+    sync::mpsc::{channel, Receiver},
+    Async, Poll, Sink, Stream,
+};
+#[cfg(feature = "futures01")]
+use serde::de::DeserializeOwned;
+use serde::de::{
+    self, value::StrDeserializer, Deserialize, DeserializeSeed, Deserializer, EnumAccess,
+    IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::{borrow::Cow, marker::PhantomData};
+#[cfg(feature = "futures01")]
+use std::{
+    fmt,
+    thread::{self, JoinHandle},
+};
+
+/// Deserializes a typed value from the given tokens, failing if any tokens are left over.
+///
+/// Values nested more than 128 levels deep fail with `Error::DepthLimitExceeded`, unless the `stacker` feature is enabled.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{from_tokens, Token};
+///
+/// let tokens = vec![Token::Seq { len: Some(2) }, Token::U64(1), Token::U64(2), Token::SeqEnd];
+/// let value: Vec<u8> = from_tokens(&tokens).unwrap();
+///
+/// assert_eq!(value, vec![1, 2]);
+/// ```
+pub fn from_tokens<'a, 'de, T: Deserialize<'de>>(tokens: &'a [Token<'de>]) -> Result<T, Error> {
+    let mut de = TokenDeserializer::new(tokens);
+    let value = T::deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+/// A [`futures::Stream`] of typed values, deserialized from each consecutive document in a [`futures::Stream`] of `Token`s.
+///
+/// The values are deserialized on their own thread, which pulls each token from the underlying stream only as the value being deserialized needs it, and blocks once a value is waiting to be polled, so neither a huge document nor a backlog of values is ever buffered. Documents are delimited as by a [`DocumentSplitter`], so streams with or without `DocumentStart` and `DocumentEnd` markers can be deserialized alike. The stream ends after the first error, as the tokens of the document that failed can't be skipped reliably.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{sync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{de::StreamDeserializer, tokenize, Token};
+///
+/// let (mut token_sink, token_stream) = unbounded::<Token>();
+/// for json_str in &[r#" ["a", 1] "#, r#" ["b", 2] "#] {
+///     let mut de = serde_json::Deserializer::from_str(json_str);
+///     tokenize(&mut de, &mut token_sink).unwrap();
+/// }
+/// drop(token_sink);
+///
+/// let values = StreamDeserializer::<_, (String, u8)>::new(token_stream)
+///     .collect()
+///     .wait()
+///     .unwrap();
+///
+/// assert_eq!(values, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+/// ```
+///
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+/// [`DocumentSplitter`]: ../struct.DocumentSplitter.html
+#[cfg(feature = "futures01")]
+pub struct StreamDeserializer<St: Stream, T> {
+    values: Receiver<Result<T, StreamDeserializerError<St::Error>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "futures01")]
+impl<St, T> StreamDeserializer<St, T>
+where
+    St: Stream<Item = Token<'static>> + Send + 'static,
+    St::Error: Send,
+    T: DeserializeOwned + Send + 'static,
+{
+    /// Creates a new `StreamDeserializer` over the given stream of tokens.
+    pub fn new(stream: St) -> Self {
+        let (tx, values) = channel(0);
+        let thread = thread::spawn(move || {
+            let mut tx = tx.wait();
+            let mut de = Walker::new(StreamRead {
+                stream: stream.wait(),
+                peeked: None,
+                error: None,
+            });
+            loop {
+                let value = match de.document() {
+                    Ok(None) => return,
+                    Ok(Some(value)) => Ok(value),
+                    Err(err) => Err(match de.read.error.take() {
+                        Some(err) => StreamDeserializerError::Stream(err),
+                        None => StreamDeserializerError::Deserialize(err),
+                    }),
+                };
+                let failed = value.is_err();
+                // Fails only if the `StreamDeserializer` has been dropped.
+                if tx.send(value).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        StreamDeserializer {
+            values,
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<St: Stream, T> Stream for StreamDeserializer<St, T> {
+    type Item = T;
+    type Error = StreamDeserializerError<St::Error>;
+
+    fn poll(&mut self) -> Poll<Option<T>, Self::Error> {
+        match self.values.poll() {
+            Ok(Async::Ready(Some(Ok(value)))) => Ok(Async::Ready(Some(value))),
+            Ok(Async::Ready(Some(Err(err)))) => Err(err),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(None)) | Err(()) => match self.thread.take().map(JoinHandle::join) {
+                Some(Err(_)) => Err(StreamDeserializerError::Deserialize(Error::TokenizerError(
+                    "deserializer thread panicked".to_string(),
+                ))),
+                _ => Ok(Async::Ready(None)),
+            },
+        }
+    }
+}
+
+/// An error returned by a [`StreamDeserializer`].
+///
+/// [`StreamDeserializer`]: struct.StreamDeserializer.html
+#[cfg(feature = "futures01")]
+#[derive(Debug)]
+pub enum StreamDeserializerError<E> {
+    /// A document could not be deserialized into the requested type.
+    Deserialize(Error),
+
+    /// The underlying token stream failed.
+    Stream(E),
+}
+
+#[cfg(feature = "futures01")]
+impl<E: fmt::Debug> fmt::Display for StreamDeserializerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamDeserializerError::Deserialize(err) => write!(f, "deserialize error: {}", err),
+            StreamDeserializerError::Stream(err) => write!(f, "token stream error: {:?}", err),
+        }
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<E: fmt::Debug> std::error::Error for StreamDeserializerError<E> {}
+
+/// A [`serde::Deserializer`] that walks a slice of `Token`s, so a token buffer can be consumed by any `Deserialize` impl.
+///
+/// Borrowed `Str` and `Bytes` tokens are visited as data borrowed for `'de`, so borrowing types such as `&'de str` can be deserialized without copying.
+///
+/// Besides the tokens produced by [`tokenize`], enums may be represented as in the [Serde data model] (e.g. `UnitVariant` or `Enum` tokens), or in the externally tagged form produced by most self-describing formats: a variant name, or a map with a single entry from the variant name to its content. Likewise, a `Unit` is accepted as `None`.
+///
+/// # Example:
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_token::{de::TokenDeserializer, Token};
+///
+/// let tokens = vec![
+///     Token::Seq { len: None },
+///     Token::Str("a".into()),
+///     Token::Unit,
+///     Token::SeqEnd,
+/// ];
+/// let mut de = TokenDeserializer::new(&tokens);
+/// let value = <(&str, Option<u8>)>::deserialize(&mut de).unwrap();
+/// de.end().unwrap();
+///
+/// assert_eq!(value, ("a", None));
+/// ```
+///
+/// [`serde::Deserializer`]: https://docs.serde.rs/serde/trait.Deserializer.html
+/// [`tokenize`]: ../fn.tokenize.html
+/// [Serde data model]: https://serde.rs/data-model.html
+#[derive(Clone, Debug)]
+pub struct TokenDeserializer<'a, 'de>(Walker<'a, 'de, SliceRead<'a, 'de>>);
+
+impl<'a, 'de> TokenDeserializer<'a, 'de> {
+    /// Creates a deserializer over the given tokens.
+    pub fn new(tokens: &'a [Token<'de>]) -> Self {
+        TokenDeserializer(Walker::new(SliceRead(tokens)))
+    }
+
+    /// The tokens that have not yet been deserialized.
+    pub fn remaining(&self) -> &'a [Token<'de>] {
+        self.0.read.0
+    }
+
+    /// Checks that every token (other than trailing markers) has been deserialized.
+    pub fn end(&self) -> Result<(), Error> {
+        match self.0.clone().peek()? {
+            None => Ok(()),
+            Some(token) => Err(de::Error::custom(format!(
+                "trailing tokens, starting at {:?}",
+                token
+            ))),
+        }
+    }
+}
+
+impl<'b, 'a, 'de> Deserializer<'de> for &'b mut TokenDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.0.deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.0.deserialize_option(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// A source of tokens for a `Walker`.
+trait Read<'a, 'de> {
+    /// Peeks at the next token, skipping any markers.
+    fn peek(&mut self) -> Result<Option<&Token<'de>>, Error>;
+
+    /// Takes the next token, skipping any markers.
+    fn next(&mut self) -> Result<Option<Cow<'a, Token<'de>>>, Error>;
+}
+
+/// Reads the tokens of a slice, lending each one for as long as the slice.
+#[derive(Clone, Debug)]
+struct SliceRead<'a, 'de>(&'a [Token<'de>]);
+
+impl<'a, 'de> Read<'a, 'de> for SliceRead<'a, 'de> {
+    fn peek(&mut self) -> Result<Option<&Token<'de>>, Error> {
+        Ok(self.0.iter().find(|token| role(token) != Role::Marker))
+    }
+
+    fn next(&mut self) -> Result<Option<Cow<'a, Token<'de>>>, Error> {
+        while let Some((token, rest)) = self.0.split_first() {
+            self.0 = rest;
+            if role(token) != Role::Marker {
+                return Ok(Some(Cow::Borrowed(token)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Pulls tokens from a blocking `futures::Stream` one at a time, keeping only the token that has been peeked at.
+#[cfg(feature = "futures01")]
+struct StreamRead<St: Stream> {
+    stream: Wait<St>,
+    peeked: Option<Token<'static>>,
+    error: Option<St::Error>,
+}
+
+#[cfg(feature = "futures01")]
+impl<St: Stream<Item = Token<'static>>> StreamRead<St> {
+    // Makes sure a token has been peeked at, unless the stream has ended.
+    fn fill(&mut self) -> Result<(), Error> {
+        if self.peeked.is_none() {
+            match self.stream.next() {
+                Some(Ok(token)) => self.peeked = Some(token),
+                Some(Err(err)) => {
+                    self.error = Some(err);
+                    return Err(Error::TokenStreamError("token stream failed".to_string()));
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Takes the next token, including a marker, if it is the given token.
+    fn next_if(&mut self, expected: &Token) -> Result<bool, Error> {
+        self.fill()?;
+        if self.peeked.as_ref() != Some(expected) {
+            return Ok(false);
+        }
+        self.peeked = None;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, St: Stream<Item = Token<'static>>> Read<'a, 'static> for StreamRead<St> {
+    fn peek(&mut self) -> Result<Option<&Token<'static>>, Error> {
+        self.fill()?;
+        while self
+            .peeked
+            .as_ref()
+            .is_some_and(|token| role(token) == Role::Marker)
+        {
+            self.peeked = None;
+            self.fill()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<Cow<'a, Token<'static>>>, Error> {
+        self.peek()?;
+        Ok(self.peeked.take().map(Cow::Owned))
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<St: Stream<Item = Token<'static>>> Walker<'static, 'static, StreamRead<St>> {
+    // Deserializes the next document, or returns `None` once the stream has ended.
+    fn document<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        let framed = self.read.next_if(&Token::DocumentStart)?;
+        if !framed && self.peek()?.is_none() {
+            return Ok(None);
+        }
+        let value = T::deserialize(&mut *self)?;
+        if framed && !self.read.next_if(&Token::DocumentEnd)? {
+            return Err(match self.peek()? {
+                None => malformed("unexpected end of tokens"),
+                Some(token) => malformed(&format!("expected DocumentEnd, found {:?}", token)),
+            });
+        }
+        Ok(Some(value))
+    }
+}
+
+/// The deserializer behind a `TokenDeserializer`, reading its tokens from any source.
+#[derive(Clone, Debug)]
+struct Walker<'a, 'de, R> {
+    read: R,
+    depth: usize,
+    tokens: PhantomData<&'a Token<'de>>,
+}
+
+impl<'a, 'de, R: Read<'a, 'de>> Walker<'a, 'de, R> {
+    fn new(read: R) -> Self {
+        Walker {
+            read,
+            depth: 0,
+            tokens: PhantomData,
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token<'de>>, Error> {
+        self.read.peek()
+    }
+
+    // Descends one level of nesting, failing if too deep.
+    fn nested<T, F>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        if self.depth == MAX_DEPTH {
+            let depth = self.depth + 1;
+            return Err(Error::DepthLimitExceeded { depth });
+        }
+
+        self.depth += 1;
+        #[cfg(feature = "stacker")]
+        let result = stacker::maybe_grow(
+            crate::transcode::RED_ZONE,
+            crate::transcode::STACK_SIZE,
+            || f(self),
+        );
+        #[cfg(not(feature = "stacker"))]
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn next(&mut self) -> Result<Cow<'a, Token<'de>>, Error> {
+        self.read
+            .next()?
+            .ok_or_else(|| malformed("unexpected end of tokens"))
+    }
+
+    // Joins the remaining `StrChunk`s of a chunked string, up to and including its `StrEnd`.
+    fn join_chunks(&mut self, first: &str) -> Result<String, Error> {
+        let mut string = first.to_string();
+        loop {
+            match *self.next()? {
+                Token::StrChunk(ref v) => string.push_str(v),
+                Token::StrEnd => return Ok(string),
+                ref token => return Err(malformed(&format!("expected StrEnd, found {:?}", token))),
+            }
+        }
+    }
+
+    // Joins the remaining `BytesChunk`s of a chunked byte array, up to and including its `BytesEnd`.
+    fn join_bytes_chunks(&mut self, first: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut bytes = first.to_vec();
+        loop {
+            match *self.next()? {
+                Token::BytesChunk(ref v) => bytes.extend_from_slice(v),
+                Token::BytesEnd => return Ok(bytes),
+                ref token => {
+                    return Err(malformed(&format!("expected BytesEnd, found {:?}", token)))
+                }
+            }
+        }
+    }
+
+    fn expect(&mut self, end: Token) -> Result<(), Error> {
+        match self.next()? {
+            token if *token == end => Ok(()),
+            token => Err(de::Error::custom(format!(
+                "expected {:?}, found {:?}",
+                end, token
+            ))),
+        }
+    }
+
+    fn visit_seq<V: Visitor<'de>>(&mut self, visitor: V, end: Token) -> Result<V::Value, Error> {
+        let value = visitor.visit_seq(Compound { de: self })?;
+        self.expect(end)?;
+        Ok(value)
+    }
+
+    fn visit_map<V: Visitor<'de>>(&mut self, visitor: V, end: Token) -> Result<V::Value, Error> {
+        let value = visitor.visit_map(Compound { de: self })?;
+        self.expect(end)?;
+        Ok(value)
+    }
+
+    // Visits the value starting at the next token.
+    fn visit_any<V: Visitor<'de>>(&mut self, visitor: V) -> Result<V::Value, Error> {
+        let next = self.next()?;
+        match *next {
+            Token::Bool(v) => visitor.visit_bool(v),
+            Token::I8(v) => visitor.visit_i8(v),
+            Token::I16(v) => visitor.visit_i16(v),
+            Token::I32(v) => visitor.visit_i32(v),
+            Token::I64(v) => visitor.visit_i64(v),
+            Token::I128(v) => visitor.visit_i128(v),
+            Token::U8(v) => visitor.visit_u8(v),
+            Token::U16(v) => visitor.visit_u16(v),
+            Token::U32(v) => visitor.visit_u32(v),
+            Token::U64(v) => visitor.visit_u64(v),
+            Token::U128(v) => visitor.visit_u128(v),
+            Token::F32(v) => visitor.visit_f32(v),
+            Token::F64(v) => visitor.visit_f64(v),
+            Token::Char(v) => visitor.visit_char(v),
+            Token::Number(ref v) => visitor.visit_map(de::value::MapDeserializer::new(
+                std::iter::once((NUMBER_TOKEN, &**v)),
+            )),
+            Token::Str(Cow::Borrowed(v)) | Token::Field(v) => visitor.visit_borrowed_str(v),
+            Token::Str(Cow::Owned(ref v)) | Token::String(ref v) => visitor.visit_str(v),
+            Token::Interned(ref v) => visitor.visit_str(v),
+            Token::Inline(ref v) => visitor.visit_str(v),
+            Token::StrChunk(ref v) => visitor.visit_string(self.join_chunks(v)?),
+            Token::BytesChunk(ref v) => visitor.visit_byte_buf(self.join_bytes_chunks(v)?),
+            Token::Bytes(Cow::Borrowed(v)) => visitor.visit_borrowed_bytes(v),
+            Token::Bytes(Cow::Owned(ref v)) | Token::ByteBuf(ref v) => visitor.visit_bytes(v),
+            Token::None => visitor.visit_none(),
+            Token::Some => visitor.visit_some(self),
+            Token::Unit | Token::UnitStruct { .. } => visitor.visit_unit(),
+            Token::NewtypeStruct { .. } => visitor.visit_newtype_struct(self),
+            Token::Extension { tag } => visitor.visit_seq(ExtensionAccess {
+                tag: Some(tag),
+                de: Some(self),
+            }),
+            Token::Seq { .. } => self.visit_seq(visitor, Token::SeqEnd),
+            Token::Tuple { .. } => self.visit_seq(visitor, Token::TupleEnd),
+            Token::TupleStruct { .. } => self.visit_seq(visitor, Token::TupleStructEnd),
+            Token::Map { .. } => self.visit_map(visitor, Token::MapEnd),
+            Token::Struct { .. } => self.visit_map(visitor, Token::StructEnd),
+            ref token @ Token::UnitVariant { .. }
+            | ref token @ Token::NewtypeVariant { .. }
+            | ref token @ Token::TupleVariant { .. }
+            | ref token @ Token::StructVariant { .. } => visitor.visit_enum(Enum {
+                de: self,
+                variant: Some(token),
+                end: None,
+            }),
+            // An `Enum` header may be followed by the variant token itself.
+            Token::Enum { .. } => match self.peek()? {
+                Some(Token::UnitVariant { .. })
+                | Some(Token::NewtypeVariant { .. })
+                | Some(Token::TupleVariant { .. })
+                | Some(Token::StructVariant { .. }) => self.deserialize_any(visitor),
+                _ => visitor.visit_enum(Enum {
+                    de: self,
+                    variant: None,
+                    end: None,
+                }),
+            },
+            ref token => Err(de::Error::custom(format!("unexpected {:?}", token))),
+        }
+    }
+}
+
+impl<'a, 'de, R: Read<'a, 'de>> Deserializer<'de> for &mut Walker<'a, 'de, R> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.peek()?.map(role) {
+            Some(Role::Open) | Some(Role::Prefix) => self.nested(|de| de.visit_any(visitor)),
+            _ => self.visit_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.peek()? {
+            Some(Token::None) | Some(Token::Unit) => {
+                self.next()?;
+                visitor.visit_none()
+            }
+            Some(Token::Some) => self.nested(|de| {
+                de.next()?;
+                visitor.visit_some(de)
+            }),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.peek()? {
+            Some(Token::NewtypeStruct { .. }) => self.nested(|de| {
+                de.next()?;
+                visitor.visit_newtype_struct(de)
+            }),
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.peek()? {
+            Some(Token::Map { .. }) => self.nested(|de| {
+                de.next()?;
+                visitor.visit_enum(Enum {
+                    de,
+                    variant: None,
+                    end: Some(Token::MapEnd),
+                })
+            }),
+            Some(Token::UnitVariant { .. }) => self.deserialize_any(visitor),
+            Some(token) if role(token) == Role::Scalar => visitor.visit_enum(Enum {
+                de: self,
+                variant: None,
+                end: None,
+            }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Compound<'c, 'a, 'de, R> {
+    de: &'c mut Walker<'a, 'de, R>,
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> Compound<'c, 'a, 'de, R> {
+    fn at_end(&mut self) -> Result<bool, Error> {
+        Ok(self
+            .de
+            .peek()?
+            .is_none_or(|token| role(token) == Role::Close))
+    }
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> SeqAccess<'de> for Compound<'c, 'a, 'de, R> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.at_end()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+/// The tag and wrapped value of an `Extension`, visited as a sequence.
+struct ExtensionAccess<'c, 'a, 'de, R> {
+    tag: Option<u64>,
+    de: Option<&'c mut Walker<'a, 'de, R>>,
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> SeqAccess<'de> for ExtensionAccess<'c, 'a, 'de, R> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if let Some(tag) = self.tag.take() {
+            return seed.deserialize(tag.into_deserializer()).map(Some);
+        }
+        match self.de.take() {
+            Some(de) => seed.deserialize(de).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.tag.is_some() as usize + self.de.is_some() as usize)
+    }
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> MapAccess<'de> for Compound<'c, 'a, 'de, R> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.at_end()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+/// Accesses an enum whose variant is either given by a `*Variant` token, or read from the following tokens.
+struct Enum<'c, 'a, 'de, R> {
+    de: &'c mut Walker<'a, 'de, R>,
+    variant: Option<&'c Token<'de>>,
+    end: Option<Token<'static>>,
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> EnumAccess<'de> for Enum<'c, 'a, 'de, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let value = match self.variant {
+            Some(Token::UnitVariant { variant, .. })
+            | Some(Token::NewtypeVariant { variant, .. })
+            | Some(Token::TupleVariant { variant, .. })
+            | Some(Token::StructVariant { variant, .. }) => {
+                let variant: StrDeserializer<Error> = variant.into_deserializer();
+                seed.deserialize(variant)?
+            }
+            _ => seed.deserialize(&mut *self.de)?,
+        };
+        Ok((value, self))
+    }
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> Enum<'c, 'a, 'de, R> {
+    fn finish<T>(self, value: T) -> Result<T, Error> {
+        if let Some(end) = self.end {
+            self.de.expect(end)?;
+        }
+        Ok(value)
+    }
+}
+
+impl<'c, 'a, 'de, R: Read<'a, 'de>> VariantAccess<'de> for Enum<'c, 'a, 'de, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.variant {
+            Some(Token::UnitVariant { .. }) => Ok(()),
+            Some(token) => Err(de::Error::custom(format!(
+                "expected a unit variant, found {:?}",
+                token
+            ))),
+            None => {
+                if let Some(Token::Unit) = self.de.peek()? {
+                    self.de.next()?;
+                }
+                self.finish(())
+            }
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.variant {
+            Some(Token::NewtypeVariant { .. }) | None => {
+                let value = seed.deserialize(&mut *self.de)?;
+                self.finish(value)
+            }
+            Some(token) => Err(de::Error::custom(format!(
+                "expected a newtype variant, found {:?}",
+                token
+            ))),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.variant {
+            Some(Token::TupleVariant { .. }) => self.de.visit_seq(visitor, Token::TupleVariantEnd),
+            None => {
+                let value = self.de.deserialize_any(visitor)?;
+                self.finish(value)
+            }
+            Some(token) => Err(de::Error::custom(format!(
+                "expected a tuple variant, found {:?}",
+                token
+            ))),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.variant {
+            Some(Token::StructVariant { .. }) => {
+                self.de.visit_map(visitor, Token::StructVariantEnd)
+            }
+            None => {
+                let value = self.de.deserialize_any(visitor)?;
+                self.finish(value)
+            }
+            Some(token) => Err(de::Error::custom(format!(
+                "expected a struct variant, found {:?}",
+                token
+            ))),
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use crate::{
+    tree::{with_len, Node},
+    Error, OwnedToken, Path, Segment, Token,
+};
+use serde::{
+    de::{self, Deserialize, Deserializer, EnumAccess, SeqAccess, VariantAccess, Visitor},
+    ser::{Serialize, SerializeSeq, Serializer},
+};
+use std::fmt;
+
+/// A single difference between two token streams, found by [`diff`].
+///
+/// Each value is the (owned) tokens of a complete value, such as a scalar or a whole map.
+///
+/// [`diff`]: fn.diff.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A map entry or sequence element that is only in the second stream.
+    Added {
+        /// The path of the entry or element.
+        path: Path,
+        /// The added value.
+        value: Vec<OwnedToken>,
+    },
+
+    /// A map entry or sequence element that is only in the first stream.
+    Removed {
+        /// The path of the entry or element.
+        path: Path,
+        /// The removed value.
+        value: Vec<OwnedToken>,
+    },
+
+    /// A value that differs between the streams, such as a changed scalar or a container of another type.
+    Changed {
+        /// The path of the value.
+        path: Path,
+        /// The value in the first stream.
+        from: Vec<OwnedToken>,
+        /// The value in the second stream.
+        to: Vec<OwnedToken>,
+    },
+}
+
+impl Change {
+    /// The path of the value that was added, removed or changed.
+    pub fn path(&self) -> &Path {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => {
+                write!(f, "+ {}: {}", DisplayPath(path), DisplayValue(value))
+            }
+            Change::Removed { path, value } => {
+                write!(f, "- {}: {}", DisplayPath(path), DisplayValue(value))
+            }
+            Change::Changed { path, from, to } => write!(
+                f,
+                "~ {}: {} -> {}",
+                DisplayPath(path),
+                DisplayValue(from),
+                DisplayValue(to)
+            ),
+        }
+    }
+}
+
+/// The path-addressed differences between two token streams, created by [`diff`] and applied by [`patch`].
+///
+/// Its [`Display`] rendering lists one change per line: `+` for an addition, `-` for a removal and `~` for a change. It can also be serialized (and deserialized) with any serde format, as a sequence of changes that are each a sequence of that symbol, the change's path and its value (or values), so that diffs can be shipped elsewhere and replayed. Paths are serialized as a sequence of `Key` and `Index` segments (rather than in dotted form, which can't tell an empty key from none), each an externally tagged enum variant.
+///
+/// [`diff`]: fn.diff.html
+/// [`patch`]: fn.patch.html
+/// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenDiff {
+    changes: Vec<Change>,
+}
+
+impl TokenDiff {
+    /// The changes, ordered as they appear in the streams.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Whether or not the streams were structurally equal.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl From<Vec<Change>> for TokenDiff {
+    fn from(changes: Vec<Change>) -> Self {
+        TokenDiff { changes }
+    }
+}
+
+impl fmt::Display for TokenDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the single values in two token streams, returning the differences between them as a [`TokenDiff`].
+///
+/// Maps and structs are compared entry by entry, matching entries whose keys render the same, and sequences and tuples element by element, so a change deep within a value is reported at its own path rather than as a change of the whole value. Containers of different types (or whose keys aren't all scalars) are reported as a change of the whole container. Scalars are compared by their tokens, so `1u8` and `1u64` differ, but strings are compared by their contents however they are represented. Map markers are ignored, and strings split into chunks are joined.
+///
+/// An error is returned if either stream is malformed, contains more than one value or is nested more than 128 levels deep. An empty stream differs from any value by the addition or removal of the whole value.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{diff, to_tokens};
+///
+/// let a = to_tokens(&mut serde_json::Deserializer::from_str(r#" {"name": "a", "tags": [1], "old": true} "#)).unwrap();
+/// let b = to_tokens(&mut serde_json::Deserializer::from_str(r#" {"name": "b", "tags": [1, 2]} "#)).unwrap();
+///
+/// assert_eq!(
+///     diff(&a, &b).unwrap().to_string(),
+///     "~ name: \"a\" -> \"b\"\n+ tags[1]: 2u64\n- old: true\n",
+/// );
+/// ```
+///
+/// [`TokenDiff`]: struct.TokenDiff.html
+pub fn diff(a: &[Token], b: &[Token]) -> Result<TokenDiff, Error> {
+    let a = Node::parse_single(a.iter().cloned().map(Token::into_owned))?;
+    let b = Node::parse_single(b.iter().cloned().map(Token::into_owned))?;
+
+    let mut changes = Vec::new();
+    let mut path = Path::new();
+    match (a, b) {
+        (Some(a), Some(b)) => diff_nodes(&mut path, &a, &b, &mut changes),
+        (Some(a), None) => changes.push(Change::Removed {
+            path,
+            value: a.into_tokens(),
+        }),
+        (None, Some(b)) => changes.push(Change::Added {
+            path,
+            value: b.into_tokens(),
+        }),
+        (None, None) => {}
+    }
+    Ok(TokenDiff { changes })
+}
+
+/// Applies a [`TokenDiff`] to the tokens of a single value, returning the patched value's tokens.
+///
+/// Changes are applied in order, and each must apply to the value as patched so far: an added entry or element must not exist yet (though its parent must), and a removed or changed value must exist and be equal to the value recorded in the change, compared as by [`diff`]. Otherwise an `Error::PathError` is returned, so that a diff is never applied to a base it wasn't made from. Added map keys are written as `Token::String`s, and container lengths are recomputed.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{diff, patch, to_tokens, TokenDiff};
+///
+/// let a = to_tokens(&mut serde_json::Deserializer::from_str(r#" {"name": "a", "tags": [1]} "#)).unwrap();
+/// let b = to_tokens(&mut serde_json::Deserializer::from_str(r#" {"name": "b", "tags": [1, 2]} "#)).unwrap();
+///
+/// // ship the diff as JSON, and replay it against the base
+/// let json = serde_json::to_string(&diff(&a, &b).unwrap()).unwrap();
+/// let changes: TokenDiff = serde_json::from_str(&json).unwrap();
+/// let patched = patch(&a, &changes).unwrap();
+///
+/// assert!(diff(&patched, &b).unwrap().is_empty());
+/// assert!(patch(&patched, &changes).is_err());
+/// ```
+///
+/// [`TokenDiff`]: struct.TokenDiff.html
+/// [`diff`]: fn.diff.html
+pub fn patch(base: &[Token], diff: &TokenDiff) -> Result<Vec<OwnedToken>, Error> {
+    let mut root = Node::parse_single(base.iter().cloned().map(Token::into_owned))?;
+    for change in &diff.changes {
+        match change {
+            Change::Added { path, value } => {
+                let value = parse_value(value)?;
+                let node = match &mut root {
+                    None if path.is_empty() => {
+                        root = Some(value);
+                        continue;
+                    }
+                    None => return Err(mismatch(path)),
+                    Some(node) => node,
+                };
+                let parent = &path.segments()[..path.len().saturating_sub(1)];
+                if path.is_empty()
+                    || node.get(parent).is_none()
+                    || node.get(path.segments()).is_some()
+                {
+                    return Err(mismatch(path));
+                }
+                node.insert(path, value).map_err(|_| mismatch(path))?;
+            }
+            Change::Removed { path, value } => {
+                let expected = parse_value(value)?;
+                let found = match &root {
+                    Some(node) if path.is_empty() => Some(node),
+                    Some(node) => node.get(path.segments()),
+                    None => None,
+                };
+                if !found.is_some_and(|found| same_node(found, &expected)) {
+                    return Err(mismatch(path));
+                }
+                match &mut root {
+                    Some(_) if path.is_empty() => root = None,
+                    Some(node) => {
+                        node.remove(path);
+                    }
+                    None => {}
+                }
+            }
+            Change::Changed { path, from, to } => {
+                let expected = parse_value(from)?;
+                let mut node = root
+                    .as_mut()
+                    .and_then(|node| node.get_mut(path.segments()))
+                    .ok_or_else(|| mismatch(path))?;
+                // a change within a `Some` or newtype is recorded at the same path as the prefix
+                while !same_node(node, &expected) {
+                    node = match node {
+                        Node::Prefixed(_, inner) => inner,
+                        _ => return Err(mismatch(path)),
+                    };
+                }
+                *node = parse_value(to)?;
+            }
+        }
+    }
+    Ok(root.map(Node::into_tokens).unwrap_or_default())
+}
+
+fn parse_value(tokens: &[OwnedToken]) -> Result<Node<'static>, Error> {
+    Node::parse_single(tokens.iter().cloned())?
+        .ok_or_else(|| Error::PathError("empty value in diff".to_string()))
+}
+
+fn same_node(a: &Node<'static>, b: &Node<'static>) -> bool {
+    let mut changes = Vec::new();
+    diff_nodes(&mut Path::new(), a, b, &mut changes);
+    changes.is_empty()
+}
+
+fn mismatch(path: &Path) -> Error {
+    Error::PathError(format!("diff does not apply at {}", DisplayPath(path)))
+}
+
+fn diff_nodes(path: &mut Path, a: &Node<'static>, b: &Node<'static>, changes: &mut Vec<Change>) {
+    match (a, b) {
+        (Node::Scalar(x), Node::Scalar(y)) if same_scalar(x, y) => {}
+        (Node::Prefixed(x, a), Node::Prefixed(y, b)) if x == y => diff_nodes(path, a, b, changes),
+        (
+            Node::Map {
+                open: x,
+                entries: a_entries,
+                ..
+            },
+            Node::Map {
+                open: y,
+                entries: b_entries,
+                ..
+            },
+        ) if same_shape(x, y)
+            && a_entries
+                .iter()
+                .chain(b_entries)
+                .all(|(key, _)| key.key_string().is_some()) =>
+        {
+            for (key, value) in a_entries {
+                path.push(Segment::Key(key.key_string().unwrap_or_default()));
+                match b_entries.iter().find(|(k, _)| k.same_key(key)) {
+                    Some((_, other)) => diff_nodes(path, value, other, changes),
+                    None => changes.push(Change::Removed {
+                        path: path.clone(),
+                        value: value.clone().into_tokens(),
+                    }),
+                }
+                path.pop();
+            }
+            for (key, value) in b_entries {
+                if !a_entries.iter().any(|(k, _)| k.same_key(key)) {
+                    changes.push(Change::Added {
+                        path: path.clone().key(key.key_string().unwrap_or_default()),
+                        value: value.clone().into_tokens(),
+                    });
+                }
+            }
+        }
+        (
+            Node::Seq {
+                open: x,
+                items: a_items,
+                ..
+            },
+            Node::Seq {
+                open: y,
+                items: b_items,
+                ..
+            },
+        ) if same_shape(x, y) => {
+            for (i, (a, b)) in a_items.iter().zip(b_items).enumerate() {
+                path.push(Segment::Index(i));
+                diff_nodes(path, a, b, changes);
+                path.pop();
+            }
+            // removed from the end first, so that each index is valid when it is removed
+            for i in (b_items.len()..a_items.len()).rev() {
+                changes.push(Change::Removed {
+                    path: path.clone().index(i),
+                    value: a_items[i].clone().into_tokens(),
+                });
+            }
+            for (i, item) in b_items.iter().enumerate().skip(a_items.len()) {
+                changes.push(Change::Added {
+                    path: path.clone().index(i),
+                    value: item.clone().into_tokens(),
+                });
+            }
+        }
+        _ if a == b => {}
+        _ => changes.push(Change::Changed {
+            path: path.clone(),
+            from: a.clone().into_tokens(),
+            to: b.clone().into_tokens(),
+        }),
+    }
+}
+
+fn same_scalar(x: &Token, y: &Token) -> bool {
+    match (x.as_str(), y.as_str()) {
+        (Some(x), Some(y)) => x == y,
+        _ => x == y,
+    }
+}
+
+// Whether two container tokens open the same type of container, whatever their lengths.
+fn same_shape(x: &Token, y: &Token) -> bool {
+    fn shape(token: &Token) -> OwnedToken {
+        match token {
+            Token::Seq { .. } => Token::Seq { len: None },
+            Token::Map { .. } => Token::Map { len: None },
+            token => with_len(token.clone().into_owned(), 0),
+        }
+    }
+    shape(x) == shape(y)
+}
+
+struct DisplayPath<'p>(&'p Path);
+
+impl<'p> fmt::Display for DisplayPath<'p> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.is_empty() {
+            true => f.write_str("<root>"),
+            false => write!(f, "{}", self.0),
+        }
+    }
+}
+
+// Renders a value's tokens on a single line.
+struct DisplayValue<'t>(&'t [OwnedToken]);
+
+impl<'t> fmt::Display for DisplayValue<'t> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, token) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", token)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for TokenDiff {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.changes)
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenDiff {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<Change>::deserialize(deserializer).map(TokenDiff::from)
+    }
+}
+
+impl Serialize for Change {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (op, path, values): (_, _, &[&Vec<OwnedToken>]) = match self {
+            Change::Added { path, value } => ("+", path, &[value]),
+            Change::Removed { path, value } => ("-", path, &[value]),
+            Change::Changed { path, from, to } => ("~", path, &[from, to]),
+        };
+        let mut seq = serializer.serialize_seq(Some(2 + values.len()))?;
+        seq.serialize_element(op)?;
+        seq.serialize_element(&SerPath(path))?;
+        for value in values {
+            seq.serialize_element(value)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Change {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(ChangeVisitor)
+    }
+}
+
+struct ChangeVisitor;
+
+impl<'de> Visitor<'de> for ChangeVisitor {
+    type Value = Change;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a change")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Change, A::Error> {
+        let op: String = next_element(&mut seq, 0)?;
+        let DePath(path) = next_element(&mut seq, 1)?;
+        let value = next_element(&mut seq, 2)?;
+        Ok(match op.as_str() {
+            "+" => Change::Added { path, value },
+            "-" => Change::Removed { path, value },
+            "~" => Change::Changed {
+                path,
+                from: value,
+                to: next_element(&mut seq, 3)?,
+            },
+            op => return Err(de::Error::unknown_variant(op, &["+", "-", "~"])),
+        })
+    }
+}
+
+const SEGMENTS: &[&str] = &["Key", "Index"];
+
+struct SerPath<'a>(&'a Path);
+
+impl<'a> Serialize for SerPath<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.segments().iter().map(SerSegment))
+    }
+}
+
+struct SerSegment<'a>(&'a Segment);
+
+impl<'a> Serialize for SerSegment<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Segment::Key(key) => serializer.serialize_newtype_variant("Segment", 0, "Key", key),
+            Segment::Index(index) => {
+                serializer.serialize_newtype_variant("Segment", 1, "Index", index)
+            }
+        }
+    }
+}
+
+struct DePath(Path);
+
+impl<'de> Deserialize<'de> for DePath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let segments = Vec::<DeSegment>::deserialize(deserializer)?;
+        Ok(DePath(
+            segments.into_iter().map(|s| s.0).collect::<Vec<_>>().into(),
+        ))
+    }
+}
+
+struct DeSegment(Segment);
+
+impl<'de> Deserialize<'de> for DeSegment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum("Segment", SEGMENTS, SegmentVisitor)
+    }
+}
+
+struct SegmentVisitor;
+
+impl<'de> Visitor<'de> for SegmentVisitor {
+    type Value = DeSegment;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a path segment")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<DeSegment, A::Error> {
+        let (tag, variant): (String, _) = data.variant()?;
+        let segment = match tag.as_str() {
+            "Key" => Segment::Key(variant.newtype_variant()?),
+            "Index" => Segment::Index(variant.newtype_variant()?),
+            tag => return Err(de::Error::unknown_variant(tag, SEGMENTS)),
+        };
+        Ok(DeSegment(segment))
+    }
+}
+
+fn next_element<'de, A, T>(seq: &mut A, index: usize) -> Result<T, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de>,
+{
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, &"a change"))
+}
@@ -0,0 +1,234 @@
+use crate::{Error, FloatPolicy, Token};
+use alloc::{borrow::Cow, string::String};
+use core::{fmt, str};
+
+/// Renders the token compactly, e.g. `1u8`, `"a"`, `[` or `Point {`.
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Bool(v) => write!(f, "{}", v),
+            Token::I8(v) => write!(f, "{}i8", v),
+            Token::I16(v) => write!(f, "{}i16", v),
+            Token::I32(v) => write!(f, "{}i32", v),
+            Token::I64(v) => write!(f, "{}i64", v),
+            Token::I128(v) => write!(f, "{}i128", v),
+            Token::U8(v) => write!(f, "{}u8", v),
+            Token::U16(v) => write!(f, "{}u16", v),
+            Token::U32(v) => write!(f, "{}u32", v),
+            Token::U64(v) => write!(f, "{}u64", v),
+            Token::U128(v) => write!(f, "{}u128", v),
+            Token::F32(v) => write!(f, "{:?}f32", v),
+            Token::F64(v) => write!(f, "{:?}f64", v),
+            Token::Char(v) => write!(f, "{:?}", v),
+            Token::Number(v) => write!(f, "{}", v),
+            Token::Str(v) => write!(f, "{:?}", v),
+            Token::String(v) => write!(f, "{:?}", v),
+            Token::Interned(v) => write!(f, "{:?}", v),
+            Token::Inline(v) => write!(f, "{:?}", v),
+            Token::StrChunk(v) => write!(f, "str chunk {:?}", v),
+            Token::StrEnd => f.write_str("str end"),
+            Token::Bytes(v) => write!(f, "bytes {:?}", v),
+            Token::ByteBuf(v) => write!(f, "bytes {:?}", v),
+            Token::BytesChunk(v) => write!(f, "bytes chunk {:?}", v),
+            Token::BytesEnd => f.write_str("bytes end"),
+            Token::None => f.write_str("none"),
+            Token::Some => f.write_str("some"),
+            Token::Unit => f.write_str("()"),
+            Token::UnitStruct { name } => f.write_str(name),
+            Token::UnitVariant { name, variant, .. } => write!(f, "{}::{}", name, variant),
+            Token::NewtypeStruct { name } => write!(f, "newtype {}", name),
+            Token::NewtypeVariant { name, variant, .. } => {
+                write!(f, "newtype {}::{}", name, variant)
+            }
+            Token::Seq { .. } => f.write_str("["),
+            Token::SeqEnd => f.write_str("]"),
+            Token::Tuple { .. } => f.write_str("("),
+            Token::TupleEnd | Token::TupleStructEnd | Token::TupleVariantEnd => f.write_str(")"),
+            Token::TupleStruct { name, .. } => write!(f, "{}(", name),
+            Token::TupleVariant { name, variant, .. } => write!(f, "{}::{}(", name, variant),
+            Token::Map { .. } => f.write_str("{"),
+            Token::MapEnd | Token::StructEnd | Token::StructVariantEnd => f.write_str("}"),
+            Token::MapKey => f.write_str("key:"),
+            Token::MapValue => f.write_str("value:"),
+            Token::Struct { name, .. } => write!(f, "{} {{", name),
+            Token::Field(v) => write!(f, "field {:?}", v),
+            Token::StructVariant { name, variant, .. } => write!(f, "{}::{} {{", name, variant),
+            Token::Enum { name } => write!(f, "enum {}", name),
+            Token::Extension { tag } => write!(f, "extension {}", tag),
+            Token::DocumentStart => f.write_str("document start"),
+            Token::DocumentEnd => f.write_str("document end"),
+            Token::Truncated { len } => write!(f, "truncated from {} bytes", len),
+        }
+    }
+}
+
+/// Returns a [`Display`]able rendering of the tokens, one per line, indented to reflect their nesting, for logs and error messages.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{display_tokens, to_tokens};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" {"a": [1, "b"]} "#);
+/// let tokens = to_tokens(&mut de).unwrap();
+///
+/// assert_eq!(
+///     display_tokens(&tokens).to_string(),
+///     "{\n  \"a\"\n  [\n    1u64\n    \"b\"\n  ]\n}\n",
+/// );
+/// ```
+///
+/// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+pub fn display_tokens<'t, 'a>(tokens: &'t [Token<'a>]) -> DisplayTokens<'t, 'a> {
+    DisplayTokens {
+        tokens,
+        utf8: None,
+        non_finite: FloatPolicy::PassThrough,
+    }
+}
+
+/// A [`Display`]able rendering of a token stream, returned by [`display_tokens`].
+///
+/// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+/// [`display_tokens`]: fn.display_tokens.html
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayTokens<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    utf8: Option<Utf8Policy>,
+    non_finite: FloatPolicy,
+}
+
+impl<'t, 'a> DisplayTokens<'t, 'a> {
+    /// Renders `Bytes` and `ByteBuf` payloads as strings, converted with [`Token::bytes_to_text`] under the given policy, rather than as arrays of bytes.
+    ///
+    /// With `Utf8Policy::Strict`, rendering fails with a `fmt::Error` if any payload isn't valid UTF-8 (which makes `to_string` panic), so the tokens should be checked with [`Token::bytes_to_text`] beforehand.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{display_tokens, Token, Utf8Policy};
+    ///
+    /// let tokens = [Token::Bytes(b"ok"[..].into()), Token::Bytes(b"n\xffo"[..].into())];
+    ///
+    /// assert_eq!(
+    ///     display_tokens(&tokens).bytes_as_text(Utf8Policy::Lossy).to_string(),
+    ///     "\"ok\"\n\"n\u{fffd}o\"\n",
+    /// );
+    /// ```
+    ///
+    /// [`Token::bytes_to_text`]: enum.Token.html#method.bytes_to_text
+    pub fn bytes_as_text(mut self, policy: Utf8Policy) -> Self {
+        self.utf8 = Some(policy);
+        self
+    }
+
+    /// Renders `NaN` and infinite floats according to the given policy, as `none` with `FloatPolicy::Null`, or failing with a `fmt::Error` with `FloatPolicy::Reject` (which makes `to_string` panic).
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{display_tokens, FloatPolicy, Token};
+    ///
+    /// let tokens = [Token::F64(f64::INFINITY)];
+    ///
+    /// assert_eq!(display_tokens(&tokens).to_string(), "inff64\n");
+    /// assert_eq!(display_tokens(&tokens).non_finite_floats(FloatPolicy::Null).to_string(), "none\n");
+    /// ```
+    pub fn non_finite_floats(mut self, policy: FloatPolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+}
+
+impl<'t, 'a> fmt::Display for DisplayTokens<'t, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut depth = 0usize;
+        for token in self.tokens {
+            if token.is_container_end() {
+                depth = depth.saturating_sub(1);
+            }
+            match (self.utf8, token) {
+                (Some(policy), Token::Bytes(_)) | (Some(policy), Token::ByteBuf(_)) => {
+                    let text = token
+                        .clone()
+                        .bytes_to_text(policy)
+                        .map_err(|_| fmt::Error)?;
+                    writeln!(f, "{:indent$}{}", "", text, indent = depth * 2)?;
+                }
+                (_, Token::F32(_)) | (_, Token::F64(_)) => {
+                    let float = self
+                        .non_finite
+                        .apply(token.clone())
+                        .map_err(|_| fmt::Error)?;
+                    writeln!(f, "{:indent$}{}", "", float, indent = depth * 2)?;
+                }
+                _ => writeln!(f, "{:indent$}{}", "", token, indent = depth * 2)?,
+            }
+            if token.is_container_start() {
+                depth += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How invalid UTF-8 is handled when byte payloads are converted to text, such as by [`Token::bytes_to_text`] and [`DisplayTokens::bytes_as_text`].
+///
+/// [`Token::bytes_to_text`]: enum.Token.html#method.bytes_to_text
+/// [`DisplayTokens::bytes_as_text`]: struct.DisplayTokens.html#method.bytes_as_text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Utf8Policy {
+    /// Invalid UTF-8 is an error.
+    Strict,
+    /// Invalid UTF-8 sequences are replaced with `U+FFFD REPLACEMENT CHARACTER`.
+    Lossy,
+    /// Payloads that aren't valid UTF-8 are left as bytes.
+    #[default]
+    PassThrough,
+}
+
+impl<'a> Token<'a> {
+    /// Converts a `Bytes` or `ByteBuf` token into a `Str` or `String` token, with invalid UTF-8 handled according to the given policy, leaving any other token as it is.
+    ///
+    /// Borrowed bytes that are valid UTF-8 remain borrowed. Chunks of bytes are left as they are, as a character may be split across chunks. Fails with `Error::InvalidUtf8` if the policy is `Utf8Policy::Strict` and the payload isn't valid UTF-8.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{Token, Utf8Policy};
+    ///
+    /// let token = Token::Bytes(b"a\xffb"[..].into());
+    ///
+    /// assert!(token.clone().bytes_to_text(Utf8Policy::Strict).is_err());
+    /// assert_eq!(
+    ///     token.clone().bytes_to_text(Utf8Policy::Lossy).unwrap(),
+    ///     Token::String("a\u{fffd}b".to_string()),
+    /// );
+    /// assert_eq!(token.clone().bytes_to_text(Utf8Policy::PassThrough).unwrap(), token);
+    /// ```
+    pub fn bytes_to_text(self, policy: Utf8Policy) -> Result<Token<'a>, Error> {
+        let (bytes, buf) = match self {
+            Token::Bytes(v) => (v, false),
+            Token::ByteBuf(v) => (Cow::Owned(v), true),
+            token => return Ok(token),
+        };
+        let (err, bytes) = match bytes {
+            Cow::Borrowed(v) => match str::from_utf8(v) {
+                Ok(v) => return Ok(Token::Str(Cow::Borrowed(v))),
+                Err(err) => (err, Cow::Borrowed(v)),
+            },
+            Cow::Owned(v) => match String::from_utf8(v) {
+                Ok(v) => return Ok(Token::String(v)),
+                Err(err) => (err.utf8_error(), Cow::Owned(err.into_bytes())),
+            },
+        };
+        match policy {
+            Utf8Policy::Strict => Err(Error::InvalidUtf8 {
+                valid_up_to: err.valid_up_to(),
+            }),
+            Utf8Policy::Lossy => Ok(Token::String(String::from_utf8_lossy(&bytes).into_owned())),
+            Utf8Policy::PassThrough if buf => Ok(Token::ByteBuf(bytes.into_owned())),
+            Utf8Policy::PassThrough => Ok(Token::Bytes(bytes)),
+        }
+    }
+}
@@ -1,33 +1,154 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::fmt;
+
 #[doc(hidden)]
 #[derive(Debug)]
 pub enum Error {
     TokenizerError(String),
-    TokenSinkError,
+    TokenSinkError(Box<dyn core::error::Error + Send + Sync>),
     TokenSinkNotReadyError,
+    PathError(String),
+    TokenStreamError(String),
+    WireError(String),
+    DepthLimitExceeded { depth: usize },
+    LengthLimitExceeded { len: usize },
+    InvalidUtf8 { valid_up_to: usize },
+    NonFiniteFloat { value: f64 },
+    DuplicateKey { key: String },
+    TransientPayload { len: usize },
+    MemoryBudgetExceeded { used: usize, budget: usize },
+    DeserializeError(String),
+    #[cfg(feature = "std")]
+    IoError(std::io::Error),
 }
 
-impl std::error::Error for Error {
+impl core::error::Error for Error {
     fn description(&self) -> &str {
         match self {
             Error::TokenizerError(ref string) => string,
-            Error::TokenSinkError => "",
+            Error::TokenSinkError(_) => "token sink error",
             Error::TokenSinkNotReadyError => "",
+            Error::PathError(ref string) => string,
+            Error::TokenStreamError(ref string) => string,
+            Error::WireError(ref string) => string,
+            Error::DepthLimitExceeded { .. } => "depth limit exceeded",
+            Error::LengthLimitExceeded { .. } => "length limit exceeded",
+            Error::InvalidUtf8 { .. } => "invalid utf-8",
+            Error::NonFiniteFloat { .. } => "non-finite float",
+            Error::DuplicateKey { .. } => "duplicate key",
+            Error::TransientPayload { .. } => "transient payload",
+            Error::MemoryBudgetExceeded { .. } => "memory budget exceeded",
+            Error::DeserializeError(ref string) => string,
+            #[cfg(feature = "std")]
+            Error::IoError(_) => "io error",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::TokenSinkError(err) => Some(&**err),
+            #[cfg(feature = "std")]
+            Error::IoError(err) => Some(err),
+            _ => None,
         }
     }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::TokenizerError(ref string) => write!(f, "{}", string),
-            Error::TokenSinkError => write!(f, ""),
+            Error::TokenSinkError(err) => write!(f, "token sink error: {}", err),
             Error::TokenSinkNotReadyError => write!(f, ""),
+            Error::PathError(ref string) => write!(f, "{}", string),
+            Error::TokenStreamError(ref string) => write!(f, "{}", string),
+            Error::WireError(ref string) => write!(f, "{}", string),
+            Error::DepthLimitExceeded { depth } => write!(f, "depth limit exceeded: {}", depth),
+            Error::LengthLimitExceeded { len } => write!(f, "length limit exceeded: {}", len),
+            Error::InvalidUtf8 { valid_up_to } => {
+                write!(f, "invalid utf-8 after {} bytes", valid_up_to)
+            }
+            Error::NonFiniteFloat { value } => write!(f, "non-finite float: {}", value),
+            Error::DuplicateKey { key } => write!(f, "duplicate key: {}", key),
+            Error::TransientPayload { len } => {
+                write!(f, "transient payload of {} bytes can't be borrowed", len)
+            }
+            Error::MemoryBudgetExceeded { used, budget } => {
+                write!(f, "memory budget exceeded: {} of {} bytes", used, budget)
+            }
+            Error::DeserializeError(ref string) => write!(f, "{}", string),
+            #[cfg(feature = "std")]
+            Error::IoError(err) => write!(f, "io error: {}", err),
         }
     }
 }
 
 impl serde::ser::Error for Error {
-    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
         Error::TokenizerError(msg.to_string())
     }
 }
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::DeserializeError(msg.to_string())
+    }
+}
+
+impl From<core::convert::Infallible> for Error {
+    fn from(never: core::convert::Infallible) -> Self {
+        match never {}
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::IoError(err)
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<T: fmt::Debug + Send + Sync + 'static> From<futures::sync::mpsc::SendError<T>> for Error {
+    fn from(err: futures::sync::mpsc::SendError<T>) -> Self {
+        Error::sink(err)
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<T: fmt::Debug + Send + Sync + 'static> From<futures::unsync::mpsc::SendError<T>> for Error {
+    fn from(err: futures::unsync::mpsc::SendError<T>) -> Self {
+        Error::sink(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error {
+    /// Captures the error of a token sink, so that it can be downcast from the `source` of the returned error.
+    pub(crate) fn sink<E: core::error::Error + Send + Sync + 'static>(err: E) -> Self {
+        Error::TokenSinkError(Box::new(err))
+    }
+
+    /// Captures the `Debug` representation of the error of a token sink, for sinks whose errors can't be boxed (such as those that aren't `'static`, as they hold on to a rejected, borrowed token).
+    pub(crate) fn sink_debug<E: fmt::Debug>(err: E) -> Self {
+        Error::TokenSinkError(Box::new(SinkError(alloc::format!("{:?}", err))))
+    }
+}
+
+/// The `Debug` representation of a token sink's error.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct SinkError(String);
+
+#[cfg(feature = "std")]
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::error::Error for SinkError {}
@@ -0,0 +1,74 @@
+use crate::EXTENSION_TOKEN;
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{self, Deserialize, Deserializer, SeqAccess},
+    ser::{Serialize, SerializeTupleStruct, Serializer},
+};
+
+/// A format-specific extension value, such as a CBOR tagged value or a MessagePack ext type, wrapping a value with a numeric tag.
+///
+/// Format adapters can serialize their extension values as an `Extension` to pass them through a token stream faithfully: a [`Tokenizer`] writes it as a `Token::Extension` header followed by the wrapped value, and a `Token::Extension` deserializes (and detokenizes) back into an `Extension`. Other serializers see a tuple struct of the tag and the value.
+///
+/// # Example:
+///
+/// ```
+/// use serde::Serialize;
+/// use serde_token::{from_tokens, ChannelSink, Extension, Token, Tokenizer};
+/// use std::sync::mpsc::channel;
+///
+/// let (tx, rx) = channel();
+/// Extension { tag: 1, value: 1_500_000_000u64 }
+///     .serialize(&mut Tokenizer::new(ChannelSink::new(tx)))
+///     .unwrap();
+///
+/// let tokens: Vec<_> = rx.try_iter().collect();
+/// assert_eq!(tokens, vec![Token::Extension { tag: 1 }, Token::U64(1_500_000_000)]);
+///
+/// let extension: Extension<u64> = from_tokens(&tokens).unwrap();
+/// assert_eq!(extension, Extension { tag: 1, value: 1_500_000_000 });
+/// ```
+///
+/// [`Tokenizer`]: struct.Tokenizer.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Extension<T> {
+    /// The format-specific tag (e.g. a CBOR tag number or MessagePack ext type).
+    pub tag: u64,
+
+    /// The tagged value.
+    pub value: T,
+}
+
+impl<T: Serialize> Serialize for Extension<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_tuple_struct(EXTENSION_TOKEN, 2)?;
+        state.serialize_field(&self.tag)?;
+        state.serialize_field(&self.value)?;
+        state.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Extension<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple_struct(EXTENSION_TOKEN, 2, ExtensionVisitor(PhantomData))
+    }
+}
+
+struct ExtensionVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> de::Visitor<'de> for ExtensionVisitor<T> {
+    type Value = Extension<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an extension value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Extension<T>, A::Error> {
+        let tag = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let value = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Extension { tag, value })
+    }
+}
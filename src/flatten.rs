@@ -0,0 +1,252 @@
+use crate::{
+    path::{PathTracker, Role},
+    tree::Node,
+    Error, ParsePathError, Path, Segment, Token,
+};
+#[cfg(feature = "futures01")]
+use futures::{try_ready, Async, Poll, Stream};
+use std::collections::HashMap;
+
+/// Converts tokens into `(path, scalar)` pairs, one token at a time.
+///
+/// Paths are rendered in dotted form (e.g. `a.b[2].c`). Container, map key and `Some`/newtype prefix tokens are consumed without producing a pair, and the chunks of a chunked string or byte array are joined into a single `String` or `ByteBuf`.
+///
+/// Every kind of container is flattened: tuples, tuple structs and tuple variants are indexed like sequences, and structs, struct variants and maps (whatever the type of their keys) are keyed like maps. Variant names are not part of the path, and empty containers produce no pairs.
+#[derive(Clone, Debug, Default)]
+pub struct Flattener {
+    tracker: PathTracker,
+    chunks: Option<String>,
+    bytes_chunks: Option<Vec<u8>>,
+}
+
+impl Flattener {
+    /// Creates a flattener positioned before the top-level value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances past the given token, returning a pair if the token is a scalar value.
+    pub fn push<'a>(&mut self, token: Token<'a>) -> Option<(String, Token<'a>)> {
+        let role = self.tracker.observe(&token).role;
+        if role == Role::Key {
+            return None;
+        }
+        match token {
+            Token::StrChunk(chunk) => {
+                self.chunks.get_or_insert_with(String::new).push_str(&chunk);
+                None
+            }
+            Token::StrEnd => {
+                let string = self.chunks.take().unwrap_or_default();
+                Some((self.tracker.path().to_string(), Token::String(string)))
+            }
+            Token::BytesChunk(chunk) => {
+                self.bytes_chunks
+                    .get_or_insert_with(Vec::new)
+                    .extend_from_slice(&chunk);
+                None
+            }
+            Token::BytesEnd => {
+                let bytes = self.bytes_chunks.take().unwrap_or_default();
+                Some((self.tracker.path().to_string(), Token::ByteBuf(bytes)))
+            }
+            token if role == Role::Scalar => Some((self.tracker.path().to_string(), token)),
+            _ => None,
+        }
+    }
+}
+
+/// Flattens a token stream into `(path, scalar)` pairs.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{flatten, Token};
+///
+/// let tokens = vec![
+///     Token::Map { len: None },
+///     Token::Str("a".into()),
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ];
+///
+/// assert_eq!(flatten(tokens), vec![("a[0]".to_string(), Token::Bool(true))]);
+/// ```
+pub fn flatten<'a, I>(tokens: I) -> Vec<(String, Token<'a>)>
+where
+    I: IntoIterator<Item = Token<'a>>,
+{
+    let mut flattener = Flattener::new();
+    tokens
+        .into_iter()
+        .filter_map(|token| flattener.push(token))
+        .collect()
+}
+
+/// Adapts a [`futures::Stream`] of `Token`s into a stream of `(path, scalar)` pairs.
+///
+/// *Requires the `futures01` feature.*
+///
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+#[cfg(feature = "futures01")]
+pub fn flatten_stream<'a, S>(stream: S) -> FlattenStream<S>
+where
+    S: Stream<Item = Token<'a>>,
+{
+    FlattenStream {
+        stream,
+        flattener: Flattener::new(),
+    }
+}
+
+/// A stream of `(path, scalar)` pairs, created by [`flatten_stream`].
+///
+/// [`flatten_stream`]: fn.flatten_stream.html
+#[cfg(feature = "futures01")]
+#[derive(Debug)]
+pub struct FlattenStream<S> {
+    stream: S,
+    flattener: Flattener,
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S> Stream for FlattenStream<S>
+where
+    S: Stream<Item = Token<'a>>,
+{
+    type Item = (String, Token<'a>);
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, S::Error> {
+        loop {
+            match try_ready!(self.stream.poll()) {
+                Some(token) => {
+                    if let Some(pair) = self.flattener.push(token) {
+                        return Ok(Async::Ready(Some(pair)));
+                    }
+                }
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+/// Synthesizes a well-formed nested token stream from `(path, scalar)` pairs, the inverse of [`flatten`].
+///
+/// Keys become `String` tokens, and maps and sequences are emitted with known lengths. Keys keep the order in which they are first seen, and each sequence's elements must be given in order, starting from `0`. An error is returned if a path is malformed, skips past the end of a sequence (e.g. `a[1]` before `a[0]`), or conflicts with a previous path (e.g. `a` and `a.b`).
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{unflatten, Token};
+///
+/// let tokens = unflatten(vec![("a[0]", Token::Bool(true))]).unwrap();
+///
+/// assert_eq!(tokens, vec![
+///     Token::Map { len: Some(1) },
+///     Token::String("a".to_string()),
+///     Token::Seq { len: Some(1) },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ]);
+/// ```
+///
+/// [`flatten`]: fn.flatten.html
+pub fn unflatten<'a, I, P>(pairs: I) -> Result<Vec<Token<'a>>, Error>
+where
+    I: IntoIterator<Item = (P, Token<'a>)>,
+    P: AsRef<str>,
+{
+    let mut root = None;
+    let mut keys = HashMap::new();
+    for (path, token) in pairs {
+        let path: Path = path
+            .as_ref()
+            .parse()
+            .map_err(|err: ParsePathError| Error::PathError(err.to_string()))?;
+        insert(&mut root, &mut keys, &path, token)?;
+    }
+    Ok(root.map(Node::into_tokens).unwrap_or_default())
+}
+
+// Inserts a scalar at the given path, creating any missing maps and sequences along the way.
+//
+// `keys` maps the path of each map entry inserted so far to its position in its map, so that keys are found without scanning their maps.
+fn insert<'a>(
+    root: &mut Option<Node<'a>>,
+    keys: &mut HashMap<Vec<Segment>, usize>,
+    path: &Path,
+    token: Token<'a>,
+) -> Result<(), Error> {
+    let segments = path.segments();
+    let (last, parent) = match segments.split_last() {
+        Some(split) => split,
+        None if root.is_none() => {
+            *root = Some(Node::Scalar(token));
+            return Ok(());
+        }
+        None => return Err(conflict(path)),
+    };
+
+    let mut node = root.get_or_insert_with(|| container(&segments[0]));
+    for (i, segment) in parent.iter().enumerate() {
+        let vacant = container(&segments[i + 1]);
+        node = entry(node, keys, &segments[..=i], segment, path, vacant)?.0;
+    }
+    match entry(node, keys, segments, last, path, Node::Scalar(token))? {
+        (_, false) => Ok(()),
+        (_, true) => Err(conflict(path)),
+    }
+}
+
+// Returns the child of `node` at the given segment, inserting `vacant` if there is none yet, and whether the child already existed.
+fn entry<'n, 'a>(
+    node: &'n mut Node<'a>,
+    keys: &mut HashMap<Vec<Segment>, usize>,
+    prefix: &[Segment],
+    segment: &Segment,
+    path: &Path,
+    vacant: Node<'a>,
+) -> Result<(&'n mut Node<'a>, bool), Error> {
+    match (node, segment) {
+        (Node::Map { entries, .. }, Segment::Key(key)) => match keys.get(prefix) {
+            Some(&i) => Ok((&mut entries[i].1, true)),
+            None => {
+                keys.insert(prefix.to_vec(), entries.len());
+                entries.push((Node::Scalar(Token::String(key.clone())), vacant));
+                Ok((&mut entries.last_mut().unwrap().1, false))
+            }
+        },
+        (Node::Seq { items, .. }, Segment::Index(index)) => {
+            if *index < items.len() {
+                Ok((&mut items[*index], true))
+            } else if *index == items.len() {
+                items.push(vacant);
+                Ok((items.last_mut().unwrap(), false))
+            } else {
+                Err(Error::PathError(format!(
+                    "index {} skips past the end of a sequence of length {}: {}",
+                    index,
+                    items.len(),
+                    path
+                )))
+            }
+        }
+        _ => Err(conflict(path)),
+    }
+}
+
+// Creates the empty container that the given segment steps into.
+fn container<'a>(segment: &Segment) -> Node<'a> {
+    match segment {
+        Segment::Key(_) => Node::map(),
+        Segment::Index(_) => Node::seq(),
+    }
+}
+
+fn conflict(path: &Path) -> Error {
+    Error::PathError(format!("conflicting path: {}", path))
+}
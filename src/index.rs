@@ -0,0 +1,197 @@
+use crate::{path::PathTracker, Path, Token};
+use futures::{sink::Sink, Async, AsyncSink, Poll, StartSend};
+use std::{
+    cell::Cell, collections::HashMap, convert::Infallible, io, marker::PhantomData, ops::Range,
+    rc::Rc,
+};
+
+/// The location of a single value within a tokenized document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The path of the value.
+    pub path: Path,
+
+    /// The range of token offsets spanned by the value, including any `Some` or newtype prefixes.
+    pub tokens: Range<usize>,
+
+    /// The approximate range of input bytes spanned by the value, if a byte position source was provided.
+    ///
+    /// The start of the range is the input position after the preceding token was emitted, and so may include leading whitespace or separators.
+    pub bytes: Option<Range<usize>>,
+}
+
+/// A path→location index of a tokenized document, built by an [`IndexSink`].
+///
+/// [`IndexSink`]: struct.IndexSink.html
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    entries: Vec<IndexEntry>,
+    lookup: HashMap<Path, usize>,
+}
+
+impl Index {
+    /// Returns the entry for the first value found at the given path.
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        self.lookup.get(path).map(|&i| &self.entries[i])
+    }
+
+    /// The indexed entries, in the order in which their values begin.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// The number of indexed entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether or not the index contains any entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A [`futures::Sink`] of `Token`s that builds an [`Index`] of the paths it sees.
+///
+/// *Requires the `futures01` feature.*
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`Index`]: struct.Index.html
+pub struct IndexSink<'a> {
+    tracker: PathTracker,
+    offset: usize,
+    open: Vec<Option<usize>>,
+    paths: Option<Vec<Path>>,
+    position: Option<Box<dyn Fn() -> usize>>,
+    last_position: usize,
+    index: Index,
+    _token: PhantomData<Token<'a>>,
+}
+
+impl<'a> IndexSink<'a> {
+    /// Creates a sink that indexes every value.
+    pub fn new() -> Self {
+        IndexSink {
+            tracker: PathTracker::new(),
+            offset: 0,
+            open: Vec::new(),
+            paths: None,
+            position: None,
+            last_position: 0,
+            index: Index::default(),
+            _token: PhantomData,
+        }
+    }
+
+    /// Restricts the index to values at the given paths.
+    pub fn only<I: IntoIterator<Item = Path>>(mut self, paths: I) -> Self {
+        self.paths = Some(paths.into_iter().collect());
+        self
+    }
+
+    /// Records input byte ranges using the given source of the deserializer's current input position (e.g. a [`CountingReader`]'s `position`).
+    ///
+    /// [`CountingReader`]: struct.CountingReader.html
+    pub fn with_position<F: Fn() -> usize + 'static>(mut self, position: F) -> Self {
+        self.position = Some(Box::new(position));
+        self
+    }
+
+    /// Consumes the sink, returning the index built so far.
+    pub fn into_index(self) -> Index {
+        self.index
+    }
+
+    fn record(&mut self, token: &Token<'a>) {
+        let step = self.tracker.observe(token);
+        let position = self.position.as_ref().map(|position| position());
+
+        if step.starts_value {
+            let path = self.tracker.path();
+            let wanted = match &self.paths {
+                Some(paths) => paths.contains(path),
+                None => true,
+            };
+            let entry = if wanted {
+                let i = self.index.entries.len();
+                self.index.entries.push(IndexEntry {
+                    path: path.clone(),
+                    tokens: self.offset..self.offset,
+                    bytes: position.map(|_| self.last_position..self.last_position),
+                });
+                self.index.lookup.entry(path.clone()).or_insert(i);
+                Some(i)
+            } else {
+                None
+            };
+            self.open.push(entry);
+        }
+
+        self.offset += 1;
+        if step.ends_value {
+            if let Some(Some(i)) = self.open.pop() {
+                let entry = &mut self.index.entries[i];
+                entry.tokens.end = self.offset;
+                if let (Some(bytes), Some(end)) = (entry.bytes.as_mut(), position) {
+                    bytes.end = end;
+                }
+            }
+        }
+        if let Some(position) = position {
+            self.last_position = position;
+        }
+    }
+}
+
+impl<'a> Default for IndexSink<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Sink for IndexSink<'a> {
+    type SinkItem = Token<'a>;
+    type SinkError = Infallible;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Infallible> {
+        self.record(&token);
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Infallible> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// An `io::Read` adapter that counts the bytes read through it, for use as an [`IndexSink`] position source.
+///
+/// [`IndexSink`]: struct.IndexSink.html
+#[derive(Debug)]
+pub struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<usize>>,
+}
+
+impl<R: io::Read> CountingReader<R> {
+    /// Wraps the given reader.
+    pub fn new(inner: R) -> Self {
+        CountingReader {
+            inner,
+            count: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Returns a closure reporting the number of bytes read so far.
+    pub fn position(&self) -> impl Fn() -> usize + 'static {
+        let count = self.count.clone();
+        move || count.get()
+    }
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n);
+        Ok(n)
+    }
+}
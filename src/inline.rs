@@ -0,0 +1,100 @@
+use core::{fmt, hash, ops::Deref};
+
+/// A string of at most [`CAPACITY`] bytes, stored inline rather than on the heap.
+///
+/// Written as the payload of a `Token::Inline` by a [`Tokenizer`] configured with [`inline_strings`], so that the many short strings of a typical token stream (such as map keys) don't each need an allocation. Dereferences to `str`.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::InlineStr;
+///
+/// let s = InlineStr::new("short").unwrap();
+/// assert_eq!(&*s, "short");
+/// assert_eq!(InlineStr::new(&"long".repeat(10)), None);
+/// ```
+///
+/// [`CAPACITY`]: #associatedconstant.CAPACITY
+/// [`Tokenizer`]: struct.Tokenizer.html
+/// [`inline_strings`]: struct.TokenizeOptions.html#method.inline_strings
+#[derive(Clone, Copy)]
+pub struct InlineStr {
+    len: u8,
+    buf: [u8; InlineStr::CAPACITY],
+}
+
+impl InlineStr {
+    /// The length in bytes of the longest string that can be stored inline.
+    pub const CAPACITY: usize = 24;
+
+    /// Copies a string inline, or returns `None` if it is longer than `CAPACITY` bytes.
+    pub fn new(s: &str) -> Option<Self> {
+        if s.len() > Self::CAPACITY {
+            return None;
+        }
+        let mut buf = [0; Self::CAPACITY];
+        buf[..s.len()].copy_from_slice(s.as_bytes());
+        Some(InlineStr {
+            len: s.len() as u8,
+            buf,
+        })
+    }
+
+    /// Returns the string.
+    pub fn as_str(&self) -> &str {
+        // only ever constructed from a `str`, so the bytes are always valid utf-8
+        core::str::from_utf8(&self.buf[..usize::from(self.len)]).unwrap_or_default()
+    }
+}
+
+impl Deref for InlineStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for InlineStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for InlineStr {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for InlineStr {}
+
+impl PartialOrd for InlineStr {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InlineStr {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl hash::Hash for InlineStr {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for InlineStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for InlineStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{Error, Token, TokenSink};
+use std::io::{self, Write};
+
+/// Encodes each `Token` written to a [`WriteSink`] into bytes.
+///
+/// [`WriteSink`]: struct.WriteSink.html
+pub trait TokenEncoder {
+    /// Appends the encoding of a single token to the given buffer.
+    fn encode(&mut self, token: &Token, buf: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// A [`TokenEncoder`] that writes the `Debug` representation of each token on its own line, for inspecting token streams by eye.
+///
+/// [`TokenEncoder`]: trait.TokenEncoder.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DebugEncoder;
+
+impl TokenEncoder for DebugEncoder {
+    fn encode(&mut self, token: &Token, buf: &mut Vec<u8>) -> Result<(), Error> {
+        writeln!(buf, "{:?}", token).map_err(io_error)
+    }
+}
+
+/// A [`TokenSink`] that encodes each `Token` with a [`TokenEncoder`] (by default, a [`DebugEncoder`]) and writes the bytes to an `io::Write`, such as a file, socket or stdout.
+///
+/// Each token is written as soon as it is encoded, so unbuffered writers should be wrapped in an `io::BufWriter`.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize, WriteSink};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// let mut out = Vec::new();
+/// tokenize(&mut de, WriteSink::new(&mut out)).unwrap();
+///
+/// assert_eq!(String::from_utf8(out).unwrap(), "Seq { len: None }\nBool(true)\nSeqEnd\n");
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`TokenEncoder`]: trait.TokenEncoder.html
+/// [`DebugEncoder`]: struct.DebugEncoder.html
+#[derive(Debug)]
+pub struct WriteSink<W, E = DebugEncoder> {
+    writer: W,
+    encoder: E,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> WriteSink<W> {
+    /// Wraps the given writer, encoding tokens with a [`DebugEncoder`].
+    ///
+    /// [`DebugEncoder`]: struct.DebugEncoder.html
+    pub fn new(writer: W) -> Self {
+        Self::with_encoder(writer, DebugEncoder)
+    }
+}
+
+impl<W: Write, E: TokenEncoder> WriteSink<W, E> {
+    /// Wraps the given writer, encoding tokens with the given encoder.
+    pub fn with_encoder(writer: W, encoder: E) -> Self {
+        WriteSink {
+            writer,
+            encoder,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Gets a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Gets a mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consumes the `WriteSink`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<'de, W: Write, E: TokenEncoder> TokenSink<'de> for WriteSink<W, E> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        self.buf.clear();
+        self.encoder.encode(&token, &mut self.buf)?;
+        self.writer.write_all(&self.buf).map_err(io_error)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush().map_err(io_error)
+    }
+}
+
+fn io_error(err: io::Error) -> Error {
+    Error::TokenSinkError(Box::new(err))
+}
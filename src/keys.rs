@@ -0,0 +1,100 @@
+use crate::{
+    path::{role, PathTracker, Role},
+    Token,
+};
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::Arc,
+};
+
+/// Tracks the keys of each map (and fields of each struct) in a token stream, to detect duplicates.
+#[derive(Debug, Default)]
+pub(crate) struct KeyTracker {
+    tracker: PathTracker,
+    // The keys seen so far in each enclosing container, or `None` for sequences.
+    scopes: Vec<Option<BTreeSet<Vec<Token<'static>>>>>,
+    // The tokens of the key being observed, and how many containers within it are open.
+    key: Vec<Token<'static>>,
+    depth: usize,
+}
+
+impl KeyTracker {
+    /// Advances past the given token, returning the tokens of the key it completes if that key has already been seen in the same map.
+    pub(crate) fn observe(&mut self, token: &Token) -> Option<Vec<Token<'static>>> {
+        match self.tracker.observe(token).role {
+            Role::Key => {
+                self.key.push(key_token(token));
+                match role(token) {
+                    Role::Open => self.depth += 1,
+                    Role::Close => self.depth -= 1,
+                    Role::Prefix => return None,
+                    _ => {}
+                }
+                if self.depth > 0 {
+                    return None;
+                }
+                let key = std::mem::take(&mut self.key);
+                match self.scopes.last_mut() {
+                    Some(Some(keys)) if keys.contains(&key) => Some(key),
+                    Some(Some(keys)) => {
+                        keys.insert(key);
+                        None
+                    }
+                    _ => None,
+                }
+            }
+            Role::Open => {
+                let map = matches!(
+                    token,
+                    Token::Map { .. } | Token::Struct { .. } | Token::StructVariant { .. }
+                );
+                self.scopes
+                    .push(if map { Some(BTreeSet::new()) } else { None });
+                None
+            }
+            Role::Close => {
+                self.scopes.pop();
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Converts a key token into an owned token that compares equal to any other spelling of the same key, such as a `Field` and a `Str` of the same name.
+fn key_token(token: &Token) -> Token<'static> {
+    match token {
+        Token::Field(v) => Token::String(v.to_string()),
+        Token::Interned(v) => Token::String(v.to_string()),
+        Token::Inline(v) => Token::String(v.to_string()),
+        token => token.clone().into_owned(),
+    }
+}
+
+/// Replaces the strings written as map keys (and struct field names) with `Token::Interned`s, so that equal keys share a single allocation.
+#[derive(Debug, Default)]
+pub(crate) struct KeyInterner {
+    tracker: PathTracker,
+    symbols: HashSet<Arc<str>>,
+}
+
+impl KeyInterner {
+    /// Advances past the given token, interning it if it is a string in a key position.
+    pub(crate) fn intern<'a>(&mut self, token: Token<'a>) -> Token<'a> {
+        if self.tracker.observe(&token).role != Role::Key {
+            return token;
+        }
+        let key = match token {
+            Token::Str(ref v) => &**v,
+            Token::String(ref v) => v,
+            Token::Inline(ref v) => v,
+            _ => return token,
+        };
+        if let Some(symbol) = self.symbols.get(key) {
+            return Token::Interned(symbol.clone());
+        }
+        let symbol = Arc::<str>::from(key);
+        self.symbols.insert(symbol.clone());
+        Token::Interned(symbol)
+    }
+}
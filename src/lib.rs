@@ -1,4 +1,4 @@
-//! Utility for [transcoding] a [Serde] [deserializer] into a [`futures::Sink`] of `Token`s.
+//! Utility for [transcoding] a [Serde] [deserializer] into a sink of `Token`s, such as a `Vec`, a channel or (with the `futures01` feature) a [`futures::Sink`].
 //!
 //! [transcoding]: https://docs.serde.rs/serde_transcode/index.html
 //! [Serde]: https://serde.rs
@@ -8,6 +8,7 @@
 //! # Example:
 //!
 //! ```
+//! # #[cfg(feature = "futures01")] {
 //! use futures::{unsync::mpsc::unbounded, Future, Sink, Stream};
 //! use serde_json::Deserializer;
 //! use serde_token::{tokenize, Token};
@@ -21,30 +22,212 @@
 //! assert_eq!(expected, vec![
 //!     Token::Seq { len: None },
 //!     Token::Map { len: None },
-//!     Token::Str("a"),
+//!     Token::Str("a".into()),
 //!     Token::Bool(false),
 //!     Token::MapEnd,
-//!     Token::Str("hello"),
+//!     Token::Str("hello".into()),
 //!     Token::U64(3),
 //!     Token::SeqEnd,
 //! ])
+//! # }
 //! ```
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
 #![doc(html_root_url = "https://docs.rs/serde_token/0.0.1")]
 
+extern crate alloc;
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::convert::TryFrom;
+
+#[cfg(all(feature = "futures03", feature = "json"))]
+mod async_read;
+#[cfg(feature = "futures03")]
+mod async_tokenize;
+mod cmp;
+#[cfg(feature = "codec")]
+mod codec;
+#[cfg(feature = "std")]
+pub mod de;
+#[cfg(feature = "std")]
+mod diff;
+mod display;
 mod error;
+mod extension;
+#[cfg(feature = "std")]
+mod flatten;
+#[cfg(feature = "futures01")]
+mod index;
+mod inline;
+#[cfg(feature = "std")]
+mod io_sink;
+#[cfg(feature = "std")]
+mod keys;
+#[cfg(feature = "std")]
+mod merge;
+#[cfg(feature = "std")]
+mod migrate;
+// Mapping a file is inherently `unsafe`, so `unsafe` code is only allowed (and `MappedFile::open` only exists) with the `mmap` feature.
+#[cfg(feature = "mmap")]
+#[allow(unsafe_code)]
+mod mmap;
+#[cfg(feature = "std")]
+mod parallel;
+#[cfg(feature = "std")]
+mod path;
+#[cfg(feature = "futures01")]
+mod pipeline;
+#[cfg(feature = "json")]
+mod push_json;
+#[cfg(feature = "std")]
+mod ring;
+#[cfg(feature = "futures01")]
+mod sample;
+#[cfg(feature = "std")]
+mod search;
+#[cfg(feature = "std")]
+mod ser;
+#[cfg(feature = "serde_test")]
+mod serde_test_compat;
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "std")]
+mod sink;
+#[cfg(feature = "std")]
+mod split;
+#[cfg(feature = "futures01")]
+mod stream;
+#[cfg(feature = "futures01")]
+mod substitute;
+#[cfg(feature = "std")]
+mod tee;
+#[cfg(feature = "std")]
+mod token_serde;
 mod tokenize;
+#[cfg(feature = "tokio")]
+mod tokio_sink;
+mod transcode;
+#[cfg(feature = "std")]
+mod transform;
+#[cfg(feature = "std")]
+mod tree;
+#[cfg(feature = "std")]
+pub mod wire;
+#[cfg(feature = "std")]
+mod write;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test;
 
+#[cfg(all(feature = "futures03", feature = "json"))]
+pub use async_read::{tokenize_async_read, AsyncReadTokens, JsonFormat, PushParser};
+#[cfg(feature = "futures03")]
+pub use async_tokenize::{tokenize_async, tokenize_async_yielding, BlockingSink};
+#[cfg(feature = "std")]
+pub use cmp::sort_maps;
+#[cfg(feature = "codec")]
+pub use codec::TokenCodec;
+#[cfg(feature = "std")]
+pub use de::from_tokens;
+#[cfg(feature = "std")]
+pub use diff::{diff, patch, Change, TokenDiff};
+pub use display::{display_tokens, DisplayTokens, Utf8Policy};
 pub use error::Error;
-pub use tokenize::tokenize;
+pub use extension::Extension;
+#[cfg(feature = "std")]
+pub use flatten::{flatten, unflatten, Flattener};
+#[cfg(feature = "futures01")]
+pub use flatten::{flatten_stream, FlattenStream};
+#[cfg(feature = "futures01")]
+pub use index::{CountingReader, Index, IndexEntry, IndexSink};
+pub use inline::InlineStr;
+#[cfg(feature = "std")]
+pub use io_sink::{DebugEncoder, TokenEncoder, WriteSink};
+#[cfg(feature = "std")]
+pub use merge::{merge, ArrayMerge};
+#[cfg(feature = "std")]
+pub use migrate::Migration;
+#[cfg(feature = "futures01")]
+pub use migrate::{Migrate, MigrateError};
+#[cfg(feature = "mmap")]
+pub use mmap::MappedFile;
+#[cfg(feature = "std")]
+pub use parallel::{tokenize_parallel, ParallelTokens};
+#[cfg(feature = "std")]
+pub use path::{ParsePathError, Path, PathTracker, Role, Segment, Step};
+#[cfg(feature = "futures01")]
+pub use pipeline::{Pipeline, PipelineError, PipelineHandle, PipelineSink};
+#[cfg(feature = "json")]
+pub use push_json::JsonPushParser;
+#[cfg(feature = "std")]
+pub use ring::{token_ring, RingSink, RingStream};
+#[cfg(feature = "futures01")]
+pub use sample::Sample;
+#[cfg(feature = "std")]
+pub use search::{contains, find_first};
+#[cfg(feature = "std")]
+pub use ser::{detokenize, SerializerSink, SerializerSinkError};
+#[cfg(feature = "signing")]
+pub use signing::{SigningSink, VerifyError, VerifyingStream, TAG_LEN};
+#[cfg(feature = "std")]
+pub use sink::ChannelSink;
+#[cfg(feature = "futures01")]
+pub use sink::{BatchSink, OverflowSink};
+#[cfg(feature = "futures01")]
+pub use split::split_document_stream;
+#[cfg(feature = "std")]
+pub use split::{split_documents, DocumentSplitter, SplitDocuments};
+#[cfg(feature = "futures01")]
+pub use stream::{spawn_tokenize, SpawnSink, TokenStream, Tokenization, TokenizationError};
+#[cfg(feature = "futures01")]
+pub use substitute::{Substitute, SubstituteError};
+#[cfg(feature = "futures01")]
+pub use tee::{broadcast, Broadcast};
+#[cfg(feature = "std")]
+pub use tee::{TeePolicy, TeeSink};
+#[cfg(feature = "std")]
+pub use token_serde::TokenSeed;
+pub use tokenize::{
+    to_tokens, tokenize, tokenize_adaptive, tokenize_and_close, tokenize_infallible, tokenize_into,
+    tokenize_iter, tokenize_many, tokenize_owned, tokenize_value, tokenize_with,
+    tokenize_with_options, CompoundTokenizer, ExtendSink, FloatPolicy, InfallibleTokenSink,
+    IntegerWidth, StringPolicy, TokenIter, TokenSink, TokenizeOptions, Tokenizer,
+};
+#[cfg(feature = "erased-serde")]
+pub use tokenize::{tokenize_dyn, DynTokenSink};
+#[cfg(feature = "futures01")]
+pub use tokenize::{tokenize_send, TokenizeSend};
+#[cfg(feature = "tokio")]
+pub use tokio_sink::tokenize_tokio;
+#[cfg(feature = "std")]
+pub use transform::{
+    prune, FilterTokens, Identity, MapTokens, Prune, RenameKeys, Then, TokenTransform,
+    TransformContext, TransformSink, TruncateSeqs,
+};
+#[cfg(feature = "std")]
+pub use write::TokenWrite;
+#[cfg(feature = "futures01")]
+pub use write::TokenWriter;
+
+/// The name and sole field of the struct (or key of the map) that `serde_json` represents arbitrary-precision numbers with.
+pub(crate) const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// The name of the tuple struct that an [`Extension`] is serialized as.
+///
+/// [`Extension`]: struct.Extension.html
+pub(crate) const EXTENSION_TOKEN: &str = "$serde_token::private::Extension";
 
 /// A token corresponding to one of the types defined in the [Serde data model].
 ///
 /// [Serde data model]: https://serde.rs/data-model.html
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Token<'a> {
     /// A serialized `bool`.
     Bool(bool),
@@ -88,18 +271,64 @@ pub enum Token<'a> {
     /// A serialized `char`.
     Char(char),
 
-    /// A borrowed `str`.
-    Str(&'a str),
+    /// A number in its original textual form, such as one of `serde_json`'s arbitrary-precision numbers, which may not fit in any primitive numeric type.
+    ///
+    /// Both `serde_json::Number`s serialized with the `arbitrary_precision` feature and numbers read by a `serde_json::Deserializer` with it are tokenized as `Number`s, and `Number`s are detokenized and deserialized back into the same representation, so their precision is preserved end to end.
+    Number(Cow<'a, str>),
+
+    /// A `str`, either borrowed from the input or, if the input only lent it transiently, copied.
+    ///
+    /// The three flavors of string in the Serde data model are kept distinct: data borrowed for the input's lifetime is a `Cow::Borrowed` `Str` (see [`borrowed_str`]), transient data is a `Cow::Owned` `Str`, and owned data is a `String`.
+    ///
+    /// [`borrowed_str`]: #method.borrowed_str
+    Str(Cow<'a, str>),
 
     /// An owned `String`.
     String(String),
 
-    /// A borrowed `[u8]`.
-    Bytes(&'a [u8]),
+    /// An owned string shared with other tokens, such as a map key interned by a [`Tokenizer`] configured with [`intern_keys`], which is cheap to clone and to convert with [`into_owned`].
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`intern_keys`]: struct.TokenizeOptions.html#method.intern_keys
+    /// [`into_owned`]: #method.into_owned
+    Interned(Arc<str>),
+
+    /// An owned string short enough to be stored inline in the token, rather than on the heap.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`inline_strings`].
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`inline_strings`]: struct.TokenizeOptions.html#method.inline_strings
+    Inline(InlineStr),
+
+    /// A piece of a string too long to be written as a single token, either borrowed from the input or copied.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`chunk_strings`], which writes each string longer than the chunk size as a run of `StrChunk`s followed by a `StrEnd`.
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`chunk_strings`]: struct.TokenizeOptions.html#method.chunk_strings
+    StrChunk(Cow<'a, str>),
+
+    /// An indicator of the end of a string written as `StrChunk`s.
+    StrEnd,
+
+    /// A `[u8]`, either borrowed from the input or, if the input only lent it transiently, copied.
+    Bytes(Cow<'a, [u8]>),
 
     /// A owned `Vec<u8>`.
     ByteBuf(Vec<u8>),
 
+    /// A piece of a byte array too long to be written as a single token, either borrowed from the input or copied.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`chunk_bytes`], which writes each byte array longer than the chunk size as a run of `BytesChunk`s followed by a `BytesEnd`.
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`chunk_bytes`]: struct.TokenizeOptions.html#method.chunk_bytes
+    BytesChunk(Cow<'a, [u8]>),
+
+    /// An indicator of the end of a byte array written as `BytesChunk`s.
+    BytesEnd,
+
     /// A serialized `Option<T>` containing none.
     None,
 
@@ -120,6 +349,9 @@ pub enum Token<'a> {
         #[doc(hidden)]
         name: &'static str,
 
+        #[doc(hidden)]
+        variant_index: u32,
+
         #[doc(hidden)]
         variant: &'static str,
     },
@@ -135,6 +367,9 @@ pub enum Token<'a> {
         #[doc(hidden)]
         name: &'static str,
 
+        #[doc(hidden)]
+        variant_index: u32,
+
         #[doc(hidden)]
         variant: &'static str,
     },
@@ -174,6 +409,9 @@ pub enum Token<'a> {
         #[doc(hidden)]
         name: &'static str,
 
+        #[doc(hidden)]
+        variant_index: u32,
+
         #[doc(hidden)]
         variant: &'static str,
 
@@ -193,6 +431,22 @@ pub enum Token<'a> {
     /// An indicator of the end of a map.
     MapEnd,
 
+    /// A marker written before each map key.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`map_markers`], so that consumers can tell keys from values without tracking the map's state themselves.
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`map_markers`]: struct.TokenizeOptions.html#method.map_markers
+    MapKey,
+
+    /// A marker written before each map value.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`map_markers`].
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`map_markers`]: struct.TokenizeOptions.html#method.map_markers
+    MapValue,
+
     /// The header of a struct.
     Struct {
         #[doc(hidden)]
@@ -202,6 +456,14 @@ pub enum Token<'a> {
         len: usize,
     },
 
+    /// The name of a struct field, written before its value.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`field_tokens`]; otherwise field names are written as `Str`s.
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`field_tokens`]: struct.TokenizeOptions.html#method.field_tokens
+    Field(&'static str),
+
     /// An indicator of the end of a struct.
     StructEnd,
 
@@ -210,6 +472,9 @@ pub enum Token<'a> {
         #[doc(hidden)]
         name: &'static str,
 
+        #[doc(hidden)]
+        variant_index: u32,
+
         #[doc(hidden)]
         variant: &'static str,
 
@@ -225,4 +490,736 @@ pub enum Token<'a> {
         #[doc(hidden)]
         name: &'static str,
     },
+
+    /// The header to a format-specific [`Extension`] value with the given tag (e.g. a CBOR tag or MessagePack ext type), which wraps the value that follows.
+    ///
+    /// [`Extension`]: struct.Extension.html
+    Extension {
+        #[doc(hidden)]
+        tag: u64,
+    },
+
+    /// A marker written before each top-level value, so that consumers of a stream of many values can tell where each begins.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`document_markers`].
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`document_markers`]: struct.TokenizeOptions.html#method.document_markers
+    DocumentStart,
+
+    /// A marker written after each top-level value, once it is complete.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`document_markers`].
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`document_markers`]: struct.TokenizeOptions.html#method.document_markers
+    DocumentEnd,
+
+    /// A marker written before a string or byte array that was truncated to the maximum payload length, carrying its original length in bytes.
+    ///
+    /// Only emitted by a [`Tokenizer`] configured with [`max_payload_len`] and [`truncate_long_payloads`], or (as the original number of elements, before the `SeqEnd` of a truncated sequence) by a [`TruncateSeqs`] transform with [`markers`].
+    ///
+    /// [`Tokenizer`]: struct.Tokenizer.html
+    /// [`TruncateSeqs`]: struct.TruncateSeqs.html
+    /// [`markers`]: struct.TruncateSeqs.html#method.markers
+    /// [`max_payload_len`]: struct.TokenizeOptions.html#method.max_payload_len
+    /// [`truncate_long_payloads`]: struct.TokenizeOptions.html#method.truncate_long_payloads
+    Truncated {
+        #[doc(hidden)]
+        len: usize,
+    },
+}
+
+/// A `Token` that owns all of its data, and so can outlive the deserializer it came from or be sent to another thread or task.
+pub type OwnedToken = Token<'static>;
+
+/// The kind of a [`Token`], without its payload, for matching on categories of tokens.
+///
+/// [`Token`]: enum.Token.html
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum TokenKind {
+    /// A `Token::Bool`.
+    Bool,
+
+    /// A `Token::I8`.
+    I8,
+
+    /// A `Token::I16`.
+    I16,
+
+    /// A `Token::I32`.
+    I32,
+
+    /// A `Token::I64`.
+    I64,
+
+    /// A `Token::I128`.
+    I128,
+
+    /// A `Token::U8`.
+    U8,
+
+    /// A `Token::U16`.
+    U16,
+
+    /// A `Token::U32`.
+    U32,
+
+    /// A `Token::U64`.
+    U64,
+
+    /// A `Token::U128`.
+    U128,
+
+    /// A `Token::F32`.
+    F32,
+
+    /// A `Token::F64`.
+    F64,
+
+    /// A `Token::Char`.
+    Char,
+
+    /// A `Token::Number`.
+    Number,
+
+    /// A `Token::Str`.
+    Str,
+
+    /// A `Token::String`.
+    String,
+
+    /// A `Token::Interned`.
+    Interned,
+
+    /// A `Token::Inline`.
+    Inline,
+
+    /// A `Token::StrChunk`.
+    StrChunk,
+
+    /// A `Token::StrEnd`.
+    StrEnd,
+
+    /// A `Token::Bytes`.
+    Bytes,
+
+    /// A `Token::ByteBuf`.
+    ByteBuf,
+
+    /// A `Token::BytesChunk`.
+    BytesChunk,
+
+    /// A `Token::BytesEnd`.
+    BytesEnd,
+
+    /// A `Token::None`.
+    None,
+
+    /// A `Token::Some`.
+    Some,
+
+    /// A `Token::Unit`.
+    Unit,
+
+    /// A `Token::UnitStruct`.
+    UnitStruct,
+
+    /// A `Token::UnitVariant`.
+    UnitVariant,
+
+    /// A `Token::NewtypeStruct`.
+    NewtypeStruct,
+
+    /// A `Token::NewtypeVariant`.
+    NewtypeVariant,
+
+    /// A `Token::Seq`.
+    Seq,
+
+    /// A `Token::SeqEnd`.
+    SeqEnd,
+
+    /// A `Token::Tuple`.
+    Tuple,
+
+    /// A `Token::TupleEnd`.
+    TupleEnd,
+
+    /// A `Token::TupleStruct`.
+    TupleStruct,
+
+    /// A `Token::TupleStructEnd`.
+    TupleStructEnd,
+
+    /// A `Token::TupleVariant`.
+    TupleVariant,
+
+    /// A `Token::TupleVariantEnd`.
+    TupleVariantEnd,
+
+    /// A `Token::Map`.
+    Map,
+
+    /// A `Token::MapEnd`.
+    MapEnd,
+
+    /// A `Token::MapKey`.
+    MapKey,
+
+    /// A `Token::MapValue`.
+    MapValue,
+
+    /// A `Token::Struct`.
+    Struct,
+
+    /// A `Token::Field`.
+    Field,
+
+    /// A `Token::StructEnd`.
+    StructEnd,
+
+    /// A `Token::StructVariant`.
+    StructVariant,
+
+    /// A `Token::StructVariantEnd`.
+    StructVariantEnd,
+
+    /// A `Token::Enum`.
+    Enum,
+
+    /// A `Token::Extension`.
+    Extension,
+
+    /// A `Token::DocumentStart`.
+    DocumentStart,
+
+    /// A `Token::DocumentEnd`.
+    DocumentEnd,
+
+    /// A `Token::Truncated`.
+    Truncated,
+}
+
+impl TokenKind {
+    /// Returns whether this kind of token opens a sequence, tuple, map or struct, which is closed by a matching end token.
+    pub fn is_container_start(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Seq
+                | TokenKind::Tuple
+                | TokenKind::TupleStruct
+                | TokenKind::TupleVariant
+                | TokenKind::Map
+                | TokenKind::Struct
+                | TokenKind::StructVariant
+        )
+    }
+
+    /// Returns whether this kind of token closes a sequence, tuple, map or struct.
+    pub fn is_container_end(self) -> bool {
+        matches!(
+            self,
+            TokenKind::SeqEnd
+                | TokenKind::TupleEnd
+                | TokenKind::TupleStructEnd
+                | TokenKind::TupleVariantEnd
+                | TokenKind::MapEnd
+                | TokenKind::StructEnd
+                | TokenKind::StructVariantEnd
+        )
+    }
+
+    /// Returns whether this kind of token is a complete value on its own, such as a number, string, `None` or unit variant.
+    ///
+    /// Prefixes (such as `Some` or a newtype header), container tokens, map markers and string or byte chunks are not scalars.
+    pub fn is_scalar(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Bool
+                | TokenKind::I8
+                | TokenKind::I16
+                | TokenKind::I32
+                | TokenKind::I64
+                | TokenKind::I128
+                | TokenKind::U8
+                | TokenKind::U16
+                | TokenKind::U32
+                | TokenKind::U64
+                | TokenKind::U128
+                | TokenKind::F32
+                | TokenKind::F64
+                | TokenKind::Char
+                | TokenKind::Number
+                | TokenKind::Str
+                | TokenKind::String
+                | TokenKind::Interned
+                | TokenKind::Inline
+                | TokenKind::Bytes
+                | TokenKind::ByteBuf
+                | TokenKind::None
+                | TokenKind::Unit
+                | TokenKind::UnitStruct
+                | TokenKind::UnitVariant
+                | TokenKind::Field
+        )
+    }
+}
+
+impl<'a> Token<'a> {
+    /// Returns the kind of this token.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{Token, TokenKind};
+    ///
+    /// assert_eq!(Token::Str("a".into()).kind(), TokenKind::Str);
+    /// assert_eq!(Token::Seq { len: None }.kind(), TokenKind::Seq);
+    /// ```
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::Bool(_) => TokenKind::Bool,
+            Token::I8(_) => TokenKind::I8,
+            Token::I16(_) => TokenKind::I16,
+            Token::I32(_) => TokenKind::I32,
+            Token::I64(_) => TokenKind::I64,
+            Token::I128(_) => TokenKind::I128,
+            Token::U8(_) => TokenKind::U8,
+            Token::U16(_) => TokenKind::U16,
+            Token::U32(_) => TokenKind::U32,
+            Token::U64(_) => TokenKind::U64,
+            Token::U128(_) => TokenKind::U128,
+            Token::F32(_) => TokenKind::F32,
+            Token::F64(_) => TokenKind::F64,
+            Token::Char(_) => TokenKind::Char,
+            Token::Number(_) => TokenKind::Number,
+            Token::Str(_) => TokenKind::Str,
+            Token::String(_) => TokenKind::String,
+            Token::Interned(_) => TokenKind::Interned,
+            Token::Inline(_) => TokenKind::Inline,
+            Token::StrChunk(_) => TokenKind::StrChunk,
+            Token::StrEnd => TokenKind::StrEnd,
+            Token::Bytes(_) => TokenKind::Bytes,
+            Token::ByteBuf(_) => TokenKind::ByteBuf,
+            Token::BytesChunk(_) => TokenKind::BytesChunk,
+            Token::BytesEnd => TokenKind::BytesEnd,
+            Token::None => TokenKind::None,
+            Token::Some => TokenKind::Some,
+            Token::Unit => TokenKind::Unit,
+            Token::UnitStruct { .. } => TokenKind::UnitStruct,
+            Token::UnitVariant { .. } => TokenKind::UnitVariant,
+            Token::NewtypeStruct { .. } => TokenKind::NewtypeStruct,
+            Token::NewtypeVariant { .. } => TokenKind::NewtypeVariant,
+            Token::Seq { .. } => TokenKind::Seq,
+            Token::SeqEnd => TokenKind::SeqEnd,
+            Token::Tuple { .. } => TokenKind::Tuple,
+            Token::TupleEnd => TokenKind::TupleEnd,
+            Token::TupleStruct { .. } => TokenKind::TupleStruct,
+            Token::TupleStructEnd => TokenKind::TupleStructEnd,
+            Token::TupleVariant { .. } => TokenKind::TupleVariant,
+            Token::TupleVariantEnd => TokenKind::TupleVariantEnd,
+            Token::Map { .. } => TokenKind::Map,
+            Token::MapEnd => TokenKind::MapEnd,
+            Token::MapKey => TokenKind::MapKey,
+            Token::MapValue => TokenKind::MapValue,
+            Token::Struct { .. } => TokenKind::Struct,
+            Token::Field(_) => TokenKind::Field,
+            Token::StructEnd => TokenKind::StructEnd,
+            Token::StructVariant { .. } => TokenKind::StructVariant,
+            Token::StructVariantEnd => TokenKind::StructVariantEnd,
+            Token::Enum { .. } => TokenKind::Enum,
+            Token::Extension { .. } => TokenKind::Extension,
+            Token::DocumentStart => TokenKind::DocumentStart,
+            Token::DocumentEnd => TokenKind::DocumentEnd,
+            Token::Truncated { .. } => TokenKind::Truncated,
+        }
+    }
+
+    /// Returns whether this token opens a sequence, tuple, map or struct.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::Token;
+    ///
+    /// assert!(Token::Map { len: None }.is_container_start());
+    /// assert!(Token::MapEnd.is_container_end());
+    /// assert!(Token::U8(1).is_scalar());
+    /// assert!(!Token::Some.is_scalar());
+    /// ```
+    pub fn is_container_start(&self) -> bool {
+        self.kind().is_container_start()
+    }
+
+    /// Returns whether this token closes a sequence, tuple, map or struct.
+    pub fn is_container_end(&self) -> bool {
+        self.kind().is_container_end()
+    }
+
+    /// Returns whether this token is a complete value on its own.
+    ///
+    /// See [`TokenKind::is_scalar`] for details.
+    ///
+    /// [`TokenKind::is_scalar`]: enum.TokenKind.html#method.is_scalar
+    pub fn is_scalar(&self) -> bool {
+        self.kind().is_scalar()
+    }
+
+    /// Converts any borrowed `Str` or `Bytes` payload into an owned `String` or `ByteBuf`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{OwnedToken, Token};
+    ///
+    /// let owned: OwnedToken = Token::Str("a".into()).into_owned();
+    /// assert_eq!(owned, Token::String("a".to_string()));
+    /// ```
+    pub fn into_owned(self) -> OwnedToken {
+        match self {
+            Token::Bool(v) => Token::Bool(v),
+            Token::I8(v) => Token::I8(v),
+            Token::I16(v) => Token::I16(v),
+            Token::I32(v) => Token::I32(v),
+            Token::I64(v) => Token::I64(v),
+            Token::I128(v) => Token::I128(v),
+            Token::U8(v) => Token::U8(v),
+            Token::U16(v) => Token::U16(v),
+            Token::U32(v) => Token::U32(v),
+            Token::U64(v) => Token::U64(v),
+            Token::U128(v) => Token::U128(v),
+            Token::F32(v) => Token::F32(v),
+            Token::F64(v) => Token::F64(v),
+            Token::Char(v) => Token::Char(v),
+            Token::Number(v) => Token::Number(Cow::Owned(v.into_owned())),
+            Token::Str(v) => Token::String(v.into_owned()),
+            Token::String(v) => Token::String(v),
+            Token::Interned(v) => Token::Interned(v),
+            Token::Inline(v) => Token::Inline(v),
+            Token::StrChunk(v) => Token::StrChunk(Cow::Owned(v.into_owned())),
+            Token::StrEnd => Token::StrEnd,
+            Token::Bytes(v) => Token::ByteBuf(v.into_owned()),
+            Token::ByteBuf(v) => Token::ByteBuf(v),
+            Token::BytesChunk(v) => Token::BytesChunk(Cow::Owned(v.into_owned())),
+            Token::BytesEnd => Token::BytesEnd,
+            Token::None => Token::None,
+            Token::Some => Token::Some,
+            Token::Unit => Token::Unit,
+            Token::UnitStruct { name } => Token::UnitStruct { name },
+            Token::UnitVariant {
+                name,
+                variant_index,
+                variant,
+            } => Token::UnitVariant {
+                name,
+                variant_index,
+                variant,
+            },
+            Token::NewtypeStruct { name } => Token::NewtypeStruct { name },
+            Token::NewtypeVariant {
+                name,
+                variant_index,
+                variant,
+            } => Token::NewtypeVariant {
+                name,
+                variant_index,
+                variant,
+            },
+            Token::Seq { len } => Token::Seq { len },
+            Token::SeqEnd => Token::SeqEnd,
+            Token::Tuple { len } => Token::Tuple { len },
+            Token::TupleEnd => Token::TupleEnd,
+            Token::TupleStruct { name, len } => Token::TupleStruct { name, len },
+            Token::TupleStructEnd => Token::TupleStructEnd,
+            Token::TupleVariant {
+                name,
+                variant_index,
+                variant,
+                len,
+            } => Token::TupleVariant {
+                name,
+                variant_index,
+                variant,
+                len,
+            },
+            Token::TupleVariantEnd => Token::TupleVariantEnd,
+            Token::Map { len } => Token::Map { len },
+            Token::MapEnd => Token::MapEnd,
+            Token::MapKey => Token::MapKey,
+            Token::MapValue => Token::MapValue,
+            Token::Struct { name, len } => Token::Struct { name, len },
+            Token::Field(v) => Token::Field(v),
+            Token::StructEnd => Token::StructEnd,
+            Token::StructVariant {
+                name,
+                variant_index,
+                variant,
+                len,
+            } => Token::StructVariant {
+                name,
+                variant_index,
+                variant,
+                len,
+            },
+            Token::StructVariantEnd => Token::StructVariantEnd,
+            Token::Enum { name } => Token::Enum { name },
+            Token::Extension { tag } => Token::Extension { tag },
+            Token::DocumentStart => Token::DocumentStart,
+            Token::DocumentEnd => Token::DocumentEnd,
+            Token::Truncated { len } => Token::Truncated { len },
+        }
+    }
+
+    /// Copies the token into an [`OwnedToken`], copying any borrowed `Str` or `Bytes` payload into an owned `String` or `ByteBuf`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{OwnedToken, Token};
+    ///
+    /// let token = Token::Str("a".into());
+    /// let owned: OwnedToken = token.to_static();
+    /// assert_eq!(owned, Token::String("a".to_string()));
+    /// ```
+    ///
+    /// [`OwnedToken`]: type.OwnedToken.html
+    pub fn to_static(&self) -> OwnedToken {
+        match *self {
+            Token::Str(ref v) => Token::String(v.to_string()),
+            Token::Bytes(ref v) => Token::ByteBuf(v.to_vec()),
+            ref token => token.clone().into_owned(),
+        }
+    }
+
+    /// Returns the token's string if it is borrowed from the input for `'a`, and so can be retained without copying for as long as the input.
+    ///
+    /// Returns `None` for strings the input only lent transiently (which were copied) or gave away as a `String`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{to_tokens, Token};
+    ///
+    /// let json = r#" ["plain", "esc\\aped"] "#;
+    /// let tokens = to_tokens(&mut serde_json::Deserializer::from_str(json)).unwrap();
+    ///
+    /// assert_eq!(tokens[1].borrowed_str(), Some("plain"));
+    /// assert_eq!(tokens[2].borrowed_str(), None);
+    /// assert_eq!(tokens[2], Token::Str("esc\\aped".into()));
+    /// ```
+    pub fn borrowed_str(&self) -> Option<&'a str> {
+        match *self {
+            Token::Str(Cow::Borrowed(v)) => Some(v),
+            Token::Field(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's bytes if they are borrowed from the input for `'a`, and so can be retained without copying for as long as the input.
+    pub fn borrowed_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Token::Bytes(Cow::Borrowed(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's string, if it is a `Str`, `String`, `Interned`, `Inline` or `Field`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::Token;
+    ///
+    /// assert_eq!(Token::Str("a".into()).as_str(), Some("a"));
+    /// assert_eq!(Token::String("b".to_string()).as_str(), Some("b"));
+    /// assert_eq!(Token::U8(1).as_str(), None);
+    /// ```
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Token::Str(v) => Some(v),
+            Token::String(v) => Some(v),
+            Token::Interned(v) => Some(v),
+            Token::Inline(v) => Some(v),
+            Token::Field(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's bytes, if it is a `Bytes` or `ByteBuf`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Token::Bytes(v) => Some(v),
+            Token::ByteBuf(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's value as a `u64`, if it is an integer (or arbitrary-precision `Number`) that fits in one.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::Token;
+    ///
+    /// assert_eq!(Token::U8(1).as_u64(), Some(1));
+    /// assert_eq!(Token::I32(2).as_u64(), Some(2));
+    /// assert_eq!(Token::I32(-2).as_u64(), None);
+    /// assert_eq!(Token::F64(1.0).as_u64(), None);
+    /// ```
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Token::U8(v) => Some(v.into()),
+            Token::U16(v) => Some(v.into()),
+            Token::U32(v) => Some(v.into()),
+            Token::U64(v) => Some(v),
+            Token::U128(v) => u64::try_from(v).ok(),
+            Token::I8(v) => u64::try_from(v).ok(),
+            Token::I16(v) => u64::try_from(v).ok(),
+            Token::I32(v) => u64::try_from(v).ok(),
+            Token::I64(v) => u64::try_from(v).ok(),
+            Token::I128(v) => u64::try_from(v).ok(),
+            Token::Number(ref v) => v.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's value as an `i64`, if it is an integer (or arbitrary-precision `Number`) that fits in one.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::Token;
+    ///
+    /// assert_eq!(Token::I8(-1).as_i64(), Some(-1));
+    /// assert_eq!(Token::U64(u64::MAX).as_i64(), None);
+    /// ```
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Token::I8(v) => Some(v.into()),
+            Token::I16(v) => Some(v.into()),
+            Token::I32(v) => Some(v.into()),
+            Token::I64(v) => Some(v),
+            Token::I128(v) => i64::try_from(v).ok(),
+            Token::U8(v) => Some(v.into()),
+            Token::U16(v) => Some(v.into()),
+            Token::U32(v) => Some(v.into()),
+            Token::U64(v) => i64::try_from(v).ok(),
+            Token::U128(v) => i64::try_from(v).ok(),
+            Token::Number(ref v) => v.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's value as an `f64`, if it is a number of any kind.
+    ///
+    /// Integers too large to be represented exactly are rounded, as with an `as` cast.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::Token;
+    ///
+    /// assert_eq!(Token::F32(1.5).as_f64(), Some(1.5));
+    /// assert_eq!(Token::U8(2).as_f64(), Some(2.0));
+    /// assert_eq!(Token::Number("0.25".into()).as_f64(), Some(0.25));
+    /// ```
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Token::F32(v) => Some(v.into()),
+            Token::F64(v) => Some(v),
+            Token::I8(v) => Some(v.into()),
+            Token::I16(v) => Some(v.into()),
+            Token::I32(v) => Some(v.into()),
+            Token::I64(v) => Some(v as f64),
+            Token::I128(v) => Some(v as f64),
+            Token::U8(v) => Some(v.into()),
+            Token::U16(v) => Some(v.into()),
+            Token::U32(v) => Some(v.into()),
+            Token::U64(v) => Some(v as f64),
+            Token::U128(v) => Some(v as f64),
+            Token::Number(ref v) => v.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the token's value, if it is a `Bool`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Token::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Creates a `Seq` header with the given length hint.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::Token;
+    ///
+    /// let tokens: Vec<Token> = vec![Token::seq(Some(2)), 1u8.into(), "a".into(), Token::SeqEnd];
+    /// assert_eq!(tokens, vec![
+    ///     Token::Seq { len: Some(2) },
+    ///     Token::U8(1),
+    ///     Token::Str("a".into()),
+    ///     Token::SeqEnd,
+    /// ]);
+    /// ```
+    pub fn seq(len: Option<usize>) -> Self {
+        Token::Seq { len }
+    }
+
+    /// Creates a `Tuple` header of the given length.
+    pub fn tuple(len: usize) -> Self {
+        Token::Tuple { len }
+    }
+
+    /// Creates a `Map` header with the given length hint.
+    pub fn map(len: Option<usize>) -> Self {
+        Token::Map { len }
+    }
+}
+
+macro_rules! impl_from {
+    ($($ty:ty => $variant:ident,)*) => {
+        $(
+            impl<'a> From<$ty> for Token<'a> {
+                fn from(v: $ty) -> Self {
+                    Token::$variant(v)
+                }
+            }
+        )*
+    };
+}
+
+impl_from! {
+    bool => Bool,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    i128 => I128,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    u128 => U128,
+    f32 => F32,
+    f64 => F64,
+    char => Char,
+    String => String,
+    Vec<u8> => ByteBuf,
+}
+
+impl<'a> From<&'a str> for Token<'a> {
+    fn from(v: &'a str) -> Self {
+        Token::Str(Cow::Borrowed(v))
+    }
+}
+
+impl<'a> From<&'a [u8]> for Token<'a> {
+    fn from(v: &'a [u8]) -> Self {
+        Token::Bytes(Cow::Borrowed(v))
+    }
 }
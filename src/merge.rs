@@ -0,0 +1,53 @@
+use crate::{tree::Node, Error, Token};
+
+/// How [`merge`] combines two sequences (or tuples) found at the same path.
+///
+/// [`merge`]: fn.merge.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ArrayMerge {
+    /// The right sequence replaces the left one.
+    #[default]
+    Replace,
+    /// The right sequence's elements are appended to the left one's.
+    Concat,
+    /// Elements at the same index are merged, and any extra elements of the longer sequence are kept.
+    ByIndex,
+}
+
+/// Deeply merges two token streams of a single value each, with `right` taking precedence over `left`.
+///
+/// Maps and structs are merged recursively: entries whose keys render the same (so `"1"` and `1` are the same key, whatever format each stream came from) have their values merged, the left entries keep their order, and entries only in `right` are appended. Sequences are combined according to `arrays`, and any other value in `right` (including `None` or `Unit`) replaces the value in `left`. Where both sides have a value, the result takes its shape (such as a struct's name, or a `Some` prefix) from `right`.
+///
+/// Either stream may be empty, in which case the other is returned as is, but an error is returned if either is malformed or contains more than one value (or is nested more than 128 levels deep). Map markers are dropped, and strings split into chunks are joined.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{merge, to_tokens, ArrayMerge, Token};
+///
+/// let base = to_tokens(&mut serde_json::Deserializer::from_str(
+///     r#" {"port": 80, "tls": {"enabled": false, "ciphers": ["a"]}} "#,
+/// )).unwrap();
+/// let overrides = to_tokens(&mut serde_json::Deserializer::from_str(
+///     r#" {"tls": {"enabled": true, "ciphers": ["b"]}} "#,
+/// )).unwrap();
+///
+/// let merged = merge(base, overrides, ArrayMerge::Concat).unwrap();
+/// let value: serde_json::Value = serde_token::from_tokens(&merged).unwrap();
+/// assert_eq!(value, serde_json::json!({
+///     "port": 80,
+///     "tls": {"enabled": true, "ciphers": ["a", "b"]},
+/// }));
+/// ```
+pub fn merge<'a, L, R>(left: L, right: R, arrays: ArrayMerge) -> Result<Vec<Token<'a>>, Error>
+where
+    L: IntoIterator<Item = Token<'a>>,
+    R: IntoIterator<Item = Token<'a>>,
+{
+    let merged = match (Node::parse_single(left)?, Node::parse_single(right)?) {
+        (Some(left), Some(right)) => left.merge(right, arrays),
+        (Some(node), None) | (None, Some(node)) => node,
+        (None, None) => return Ok(Vec::new()),
+    };
+    Ok(merged.into_tokens())
+}
@@ -0,0 +1,290 @@
+#[cfg(feature = "futures01")]
+use crate::{sink::Forward, DocumentSplitter};
+use crate::{
+    tree::{malformed, Node},
+    Error, Path, Token,
+};
+#[cfg(feature = "futures01")]
+use futures::{sink::Sink, AsyncSink, Poll, StartSend};
+#[cfg(feature = "futures01")]
+use std::fmt;
+
+type SplitFn<'a> = Box<dyn Fn(Vec<Token<'a>>) -> Vec<Vec<Token<'a>>>>;
+type MergeFn<'a> = Box<dyn Fn(Vec<Vec<Token<'a>>>) -> Vec<Token<'a>>>;
+
+enum Rule<'a> {
+    Rename {
+        path: Path,
+        to: String,
+    },
+    Move {
+        from: Path,
+        to: Path,
+    },
+    Default {
+        path: Path,
+        value: Vec<Token<'a>>,
+    },
+    Split {
+        from: Path,
+        into: Vec<Path>,
+        split: SplitFn<'a>,
+    },
+    Merge {
+        from: Vec<Path>,
+        into: Path,
+        merge: MergeFn<'a>,
+    },
+}
+
+/// A declarative list of rules for upgrading documents to a new schema, applied to token streams without any intermediate typed structs.
+///
+/// Rules are applied in the order they were added, each to the entire document. Rules whose source paths are missing from a document are skipped.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{Migration, Token};
+///
+/// let migration = Migration::new()
+///     .rename("name".parse().unwrap(), "full_name")
+///     .default("version".parse().unwrap(), vec![Token::U64(2)]);
+///
+/// let tokens = vec![
+///     Token::Map { len: None },
+///     Token::Str("name".into()),
+///     Token::Str("John Doe".into()),
+///     Token::MapEnd,
+/// ];
+///
+/// assert_eq!(migration.apply(tokens).unwrap(), vec![
+///     Token::Map { len: None },
+///     Token::String("full_name".to_string()),
+///     Token::Str("John Doe".into()),
+///     Token::String("version".to_string()),
+///     Token::U64(2),
+///     Token::MapEnd,
+/// ]);
+/// ```
+#[derive(Default)]
+pub struct Migration<'a> {
+    rules: Vec<Rule<'a>>,
+}
+
+impl<'a> Migration<'a> {
+    /// Creates a migration with no rules.
+    pub fn new() -> Self {
+        Migration { rules: Vec::new() }
+    }
+
+    /// Renames the map key or struct field at the given path.
+    pub fn rename<K: Into<String>>(mut self, path: Path, to: K) -> Self {
+        self.rules.push(Rule::Rename {
+            path,
+            to: to.into(),
+        });
+        self
+    }
+
+    /// Moves the value at one path to another, creating any missing intermediate maps or sequences.
+    pub fn move_path(mut self, from: Path, to: Path) -> Self {
+        self.rules.push(Rule::Move { from, to });
+        self
+    }
+
+    /// Inserts the given value at the given path if no value is present.
+    pub fn default(mut self, path: Path, value: Vec<Token<'a>>) -> Self {
+        self.rules.push(Rule::Default { path, value });
+        self
+    }
+
+    /// Replaces the value at one path with the values returned by `split`, placed at each of the `into` paths in order.
+    pub fn split<F>(mut self, from: Path, into: Vec<Path>, split: F) -> Self
+    where
+        F: Fn(Vec<Token<'a>>) -> Vec<Vec<Token<'a>>> + 'static,
+    {
+        self.rules.push(Rule::Split {
+            from,
+            into,
+            split: Box::new(split),
+        });
+        self
+    }
+
+    /// Replaces the values at each of the `from` paths with the single value returned by `merge`, placed at the `into` path.
+    ///
+    /// The rule is skipped unless every `from` path is present.
+    pub fn merge<F>(mut self, from: Vec<Path>, into: Path, merge: F) -> Self
+    where
+        F: Fn(Vec<Vec<Token<'a>>>) -> Vec<Token<'a>> + 'static,
+    {
+        self.rules.push(Rule::Merge {
+            from,
+            into,
+            merge: Box::new(merge),
+        });
+        self
+    }
+
+    /// Applies the migration to each document in the given tokens.
+    pub fn apply<I>(&self, tokens: I) -> Result<Vec<Token<'a>>, Error>
+    where
+        I: IntoIterator<Item = Token<'a>>,
+    {
+        let mut migrated = Vec::new();
+        for mut document in Node::parse_all(tokens)? {
+            for rule in &self.rules {
+                rule.apply(&mut document)?;
+            }
+            document.emit(&mut migrated);
+        }
+        Ok(migrated)
+    }
+}
+
+impl<'a> Rule<'a> {
+    fn apply(&self, document: &mut Node<'a>) -> Result<(), Error> {
+        match self {
+            Rule::Rename { path, to } => {
+                if let Some(entry) = document.entry_mut(path) {
+                    entry.0 = Node::Scalar(Token::String(to.clone()));
+                }
+            }
+            Rule::Move { from, to } => {
+                if let Some(value) = document.remove(from) {
+                    document.insert(to, value)?;
+                }
+            }
+            Rule::Default { path, value } => {
+                if document.get(path.segments()).is_none() {
+                    document.insert(path, parse_one(value.clone())?)?;
+                }
+            }
+            Rule::Split { from, into, split } => {
+                if let Some(value) = document.remove(from) {
+                    let values = split(value.into_tokens());
+                    for (path, value) in into.iter().zip(values) {
+                        document.insert(path, parse_one(value)?)?;
+                    }
+                }
+            }
+            Rule::Merge { from, into, merge } => {
+                if from
+                    .iter()
+                    .all(|path| document.get(path.segments()).is_some())
+                {
+                    let values = from
+                        .iter()
+                        .filter_map(|path| document.remove(path))
+                        .map(Node::into_tokens)
+                        .collect();
+                    document.insert(into, parse_one(merge(values))?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_one(tokens: Vec<Token>) -> Result<Node, Error> {
+    let mut tokens = tokens.into_iter();
+    let node = Node::parse(&mut tokens)?.ok_or_else(|| malformed("empty value"))?;
+    match tokens.next() {
+        Some(_) => Err(malformed("expected a single value")),
+        None => Ok(node),
+    }
+}
+
+/// A [`futures::Sink`] adapter that buffers each top-level document, applies a [`Migration`] to it, and forwards the migrated tokens to the wrapped sink.
+///
+/// Documents are delimited as by a [`DocumentSplitter`], and any `DocumentStart` and `DocumentEnd` markers are forwarded around the migrated tokens of their document.
+///
+/// *Requires the `futures01` feature.*
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`Migration`]: struct.Migration.html
+/// [`DocumentSplitter`]: struct.DocumentSplitter.html
+#[cfg(feature = "futures01")]
+pub struct Migrate<'a, S: Sink<SinkItem = Token<'a>>> {
+    migration: Migration<'a>,
+    splitter: DocumentSplitter<'a>,
+    forward: Forward<S>,
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S: Sink<SinkItem = Token<'a>>> Migrate<'a, S> {
+    /// Wraps a sink, migrating each document with the given migration.
+    pub fn new(sink: S, migration: Migration<'a>) -> Self {
+        Migrate {
+            migration,
+            splitter: DocumentSplitter::new(),
+            forward: Forward::new(sink),
+        }
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S: Sink<SinkItem = Token<'a>>> Sink for Migrate<'a, S> {
+    type SinkItem = Token<'a>;
+    type SinkError = MigrateError<S::SinkError>;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Self::SinkError> {
+        let end = token == Token::DocumentEnd;
+        if token == Token::DocumentStart {
+            self.forward.extend(Some(Token::DocumentStart));
+        }
+        if let Some(document) = self.splitter.push(token) {
+            let migrated = self
+                .migration
+                .apply(document)
+                .map_err(MigrateError::Migration)?;
+            self.forward.extend(migrated);
+        }
+        if end {
+            self.forward.extend(Some(Token::DocumentEnd));
+        }
+        self.forward.flush().map_err(MigrateError::Sink)?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.forward.poll_complete().map_err(MigrateError::Sink)
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.forward.close().map_err(MigrateError::Sink)
+    }
+}
+
+/// An error returned by a [`Migrate`] sink.
+///
+/// [`Migrate`]: struct.Migrate.html
+#[cfg(feature = "futures01")]
+#[derive(Debug)]
+pub enum MigrateError<E> {
+    /// A document could not be migrated.
+    Migration(Error),
+
+    /// The wrapped sink failed.
+    Sink(E),
+}
+
+#[cfg(feature = "futures01")]
+impl<E: fmt::Debug> fmt::Display for MigrateError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrateError::Migration(err) => write!(f, "migration error: {}", err),
+            MigrateError::Sink(err) => write!(f, "sink error: {:?}", err),
+        }
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<E: fmt::Debug> std::error::Error for MigrateError<E> {}
+
+#[cfg(feature = "futures01")]
+impl<E: fmt::Debug + Send + Sync + 'static> From<MigrateError<E>> for Error {
+    fn from(err: MigrateError<E>) -> Self {
+        Error::sink(err)
+    }
+}
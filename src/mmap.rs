@@ -0,0 +1,60 @@
+use memmap2::Mmap;
+use std::{fs::File, io, ops::Deref, path::Path};
+
+/// A read-only memory map of a file, from which deserializers can borrow for zero-copy tokenization.
+///
+/// `Str` and `Bytes` tokens borrowed from the map (e.g. by a `serde_json::Deserializer::from_slice`) are guaranteed by their lifetime to remain valid for as long as the map, and so can be consumed within its scope without copying.
+///
+/// *Requires the `mmap` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize_infallible, MappedFile, Token};
+///
+/// let path = std::env::temp_dir().join("serde_token_mmap_example.json");
+/// std::fs::write(&path, r#" ["hello"] "#).unwrap();
+///
+/// let map = unsafe { MappedFile::open(&path) }.unwrap();
+/// let mut de = serde_json::Deserializer::from_slice(&map);
+/// let mut tokens = Vec::new();
+/// tokenize_infallible(&mut de, &mut tokens).unwrap();
+///
+/// assert_eq!(tokens[1], Token::Str("hello".into()));
+/// ```
+#[derive(Debug)]
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// Memory-maps the file at the given path.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified (by this or any other process) while it is mapped, otherwise any tokens borrowed from it may change or become invalid.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let map = Mmap::map(&file)?;
+        Ok(MappedFile { map })
+    }
+
+    /// The contents of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        &self.map
+    }
+}
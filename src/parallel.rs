@@ -0,0 +1,221 @@
+use crate::{Error, OwnedToken};
+use std::{
+    collections::BTreeMap,
+    panic,
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Tokenizes independent documents (such as the lines of an NDJSON file, or a list of files) across a pool of `threads` worker threads, returning a [`ParallelTokens`] iterator of each document's index and owned `Token`s, in the order of the inputs.
+///
+/// Each worker takes the next input, calls `tokenize` with it to produce its tokens (such as by collecting [`tokenize_iter`] into owned tokens), and hands the result back to be reordered. A document that fails to tokenize is yielded as its error, without stopping the rest. Inputs are pulled from the iterator as workers become free, so it may be lazy (such as the lines of a file being read), but no more than `2 * threads` documents are taken ahead of the last one yielded, so a slow document stalls the workers rather than letting finished documents pile up behind it.
+///
+/// *Requires the `std` feature.*
+///
+/// # Panics
+///
+/// Panics if `threads` is zero, and resumes the panic of any worker whose `tokenize` panicked.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize_iter, tokenize_parallel, Token};
+///
+/// let lines = vec![r#"["a"]"#.to_string(), "true".to_string(), "nul".to_string()];
+/// let mut docs = tokenize_parallel(lines, 2, |line: String| {
+///     let mut de = serde_json::Deserializer::from_str(&line);
+///     tokenize_iter(&mut de).map(|token| token.map(Token::into_owned)).collect()
+/// });
+///
+/// let (index, tokens) = docs.next().unwrap();
+/// assert_eq!(index, 0);
+/// assert_eq!(tokens.unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::String("a".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// assert_eq!(docs.next().unwrap().1.unwrap(), vec![Token::Bool(true)]);
+/// assert!(docs.next().unwrap().1.is_err());
+/// assert!(docs.next().is_none());
+/// ```
+///
+/// [`ParallelTokens`]: struct.ParallelTokens.html
+/// [`tokenize_iter`]: fn.tokenize_iter.html
+pub fn tokenize_parallel<I, F>(inputs: I, threads: usize, tokenize: F) -> ParallelTokens
+where
+    I: IntoIterator,
+    I::IntoIter: Send + 'static,
+    F: Fn(I::Item) -> Result<Vec<OwnedToken>, Error> + Send + Sync + 'static,
+{
+    assert!(threads > 0, "thread count must be non-zero");
+    let inputs = Arc::new(Mutex::new((0, inputs.into_iter())));
+    let window = Arc::new(Window {
+        limit: threads * WINDOW_PER_THREAD,
+        progress: Mutex::new(Progress::default()),
+        room: Condvar::new(),
+    });
+    let tokenize = Arc::new(tokenize);
+    let (tx, rx) = sync_channel(threads);
+    let workers = (0..threads)
+        .map(|_| {
+            let (inputs, window, tokenize, tx) =
+                (inputs.clone(), window.clone(), tokenize.clone(), tx.clone());
+            thread::spawn(move || work(&inputs, &window, &*tokenize, &tx))
+        })
+        .collect();
+    ParallelTokens {
+        results: rx,
+        pending: BTreeMap::new(),
+        next: 0,
+        window,
+        workers,
+    }
+}
+
+/// The number of documents per worker that may be taken ahead of the last one yielded.
+const WINDOW_PER_THREAD: usize = 2;
+
+/// Bounds the documents in flight (being tokenized, or finished and waiting to be yielded) to those within `limit` of the next one to be yielded.
+#[derive(Debug)]
+struct Window {
+    limit: usize,
+    progress: Mutex<Progress>,
+    room: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    // The index of the next document to be yielded.
+    yielded: usize,
+    // Whether the workers should stop, as the iterator has been dropped or a worker has panicked.
+    closed: bool,
+}
+
+impl Window {
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        self.progress.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Waits until the document with the given index is within the window, returning `false` if the workers should stop instead.
+    fn wait(&self, index: usize) -> bool {
+        let mut progress = self.progress();
+        while !progress.closed && index >= progress.yielded + self.limit {
+            progress = self
+                .room
+                .wait(progress)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        !progress.closed
+    }
+
+    fn advance(&self, yielded: usize) {
+        self.progress().yielded = yielded;
+        self.room.notify_all();
+    }
+
+    fn close(&self) {
+        self.progress().closed = true;
+        self.room.notify_all();
+    }
+}
+
+/// Closes the window if a worker panics, so the others stop rather than waiting for a document that will never be yielded.
+struct PanicGuard<'a>(&'a Window);
+
+impl<'a> Drop for PanicGuard<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.close();
+        }
+    }
+}
+
+/// The result of tokenizing a single document.
+type Document = (usize, Result<Vec<OwnedToken>, Error>);
+
+fn work<I, F>(
+    inputs: &Mutex<(usize, I)>,
+    window: &Window,
+    tokenize: &F,
+    results: &SyncSender<Document>,
+) where
+    I: Iterator,
+    F: Fn(I::Item) -> Result<Vec<OwnedToken>, Error>,
+{
+    let _guard = PanicGuard(window);
+    loop {
+        // the lock is released before tokenizing, so only pulling the next input is serialized
+        let next = {
+            let mut inputs = inputs.lock().unwrap_or_else(|err| err.into_inner());
+            let index = inputs.0;
+            if !window.wait(index) {
+                return;
+            }
+            inputs.1.next().map(|input| {
+                inputs.0 += 1;
+                (index, input)
+            })
+        };
+        let (index, input) = match next {
+            Some(next) => next,
+            None => return,
+        };
+        if results.send((index, tokenize(input))).is_err() {
+            // the `ParallelTokens` has been dropped
+            return;
+        }
+    }
+}
+
+/// An iterator of the index and owned `Token`s (or error) of each document given to [`tokenize_parallel`], in the order of the inputs.
+///
+/// Documents finished ahead of an earlier, slower one are held until it is yielded, up to `2 * threads` documents in all. Dropping the iterator stops the workers once they finish their current documents.
+///
+/// *Requires the `std` feature.*
+///
+/// [`tokenize_parallel`]: fn.tokenize_parallel.html
+#[derive(Debug)]
+pub struct ParallelTokens {
+    results: Receiver<Document>,
+    pending: BTreeMap<usize, Result<Vec<OwnedToken>, Error>>,
+    next: usize,
+    window: Arc<Window>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Iterator for ParallelTokens {
+    type Item = (usize, Result<Vec<OwnedToken>, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                self.next += 1;
+                self.window.advance(self.next);
+                return Some((self.next - 1, result));
+            }
+            match self.results.recv() {
+                Ok((index, result)) => {
+                    self.pending.insert(index, result);
+                }
+                Err(_) => {
+                    // every worker has finished, and only a panicked one can have left a gap
+                    for worker in self.workers.drain(..) {
+                        if let Err(payload) = worker.join() {
+                            panic::resume_unwind(payload);
+                        }
+                    }
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ParallelTokens {
+    fn drop(&mut self) {
+        self.window.close();
+    }
+}
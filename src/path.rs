@@ -0,0 +1,393 @@
+use crate::Token;
+use std::{fmt, mem, str::FromStr};
+
+/// A single step into a container: a map key (or struct field name) or a sequence index.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Segment {
+    /// A map key or struct field name.
+    Key(String),
+
+    /// The position of an element within a sequence, tuple or tuple struct/variant.
+    Index(usize),
+}
+
+/// The location of a value within a token stream, rendered as a dotted path (e.g. `a.b[2].c`).
+///
+/// The empty path refers to the top-level value.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path(Vec<Segment>);
+
+impl Path {
+    /// Creates an empty path, referring to the top-level value.
+    pub fn new() -> Self {
+        Path(Vec::new())
+    }
+
+    /// The segments of this path, outermost first.
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+
+    /// The number of segments in this path.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether or not this path refers to the top-level value.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Appends a segment to the end of this path.
+    pub fn push(&mut self, segment: Segment) {
+        self.0.push(segment)
+    }
+
+    /// Removes and returns the last segment of this path.
+    pub fn pop(&mut self) -> Option<Segment> {
+        self.0.pop()
+    }
+
+    /// Whether or not `prefix` is equal to, or an ancestor of, this path.
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// Returns a new path with the given key appended.
+    pub fn key<K: Into<String>>(mut self, key: K) -> Self {
+        self.push(Segment::Key(key.into()));
+        self
+    }
+
+    /// Returns a new path with the given index appended.
+    pub fn index(mut self, index: usize) -> Self {
+        self.push(Segment::Index(index));
+        self
+    }
+}
+
+impl From<Vec<Segment>> for Path {
+    fn from(segments: Vec<Segment>) -> Self {
+        Path(segments)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+                Segment::Key(key) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    for c in key.chars() {
+                        if let '.' | '[' | ']' | '\\' = c {
+                            f.write_str("\\")?;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An error returned when parsing a malformed dotted path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsePathError(String);
+
+impl fmt::Display for ParsePathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid path: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParsePathError {}
+
+impl FromStr for Path {
+    type Err = ParsePathError;
+
+    /// Parses a dotted path such as `a.b[2].c`; `.`, `[`, `]` and `\` within keys are escaped with `\`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut path = Path::new();
+        let mut chars = s.chars().peekable();
+        let mut key: Option<String> = None;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(escaped) => key.get_or_insert_with(String::new).push(escaped),
+                    None => return Err(ParsePathError(s.to_string())),
+                },
+                '.' => match key.take() {
+                    Some(k) => path.push(Segment::Key(k)),
+                    None if path.is_empty() => return Err(ParsePathError(s.to_string())),
+                    None => {}
+                },
+                '[' => {
+                    if let Some(k) = key.take() {
+                        path.push(Segment::Key(k));
+                    }
+                    let mut digits = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(d) if d.is_ascii_digit() => digits.push(d),
+                            _ => return Err(ParsePathError(s.to_string())),
+                        }
+                    }
+                    let index = digits.parse().map_err(|_| ParsePathError(s.to_string()))?;
+                    path.push(Segment::Index(index));
+                    if let Some(&next) = chars.peek() {
+                        if next != '.' && next != '[' {
+                            return Err(ParsePathError(s.to_string()));
+                        }
+                    }
+                }
+                ']' => return Err(ParsePathError(s.to_string())),
+                c => key.get_or_insert_with(String::new).push(c),
+            }
+        }
+
+        if let Some(k) = key {
+            path.push(Segment::Key(k));
+        } else if s.ends_with('.') {
+            return Err(ParsePathError(s.to_string()));
+        }
+        Ok(path)
+    }
+}
+
+/// The structural role a token plays within a token stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The token is (part of) a map key or struct field name.
+    Key,
+
+    /// The token is a prefix to the value that follows (`Some`, newtypes and enum headers).
+    Prefix,
+
+    /// The token is a complete scalar value.
+    Scalar,
+
+    /// The token opens a sequence-like or map-like container.
+    Open,
+
+    /// The token closes the innermost open container.
+    Close,
+
+    /// The token is a `MapKey`, `MapValue`, `DocumentStart`, `DocumentEnd` or `Truncated` marker, which carries no value and leaves the path unchanged.
+    Marker,
+}
+
+/// The outcome of observing a single token with a [`PathTracker`].
+///
+/// [`PathTracker`]: struct.PathTracker.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The structural role of the observed token.
+    pub role: Role,
+
+    /// Whether or not the token is the first token of a value.
+    pub starts_value: bool,
+
+    /// Whether or not the token is the last token of a value.
+    pub ends_value: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Frame {
+    Seq { next: usize },
+    Map { key: bool },
+    Key { depth: usize },
+}
+
+/// Tracks the [`Path`] of each token as it flows through a token stream.
+///
+/// After each call to `observe`, `path` returns the path of the value the observed token belongs to (or, for map keys, the path of the value the key leads to).
+///
+/// [`Path`]: struct.Path.html
+#[derive(Clone, Debug, Default)]
+pub struct PathTracker {
+    path: Path,
+    frames: Vec<Frame>,
+    in_value: bool,
+    done: bool,
+    // The text of a map key split into chunks, joined until its `StrEnd` or `BytesEnd`.
+    key_chunks: Vec<u8>,
+}
+
+impl PathTracker {
+    /// Creates a tracker positioned before the top-level value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The path of the most recently observed token.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of containers enclosing the most recently observed token.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Advances the tracker past the given token, returning its structural role.
+    pub fn observe(&mut self, token: &Token) -> Step {
+        if role(token) == Role::Marker {
+            return self.step(Role::Marker, false, false);
+        }
+        if self.done {
+            self.done = false;
+            self.in_value = false;
+            match self.frames.last_mut() {
+                Some(Frame::Seq { next }) => {
+                    *next += 1;
+                    self.path.pop();
+                }
+                Some(Frame::Map { key }) => {
+                    *key = true;
+                    self.path.pop();
+                }
+                _ => {}
+            }
+        }
+
+        let role = role(token);
+        match self.frames.last_mut() {
+            Some(Frame::Key { depth }) => {
+                match role {
+                    Role::Open => *depth += 1,
+                    Role::Close => *depth -= 1,
+                    _ => {}
+                }
+                if *depth == 0 {
+                    self.frames.pop();
+                    self.path.push(Segment::Key(String::new()));
+                    if let Some(Frame::Map { key }) = self.frames.last_mut() {
+                        *key = false;
+                    }
+                }
+                return self.step(Role::Key, false, false);
+            }
+            Some(Frame::Map { key: true }) if role != Role::Close => {
+                match (role, token) {
+                    (_, Token::StrChunk(chunk)) => {
+                        self.key_chunks.extend_from_slice(chunk.as_bytes())
+                    }
+                    (_, Token::BytesChunk(chunk)) => self.key_chunks.extend_from_slice(chunk),
+                    (Role::Scalar, _) => {
+                        let key = match token {
+                            Token::StrEnd | Token::BytesEnd => {
+                                let chunks = mem::take(&mut self.key_chunks);
+                                String::from_utf8_lossy(&chunks).into_owned()
+                            }
+                            token => key_string(token),
+                        };
+                        self.path.push(Segment::Key(key));
+                        self.frames.pop();
+                        self.frames.push(Frame::Map { key: false })
+                    }
+                    (Role::Open, _) => self.frames.push(Frame::Key { depth: 1 }),
+                    _ => {}
+                }
+                return self.step(Role::Key, false, false);
+            }
+            Some(Frame::Seq { next }) if !self.in_value && role != Role::Close => {
+                let index = *next;
+                self.path.push(Segment::Index(index));
+            }
+            _ => {}
+        }
+
+        let starts_value = !self.in_value && role != Role::Close;
+        match role {
+            Role::Prefix => {
+                self.in_value = true;
+                self.step(role, starts_value, false)
+            }
+            Role::Scalar => {
+                self.done = true;
+                self.step(role, starts_value, true)
+            }
+            Role::Open => {
+                self.frames.push(match token {
+                    Token::Map { .. } | Token::Struct { .. } | Token::StructVariant { .. } => {
+                        Frame::Map { key: true }
+                    }
+                    _ => Frame::Seq { next: 0 },
+                });
+                self.in_value = false;
+                self.step(role, starts_value, false)
+            }
+            Role::Close => {
+                self.frames.pop();
+                self.done = true;
+                self.step(role, false, true)
+            }
+            Role::Key | Role::Marker => unreachable!(),
+        }
+    }
+
+    fn step(&self, role: Role, starts_value: bool, ends_value: bool) -> Step {
+        Step {
+            role,
+            starts_value,
+            ends_value,
+        }
+    }
+}
+
+/// Classifies a token by the structural role it plays outside of a map key position.
+pub(crate) fn role(token: &Token) -> Role {
+    match token {
+        Token::Some
+        | Token::NewtypeStruct { .. }
+        | Token::NewtypeVariant { .. }
+        | Token::Enum { .. }
+        | Token::StrChunk(_)
+        | Token::BytesChunk(_)
+        | Token::Extension { .. } => Role::Prefix,
+        Token::MapKey
+        | Token::MapValue
+        | Token::DocumentStart
+        | Token::DocumentEnd
+        | Token::Truncated { .. } => Role::Marker,
+        token if token.is_container_start() => Role::Open,
+        token if token.is_container_end() => Role::Close,
+        _ => Role::Scalar,
+    }
+}
+
+/// Renders a scalar token as a path key.
+pub(crate) fn key_string(token: &Token) -> String {
+    match token {
+        Token::Bool(v) => v.to_string(),
+        Token::I8(v) => v.to_string(),
+        Token::I16(v) => v.to_string(),
+        Token::I32(v) => v.to_string(),
+        Token::I64(v) => v.to_string(),
+        Token::I128(v) => v.to_string(),
+        Token::U8(v) => v.to_string(),
+        Token::U16(v) => v.to_string(),
+        Token::U32(v) => v.to_string(),
+        Token::U64(v) => v.to_string(),
+        Token::U128(v) => v.to_string(),
+        Token::F32(v) => v.to_string(),
+        Token::F64(v) => v.to_string(),
+        Token::Char(v) => v.to_string(),
+        Token::Str(v) | Token::Number(v) => v.to_string(),
+        Token::Field(v) => v.to_string(),
+        Token::String(v) => v.clone(),
+        Token::Interned(v) => v.to_string(),
+        Token::Inline(v) => v.to_string(),
+        Token::Bytes(v) => String::from_utf8_lossy(v).into_owned(),
+        Token::ByteBuf(v) => String::from_utf8_lossy(v).into_owned(),
+        Token::UnitStruct { name } => name.to_string(),
+        Token::UnitVariant { variant, .. } => variant.to_string(),
+        _ => String::new(),
+    }
+}
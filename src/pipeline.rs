@@ -0,0 +1,337 @@
+use crate::{Error, SerializerSink, Token};
+use futures::{sink::Sink, Async, AsyncSink, Poll, StartSend};
+use serde::Serializer;
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+type Input = Box<dyn for<'a> FnOnce(PipelineSink<'a>) -> Result<(), Error> + Send>;
+type Stage =
+    Box<dyn FnOnce(Receiver<Token<'static>>, PipelineSink<'static>) -> Result<(), String> + Send>;
+
+/// A builder for a multithreaded pipeline that tokenizes an input, passes the tokens through a series of transform stages, and forwards them to a sink, each on its own thread.
+///
+/// Stages are connected by bounded queues of owned tokens. If any stage fails (or panics), the whole pipeline is shut down: upstream stages stop at their next token, and downstream stages stop without closing their sinks.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{sync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize, Pipeline, Substitute, Token};
+///
+/// let (token_sink, token_stream) = unbounded::<Token<'static>>();
+///
+/// Pipeline::new(|sink| {
+///     let mut de = serde_json::Deserializer::from_str(r#" ["${HOST:-localhost}"] "#);
+///     tokenize(&mut de, sink)
+/// })
+/// .transform(|sink| Substitute::new(sink, |_: &str| None))
+/// .sink(token_sink)
+/// .join()
+/// .unwrap();
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Seq { len: None },
+///     Token::String("localhost".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+pub struct Pipeline {
+    input: Input,
+    stages: Vec<Stage>,
+    capacity: usize,
+}
+
+impl Pipeline {
+    /// Creates a pipeline whose tokens are produced by the given function, typically by calling [`tokenize`] with the provided sink.
+    ///
+    /// The function is run on its own thread, and borrowed tokens sent to the sink are converted to owned tokens.
+    ///
+    /// [`tokenize`]: fn.tokenize.html
+    pub fn new<F>(input: F) -> Self
+    where
+        F: for<'a> FnOnce(PipelineSink<'a>) -> Result<(), Error> + Send + 'static,
+    {
+        Pipeline {
+            input: Box::new(input),
+            stages: Vec::new(),
+            capacity: 1024,
+        }
+    }
+
+    /// Sets the capacity of the queues between stages (defaults to 1024 tokens).
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Appends a transform stage, built on its own thread by wrapping the sink of the next stage in a [`futures::Sink`] adapter (e.g. a [`Substitute`] or [`Migrate`]).
+    ///
+    /// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+    /// [`Substitute`]: struct.Substitute.html
+    /// [`Migrate`]: struct.Migrate.html
+    pub fn transform<F, T>(mut self, make: F) -> Self
+    where
+        F: FnOnce(PipelineSink<'static>) -> T + Send + 'static,
+        T: Sink<SinkItem = Token<'static>>,
+        T::SinkError: fmt::Debug,
+    {
+        self.stages.push(Box::new(move |tokens, sink| {
+            let failed = sink.failed.clone();
+            drain(tokens, make(sink), &failed)
+        }));
+        self
+    }
+
+    /// Spawns every stage, forwarding the final tokens to the given sink, which is closed once all tokens have been sent.
+    pub fn sink<S>(self, sink: S) -> PipelineHandle
+    where
+        S: Sink<SinkItem = Token<'static>> + Send + 'static,
+        S::SinkError: fmt::Debug,
+    {
+        let failed = Arc::new(AtomicBool::new(false));
+        let (mut threads, rx) = self.spawn_stages(&failed);
+
+        let sink_failed = failed.clone();
+        threads.push(spawn(&failed, move || {
+            drain(rx, sink, &sink_failed).map_err(PipelineError::Sink)
+        }));
+
+        PipelineHandle { threads }
+    }
+
+    /// Spawns every stage, feeding the final tokens into the given [`serde::Serializer`] on the current thread, and waits for the pipeline to finish.
+    ///
+    /// As with a [`SerializerSink`], the tokens must form a single document, so that the serializer need not be `Send`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{tokenize, Pipeline, Substitute};
+    ///
+    /// let mut out = Vec::new();
+    ///
+    /// Pipeline::new(|sink| {
+    ///     let mut de = serde_json::Deserializer::from_str(r#" {"host": "${HOST:-localhost}"} "#);
+    ///     tokenize(&mut de, sink)
+    /// })
+    /// .transform(|sink| Substitute::new(sink, |_: &str| None))
+    /// .serialize(&mut serde_json::Serializer::new(&mut out))
+    /// .unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(out).unwrap(), r#"{"host":"localhost"}"#);
+    /// ```
+    ///
+    /// [`serde::Serializer`]: https://docs.serde.rs/serde/trait.Serializer.html
+    /// [`SerializerSink`]: struct.SerializerSink.html
+    pub fn serialize<S: Serializer>(self, serializer: S) -> Result<S::Ok, PipelineError> {
+        let failed = Arc::new(AtomicBool::new(false));
+        let (threads, rx) = self.spawn_stages(&failed);
+
+        let mut sink = SerializerSink::new(serializer);
+        let mut result = Ok(());
+        for token in &rx {
+            if failed.load(Ordering::SeqCst) {
+                break;
+            }
+            if let Err(err) = sink.push(token) {
+                failed.store(true, Ordering::SeqCst);
+                result = Err(PipelineError::Sink(format!("{:?}", err)));
+                break;
+            }
+        }
+        // Unblocks any upstream stage still sending, before waiting for them to stop.
+        drop(rx);
+
+        let upstream = PipelineHandle { threads }.join();
+        result?;
+        upstream?;
+        sink.finish()
+            .map_err(|err| PipelineError::Sink(format!("{:?}", err)))
+    }
+
+    // Spawns the input and transform stages, returning their threads and the receiver of the final tokens.
+    fn spawn_stages(
+        self,
+        failed: &Arc<AtomicBool>,
+    ) -> (
+        Vec<JoinHandle<Result<(), PipelineError>>>,
+        Receiver<Token<'static>>,
+    ) {
+        let mut threads = Vec::new();
+
+        let (tx, mut rx) = sync_channel(self.capacity);
+        let input = self.input;
+        let sink_failed = failed.clone();
+        threads.push(spawn(failed, move || {
+            input(PipelineSink::new(tx, sink_failed)).map_err(PipelineError::Input)
+        }));
+
+        for (i, stage) in self.stages.into_iter().enumerate() {
+            let (tx, next_rx) = sync_channel(self.capacity);
+            let tokens = std::mem::replace(&mut rx, next_rx);
+            let sink = PipelineSink::new(tx, failed.clone());
+            threads.push(spawn(failed, move || {
+                stage(tokens, sink).map_err(|err| PipelineError::Transform(i, err))
+            }));
+        }
+
+        (threads, rx)
+    }
+}
+
+// Sends every received token to the sink, closing it unless the pipeline has failed.
+fn drain<S>(tokens: Receiver<Token<'static>>, sink: S, failed: &AtomicBool) -> Result<(), String>
+where
+    S: Sink<SinkItem = Token<'static>>,
+    S::SinkError: fmt::Debug,
+{
+    let mut sink = sink.wait();
+    for token in tokens {
+        if failed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        sink.send(token).map_err(|err| format!("{:?}", err))?;
+    }
+    if failed.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    sink.close().map_err(|err| format!("{:?}", err))
+}
+
+fn spawn<F>(failed: &Arc<AtomicBool>, f: F) -> JoinHandle<Result<(), PipelineError>>
+where
+    F: FnOnce() -> Result<(), PipelineError> + Send + 'static,
+{
+    let poison = Poison(failed.clone());
+    thread::spawn(move || {
+        let result = f();
+        if result.is_err() {
+            poison.0.store(true, Ordering::SeqCst);
+        }
+        result
+    })
+}
+
+/// Marks the pipeline as failed if its thread panics.
+struct Poison(Arc<AtomicBool>);
+
+impl Drop for Poison {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// A handle to a running [`Pipeline`].
+///
+/// [`Pipeline`]: struct.Pipeline.html
+pub struct PipelineHandle {
+    threads: Vec<JoinHandle<Result<(), PipelineError>>>,
+}
+
+impl PipelineHandle {
+    /// Waits for every stage to finish, returning the error of the failed stage, if any.
+    ///
+    /// When a stage fails, the stages before it also fail as they are shut down, so only the last error is returned.
+    pub fn join(self) -> Result<(), PipelineError> {
+        let mut result = Ok(());
+        for thread in self.threads {
+            if let Err(err) = thread.join().unwrap_or(Err(PipelineError::Panicked)) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+}
+
+/// The [`futures::Sink`] connecting one [`Pipeline`] stage to the next, which converts tokens to owned tokens and blocks while the queue is full.
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`Pipeline`]: struct.Pipeline.html
+pub struct PipelineSink<'a> {
+    tokens: SyncSender<Token<'static>>,
+    failed: Arc<AtomicBool>,
+    _token: PhantomData<Token<'a>>,
+}
+
+impl<'a> PipelineSink<'a> {
+    fn new(tokens: SyncSender<Token<'static>>, failed: Arc<AtomicBool>) -> Self {
+        PipelineSink {
+            tokens,
+            failed,
+            _token: PhantomData,
+        }
+    }
+}
+
+impl<'a> Sink for PipelineSink<'a> {
+    type SinkItem = Token<'a>;
+    type SinkError = PipelineError;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, PipelineError> {
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(PipelineError::Shutdown);
+        }
+        self.tokens
+            .send(token.into_owned())
+            .map_err(|_| PipelineError::Shutdown)?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), PipelineError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// An error returned by a [`Pipeline`].
+///
+/// [`Pipeline`]: struct.Pipeline.html
+#[derive(Debug)]
+pub enum PipelineError {
+    /// Tokenizing the input failed.
+    Input(Error),
+
+    /// The transform stage at the given index failed.
+    Transform(usize, String),
+
+    /// The final sink failed.
+    Sink(String),
+
+    /// A pipeline thread panicked.
+    Panicked,
+
+    /// The pipeline was shut down by the failure of another stage.
+    Shutdown,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::Input(err) => write!(f, "input error: {}", err),
+            PipelineError::Transform(i, err) => write!(f, "transform {} error: {}", i, err),
+            PipelineError::Sink(err) => write!(f, "sink error: {}", err),
+            PipelineError::Panicked => write!(f, "pipeline thread panicked"),
+            PipelineError::Shutdown => write!(f, "pipeline shut down"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl From<PipelineError> for Error {
+    fn from(err: PipelineError) -> Self {
+        Error::sink(err)
+    }
+}
@@ -0,0 +1,551 @@
+use crate::{Error, Token, TokenSink, TokenizeOptions, Tokenizer};
+use alloc::{format, string::String, vec::Vec};
+use core::{mem, str};
+
+/// An incremental JSON tokenizer, which is fed arbitrary chunks of input (such as those received over a network) and writes every token it can complete into a [`TokenSink`] of owned `Token`s, buffering any partial token until the next chunk.
+///
+/// Tokens are the same as those [`tokenize`] writes for `serde_json` (with strings as owned `Token::String`s), and may be configured with [`TokenizeOptions`]. The input may hold any number of top-level values, which must be separated by whitespace unless one of them is a string, array or object. Since the parser never recurses, nesting depth is only limited by the options' [`max_depth`], and a partial string or number is never buffered beyond the options' [`max_payload_len`].
+///
+/// *Requires the `json` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{JsonPushParser, Token};
+/// use std::sync::mpsc::channel;
+///
+/// let (tx, rx) = channel();
+/// let mut parser = JsonPushParser::new(serde_token::ChannelSink::new(tx));
+///
+/// parser.feed(br#"{"a": [tr"#).unwrap();
+/// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+///     Token::Map { len: None },
+///     Token::String("a".to_string()),
+///     Token::Seq { len: None },
+/// ]);
+///
+/// parser.feed(br#"ue, 1.5]} 12"#).unwrap();
+/// parser.finish().unwrap();
+/// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+///     Token::Bool(true),
+///     Token::F64(1.5),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+///     Token::U64(12),
+/// ]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`tokenize`]: fn.tokenize.html
+/// [`TokenizeOptions`]: struct.TokenizeOptions.html
+/// [`max_depth`]: struct.TokenizeOptions.html#method.max_depth
+/// [`max_payload_len`]: struct.TokenizeOptions.html#method.max_payload_len
+pub struct JsonPushParser<S: TokenSink<'static>> {
+    tokenizer: Tokenizer<'static, S>,
+    stack: Vec<Container>,
+    expect: Expect,
+    lexeme: Lexeme,
+    buf: Vec<u8>,
+    // The bytes of a partial UTF-8 character in a string, and the offset of its first byte.
+    partial: Vec<u8>,
+    partial_start: usize,
+    offset: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Container {
+    Array,
+    Object,
+}
+
+/// What the next byte outside of a lexeme may be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Expect {
+    Value,
+    FirstElement,
+    FirstKey,
+    Key,
+    Colon,
+    CommaOrEnd,
+    // just after a top-level number or literal, which can't be directly followed by another
+    End,
+}
+
+/// The partial string, number or literal being buffered, if any.
+#[derive(Debug)]
+enum Lexeme {
+    None,
+    Str {
+        key: bool,
+        escape: Escape,
+        // the length of the unescaped string so far, including any bytes dropped by truncation
+        len: usize,
+    },
+    Number,
+    Literal {
+        rest: &'static [u8],
+        token: Token<'static>,
+    },
+}
+
+/// How far through an escape sequence a string is.
+#[derive(Clone, Copy, Debug)]
+enum Escape {
+    None,
+    Backslash,
+    // `digits` hex digits into a `\u` escape, following the high surrogate of a pair, if any
+    Unicode {
+        code: u32,
+        digits: u8,
+        high: Option<u32>,
+    },
+    // after a high surrogate, which must be followed by a `\u` escape of a low surrogate
+    Surrogate {
+        high: u32,
+        backslash: bool,
+    },
+}
+
+impl<S: TokenSink<'static>> JsonPushParser<S> {
+    /// Creates a parser that writes into the given sink.
+    pub fn new(sink: S) -> Self {
+        JsonPushParser {
+            tokenizer: Tokenizer::new(sink),
+            stack: Vec::new(),
+            expect: Expect::Value,
+            lexeme: Lexeme::None,
+            buf: Vec::new(),
+            partial: Vec::new(),
+            partial_start: 0,
+            offset: 0,
+        }
+    }
+
+    /// Configures the tokens the parser writes with the given options.
+    pub fn with_options(mut self, options: &TokenizeOptions) -> Self {
+        self.tokenizer = self.tokenizer.with_options(options);
+        self
+    }
+
+    /// Parses the next chunk of input, writing every token it completes.
+    ///
+    /// Fails on the first syntax error, after which the parser should be discarded.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        for &byte in chunk {
+            self.byte(byte)?;
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    /// Completes a trailing top-level number, then flushes and returns the sink, failing if the input ended mid-value.
+    pub fn finish(mut self) -> Result<S, Error> {
+        self.end()?;
+        let mut sink = self.tokenizer.into_inner();
+        sink.flush().map_err(Into::into)?;
+        Ok(sink)
+    }
+
+    /// Gets a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        self.tokenizer.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.tokenizer.get_mut()
+    }
+
+    // Completes a trailing number, failing if the input ended mid-value.
+    pub(crate) fn end(&mut self) -> Result<(), Error> {
+        if let Lexeme::Number = self.lexeme {
+            self.number()?;
+        }
+        if !matches!(self.lexeme, Lexeme::None) || !self.stack.is_empty() {
+            return Err(self.error("EOF while parsing a value"));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), Error> {
+        match self.lexeme {
+            Lexeme::Str { escape, .. } => self.string_byte(escape, byte),
+            Lexeme::Number => match byte {
+                b'0'..=b'9' | b'+' | b'-' | b'.' | b'e' | b'E' => {
+                    if self.tokenizer.options().max_len == Some(self.buf.len()) {
+                        let len = self.buf.len() + 1;
+                        return Err(Error::LengthLimitExceeded { len });
+                    }
+                    self.buf.push(byte);
+                    Ok(())
+                }
+                _ => {
+                    self.number()?;
+                    self.structural(byte)
+                }
+            },
+            Lexeme::Literal { ref mut rest, .. } => {
+                if rest.first() != Some(&byte) {
+                    return Err(self.error("expected ident"));
+                }
+                *rest = &rest[1..];
+                if !rest.is_empty() {
+                    return Ok(());
+                }
+                match mem::replace(&mut self.lexeme, Lexeme::None) {
+                    Lexeme::Literal { token, .. } => self.scalar(token)?,
+                    _ => unreachable!(),
+                }
+                if self.stack.is_empty() {
+                    self.expect = Expect::End;
+                }
+                Ok(())
+            }
+            Lexeme::None => self.structural(byte),
+        }
+    }
+
+    // Handles a byte of a string, after the given escape state.
+    fn string_byte(&mut self, escape: Escape, byte: u8) -> Result<(), Error> {
+        match escape {
+            Escape::None => match byte {
+                b'"' => {
+                    self.utf8_end()?;
+                    self.string()
+                }
+                b'\\' => {
+                    self.utf8_end()?;
+                    self.escape(Escape::Backslash);
+                    Ok(())
+                }
+                0..=0x1f => Err(self.error("control character while parsing a string")),
+                _ => {
+                    self.utf8(byte)?;
+                    self.payload(&[byte])
+                }
+            },
+            Escape::Backslash => {
+                let unescaped = match byte {
+                    b'"' => b'"',
+                    b'\\' => b'\\',
+                    b'/' => b'/',
+                    b'b' => b'\x08',
+                    b'f' => b'\x0c',
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'u' => {
+                        self.escape(Escape::Unicode {
+                            code: 0,
+                            digits: 0,
+                            high: None,
+                        });
+                        return Ok(());
+                    }
+                    _ => return Err(self.error("invalid escape")),
+                };
+                self.escape(Escape::None);
+                self.payload(&[unescaped])
+            }
+            Escape::Unicode { code, digits, high } => {
+                let digit = char::from(byte)
+                    .to_digit(16)
+                    .ok_or_else(|| self.error("invalid escape"))?;
+                let code = code * 16 + digit;
+                if digits < 3 {
+                    self.escape(Escape::Unicode {
+                        code,
+                        digits: digits + 1,
+                        high,
+                    });
+                    return Ok(());
+                }
+                let code = match high {
+                    Some(high) if (0xDC00..0xE000).contains(&code) => {
+                        0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00)
+                    }
+                    Some(_) => return Err(self.error("unpaired surrogate")),
+                    None if (0xD800..0xDC00).contains(&code) => {
+                        let backslash = false;
+                        self.escape(Escape::Surrogate {
+                            high: code,
+                            backslash,
+                        });
+                        return Ok(());
+                    }
+                    None => code,
+                };
+                let c = char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))?;
+                self.escape(Escape::None);
+                self.payload(c.encode_utf8(&mut [0; 4]).as_bytes())
+            }
+            Escape::Surrogate { high, backslash } => {
+                match (backslash, byte) {
+                    (false, b'\\') => self.escape(Escape::Surrogate {
+                        high,
+                        backslash: true,
+                    }),
+                    (true, b'u') => self.escape(Escape::Unicode {
+                        code: 0,
+                        digits: 0,
+                        high: Some(high),
+                    }),
+                    _ => return Err(self.error("unpaired surrogate")),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn escape(&mut self, state: Escape) {
+        if let Lexeme::Str { ref mut escape, .. } = self.lexeme {
+            *escape = state;
+        }
+    }
+
+    // Validates a raw byte of a string as it arrives, so that invalid UTF-8 is reported at its offset in the input.
+    fn utf8(&mut self, byte: u8) -> Result<(), Error> {
+        if byte < 0x80 && self.partial.is_empty() {
+            return Ok(());
+        }
+        if self.partial.is_empty() {
+            self.partial_start = self.offset;
+        }
+        self.partial.push(byte);
+        match str::from_utf8(&self.partial) {
+            Ok(_) => {
+                self.partial.clear();
+                Ok(())
+            }
+            Err(err) if err.error_len().is_none() => Ok(()),
+            Err(_) => Err(Error::InvalidUtf8 {
+                valid_up_to: self.partial_start,
+            }),
+        }
+    }
+
+    // Fails if a string's raw bytes end partway through a character.
+    fn utf8_end(&self) -> Result<(), Error> {
+        if !self.partial.is_empty() {
+            return Err(Error::InvalidUtf8 {
+                valid_up_to: self.partial_start,
+            });
+        }
+        Ok(())
+    }
+
+    // Appends unescaped bytes to a string, failing as soon as it is longer than the maximum payload length, or only keeping enough of it to be truncated.
+    fn payload(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let len = match self.lexeme {
+            Lexeme::Str { ref mut len, .. } => {
+                *len += bytes.len();
+                *len
+            }
+            _ => unreachable!(),
+        };
+        let options = self.tokenizer.options();
+        match options.max_len {
+            Some(max_len) if len > max_len && !options.truncate => {
+                Err(Error::LengthLimitExceeded { len })
+            }
+            // the byte after the limit is kept, to tell whether it starts a character
+            Some(max_len) if self.buf.len() > max_len => Ok(()),
+            _ => {
+                self.buf.extend_from_slice(bytes);
+                Ok(())
+            }
+        }
+    }
+
+    // Handles a byte outside of any lexeme.
+    fn structural(&mut self, byte: u8) -> Result<(), Error> {
+        if let b' ' | b'\n' | b'\r' | b'\t' = byte {
+            if self.expect == Expect::End {
+                self.expect = Expect::Value;
+            }
+            return Ok(());
+        }
+        match (self.expect, byte) {
+            (Expect::FirstElement, b']') => self.close(Container::Array),
+            (Expect::Value, _) | (Expect::FirstElement, _) => self.value(byte),
+            (Expect::FirstKey, b'}') => self.close(Container::Object),
+            (Expect::FirstKey, b'"') | (Expect::Key, b'"') => {
+                self.tokenizer.write_marker(Token::MapKey)?;
+                self.lexeme = Lexeme::Str {
+                    key: true,
+                    escape: Escape::None,
+                    len: 0,
+                };
+                Ok(())
+            }
+            (Expect::FirstKey, _) | (Expect::Key, _) => Err(self.error("key must be a string")),
+            (Expect::Colon, b':') => {
+                self.tokenizer.write_marker(Token::MapValue)?;
+                self.expect = Expect::Value;
+                Ok(())
+            }
+            (Expect::Colon, _) => Err(self.error("expected `:`")),
+            (Expect::CommaOrEnd, b',') => {
+                self.expect = match self.stack.last() {
+                    Some(Container::Object) => Expect::Key,
+                    _ => Expect::Value,
+                };
+                Ok(())
+            }
+            (Expect::CommaOrEnd, b']') => self.close(Container::Array),
+            (Expect::CommaOrEnd, b'}') => self.close(Container::Object),
+            (Expect::CommaOrEnd, _) => Err(self.error("expected `,` or the end of a container")),
+            (Expect::End, b'"') | (Expect::End, b'[') | (Expect::End, b'{') => self.value(byte),
+            (Expect::End, _) => Err(self.error("expected whitespace after a top-level value")),
+        }
+    }
+
+    // Starts a value with its first byte.
+    fn value(&mut self, byte: u8) -> Result<(), Error> {
+        let literal = |rest, token| Lexeme::Literal { rest, token };
+        self.lexeme = match byte {
+            b'{' => return self.open(Container::Object, Token::Map { len: None }),
+            b'[' => return self.open(Container::Array, Token::Seq { len: None }),
+            b'"' => Lexeme::Str {
+                key: false,
+                escape: Escape::None,
+                len: 0,
+            },
+            b'-' | b'0'..=b'9' => {
+                self.buf.push(byte);
+                Lexeme::Number
+            }
+            b't' => literal(&b"rue"[..], Token::Bool(true)),
+            b'f' => literal(&b"alse"[..], Token::Bool(false)),
+            b'n' => literal(&b"ull"[..], Token::Unit),
+            _ => return Err(self.error("expected value")),
+        };
+        Ok(())
+    }
+
+    fn open(&mut self, container: Container, token: Token<'static>) -> Result<(), Error> {
+        self.tokenizer.write_token(token)?;
+        self.stack.push(container);
+        self.expect = match container {
+            Container::Array => Expect::FirstElement,
+            Container::Object => Expect::FirstKey,
+        };
+        Ok(())
+    }
+
+    fn close(&mut self, container: Container) -> Result<(), Error> {
+        if self.stack.pop() != Some(container) {
+            return Err(self.error("mismatched closing delimiter"));
+        }
+        let token = match container {
+            Container::Array => Token::SeqEnd,
+            Container::Object => Token::MapEnd,
+        };
+        self.scalar(token)
+    }
+
+    // Writes a token that completes a value.
+    fn scalar(&mut self, token: Token<'static>) -> Result<(), Error> {
+        self.tokenizer.write_token(token)?;
+        self.expect = match self.stack.last() {
+            Some(_) => Expect::CommaOrEnd,
+            None => Expect::Value,
+        };
+        Ok(())
+    }
+
+    // Completes a string at its closing quote.
+    fn string(&mut self) -> Result<(), Error> {
+        let (key, len) = match self.lexeme {
+            Lexeme::Str { key, len, .. } => (key, len),
+            _ => unreachable!(),
+        };
+        self.lexeme = Lexeme::None;
+        let mut bytes = mem::take(&mut self.buf);
+        if let Some(max_len) = self.tokenizer.options().max_len.filter(|&max| len > max) {
+            // like the tokenizer, truncate on a character boundary, after a marker of the original length
+            let mut end = max_len;
+            while bytes[end] & 0xc0 == 0x80 {
+                end -= 1;
+            }
+            bytes.truncate(end);
+            self.tokenizer.write_token(Token::Truncated { len })?;
+        }
+        let string = String::from_utf8(bytes).map_err(|_| self.error("invalid utf-8"))?;
+        if key {
+            self.tokenizer.write_token(Token::String(string))?;
+            self.expect = Expect::Colon;
+            return Ok(());
+        }
+        self.scalar(Token::String(string))
+    }
+
+    // Completes a number at the first byte after it.
+    fn number(&mut self) -> Result<(), Error> {
+        self.lexeme = Lexeme::None;
+        let raw = mem::take(&mut self.buf);
+        let token = parse_number(&raw).ok_or_else(|| self.error("invalid number"))?;
+        self.scalar(token)?;
+        if self.stack.is_empty() {
+            self.expect = Expect::End;
+        }
+        Ok(())
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        Error::DeserializeError(format!("{} at byte {}", msg, self.offset))
+    }
+}
+
+/// Parses a JSON number as `serde_json` would, into a `U64` or `I64` if it is an integer that fits (other than `-0`), or else an `F64`.
+fn parse_number(raw: &[u8]) -> Option<Token<'static>> {
+    let digits = |i: usize| raw[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut i = usize::from(raw.first() == Some(&b'-'));
+    let int = digits(i);
+    if int == 0 || (int > 1 && raw[i] == b'0') {
+        return None;
+    }
+    i += int;
+    let mut integer = true;
+    if raw.get(i) == Some(&b'.') {
+        let frac = digits(i + 1);
+        if frac == 0 {
+            return None;
+        }
+        i += 1 + frac;
+        integer = false;
+    }
+    if let Some(b'e') | Some(b'E') = raw.get(i) {
+        i += 1;
+        if let Some(b'+') | Some(b'-') = raw.get(i) {
+            i += 1;
+        }
+        let exp = digits(i);
+        if exp == 0 {
+            return None;
+        }
+        i += exp;
+        integer = false;
+    }
+    if i != raw.len() {
+        return None;
+    }
+
+    let text = str::from_utf8(raw).ok()?;
+    if integer {
+        if raw[0] == b'-' {
+            // like `serde_json`, `-0` is a float, so its sign isn't lost
+            match text.parse() {
+                Ok(0) => return Some(Token::F64(-0.0)),
+                Ok(v) => return Some(Token::I64(v)),
+                Err(_) => {}
+            }
+        } else if let Ok(v) = text.parse() {
+            return Some(Token::U64(v));
+        }
+    }
+    match text.parse::<f64>() {
+        Ok(v) if v.is_finite() => Some(Token::F64(v)),
+        _ => None,
+    }
+}
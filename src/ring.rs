@@ -0,0 +1,241 @@
+use crate::{Error, OwnedToken, Token, TokenSink};
+use std::{
+    hint,
+    sync::{
+        atomic::{self, AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, Thread},
+};
+
+/// Creates a bounded single-producer, single-consumer ring buffer of owned `Token`s, returning its [`RingSink`] and [`RingStream`] halves.
+///
+/// The positions of the two halves are published atomically, and each slot is only ever touched by one side at a time, so the mutex guarding each slot is never contended and neither half waits on the other unless the ring is full (or empty). A full ring blocks the sink, and an empty one the stream, spinning briefly, then yielding the thread, and finally parking it until the other half makes progress (or is dropped). This makes it cheaper than a channel for handing tokens from a tokenizing thread to a single consumer.
+///
+/// *Requires the `std` feature.*
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{token_ring, tokenize, Token};
+/// use std::thread;
+///
+/// let (sink, stream) = token_ring(2);
+/// let consumer = thread::spawn(move || stream.collect::<Vec<_>>());
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" ["a", "b"] "#);
+/// tokenize(&mut de, sink).unwrap();
+///
+/// assert_eq!(consumer.join().unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::String("a".to_string()),
+///     Token::String("b".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`RingSink`]: struct.RingSink.html
+/// [`RingStream`]: struct.RingStream.html
+pub fn token_ring(capacity: usize) -> (RingSink, RingStream) {
+    assert!(capacity > 0, "ring capacity must be non-zero");
+    let ring = Arc::new(Ring {
+        slots: (0..capacity).map(|_| Mutex::new(None)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        dropped: AtomicBool::new(false),
+        sink_waiter: Waiter::default(),
+        stream_waiter: Waiter::default(),
+    });
+    (RingSink(ring.clone()), RingStream(ring))
+}
+
+#[derive(Debug)]
+struct Ring {
+    slots: Box<[Mutex<Option<OwnedToken>>]>,
+    // The positions of the next token to read and to write, which only increase.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    // Whether the sink or stream has been dropped.
+    closed: AtomicBool,
+    dropped: AtomicBool,
+    // The sink waiting for room, and the stream waiting for tokens.
+    sink_waiter: Waiter,
+    stream_waiter: Waiter,
+}
+
+impl Ring {
+    fn slot(&self, position: usize) -> MutexGuard<'_, Option<OwnedToken>> {
+        let slot = &self.slots[position % self.slots.len()];
+        slot.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Takes the next token, if any. Only called by the `RingStream`.
+    fn pop(&self) -> Option<OwnedToken> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let token = self.slot(head).take();
+        self.head.store(head + 1, Ordering::Release);
+        self.sink_waiter.wake();
+        token
+    }
+}
+
+/// The writing half of a [`token_ring`], a [`TokenSink`] that converts each token it is given into an owned token.
+///
+/// Writing fails once the [`RingStream`] has been dropped, and writing to a full ring blocks until the stream makes room.
+///
+/// *Requires the `std` feature.*
+///
+/// [`token_ring`]: fn.token_ring.html
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`RingStream`]: struct.RingStream.html
+#[derive(Debug)]
+pub struct RingSink(Arc<Ring>);
+
+impl RingSink {
+    /// The number of tokens the ring can hold.
+    pub fn capacity(&self) -> usize {
+        self.0.slots.len()
+    }
+}
+
+impl<'de> TokenSink<'de> for RingSink {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        let ring = &*self.0;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        ring.sink_waiter.wait(|| {
+            if ring.dropped.load(Ordering::Acquire) {
+                Some(Err(Error::sink_debug("token ring stream dropped")))
+            } else if tail - ring.head.load(Ordering::Acquire) < ring.slots.len() {
+                Some(Ok(()))
+            } else {
+                None
+            }
+        })?;
+        *ring.slot(tail) = Some(token.into_owned());
+        ring.tail.store(tail + 1, Ordering::Release);
+        ring.stream_waiter.wake();
+        Ok(())
+    }
+}
+
+impl Drop for RingSink {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+        self.0.stream_waiter.wake();
+    }
+}
+
+/// The reading half of a [`token_ring`], which iterates over the tokens written to the [`RingSink`] until it has been dropped and the ring is empty.
+///
+/// *Requires the `std` feature.*
+///
+/// [`token_ring`]: fn.token_ring.html
+/// [`RingSink`]: struct.RingSink.html
+#[derive(Debug)]
+pub struct RingStream(Arc<Ring>);
+
+impl RingStream {
+    /// Takes the next token without blocking, or returns `None` if the ring is empty.
+    pub fn try_recv(&mut self) -> Option<OwnedToken> {
+        self.0.pop()
+    }
+
+    /// Takes the next token, blocking while the ring is empty, or returns `None` once the [`RingSink`] has been dropped and every token it wrote has been taken.
+    ///
+    /// [`RingSink`]: struct.RingSink.html
+    pub fn recv(&mut self) -> Option<OwnedToken> {
+        let ring = &*self.0;
+        ring.stream_waiter.wait(|| match ring.pop() {
+            Some(token) => Some(Some(token)),
+            // checked after the ring, so tokens written just before the sink was dropped are still taken
+            None if ring.closed.load(Ordering::Acquire) => Some(ring.pop()),
+            None => None,
+        })
+    }
+
+    /// The number of tokens waiting in the ring.
+    pub fn len(&self) -> usize {
+        self.0.tail.load(Ordering::Acquire) - self.0.head.load(Ordering::Relaxed)
+    }
+
+    /// Whether or not the ring is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Iterator for RingStream {
+    type Item = OwnedToken;
+
+    fn next(&mut self) -> Option<OwnedToken> {
+        self.recv()
+    }
+}
+
+impl Drop for RingStream {
+    fn drop(&mut self) {
+        self.0.dropped.store(true, Ordering::Release);
+        self.0.sink_waiter.wake();
+    }
+}
+
+/// The number of times a waiting half spins (for exponentially longer) before yielding the thread.
+const SPINS: u32 = 6;
+
+/// The number of times a waiting half yields the thread before parking it.
+const YIELDS: u32 = 10;
+
+/// One half of the ring's record of whether it is parked, so the other half knows to wake it.
+#[derive(Debug, Default)]
+struct Waiter {
+    parked: AtomicBool,
+    thread: Mutex<Option<Thread>>,
+}
+
+impl Waiter {
+    /// Waits until `ready` returns a value, spinning at first, then yielding the thread, and finally parking it until woken by the other half.
+    fn wait<T>(&self, mut ready: impl FnMut() -> Option<T>) -> T {
+        for step in 0..SPINS + YIELDS {
+            if let Some(value) = ready() {
+                return value;
+            }
+            if step < SPINS {
+                (0..1 << step).for_each(|_| hint::spin_loop());
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        *self.thread.lock().unwrap_or_else(|err| err.into_inner()) = Some(thread::current());
+        loop {
+            self.parked.store(true, Ordering::Relaxed);
+            // pairs with the fence in `wake`, so either `ready` sees the other half's progress, or it sees that this half is parked
+            atomic::fence(Ordering::SeqCst);
+            if let Some(value) = ready() {
+                self.parked.store(false, Ordering::Relaxed);
+                return value;
+            }
+            thread::park();
+        }
+    }
+
+    /// Wakes this half if it is parked, after the other half has made progress.
+    fn wake(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.parked.swap(false, Ordering::Relaxed) {
+            if let Some(thread) = &*self.thread.lock().unwrap_or_else(|err| err.into_inner()) {
+                thread.unpark();
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    path::{role, Role},
+    sink::Forward,
+    Token,
+};
+use futures::{sink::Sink, AsyncSink, Poll, StartSend};
+
+/// A [`futures::Sink`] adapter that forwards a random sample of the elements of every sequence to the wrapped sink.
+///
+/// Only the elements of `Seq`s are sampled (tuples are left intact), at every depth, and the lengths of sampled sequences are forwarded as unknown. Sampling is deterministic for a given seed.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize, Sample, Token};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [1, 2, 3, 4, 5, 6, 7, 8] "#);
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize(&mut de, Sample::reservoir(token_sink, 3, 42)).unwrap();
+///
+/// let sampled = token_stream.collect().wait().unwrap();
+/// assert_eq!(sampled.len(), 2 + 3);
+/// assert_eq!(sampled[0], Token::Seq { len: None });
+/// assert_eq!(sampled[4], Token::SeqEnd);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+pub struct Sample<'a, S: Sink<SinkItem = Token<'a>>> {
+    mode: Mode,
+    rng: Rng,
+    frames: Vec<Frame<'a>>,
+    dropping: Option<usize>,
+    // Markers held until the element they precede is kept or dropped.
+    pending: Vec<Token<'a>>,
+    forward: Forward<S>,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Bernoulli(f64),
+    Reservoir(usize),
+}
+
+struct Frame<'a> {
+    sampled: bool,
+    seen: usize,
+    element: Element<'a>,
+    kept: Vec<(usize, Vec<Token<'a>>)>,
+}
+
+enum Element<'a> {
+    None,
+    Kept,
+    Buffered(usize, Vec<Token<'a>>),
+}
+
+impl<'a, S: Sink<SinkItem = Token<'a>>> Sample<'a, S> {
+    /// Wraps a sink, keeping each sequence element with probability `p`.
+    pub fn new(sink: S, p: f64, seed: u64) -> Self {
+        Self::with_mode(sink, Mode::Bernoulli(p), seed)
+    }
+
+    /// Wraps a sink, keeping a uniformly random subset of at most `k` elements of each sequence, in their original order.
+    ///
+    /// The kept elements of each sequence are buffered until the sequence ends.
+    pub fn reservoir(sink: S, k: usize, seed: u64) -> Self {
+        Self::with_mode(sink, Mode::Reservoir(k), seed)
+    }
+
+    fn with_mode(sink: S, mode: Mode, seed: u64) -> Self {
+        Sample {
+            mode,
+            rng: Rng(seed),
+            frames: Vec::new(),
+            dropping: None,
+            pending: Vec::new(),
+            forward: Forward::new(sink),
+        }
+    }
+
+    /// Consumes the adapter, returning the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.forward.into_inner()
+    }
+
+    fn push(&mut self, token: Token<'a>) {
+        let role = role(&token);
+
+        if self.dropping.is_none() && role != Role::Close && role != Role::Marker {
+            self.start_element();
+            match self.dropping {
+                Some(_) => self.pending.clear(),
+                None => self.output_pending(),
+            }
+        }
+        if let Some(depth) = self.dropping.as_mut() {
+            match role {
+                Role::Open => *depth += 1,
+                Role::Close => *depth -= 1,
+                _ => {}
+            }
+            if *depth == 0 && role != Role::Prefix {
+                self.dropping = None;
+                self.end_element();
+            }
+            return;
+        }
+
+        match role {
+            Role::Open => {
+                let sampled = matches!(token, Token::Seq { .. });
+                let token = if sampled {
+                    Token::Seq { len: None }
+                } else {
+                    token
+                };
+                self.output(token);
+                self.frames.push(Frame {
+                    sampled,
+                    seen: 0,
+                    element: Element::None,
+                    kept: Vec::new(),
+                });
+            }
+            Role::Close => {
+                if let Some(mut frame) = self.frames.pop() {
+                    frame.kept.sort_by_key(|(i, _)| *i);
+                    for (_, tokens) in frame.kept {
+                        for token in tokens {
+                            self.output(token);
+                        }
+                    }
+                }
+                self.output_pending();
+                self.output(token);
+                self.end_element();
+            }
+            Role::Marker if self.between_elements() => self.pending.push(token),
+            Role::Prefix | Role::Marker => self.output(token),
+            _ => {
+                self.output(token);
+                self.end_element();
+            }
+        }
+    }
+
+    // Decides whether to keep the element starting at the current token, if in a sampled sequence.
+    fn start_element(&mut self) {
+        let frame = match self.frames.last_mut() {
+            Some(frame) if frame.sampled => frame,
+            _ => return,
+        };
+        if !matches!(frame.element, Element::None) {
+            return;
+        }
+
+        let index = frame.seen;
+        frame.seen += 1;
+        frame.element = match self.mode {
+            Mode::Bernoulli(p) if self.rng.next_f64() < p => Element::Kept,
+            Mode::Reservoir(k) if frame.kept.len() < k => Element::Buffered(index, Vec::new()),
+            Mode::Reservoir(k) if (self.rng.below(frame.seen as u64) as usize) < k => {
+                Element::Buffered(index, Vec::new())
+            }
+            _ => {
+                self.dropping = Some(0);
+                return;
+            }
+        };
+    }
+
+    // Completes the current element of the innermost sampled sequence, if any.
+    fn end_element(&mut self) {
+        let frame = match self.frames.last_mut() {
+            Some(frame) if frame.sampled => frame,
+            _ => return,
+        };
+        let k = match self.mode {
+            Mode::Reservoir(k) => k,
+            Mode::Bernoulli(_) => 0,
+        };
+        if let Element::Buffered(index, tokens) =
+            std::mem::replace(&mut frame.element, Element::None)
+        {
+            if frame.kept.len() < k {
+                frame.kept.push((index, tokens));
+            } else {
+                let slot = self.rng.below(k as u64) as usize;
+                frame.kept[slot] = (index, tokens);
+            }
+        }
+    }
+
+    // Whether the next token starts an element of the innermost sequence, if it's sampled.
+    fn between_elements(&self) -> bool {
+        match self.frames.last() {
+            Some(frame) => frame.sampled && matches!(frame.element, Element::None),
+            None => false,
+        }
+    }
+
+    fn output_pending(&mut self) {
+        for token in std::mem::take(&mut self.pending) {
+            self.output(token);
+        }
+    }
+
+    // Appends a token to the innermost buffered element, or forwards it to the wrapped sink.
+    fn output(&mut self, token: Token<'a>) {
+        for frame in self.frames.iter_mut().rev() {
+            if let Element::Buffered(_, tokens) = &mut frame.element {
+                tokens.push(token);
+                return;
+            }
+        }
+        self.forward.extend(Some(token));
+    }
+}
+
+impl<'a, S: Sink<SinkItem = Token<'a>>> Sink for Sample<'a, S> {
+    type SinkItem = Token<'a>;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Self::SinkError> {
+        self.push(token);
+        self.forward.flush()?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.forward.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.forward.close()
+    }
+}
+
+/// A small, seedable xorshift64* generator, so that samples are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        // xorshift has a fixed point at zero
+        if self.0 == 0 {
+            self.0 = 0x9E37_79B9_7F4A_7C15;
+        }
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{
+    path::{PathTracker, Role},
+    tokenize,
+    tree::{malformed, Node},
+    Error, Path, Segment, Token, TokenSink,
+};
+use serde::de;
+use std::convert::TryFrom;
+
+/// Tokenizes a deserializer until a value satisfies the predicate, returning the value's path and tokens.
+///
+/// The predicate is called with the first token of each scalar and container value (map keys are skipped) along with its path. If it matches a container, the container's entire subtree is returned. Tokenization stops as soon as the matching value has been collected.
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::Deserializer;
+/// use serde_token::{find_first, Token};
+///
+/// let mut de = Deserializer::from_str(r#" {"a": [1, 2], "b": {"c": 3}} "#);
+/// let (path, tokens) = find_first(&mut de, |token, _| *token == Token::U64(2))
+///     .unwrap()
+///     .unwrap();
+///
+/// assert_eq!(path.to_string(), "a[1]");
+/// assert_eq!(tokens, vec![Token::U64(2)]);
+/// ```
+pub fn find_first<'de, D, P>(
+    deserializer: D,
+    predicate: P,
+) -> Result<Option<(Path, Vec<Token<'de>>)>, Error>
+where
+    D: de::Deserializer<'de>,
+    P: FnMut(&Token<'de>, &Path) -> bool,
+{
+    let mut sink = FindSink {
+        tracker: PathTracker::new(),
+        predicate,
+        matched: None,
+        found: false,
+    };
+
+    match tokenize(deserializer, &mut sink) {
+        _ if sink.found => Ok(sink.matched.map(|(path, _, tokens)| (path, tokens))),
+        Ok(()) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+struct FindSink<'de, P> {
+    tracker: PathTracker,
+    predicate: P,
+    matched: Option<(Path, usize, Vec<Token<'de>>)>,
+    found: bool,
+}
+
+// Stops tokenization by failing once the matching value has been collected.
+impl<'de, P> TokenSink<'de> for &mut FindSink<'de, P>
+where
+    P: FnMut(&Token<'de>, &Path) -> bool,
+{
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        if self.found {
+            return Err(Error::TokenSinkNotReadyError);
+        }
+
+        let depth = self.tracker.depth();
+        let step = self.tracker.observe(&token);
+
+        if self.matched.is_none() {
+            if !step.starts_value || !(self.predicate)(&token, self.tracker.path()) {
+                return Ok(());
+            }
+            self.matched = Some((self.tracker.path().clone(), depth, Vec::new()));
+        }
+
+        let (_, start_depth, tokens) = self.matched.as_mut().unwrap();
+        tokens.push(token);
+        if step.ends_value && step.role != Role::Key && self.tracker.depth() == *start_depth {
+            self.found = true;
+            return Err(Error::TokenSinkNotReadyError);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path of the first value (in pre-order) within the `haystack` document that structurally contains the `needle` document.
+///
+/// A value contains the needle if:
+///
+/// - both are scalars with the same value (borrowed and owned strings or bytes are equal, as are integers of different widths),
+/// - both are sequences of the same length, and each element contains the corresponding needle element,
+/// - both are maps, and for every needle entry, the value has an entry with the same key whose value contains the needle entry's value.
+///
+/// `Some` and newtype prefixes are ignored.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{contains, Token};
+///
+/// let haystack = vec![
+///     Token::Map { len: None },
+///     Token::Str("user".into()),
+///     Token::Map { len: None },
+///     Token::Str("id".into()),
+///     Token::U64(1),
+///     Token::Str("admin".into()),
+///     Token::Bool(true),
+///     Token::MapEnd,
+///     Token::MapEnd,
+/// ];
+/// let needle = vec![
+///     Token::Map { len: None },
+///     Token::String("admin".to_string()),
+///     Token::Bool(true),
+///     Token::MapEnd,
+/// ];
+///
+/// assert_eq!(contains(haystack, needle).unwrap().unwrap().to_string(), "user");
+/// ```
+pub fn contains<'a, 'b, H, N>(haystack: H, needle: N) -> Result<Option<Path>, Error>
+where
+    H: IntoIterator<Item = Token<'a>>,
+    N: IntoIterator<Item = Token<'b>>,
+{
+    let haystack = parse_document(haystack)?;
+    let needle = parse_document(needle)?;
+
+    let mut path = Path::new();
+    if find_match(&haystack, &needle, &mut path) {
+        Ok(Some(path))
+    } else {
+        Ok(None)
+    }
+}
+
+fn parse_document<'a, I: IntoIterator<Item = Token<'a>>>(tokens: I) -> Result<Node<'a>, Error> {
+    Node::parse(&mut tokens.into_iter())?.ok_or_else(|| malformed("empty document"))
+}
+
+// Searches the value and its descendants in pre-order, leaving the path of the first match in `path`.
+fn find_match(value: &Node, needle: &Node, path: &mut Path) -> bool {
+    if matches(value, needle) {
+        return true;
+    }
+    match value.unwrap_prefixes() {
+        Node::Seq { items, .. } => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(Segment::Index(i));
+                if find_match(item, needle, path) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        Node::Map { entries, .. } => {
+            for (key, item) in entries {
+                path.push(Segment::Key(key.key_string().unwrap_or_default()));
+                if find_match(item, needle, path) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    false
+}
+
+fn matches(value: &Node, needle: &Node) -> bool {
+    match (value.unwrap_prefixes(), needle.unwrap_prefixes()) {
+        (Node::Scalar(value), Node::Scalar(needle)) => scalar_eq(value, needle),
+        (Node::Seq { items, .. }, Node::Seq { items: needles, .. }) => {
+            items.len() == needles.len()
+                && items
+                    .iter()
+                    .zip(needles)
+                    .all(|(item, needle)| matches(item, needle))
+        }
+        (
+            Node::Map { entries, .. },
+            Node::Map {
+                entries: needles, ..
+            },
+        ) => needles.iter().all(|(needle_key, needle)| {
+            entries
+                .iter()
+                .any(|(key, value)| matches(key, needle_key) && matches(value, needle))
+        }),
+        _ => false,
+    }
+}
+
+fn scalar_eq(a: &Token, b: &Token) -> bool {
+    match (a.as_str(), b.as_str()) {
+        (Some(a), Some(b)) => return a == b,
+        (None, None) => {}
+        _ => return false,
+    }
+    match (a.as_bytes(), b.as_bytes()) {
+        (Some(a), Some(b)) => return a == b,
+        (None, None) => {}
+        _ => return false,
+    }
+    match (as_int(a), as_int(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn as_int(token: &Token) -> Option<i128> {
+    match *token {
+        Token::I8(v) => Some(v.into()),
+        Token::I16(v) => Some(v.into()),
+        Token::I32(v) => Some(v.into()),
+        Token::I64(v) => Some(v.into()),
+        Token::I128(v) => Some(v),
+        Token::U8(v) => Some(v.into()),
+        Token::U16(v) => Some(v.into()),
+        Token::U32(v) => Some(v.into()),
+        Token::U64(v) => Some(v.into()),
+        Token::U128(v) => i128::try_from(v).ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{
+    error::Error,
+    path::{role, Role},
+    tree::{malformed, Node},
+    wire::intern,
+    Token, EXTENSION_TOKEN, NUMBER_TOKEN,
+};
+#[cfg(feature = "futures01")]
+use futures::{sink::Sink, Async, AsyncSink, Poll, StartSend};
+use serde::ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+use std::fmt;
+
+impl<'a> Serialize for Node<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Node::Scalar(token) => serialize_scalar(token, serializer),
+            Node::Prefixed(prefix, node) => match *prefix {
+                Token::Some => serializer.serialize_some(&**node),
+                Token::NewtypeStruct { name } => serializer.serialize_newtype_struct(name, &**node),
+                Token::NewtypeVariant {
+                    name,
+                    variant_index,
+                    variant,
+                } => serializer.serialize_newtype_variant(name, variant_index, variant, &**node),
+                Token::Extension { tag } => {
+                    let mut state = serializer.serialize_tuple_struct(EXTENSION_TOKEN, 2)?;
+                    state.serialize_field(&tag)?;
+                    state.serialize_field(&**node)?;
+                    state.end()
+                }
+                _ => node.serialize(serializer),
+            },
+            Node::Seq { open, items, .. } => match *open {
+                Token::Tuple { .. } => {
+                    let mut tuple = serializer.serialize_tuple(items.len())?;
+                    for item in items {
+                        tuple.serialize_element(item)?;
+                    }
+                    tuple.end()
+                }
+                Token::TupleStruct { name, .. } => {
+                    let mut tuple = serializer.serialize_tuple_struct(name, items.len())?;
+                    for item in items {
+                        tuple.serialize_field(item)?;
+                    }
+                    tuple.end()
+                }
+                Token::TupleVariant {
+                    name,
+                    variant_index,
+                    variant,
+                    ..
+                } => {
+                    let mut tuple = serializer.serialize_tuple_variant(
+                        name,
+                        variant_index,
+                        variant,
+                        items.len(),
+                    )?;
+                    for item in items {
+                        tuple.serialize_field(item)?;
+                    }
+                    tuple.end()
+                }
+                _ => {
+                    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                    for item in items {
+                        seq.serialize_element(item)?;
+                    }
+                    seq.end()
+                }
+            },
+            Node::Map { open, entries, .. } => match *open {
+                Token::Struct { name, .. } => {
+                    let mut fields = serializer.serialize_struct(name, entries.len())?;
+                    for (key, value) in entries {
+                        fields.serialize_field(field_name(key)?, value)?;
+                    }
+                    fields.end()
+                }
+                Token::StructVariant {
+                    name,
+                    variant_index,
+                    variant,
+                    ..
+                } => {
+                    let mut fields = serializer.serialize_struct_variant(
+                        name,
+                        variant_index,
+                        variant,
+                        entries.len(),
+                    )?;
+                    for (key, value) in entries {
+                        fields.serialize_field(field_name(key)?, value)?;
+                    }
+                    fields.end()
+                }
+                _ => {
+                    let mut map = serializer.serialize_map(Some(entries.len()))?;
+                    for (key, value) in entries {
+                        map.serialize_entry(key, value)?;
+                    }
+                    map.end()
+                }
+            },
+        }
+    }
+}
+
+fn serialize_scalar<S: Serializer>(token: &Token, serializer: S) -> Result<S::Ok, S::Error> {
+    match *token {
+        Token::Bool(v) => serializer.serialize_bool(v),
+        Token::I8(v) => serializer.serialize_i8(v),
+        Token::I16(v) => serializer.serialize_i16(v),
+        Token::I32(v) => serializer.serialize_i32(v),
+        Token::I64(v) => serializer.serialize_i64(v),
+        Token::I128(v) => serializer.serialize_i128(v),
+        Token::U8(v) => serializer.serialize_u8(v),
+        Token::U16(v) => serializer.serialize_u16(v),
+        Token::U32(v) => serializer.serialize_u32(v),
+        Token::U64(v) => serializer.serialize_u64(v),
+        Token::U128(v) => serializer.serialize_u128(v),
+        Token::F32(v) => serializer.serialize_f32(v),
+        Token::F64(v) => serializer.serialize_f64(v),
+        Token::Char(v) => serializer.serialize_char(v),
+        Token::Number(ref v) => {
+            let mut state = serializer.serialize_struct(NUMBER_TOKEN, 1)?;
+            state.serialize_field(NUMBER_TOKEN, v)?;
+            state.end()
+        }
+        Token::Str(ref v) => serializer.serialize_str(v),
+        Token::String(ref v) => serializer.serialize_str(v),
+        Token::Interned(ref v) => serializer.serialize_str(v),
+        Token::Inline(ref v) => serializer.serialize_str(v),
+        Token::Field(v) => serializer.serialize_str(v),
+        Token::Bytes(ref v) => serializer.serialize_bytes(v),
+        Token::ByteBuf(ref v) => serializer.serialize_bytes(v),
+        Token::None => serializer.serialize_none(),
+        Token::Unit => serializer.serialize_unit(),
+        Token::UnitStruct { name } => serializer.serialize_unit_struct(name),
+        Token::UnitVariant {
+            name,
+            variant_index,
+            variant,
+        } => serializer.serialize_unit_variant(name, variant_index, variant),
+        ref token => Err(ser::Error::custom(format!("unexpected token: {:?}", token))),
+    }
+}
+
+fn field_name<E: ser::Error>(key: &Node) -> Result<&'static str, E> {
+    let key = key
+        .key_string()
+        .ok_or_else(|| E::custom("struct field names must be scalars"))?;
+    intern(&key).map_err(E::custom)
+}
+
+/// Feeds a single document from the given tokens into a [`serde::Serializer`], returning the serializer's output: the reverse of [`tokenize`].
+///
+/// To detokenize a [`futures::Stream`] of tokens instead, `forward` it into a [`SerializerSink`].
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{detokenize, Token};
+///
+/// let tokens = vec![
+///     Token::Map { len: None },
+///     Token::Str("a".into()),
+///     Token::Seq { len: Some(1) },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ];
+/// let mut out = Vec::new();
+/// detokenize(tokens, &mut serde_json::Serializer::new(&mut out)).unwrap();
+///
+/// assert_eq!(String::from_utf8(out).unwrap(), r#"{"a":[true]}"#);
+/// ```
+///
+/// [`serde::Serializer`]: https://docs.serde.rs/serde/trait.Serializer.html
+/// [`tokenize`]: fn.tokenize.html
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+/// [`SerializerSink`]: struct.SerializerSink.html
+pub fn detokenize<'a, I, S>(
+    tokens: I,
+    serializer: S,
+) -> Result<S::Ok, SerializerSinkError<S::Error>>
+where
+    I: IntoIterator<Item = Token<'a>>,
+    S: Serializer,
+{
+    let mut sink = SerializerSink::new(serializer);
+    for token in tokens {
+        sink.push(token)?;
+    }
+    sink.finish()
+}
+
+/// A sink of `Token`s that feeds a single document into a wrapped [`serde::Serializer`], which is a [`futures::Sink`] with the `futures01` feature.
+///
+/// If the document is a sequence or map, each of its elements (or entries) is serialized as soon as it is complete, so only the largest element is ever buffered; any other document is buffered in full. The serializer's output is returned by `finish`.
+///
+/// # Example:
+///
+/// ```
+/// # #[cfg(feature = "futures01")] {
+/// use serde_token::{tokenize, SerializerSink};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" {"a": [1, 2], "b": null} "#);
+/// let mut out = Vec::new();
+/// let mut ser = serde_json::Serializer::new(&mut out);
+/// let mut sink = SerializerSink::new(&mut ser);
+///
+/// tokenize(&mut de, &mut sink).unwrap();
+/// sink.finish().unwrap();
+///
+/// assert_eq!(String::from_utf8(out).unwrap(), r#"{"a":[1,2],"b":null}"#);
+/// # }
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`serde::Serializer`]: https://docs.serde.rs/serde/trait.Serializer.html
+pub struct SerializerSink<'a, S: Serializer> {
+    state: State<S>,
+    buffer: Vec<Token<'a>>,
+    depth: usize,
+    key: Option<Node<'a>>,
+}
+
+enum State<S: Serializer> {
+    Start(S),
+    Seq(S::SerializeSeq),
+    Map(S::SerializeMap),
+    Done(S::Ok),
+    Failed,
+}
+
+impl<'a, S: Serializer> SerializerSink<'a, S> {
+    /// Wraps the given serializer.
+    pub fn new(serializer: S) -> Self {
+        SerializerSink {
+            state: State::Start(serializer),
+            buffer: Vec::new(),
+            depth: 0,
+            key: None,
+        }
+    }
+
+    /// Whether or not a complete document has been serialized.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done(_))
+    }
+
+    /// Consumes the sink, returning the serializer's output.
+    pub fn finish(self) -> Result<S::Ok, SerializerSinkError<S::Error>> {
+        match self.state {
+            State::Done(ok) => Ok(ok),
+            _ => Err(SerializerSinkError::Malformed(malformed(
+                "incomplete document",
+            ))),
+        }
+    }
+
+    pub(crate) fn push(&mut self, token: Token<'a>) -> Result<(), SerializerSinkError<S::Error>> {
+        let role = role(&token);
+        if role == Role::Marker {
+            return Ok(());
+        }
+        match std::mem::replace(&mut self.state, State::Failed) {
+            State::Start(serializer) => match token {
+                Token::Seq { len } if self.buffer.is_empty() => {
+                    let seq = serializer.serialize_seq(len);
+                    self.state = State::Seq(seq.map_err(SerializerSinkError::Serializer)?);
+                    self.depth = 1;
+                    return Ok(());
+                }
+                Token::Map { len } if self.buffer.is_empty() => {
+                    let map = serializer.serialize_map(len);
+                    self.state = State::Map(map.map_err(SerializerSinkError::Serializer)?);
+                    self.depth = 1;
+                    return Ok(());
+                }
+                token => return self.buffer_document(serializer, token, role),
+            },
+            State::Done(_) | State::Failed => {
+                return Err(SerializerSinkError::Malformed(malformed(
+                    "unexpected token after end of document",
+                )))
+            }
+            state => self.state = state,
+        }
+
+        match role {
+            Role::Open => self.depth += 1,
+            Role::Close => self.depth -= 1,
+            _ => {}
+        }
+        if self.depth == 0 {
+            // the end of the document itself
+            let ok = match std::mem::replace(&mut self.state, State::Failed) {
+                State::Seq(seq) if token == Token::SeqEnd => seq.end(),
+                State::Map(map) if token == Token::MapEnd && self.key.is_none() => map.end(),
+                _ => return Err(unexpected_end()),
+            };
+            self.state = State::Done(ok.map_err(SerializerSinkError::Serializer)?);
+            return Ok(());
+        }
+
+        self.buffer.push(token);
+        if self.depth > 1 || role == Role::Prefix {
+            return Ok(());
+        }
+        let node = self.take_node()?;
+        let result = match (&mut self.state, self.key.take()) {
+            (State::Seq(seq), _) => seq.serialize_element(&node),
+            (State::Map(map), Some(key)) => map.serialize_entry(&key, &node),
+            _ => {
+                self.key = Some(node);
+                Ok(())
+            }
+        };
+        result.map_err(|err| {
+            self.state = State::Failed;
+            SerializerSinkError::Serializer(err)
+        })
+    }
+
+    // Buffers a document that is not streamed, serializing it once complete.
+    fn buffer_document(
+        &mut self,
+        serializer: S,
+        token: Token<'a>,
+        role: Role,
+    ) -> Result<(), SerializerSinkError<S::Error>> {
+        match role {
+            Role::Open => self.depth += 1,
+            Role::Close if self.depth == 0 => return Err(unexpected_end()),
+            Role::Close => self.depth -= 1,
+            _ => {}
+        }
+        self.buffer.push(token);
+        if self.depth > 0 || role == Role::Prefix {
+            self.state = State::Start(serializer);
+            return Ok(());
+        }
+
+        let node = self.take_node()?;
+        let ok = node.serialize(serializer);
+        self.state = State::Done(ok.map_err(SerializerSinkError::Serializer)?);
+        Ok(())
+    }
+
+    fn take_node(&mut self) -> Result<Node<'a>, SerializerSinkError<S::Error>> {
+        let mut tokens = std::mem::take(&mut self.buffer).into_iter();
+        Node::parse(&mut tokens)
+            .and_then(|node| node.ok_or_else(|| malformed("empty value")))
+            .map_err(SerializerSinkError::Malformed)
+    }
+}
+
+fn unexpected_end<E>() -> SerializerSinkError<E> {
+    SerializerSinkError::Malformed(malformed("unexpected end of container"))
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S: Serializer> Sink for SerializerSink<'a, S> {
+    type SinkItem = Token<'a>;
+    type SinkError = SerializerSinkError<S::Error>;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Self::SinkError> {
+        self.push(token)?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// An error returned by a [`SerializerSink`].
+///
+/// [`SerializerSink`]: struct.SerializerSink.html
+#[derive(Debug)]
+pub enum SerializerSinkError<E> {
+    /// The tokens did not form a single, well-formed document.
+    Malformed(Error),
+
+    /// The wrapped serializer failed.
+    Serializer(E),
+}
+
+impl<E: fmt::Display> fmt::Display for SerializerSinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerializerSinkError::Malformed(err) => write!(f, "malformed tokens: {}", err),
+            SerializerSinkError::Serializer(err) => write!(f, "serializer error: {}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for SerializerSinkError<E> {}
+
+impl<E: fmt::Debug + fmt::Display + Send + Sync + 'static> From<SerializerSinkError<E>> for Error {
+    fn from(err: SerializerSinkError<E>) -> Self {
+        Error::sink(err)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{tree::malformed, Error, Token};
+use std::{borrow::Cow, convert::TryFrom};
+
+/// Converts a [`serde_test::Token`] into a `Token`, so that existing `serde_test` expectations can be compared with tokenized output or fed to [`from_tokens`].
+///
+/// `serde_test` tokens don't record variant indices, so variant tokens are given a `variant_index` of `0` (deserializing them with [`from_tokens`] still works, as variants are identified by name). `BorrowedStr` and `BorrowedBytes` become borrowed `Str` and `Bytes` tokens.
+///
+/// *Requires the `serde_test` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{from_tokens, Token};
+///
+/// let tokens: Vec<Token> = vec![
+///     serde_test::Token::Seq { len: Some(1) },
+///     serde_test::Token::Str("a"),
+///     serde_test::Token::SeqEnd,
+/// ]
+/// .into_iter()
+/// .map(Token::from)
+/// .collect();
+///
+/// assert_eq!(from_tokens::<Vec<String>>(&tokens).unwrap(), vec!["a"]);
+/// ```
+///
+/// [`serde_test::Token`]: https://docs.rs/serde_test/1/serde_test/enum.Token.html
+/// [`from_tokens`]: fn.from_tokens.html
+impl From<serde_test::Token> for Token<'static> {
+    fn from(token: serde_test::Token) -> Self {
+        use serde_test::Token as T;
+        match token {
+            T::Bool(v) => Token::Bool(v),
+            T::I8(v) => Token::I8(v),
+            T::I16(v) => Token::I16(v),
+            T::I32(v) => Token::I32(v),
+            T::I64(v) => Token::I64(v),
+            T::U8(v) => Token::U8(v),
+            T::U16(v) => Token::U16(v),
+            T::U32(v) => Token::U32(v),
+            T::U64(v) => Token::U64(v),
+            T::F32(v) => Token::F32(v),
+            T::F64(v) => Token::F64(v),
+            T::Char(v) => Token::Char(v),
+            T::Str(v) | T::BorrowedStr(v) => Token::Str(Cow::Borrowed(v)),
+            T::String(v) => Token::String(v.to_string()),
+            T::Bytes(v) | T::BorrowedBytes(v) => Token::Bytes(Cow::Borrowed(v)),
+            T::ByteBuf(v) => Token::ByteBuf(v.to_vec()),
+            T::None => Token::None,
+            T::Some => Token::Some,
+            T::Unit => Token::Unit,
+            T::UnitStruct { name } => Token::UnitStruct { name },
+            T::UnitVariant { name, variant } => Token::UnitVariant {
+                name,
+                variant_index: 0,
+                variant,
+            },
+            T::NewtypeStruct { name } => Token::NewtypeStruct { name },
+            T::NewtypeVariant { name, variant } => Token::NewtypeVariant {
+                name,
+                variant_index: 0,
+                variant,
+            },
+            T::Seq { len } => Token::Seq { len },
+            T::SeqEnd => Token::SeqEnd,
+            T::Tuple { len } => Token::Tuple { len },
+            T::TupleEnd => Token::TupleEnd,
+            T::TupleStruct { name, len } => Token::TupleStruct { name, len },
+            T::TupleStructEnd => Token::TupleStructEnd,
+            T::TupleVariant { name, variant, len } => Token::TupleVariant {
+                name,
+                variant_index: 0,
+                variant,
+                len,
+            },
+            T::TupleVariantEnd => Token::TupleVariantEnd,
+            T::Map { len } => Token::Map { len },
+            T::MapEnd => Token::MapEnd,
+            T::Struct { name, len } => Token::Struct { name, len },
+            T::StructEnd => Token::StructEnd,
+            T::StructVariant { name, variant, len } => Token::StructVariant {
+                name,
+                variant_index: 0,
+                variant,
+                len,
+            },
+            T::StructVariantEnd => Token::StructVariantEnd,
+            T::Enum { name } => Token::Enum { name },
+        }
+    }
+}
+
+/// Converts a `Token` into a [`serde_test::Token`], so that tokenized output can be asserted against existing `serde_test` expectations.
+///
+/// As `serde_test` tokens only hold `'static` strings and bytes, string and byte payloads are leaked, which makes this conversion suitable only for tests. `Field`s become `Str`s and variant indices are dropped. Tokens with no `serde_test` equivalent (128-bit integers, `Number`s, chunks, markers and `Extension` headers) can't be converted.
+///
+/// *Requires the `serde_test` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{to_tokens, Token};
+/// use std::convert::TryFrom;
+///
+/// let tokens = to_tokens(&mut serde_json::Deserializer::from_str(r#" [1, "a"] "#)).unwrap();
+/// let tokens: Vec<_> = tokens
+///     .iter()
+///     .map(serde_test::Token::try_from)
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+///
+/// assert_eq!(tokens, [
+///     serde_test::Token::Seq { len: None },
+///     serde_test::Token::U64(1),
+///     serde_test::Token::Str("a"),
+///     serde_test::Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`serde_test::Token`]: https://docs.rs/serde_test/1/serde_test/enum.Token.html
+impl<'t, 'a> TryFrom<&'t Token<'a>> for serde_test::Token {
+    type Error = Error;
+
+    fn try_from(token: &'t Token<'a>) -> Result<Self, Error> {
+        use serde_test::Token as T;
+        Ok(match *token {
+            Token::Bool(v) => T::Bool(v),
+            Token::I8(v) => T::I8(v),
+            Token::I16(v) => T::I16(v),
+            Token::I32(v) => T::I32(v),
+            Token::I64(v) => T::I64(v),
+            Token::U8(v) => T::U8(v),
+            Token::U16(v) => T::U16(v),
+            Token::U32(v) => T::U32(v),
+            Token::U64(v) => T::U64(v),
+            Token::F32(v) => T::F32(v),
+            Token::F64(v) => T::F64(v),
+            Token::Char(v) => T::Char(v),
+            Token::Str(ref v) => T::Str(leak_str(v)),
+            Token::Field(v) => T::Str(leak_str(v)),
+            Token::String(ref v) => T::String(leak_str(v)),
+            Token::Interned(ref v) => T::String(leak_str(v)),
+            Token::Inline(ref v) => T::String(leak_str(v)),
+            Token::Bytes(ref v) => T::Bytes(leak_bytes(v)),
+            Token::ByteBuf(ref v) => T::ByteBuf(leak_bytes(v)),
+            Token::None => T::None,
+            Token::Some => T::Some,
+            Token::Unit => T::Unit,
+            Token::UnitStruct { name } => T::UnitStruct { name },
+            Token::UnitVariant { name, variant, .. } => T::UnitVariant { name, variant },
+            Token::NewtypeStruct { name } => T::NewtypeStruct { name },
+            Token::NewtypeVariant { name, variant, .. } => T::NewtypeVariant { name, variant },
+            Token::Seq { len } => T::Seq { len },
+            Token::SeqEnd => T::SeqEnd,
+            Token::Tuple { len } => T::Tuple { len },
+            Token::TupleEnd => T::TupleEnd,
+            Token::TupleStruct { name, len } => T::TupleStruct { name, len },
+            Token::TupleStructEnd => T::TupleStructEnd,
+            Token::TupleVariant {
+                name, variant, len, ..
+            } => T::TupleVariant { name, variant, len },
+            Token::TupleVariantEnd => T::TupleVariantEnd,
+            Token::Map { len } => T::Map { len },
+            Token::MapEnd => T::MapEnd,
+            Token::Struct { name, len } => T::Struct { name, len },
+            Token::StructEnd => T::StructEnd,
+            Token::StructVariant {
+                name, variant, len, ..
+            } => T::StructVariant { name, variant, len },
+            Token::StructVariantEnd => T::StructVariantEnd,
+            Token::Enum { name } => T::Enum { name },
+            ref token => {
+                return Err(malformed(&format!(
+                    "{:?} has no serde_test equivalent",
+                    token
+                )))
+            }
+        })
+    }
+}
+
+fn leak_str(v: &str) -> &'static str {
+    Box::leak(v.into())
+}
+
+fn leak_bytes(v: &[u8]) -> &'static [u8] {
+    Box::leak(v.into())
+}
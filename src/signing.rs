@@ -0,0 +1,222 @@
+use crate::{
+    sink::Forward,
+    wire::{Decoder, Encoder},
+    Error, Token,
+};
+use futures::{sink::Sink, try_ready, Async, AsyncSink, Poll, StartSend, Stream};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{collections::VecDeque, fmt};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The length in bytes of the HMAC-SHA256 tag appended to each signed frame.
+pub const TAG_LEN: usize = 32;
+
+/// A [`futures::Sink`] adapter that batches tokens into [wire-encoded] frames, appends an HMAC-SHA256 tag to each, and forwards them as byte buffers to the wrapped sink.
+///
+/// The first buffer forwarded is the (signed) stream header. Each tag covers the frame's sequence number as well as its bytes, so dropped, reordered or replayed frames fail verification. Any partial batch is sent when the sink is flushed or closed, and closing the sink also sends a signed terminal frame (with an empty body), so a stream truncated after any frame fails verification too.
+///
+/// *Requires the `signing` feature.*
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [wire-encoded]: wire/index.html
+pub struct SigningSink<'a, S: Sink<SinkItem = Vec<u8>>> {
+    mac: HmacSha256,
+    encoder: Encoder,
+    batch_size: usize,
+    batch: Vec<Token<'a>>,
+    seq: u64,
+    terminated: bool,
+    forward: Forward<S>,
+}
+
+impl<'a, S: Sink<SinkItem = Vec<u8>>> SigningSink<'a, S> {
+    /// Wraps a sink of byte buffers, signing frames of up to `batch_size` tokens with the given key.
+    pub fn new(sink: S, key: &[u8], batch_size: usize) -> Self {
+        let mut signing = SigningSink {
+            mac: HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            encoder: Encoder::default(),
+            batch_size: batch_size.max(1),
+            batch: Vec::new(),
+            seq: 0,
+            terminated: false,
+            forward: Forward::new(sink),
+        };
+        let mut header = Vec::new();
+        signing.encoder.encode_header(&mut header);
+        signing.sign_and_queue(header);
+        signing
+    }
+
+    fn sign_and_queue(&mut self, mut bytes: Vec<u8>) {
+        let tag = sign(&self.mac, self.seq, &bytes);
+        bytes.extend_from_slice(&tag);
+        self.seq += 1;
+        self.forward.extend(Some(bytes));
+    }
+
+    fn send_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let mut frame = Vec::new();
+        self.encoder.encode_frame(&self.batch, &mut frame);
+        self.batch.clear();
+        self.sign_and_queue(frame);
+    }
+}
+
+impl<'a, S: Sink<SinkItem = Vec<u8>>> Sink for SigningSink<'a, S> {
+    type SinkItem = Token<'a>;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, S::SinkError> {
+        self.batch.push(token);
+        if self.batch.len() >= self.batch_size {
+            self.send_batch();
+            self.forward.flush()?;
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.send_batch();
+        self.forward.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.send_batch();
+        if !self.terminated {
+            self.terminated = true;
+            self.sign_and_queue(Vec::new());
+        }
+        self.forward.close()
+    }
+}
+
+/// A [`futures::Stream`] adapter that verifies and decodes the signed frames produced by a [`SigningSink`], yielding owned tokens.
+///
+/// The wrapped stream must end with the terminal frame sent when the `SigningSink` was closed; ending without it (or sending frames after it) is an error.
+///
+/// *Requires the `signing` feature.*
+///
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+/// [`SigningSink`]: struct.SigningSink.html
+pub struct VerifyingStream<St> {
+    stream: St,
+    mac: HmacSha256,
+    decoder: Option<Decoder>,
+    seq: u64,
+    terminated: bool,
+    tokens: VecDeque<Token<'static>>,
+}
+
+impl<St: Stream<Item = Vec<u8>>> VerifyingStream<St> {
+    /// Wraps a stream of signed byte buffers, verifying them with the given key.
+    pub fn new(stream: St, key: &[u8]) -> Self {
+        VerifyingStream {
+            stream,
+            mac: HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            decoder: None,
+            seq: 0,
+            terminated: false,
+            tokens: VecDeque::new(),
+        }
+    }
+
+    fn verify<'b>(&mut self, bytes: &'b [u8]) -> Result<&'b [u8], Error> {
+        if bytes.len() < TAG_LEN {
+            return Err(Error::WireError("signed frame is too short".to_string()));
+        }
+        let (body, tag) = bytes.split_at(bytes.len() - TAG_LEN);
+        let mut mac = self.mac.clone();
+        mac.update(&self.seq.to_le_bytes());
+        mac.update(body);
+        mac.verify_slice(tag)
+            .map_err(|_| Error::WireError(format!("invalid signature on frame {}", self.seq)))?;
+        self.seq += 1;
+        Ok(body)
+    }
+
+    fn receive(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let body = self.verify(bytes)?;
+        if self.terminated {
+            return Err(Error::WireError(
+                "signed frame after the terminal frame".to_string(),
+            ));
+        }
+        let incomplete = || Error::WireError("incomplete signed frame".to_string());
+        match &mut self.decoder {
+            None => {
+                let (decoder, n) = Decoder::from_header(body)?.ok_or_else(incomplete)?;
+                if n != body.len() {
+                    return Err(incomplete());
+                }
+                self.decoder = Some(decoder);
+            }
+            Some(_) if body.is_empty() => self.terminated = true,
+            Some(decoder) => {
+                let (tokens, n) = decoder.decode_frame(body)?.ok_or_else(incomplete)?;
+                if n != body.len() {
+                    return Err(incomplete());
+                }
+                self.tokens
+                    .extend(tokens.into_iter().map(Token::into_owned));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<St: Stream<Item = Vec<u8>>> Stream for VerifyingStream<St> {
+    type Item = Token<'static>;
+    type Error = VerifyError<St::Error>;
+
+    fn poll(&mut self) -> Poll<Option<Token<'static>>, Self::Error> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Ok(Async::Ready(Some(token)));
+            }
+            match try_ready!(self.stream.poll().map_err(VerifyError::Stream)) {
+                Some(bytes) => self.receive(&bytes).map_err(VerifyError::Invalid)?,
+                None if self.terminated => return Ok(Async::Ready(None)),
+                None => {
+                    return Err(VerifyError::Invalid(Error::WireError(
+                        "signed stream ended without its terminal frame".to_string(),
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// An error returned by a [`VerifyingStream`].
+///
+/// [`VerifyingStream`]: struct.VerifyingStream.html
+#[derive(Debug)]
+pub enum VerifyError<E> {
+    /// A frame failed verification or could not be decoded.
+    Invalid(Error),
+
+    /// The wrapped stream failed.
+    Stream(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for VerifyError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::Invalid(err) => write!(f, "invalid frame: {}", err),
+            VerifyError::Stream(err) => write!(f, "stream error: {:?}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for VerifyError<E> {}
+
+fn sign(mac: &HmacSha256, seq: u64, bytes: &[u8]) -> Vec<u8> {
+    let mut mac = mac.clone();
+    mac.update(&seq.to_le_bytes());
+    mac.update(bytes);
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,350 @@
+use crate::{Error, Token, TokenSink};
+#[cfg(feature = "futures01")]
+use futures::{sink::Sink, try_ready, Async, AsyncSink, Poll, StartSend};
+#[cfg(feature = "futures01")]
+use std::collections::VecDeque;
+use std::sync::mpsc::{Sender, SyncSender};
+
+/// Forwards items to a wrapped sink, queueing any the sink is not yet ready to accept.
+#[cfg(feature = "futures01")]
+#[derive(Debug)]
+pub(crate) struct Forward<S: Sink> {
+    sink: S,
+    pending: VecDeque<S::SinkItem>,
+}
+
+#[cfg(feature = "futures01")]
+impl<S: Sink> Forward<S> {
+    pub(crate) fn new(sink: S) -> Self {
+        Forward {
+            sink,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the wrapped sink, dropping any queued items.
+    pub(crate) fn into_inner(self) -> S {
+        self.sink
+    }
+
+    /// Returns the number of queued items.
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub(crate) fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Queues several items to be sent to the wrapped sink.
+    pub(crate) fn extend<I: IntoIterator<Item = S::SinkItem>>(&mut self, items: I) {
+        self.pending.extend(items);
+    }
+
+    /// Sends as many queued items to the wrapped sink as it will accept, returning whether or not the queue was emptied.
+    pub(crate) fn flush(&mut self) -> Result<bool, S::SinkError> {
+        while let Some(item) = self.pending.pop_front() {
+            if let AsyncSink::NotReady(item) = self.sink.start_send(item)? {
+                self.pending.push_front(item);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        let flushed = self.flush()?;
+        try_ready!(self.sink.poll_complete());
+        if flushed {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    pub(crate) fn close(&mut self) -> Poll<(), S::SinkError> {
+        try_ready!(self.poll_complete());
+        self.sink.close()
+    }
+}
+
+/// A [`futures::Sink`] adapter that buffers up to a maximum number of items while the wrapped sink is not ready, retrying them as later items are sent.
+///
+/// Passing an `OverflowSink` to [`tokenize`] lets it ride out a sink that is briefly not ready, instead of immediately failing with `Error::TokenSinkNotReadyError`; it only fails once the buffer is full. Any tokens still buffered afterwards can be sent with `send_buffered` once the wrapped sink has been drained.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{sync::mpsc::channel, Future, Stream};
+/// use serde_token::{tokenize, OverflowSink, Token};
+///
+/// let (token_sink, token_stream) = channel::<Token>(0);
+/// let mut sink = OverflowSink::new(token_sink, 16);
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// futures::future::lazy(|| tokenize(&mut de, &mut sink)).wait().unwrap();
+/// assert_eq!(sink.buffered(), 2);
+///
+/// let mut token_stream = token_stream.wait();
+/// let mut tokens = Vec::new();
+/// while sink.buffered() > 0 || tokens.len() < 3 {
+///     tokens.push(token_stream.next().unwrap().unwrap());
+///     futures::future::lazy(|| sink.send_buffered()).wait().unwrap();
+/// }
+///
+/// assert_eq!(tokens, vec![Token::Seq { len: None }, Token::Bool(true), Token::SeqEnd]);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`tokenize`]: fn.tokenize.html
+#[cfg(feature = "futures01")]
+pub struct OverflowSink<S: Sink> {
+    forward: Forward<S>,
+    max: usize,
+}
+
+#[cfg(feature = "futures01")]
+impl<S: Sink> OverflowSink<S> {
+    /// Creates a new `OverflowSink`, buffering at most `max` items.
+    pub fn new(sink: S, max: usize) -> Self {
+        OverflowSink {
+            forward: Forward::new(sink),
+            max,
+        }
+    }
+
+    /// Returns the number of buffered items.
+    pub fn buffered(&self) -> usize {
+        self.forward.len()
+    }
+
+    /// Sends as many buffered items to the wrapped sink as it will accept, returning whether or not the buffer was emptied.
+    pub fn send_buffered(&mut self) -> Result<bool, S::SinkError> {
+        self.forward.flush()
+    }
+
+    /// Gets a reference to the wrapped sink.
+    pub fn get_ref(&self) -> &S {
+        self.forward.get_ref()
+    }
+
+    /// Gets a mutable reference to the wrapped sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.forward.get_mut()
+    }
+
+    /// Consumes the `OverflowSink`, returning the wrapped sink and dropping any buffered items.
+    pub fn into_inner(self) -> S {
+        self.forward.into_inner()
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<S: Sink> Sink for OverflowSink<S> {
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: S::SinkItem) -> StartSend<S::SinkItem, S::SinkError> {
+        if self.forward.len() >= self.max && !self.forward.flush()? {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        self.forward.extend(Some(item));
+        self.forward.flush()?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.forward.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.forward.close()
+    }
+}
+
+/// A [`futures::Sink`] adapter that collects items into batches of a fixed size, sending each batch to the wrapped sink as a single `Vec`, to amortize the per-item cost of sinks such as channels over many tokens.
+///
+/// A partial batch is sent when the sink is flushed, such as once [`tokenize`] has written a value. If the wrapped sink is not ready for a full batch, the `BatchSink` is not ready for any more items until it is.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize, BatchSink, Token};
+///
+/// let (batch_sink, batch_stream) = unbounded::<Vec<Token>>();
+/// let mut de = serde_json::Deserializer::from_str(r#" [1, 2, 3] "#);
+/// tokenize(&mut de, BatchSink::new(batch_sink, 2)).unwrap();
+///
+/// let batches = batch_stream.collect().wait().unwrap();
+/// assert_eq!(batches, vec![
+///     vec![Token::Seq { len: None }, Token::U64(1)],
+///     vec![Token::U64(2), Token::U64(3)],
+///     vec![Token::SeqEnd],
+/// ]);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`tokenize`]: fn.tokenize.html
+#[cfg(feature = "futures01")]
+#[derive(Debug)]
+pub struct BatchSink<S, T> {
+    sink: S,
+    batch: Vec<T>,
+    batch_size: usize,
+}
+
+#[cfg(feature = "futures01")]
+impl<S, T> BatchSink<S, T>
+where
+    S: Sink<SinkItem = Vec<T>>,
+{
+    /// Creates a new `BatchSink`, sending batches of `batch_size` items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero.
+    pub fn new(sink: S, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be non-zero");
+        BatchSink {
+            sink,
+            batch: Vec::with_capacity(batch_size),
+            batch_size,
+        }
+    }
+
+    /// Returns the number of items waiting for the current batch to be sent.
+    pub fn buffered(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Gets a reference to the wrapped sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Gets a mutable reference to the wrapped sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Consumes the `BatchSink`, returning the wrapped sink and dropping any unsent items.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    // Sends the current batch, if any, returning whether or not the wrapped sink accepted it.
+    fn send_batch(&mut self) -> Result<bool, S::SinkError> {
+        if self.batch.is_empty() {
+            return Ok(true);
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size));
+        match self.sink.start_send(batch)? {
+            AsyncSink::Ready => Ok(true),
+            AsyncSink::NotReady(batch) => {
+                self.batch = batch;
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<S, T> Sink for BatchSink<S, T>
+where
+    S: Sink<SinkItem = Vec<T>>,
+{
+    type SinkItem = T;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: T) -> StartSend<T, S::SinkError> {
+        if self.batch.len() >= self.batch_size && !self.send_batch()? {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        self.batch.push(item);
+        if self.batch.len() >= self.batch_size {
+            self.send_batch()?;
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        if !self.send_batch()? {
+            return Ok(Async::NotReady);
+        }
+        self.sink.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        try_ready!(self.poll_complete());
+        self.sink.close()
+    }
+}
+
+/// A [`TokenSink`] that sends owned `Token`s over a standard library channel, either a `Sender` or a (blocking) `SyncSender`.
+///
+/// With the `tokio` feature, `tokio` channel senders (bounded and unbounded) are also supported.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize, ChannelSink, Token};
+/// use std::{sync::mpsc::sync_channel, thread};
+///
+/// let (tx, rx) = sync_channel::<Token<'static>>(1);
+/// let consumer = thread::spawn(move || rx.iter().collect::<Vec<_>>());
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" ["a"] "#);
+/// tokenize(&mut de, ChannelSink::new(tx)).unwrap();
+///
+/// assert_eq!(consumer.join().unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::String("a".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+#[derive(Clone, Debug)]
+pub struct ChannelSink<T>(T);
+
+impl<T> ChannelSink<T> {
+    /// Wraps the given `Sender` or `SyncSender`.
+    pub fn new(sender: T) -> Self {
+        ChannelSink(sender)
+    }
+
+    /// Gets a reference to the wrapped sender.
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Consumes the `ChannelSink`, returning the wrapped sender.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<'de> TokenSink<'de> for ChannelSink<Sender<Token<'static>>> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        self.0.send(token.into_owned()).map_err(Error::sink)
+    }
+}
+
+impl<'de> TokenSink<'de> for ChannelSink<SyncSender<Token<'static>>> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        self.0.send(token.into_owned()).map_err(Error::sink)
+    }
+}
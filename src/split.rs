@@ -0,0 +1,163 @@
+use crate::{PathTracker, Token};
+#[cfg(feature = "futures01")]
+use futures::{try_ready, Async, Poll, Stream};
+use std::mem;
+
+/// Splits a token stream of many documents into the tokens of each document, one token at a time.
+///
+/// Documents are delimited by `Token::DocumentStart` and `Token::DocumentEnd` markers (such as those written by [`tokenize_many`]), which are dropped, or else each complete top-level value is a document of its own. Either way, each document can be deserialized or processed independently of the others.
+///
+/// [`tokenize_many`]: fn.tokenize_many.html
+#[derive(Clone, Debug, Default)]
+pub struct DocumentSplitter<'a> {
+    tracker: PathTracker,
+    tokens: Vec<Token<'a>>,
+    marked: bool,
+}
+
+impl<'a> DocumentSplitter<'a> {
+    /// Creates a splitter positioned before the first document.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances past the given token, returning the tokens of a document if the token completes it.
+    pub fn push(&mut self, token: Token<'a>) -> Option<Vec<Token<'a>>> {
+        match token {
+            Token::DocumentStart => {
+                self.marked = true;
+                None
+            }
+            Token::DocumentEnd => {
+                self.marked = false;
+                self.take()
+            }
+            token => {
+                let step = self.tracker.observe(&token);
+                self.tokens.push(token);
+                if !self.marked && step.ends_value && self.tracker.depth() == 0 {
+                    return self.take();
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns the tokens of an incomplete final document, if any, such as when the stream was cut off mid-document.
+    pub fn finish(&mut self) -> Option<Vec<Token<'a>>> {
+        self.marked = false;
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<Vec<Token<'a>>> {
+        self.tracker = PathTracker::new();
+        if self.tokens.is_empty() {
+            return None;
+        }
+        Some(mem::take(&mut self.tokens))
+    }
+}
+
+/// Splits a token stream of many documents into an iterator of the tokens of each document.
+///
+/// See [`DocumentSplitter`] for how documents are delimited. An incomplete final document is yielded as is.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{split_documents, Token};
+///
+/// let tokens = vec![
+///     Token::DocumentStart,
+///     Token::Seq { len: None },
+///     Token::SeqEnd,
+///     Token::DocumentEnd,
+///     Token::Bool(true),
+///     Token::Some,
+///     Token::U8(1),
+/// ];
+///
+/// assert_eq!(split_documents(tokens).collect::<Vec<_>>(), vec![
+///     vec![Token::Seq { len: None }, Token::SeqEnd],
+///     vec![Token::Bool(true)],
+///     vec![Token::Some, Token::U8(1)],
+/// ]);
+/// ```
+///
+/// [`DocumentSplitter`]: struct.DocumentSplitter.html
+pub fn split_documents<'a, I>(tokens: I) -> SplitDocuments<'a, I::IntoIter>
+where
+    I: IntoIterator<Item = Token<'a>>,
+{
+    SplitDocuments {
+        inner: tokens.into_iter(),
+        splitter: DocumentSplitter::new(),
+    }
+}
+
+/// Adapts a [`futures::Stream`] of `Token`s into a stream of the tokens of each document.
+///
+/// See [`split_documents`] for details.
+///
+/// *Requires the `futures01` feature.*
+///
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+/// [`split_documents`]: fn.split_documents.html
+#[cfg(feature = "futures01")]
+pub fn split_document_stream<'a, S>(stream: S) -> SplitDocuments<'a, S>
+where
+    S: Stream<Item = Token<'a>>,
+{
+    SplitDocuments {
+        inner: stream,
+        splitter: DocumentSplitter::new(),
+    }
+}
+
+/// An iterator (or, with the `futures01` feature, a stream) of the tokens of each document of a token stream, created by [`split_documents`] or [`split_document_stream`].
+///
+/// [`split_documents`]: fn.split_documents.html
+/// [`split_document_stream`]: fn.split_document_stream.html
+#[derive(Debug)]
+pub struct SplitDocuments<'a, S> {
+    inner: S,
+    splitter: DocumentSplitter<'a>,
+}
+
+impl<'a, I> Iterator for SplitDocuments<'a, I>
+where
+    I: Iterator<Item = Token<'a>>,
+{
+    type Item = Vec<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for token in &mut self.inner {
+            if let Some(document) = self.splitter.push(token) {
+                return Some(document);
+            }
+        }
+        self.splitter.finish()
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S> Stream for SplitDocuments<'a, S>
+where
+    S: Stream<Item = Token<'a>>,
+{
+    type Item = Vec<Token<'a>>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, S::Error> {
+        loop {
+            match try_ready!(self.inner.poll()) {
+                Some(token) => {
+                    if let Some(document) = self.splitter.push(token) {
+                        return Ok(Async::Ready(Some(document)));
+                    }
+                }
+                None => return Ok(Async::Ready(self.splitter.finish())),
+            }
+        }
+    }
+}
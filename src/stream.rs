@@ -0,0 +1,300 @@
+use crate::{tokenize, Error, OwnedToken, Token};
+use futures::{
+    sink::{Sink, Wait},
+    stream,
+    sync::mpsc::{channel, Receiver, Sender},
+    Async, AsyncSink, Poll, StartSend, Stream,
+};
+use serde::de;
+use std::{
+    fmt,
+    marker::PhantomData,
+    thread::{self, JoinHandle},
+};
+
+/// A [`futures::Stream`] of owned `Token`s, lazily produced from a deserializer as the stream is polled.
+///
+/// The deserializer is driven on its own thread, which blocks once a bounded number of tokens (1024 by default) are waiting to be polled, so a huge document is never buffered in full. Dropping the stream stops the tokenization at its next token.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{Future, Stream};
+/// use serde_token::{Token, TokenStream};
+///
+/// let json = r#" {"a": [true]} "#.as_bytes();
+/// let de = serde_json::Deserializer::from_reader(json);
+///
+/// let expected = TokenStream::with_capacity(de, 1).collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Map { len: None },
+///     Token::String("a".to_string()),
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ]);
+/// ```
+///
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+pub struct TokenStream {
+    tokens: Receiver<Result<Token<'static>, Error>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TokenStream {
+    /// Creates a stream of the tokens of the given deserializer.
+    pub fn new<D>(deserializer: D) -> Self
+    where
+        D: Send + 'static,
+        for<'a> &'a mut D: de::Deserializer<'a>,
+    {
+        Self::with_capacity(deserializer, 1024)
+    }
+
+    /// Creates a stream of the tokens of the given deserializer, tokenizing at most `capacity` tokens ahead of the consumer.
+    pub fn with_capacity<D>(mut deserializer: D, capacity: usize) -> Self
+    where
+        D: Send + 'static,
+        for<'a> &'a mut D: de::Deserializer<'a>,
+    {
+        Self::spawn(capacity, move |sink| tokenize(&mut deserializer, sink))
+    }
+
+    /// Runs the given function on its own thread, streaming the tokens it writes to the provided sink.
+    fn spawn<F>(capacity: usize, f: F) -> Self
+    where
+        F: for<'a> FnOnce(SpawnSink<'a>) -> Result<(), Error> + Send + 'static,
+    {
+        let (tx, tokens) = channel(capacity.saturating_sub(1));
+        let thread = thread::spawn(move || {
+            let errors = tx.clone();
+            if let Err(err) = f(SpawnSink(tx.wait(), PhantomData)) {
+                // Fails only if the stream has been dropped.
+                let _ = errors.wait().send(Err(err));
+            }
+        });
+
+        TokenStream {
+            tokens,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Runs a tokenization on a worker thread, returning a [`TokenStream`] of the owned tokens it produces.
+///
+/// The function is given a [`SpawnSink`] to tokenize into, typically by calling [`tokenize`] with a deserializer over input moved into the function, so neither the input nor the borrowed tokens need to outlive the worker. The worker blocks once 1024 tokens are waiting to be polled, and any error it returns is yielded by the stream after the tokens written before it.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{Future, Stream};
+/// use serde_token::{spawn_tokenize, tokenize, Token};
+/// use std::thread;
+///
+/// let json = br#" ["a", 1] "#.to_vec();
+/// let tokens = spawn_tokenize(move |sink| {
+///     tokenize(&mut serde_json::Deserializer::from_slice(&json), sink)
+/// });
+///
+/// let consumer = thread::spawn(move || tokens.collect().wait().unwrap());
+/// assert_eq!(consumer.join().unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::String("a".to_string()),
+///     Token::U64(1),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`TokenStream`]: struct.TokenStream.html
+/// [`SpawnSink`]: struct.SpawnSink.html
+/// [`tokenize`]: fn.tokenize.html
+pub fn spawn_tokenize<F>(f: F) -> TokenStream
+where
+    F: for<'a> FnOnce(SpawnSink<'a>) -> Result<(), Error> + Send + 'static,
+{
+    TokenStream::spawn(1024, f)
+}
+
+impl Stream for TokenStream {
+    type Item = Token<'static>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Token<'static>>, Error> {
+        match self.tokens.poll() {
+            Ok(Async::Ready(Some(Ok(token)))) => Ok(Async::Ready(Some(token))),
+            Ok(Async::Ready(Some(Err(err)))) => Err(err),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(None)) | Err(()) => match self.thread.take().map(JoinHandle::join) {
+                Some(Err(_)) => Err(Error::TokenizerError(
+                    "tokenizer thread panicked".to_string(),
+                )),
+                _ => Ok(Async::Ready(None)),
+            },
+        }
+    }
+}
+
+/// The `Send` [`futures::Sink`] given to the function passed to [`spawn_tokenize`], which converts each token into an [`OwnedToken`] and blocks the worker thread until the [`TokenStream`] has room for it.
+///
+/// *Requires the `futures01` feature.*
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`spawn_tokenize`]: fn.spawn_tokenize.html
+/// [`OwnedToken`]: type.OwnedToken.html
+/// [`TokenStream`]: struct.TokenStream.html
+pub struct SpawnSink<'a>(
+    Wait<Sender<Result<OwnedToken, Error>>>,
+    PhantomData<Token<'a>>,
+);
+
+impl<'a> Sink for SpawnSink<'a> {
+    type SinkItem = Token<'a>;
+    type SinkError = Error;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Error> {
+        self.0.send(Ok(token.into_owned()))?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// A resumable tokenization of a deserializer into a [`futures::Sink`], performed a bounded number of tokens at a time.
+///
+/// The deserializer is driven by a [`TokenStream`], so it only runs a bounded number of tokens ahead of the sink while the tokenization is paused.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{sync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{Token, Tokenization};
+///
+/// let json = r#" [1, 2, 3] "#.as_bytes();
+/// let (token_sink, token_stream) = unbounded::<Token<'static>>();
+/// let mut tokenization = Tokenization::new(serde_json::Deserializer::from_reader(json), token_sink);
+///
+/// assert!(!tokenization.step(2).unwrap());
+/// assert_eq!(tokenization.emitted(), 2);
+/// while !tokenization.step(2).unwrap() {}
+/// drop(tokenization);
+///
+/// assert_eq!(token_stream.collect().wait().unwrap().len(), 5);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`TokenStream`]: struct.TokenStream.html
+pub struct Tokenization<S> {
+    tokens: stream::Wait<TokenStream>,
+    sink: S,
+    pending: Option<Token<'static>>,
+    emitted: usize,
+    done: bool,
+}
+
+impl<S: Sink<SinkItem = Token<'static>>> Tokenization<S> {
+    /// Creates a paused tokenization of the given deserializer into the given sink.
+    pub fn new<D>(deserializer: D, sink: S) -> Self
+    where
+        D: Send + 'static,
+        for<'a> &'a mut D: de::Deserializer<'a>,
+    {
+        Tokenization {
+            tokens: TokenStream::new(deserializer).wait(),
+            sink,
+            pending: None,
+            emitted: 0,
+            done: false,
+        }
+    }
+
+    /// Sends up to `n` more tokens to the sink, returning whether or not the tokenization has finished.
+    ///
+    /// Fewer tokens are sent if the sink is not ready to accept them, in which case the rejected token is retried by the next step.
+    pub fn step(&mut self, n: usize) -> Result<bool, TokenizationError<S::SinkError>> {
+        for _ in 0..n {
+            let token = match self.pending.take() {
+                Some(token) => token,
+                None => match self.next_token()? {
+                    Some(token) => token,
+                    None => break,
+                },
+            };
+            if let AsyncSink::NotReady(token) = self
+                .sink
+                .start_send(token)
+                .map_err(TokenizationError::Sink)?
+            {
+                self.pending = Some(token);
+                return Ok(false);
+            }
+            self.emitted += 1;
+        }
+
+        if self.pending.is_none() && !self.done {
+            self.pending = self.next_token()?;
+        }
+        Ok(self.done)
+    }
+
+    /// The number of tokens sent to the sink so far.
+    pub fn emitted(&self) -> usize {
+        self.emitted
+    }
+
+    /// Whether or not every token has been sent to the sink.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Consumes the tokenization, returning the sink and stopping the deserializer at its next token.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'static>>, TokenizationError<S::SinkError>> {
+        match self.tokens.next() {
+            Some(Ok(token)) => Ok(Some(token)),
+            Some(Err(err)) => {
+                self.done = true;
+                Err(TokenizationError::Tokenize(err))
+            }
+            None => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// An error returned by a [`Tokenization`].
+///
+/// [`Tokenization`]: struct.Tokenization.html
+#[derive(Debug)]
+pub enum TokenizationError<E> {
+    /// Tokenizing the deserializer failed.
+    Tokenize(Error),
+
+    /// The sink failed.
+    Sink(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for TokenizationError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenizationError::Tokenize(err) => write!(f, "tokenize error: {}", err),
+            TokenizationError::Sink(err) => write!(f, "sink error: {:?}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for TokenizationError<E> {}
@@ -0,0 +1,193 @@
+use crate::{Error, Token};
+use futures::{sink::Sink, AsyncSink, Poll, StartSend};
+use std::{collections::HashMap, fmt};
+
+/// A [`futures::Sink`] adapter that expands `${VAR}`-style placeholders within string tokens before forwarding them to the wrapped sink.
+///
+/// Placeholders are expanded within `Str` and `String` tokens:
+///
+/// - `${NAME}` is replaced with the value of `NAME`,
+/// - `${NAME:-default}` is replaced with the value of `NAME`, or `default` if `NAME` is undefined,
+/// - `$$` is an escaped, literal `$`.
+///
+/// Any other `$` (including an unterminated `${`) is left as-is. Tokens without placeholders are forwarded unchanged, and expanded tokens are forwarded as owned `String`s. Undefined variables without a default are left verbatim, unless the adapter is made `strict`.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize, Substitute, Token};
+/// use std::collections::HashMap;
+///
+/// let mut vars = HashMap::new();
+/// vars.insert("HOST".to_string(), "localhost".to_string());
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" ["${HOST}:${PORT:-80}", "$$5"] "#);
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize(&mut de, Substitute::with_map(token_sink, vars)).unwrap();
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Seq { len: None },
+///     Token::String("localhost:80".to_string()),
+///     Token::String("$5".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+#[derive(Clone, Debug)]
+pub struct Substitute<S, F> {
+    sink: S,
+    lookup: F,
+    strict: bool,
+}
+
+impl<S, F> Substitute<S, F>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    /// Wraps a sink, resolving variables with the given lookup function.
+    pub fn new(sink: S, lookup: F) -> Self {
+        Substitute {
+            sink,
+            lookup,
+            strict: false,
+        }
+    }
+
+    /// Fails with `SubstituteError::Undefined` when a variable is undefined and has no default.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Consumes the adapter, returning the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    fn expand(&mut self, input: &str) -> Result<Option<String>, String> {
+        if !input.contains('$') {
+            return Ok(None);
+        }
+
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(i) = rest.find('$') {
+            output.push_str(&rest[..i]);
+            rest = &rest[i..];
+
+            if rest.starts_with("$$") {
+                output.push('$');
+                rest = &rest[2..];
+                continue;
+            }
+
+            let end = match rest.find('}') {
+                Some(end) if rest.starts_with("${") => end,
+                _ => {
+                    output.push('$');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+
+            let placeholder = &rest[2..end];
+            let (name, default) = match placeholder.find(":-") {
+                Some(i) => (&placeholder[..i], Some(&placeholder[i + 2..])),
+                None => (placeholder, None),
+            };
+            match ((self.lookup)(name), default) {
+                (Some(value), _) => output.push_str(&value),
+                (None, Some(default)) => output.push_str(default),
+                (None, None) if self.strict => return Err(name.to_string()),
+                (None, None) => output.push_str(&rest[..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        output.push_str(rest);
+        Ok(Some(output))
+    }
+}
+
+impl<S> Substitute<S, Box<dyn FnMut(&str) -> Option<String>>> {
+    /// Wraps a sink, resolving variables from the given map.
+    pub fn with_map(sink: S, vars: HashMap<String, String>) -> Self {
+        Self::new(sink, Box::new(move |name| vars.get(name).cloned()))
+    }
+
+    /// Wraps a sink, resolving variables from the process environment.
+    pub fn with_env(sink: S) -> Self {
+        Self::new(sink, Box::new(|name| std::env::var(name).ok()))
+    }
+}
+
+impl<'a, S, F> Sink for Substitute<S, F>
+where
+    S: Sink<SinkItem = Token<'a>>,
+    F: FnMut(&str) -> Option<String>,
+{
+    type SinkItem = Token<'a>;
+    type SinkError = SubstituteError<S::SinkError>;
+
+    fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Self::SinkError> {
+        let expanded = match &token {
+            Token::Str(string) => self.expand(string),
+            Token::String(string) => self.expand(string),
+            Token::Interned(string) => self.expand(string),
+            Token::Inline(string) => self.expand(string),
+            _ => Ok(None),
+        };
+        let string = match expanded.map_err(SubstituteError::Undefined)? {
+            Some(string) => string,
+            None => return self.sink.start_send(token).map_err(SubstituteError::Sink),
+        };
+        match self.sink.start_send(Token::String(string)) {
+            Ok(AsyncSink::Ready) => Ok(AsyncSink::Ready),
+            // the unexpanded token is handed back, so that it's expanded once when retried
+            Ok(AsyncSink::NotReady(_)) => Ok(AsyncSink::NotReady(token)),
+            Err(err) => Err(SubstituteError::Sink(err)),
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.sink.poll_complete().map_err(SubstituteError::Sink)
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.sink.close().map_err(SubstituteError::Sink)
+    }
+}
+
+/// An error returned by a [`Substitute`] sink.
+///
+/// [`Substitute`]: struct.Substitute.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubstituteError<E> {
+    /// A variable was undefined and had no default.
+    Undefined(String),
+
+    /// The wrapped sink failed.
+    Sink(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for SubstituteError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubstituteError::Undefined(name) => write!(f, "undefined variable: {}", name),
+            SubstituteError::Sink(err) => write!(f, "sink error: {:?}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for SubstituteError<E> {}
+
+impl<E: fmt::Debug + Send + Sync + 'static> From<SubstituteError<E>> for Error {
+    fn from(err: SubstituteError<E>) -> Self {
+        Error::sink(err)
+    }
+}
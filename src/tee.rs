@@ -0,0 +1,302 @@
+use crate::{Error, Token, TokenSink};
+#[cfg(feature = "futures01")]
+use futures::{
+    task::{self, Task},
+    Async, Poll, Stream,
+};
+#[cfg(feature = "futures01")]
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// How a [`TeeSink`] handles a failing sink.
+///
+/// [`TeeSink`]: struct.TeeSink.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TeePolicy {
+    /// The first error from any sink is returned immediately, without writing the token to the sinks after it.
+    #[default]
+    FailFast,
+    /// A failing sink is detached, its error kept in [`TeeSink::errors`], and the other sinks carry on; an error is only returned when the last sink fails.
+    ///
+    /// [`TeeSink::errors`]: struct.TeeSink.html#method.errors
+    BestEffort,
+}
+
+/// A [`TokenSink`] that writes (a clone of) each token to every one of a number of sinks, such as to hash, validate and transcode a value in a single pass of tokenization.
+///
+/// Sinks are written to in the order they were added, and each sink's error is converted into an `Error`. Flushing or finishing the tee flushes or finishes each sink.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize, ChannelSink, TeePolicy, TeeSink, Token};
+/// use std::sync::mpsc::channel;
+///
+/// let (tx1, rx1) = channel::<Token<'static>>();
+/// let (tx2, rx2) = channel::<Token<'static>>();
+/// let tee = TeeSink::new()
+///     .sink(ChannelSink::new(tx1))
+///     .sink(ChannelSink::new(tx2))
+///     .policy(TeePolicy::BestEffort);
+///
+/// drop(rx1);
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// tokenize(&mut de, tee).unwrap();
+///
+/// assert_eq!(rx2.try_iter().collect::<Vec<_>>(), vec![
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+pub struct TeeSink<'s, 'de> {
+    sinks: Vec<Option<Box<dyn ErasedSink<'de> + 's>>>,
+    policy: TeePolicy,
+    errors: Vec<(usize, Error)>,
+}
+
+impl<'s, 'de> TeeSink<'s, 'de> {
+    /// Creates a tee without any sinks, with the `FailFast` policy.
+    pub fn new() -> Self {
+        TeeSink {
+            sinks: Vec::new(),
+            policy: TeePolicy::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Adds a sink to write tokens to, after any added before.
+    pub fn sink<S>(mut self, sink: S) -> Self
+    where
+        S: TokenSink<'de> + 's,
+    {
+        self.sinks.push(Some(Box::new(sink)));
+        self
+    }
+
+    /// Sets how a failing sink is handled. Defaults to `TeePolicy::FailFast`.
+    pub fn policy(mut self, policy: TeePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The errors of the sinks detached under the `BestEffort` policy, along with the index of each sink (in the order they were added).
+    pub fn errors(&self) -> &[(usize, Error)] {
+        &self.errors
+    }
+
+    fn each<F>(&mut self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut dyn ErasedSink<'de>, bool) -> Result<(), Error>,
+    {
+        let last = self.sinks.iter().rposition(Option::is_some);
+        for i in 0..self.sinks.len() {
+            let sink = match &mut self.sinks[i] {
+                Some(sink) => sink,
+                None => continue,
+            };
+            let err = match f(&mut **sink, Some(i) == last) {
+                Ok(()) => continue,
+                Err(err) => err,
+            };
+            if self.policy == TeePolicy::FailFast || self.sinks.iter().flatten().count() == 1 {
+                return Err(err);
+            }
+            self.sinks[i] = None;
+            self.errors.push((i, err));
+        }
+        Ok(())
+    }
+}
+
+impl<'s, 'de> Default for TeeSink<'s, 'de> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'s, 'de> TokenSink<'de> for TeeSink<'s, 'de> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        let mut token = Some(token);
+        self.each(|sink, last| {
+            let token = match last {
+                true => token.take(),
+                false => token.clone(),
+            };
+            sink.write(token.expect("a token for each sink"))
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.each(|sink, _| sink.flush())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.each(|sink, _| sink.finish())
+    }
+}
+
+/// A `TokenSink` whose errors are converted into `Error`s, so that sinks of different types can be written to alike.
+trait ErasedSink<'de> {
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error>;
+    fn flush(&mut self) -> Result<(), Error>;
+    fn finish(&mut self) -> Result<(), Error>;
+}
+
+impl<'de, S: TokenSink<'de>> ErasedSink<'de> for S {
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        TokenSink::write(self, token).map_err(Into::into)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        TokenSink::flush(self).map_err(Into::into)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        TokenSink::finish(self).map_err(Into::into)
+    }
+}
+
+/// Splits a [`futures::Stream`] (such as of `Token`s) into `n` streams that each yield a clone of every item and error of the original.
+///
+/// The original stream is polled by whichever of the returned streams needs its next item, and items are buffered for each of the others until they are polled, so a slow (or idle) consumer holds on to every item the others have already consumed. Dropping a stream stops buffering items for it.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{broadcast, tokenize, Token};
+///
+/// let (token_sink, token_stream) = unbounded::<Token>();
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// tokenize(&mut de, token_sink).unwrap();
+///
+/// let mut streams = broadcast(token_stream, 2);
+/// let count = streams.pop().unwrap().fold(0, |n, _| Ok(n + 1));
+/// let tokens = streams.pop().unwrap().collect();
+///
+/// assert_eq!(count.wait(), Ok(3));
+/// assert_eq!(tokens.wait().unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`futures::Stream`]: https://docs.rs/futures/0.1.27/futures/stream/trait.Stream.html
+#[cfg(feature = "futures01")]
+pub fn broadcast<S>(stream: S, n: usize) -> Vec<Broadcast<S>>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        stream,
+        queues: (0..n).map(|_| Some(VecDeque::new())).collect(),
+        tasks: (0..n).map(|_| None).collect(),
+        done: false,
+    }));
+    (0..n)
+        .map(|index| Broadcast {
+            shared: shared.clone(),
+            index,
+        })
+        .collect()
+}
+
+/// One of the streams created by [`broadcast`].
+///
+/// *Requires the `futures01` feature.*
+///
+/// [`broadcast`]: fn.broadcast.html
+#[cfg(feature = "futures01")]
+pub struct Broadcast<S: Stream> {
+    shared: Arc<Mutex<Shared<S>>>,
+    index: usize,
+}
+
+#[cfg(feature = "futures01")]
+type Queue<S> = VecDeque<Result<<S as Stream>::Item, <S as Stream>::Error>>;
+
+#[cfg(feature = "futures01")]
+struct Shared<S: Stream> {
+    stream: S,
+    // the items buffered for each stream, or `None` once it has been dropped
+    queues: Vec<Option<Queue<S>>>,
+    // the task of each stream waiting for an item
+    tasks: Vec<Option<Task>>,
+    done: bool,
+}
+
+#[cfg(feature = "futures01")]
+impl<S: Stream> Shared<S> {
+    fn notify(&mut self) {
+        self.tasks
+            .iter_mut()
+            .flat_map(Option::take)
+            .for_each(|task| task.notify());
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<S> Stream for Broadcast<S>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            if let Some(item) = shared.queues[self.index]
+                .as_mut()
+                .and_then(VecDeque::pop_front)
+            {
+                return item.map(|item| Async::Ready(Some(item)));
+            }
+            if shared.done {
+                return Ok(Async::Ready(None));
+            }
+            let item = match shared.stream.poll() {
+                Ok(Async::NotReady) => {
+                    shared.tasks[self.index] = Some(task::current());
+                    return Ok(Async::NotReady);
+                }
+                Ok(Async::Ready(None)) => {
+                    shared.done = true;
+                    shared.notify();
+                    continue;
+                }
+                Ok(Async::Ready(Some(item))) => Ok(item),
+                Err(err) => Err(err),
+            };
+            for queue in shared.queues.iter_mut().flatten() {
+                queue.push_back(item.clone());
+            }
+            shared.notify();
+        }
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<S: Stream> Drop for Broadcast<S> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+        shared.queues[self.index] = None;
+        shared.tasks[self.index] = None;
+        // another stream may be waiting on the original stream to notify this one
+        shared.notify();
+    }
+}
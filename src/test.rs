@@ -1,5 +1,21 @@
-use crate::{tokenize, Token};
-use futures::{unsync::mpsc, Future, Stream};
+use crate::{
+    contains, de::TokenDeserializer, detokenize, display_tokens, find_first, flatten, from_tokens,
+    sort_maps, split_documents, to_tokens, token_ring, tokenize, tokenize_infallible,
+    tokenize_into, tokenize_iter, tokenize_many, tokenize_owned, tokenize_parallel, tokenize_value,
+    tokenize_with, tokenize_with_options, unflatten, wire, ChannelSink, Error, Extension,
+    FloatPolicy, InlineStr, IntegerWidth, OwnedToken, Path, StringPolicy, Token, TokenEncoder,
+    TokenKind, TokenSeed, TokenizeOptions, Tokenizer, Utf8Policy, WriteSink,
+};
+#[cfg(feature = "futures01")]
+use crate::{
+    de::{StreamDeserializer, StreamDeserializerError},
+    flatten_stream, spawn_tokenize, split_document_stream, tokenize_adaptive, tokenize_and_close,
+    tokenize_send, BatchSink, CountingReader, IndexSink, Migrate, Migration, OverflowSink,
+    Pipeline, PipelineError, Sample, SerializerSink, SerializerSinkError, Substitute,
+    SubstituteError, TokenStream, TokenWrite, TokenWriter, Tokenization, TokenizationError,
+};
+#[cfg(feature = "futures01")]
+use futures::{unsync::mpsc, Future, Sink, Stream};
 
 #[test]
 fn simple() {
@@ -7,7 +23,7 @@ fn simple() {
     let expected = vec![
         Token::Seq { len: None },
         Token::U64(1),
-        Token::Str("hello"),
+        Token::Str("hello".into()),
         Token::U64(3),
         Token::SeqEnd,
     ];
@@ -25,25 +41,4158 @@ fn complex() {
 
     let expected = vec![
         Token::Map { len: None },
-        Token::Str("name"),
-        Token::Str("John Doe"),
-        Token::Str("age"),
+        Token::Str("name".into()),
+        Token::Str("John Doe".into()),
+        Token::Str("age".into()),
         Token::U64(43),
-        Token::Str("phones"),
+        Token::Str("phones".into()),
         Token::Seq { len: None },
-        Token::Str("+44 1234567"),
-        Token::Str("+44 2345678"),
+        Token::Str("+44 1234567".into()),
+        Token::Str("+44 2345678".into()),
         Token::SeqEnd,
         Token::MapEnd,
     ];
 
-    assert_eq!(expected, tokens(&actual))
+    assert_eq!(expected, tokens(actual))
 }
 
-fn tokens(json_str: &str) -> Vec<Token> {
+fn tokens(json_str: &str) -> Vec<Token<'_>> {
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    to_tokens(&mut de).unwrap()
+}
+
+/// Collects (possibly borrowed) tokens into a `Vec`.
+struct Collect<'c, 'de>(&'c mut Vec<Token<'de>>);
+
+impl<'c, 'de> crate::TokenSink<'de> for Collect<'c, 'de> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        self.0.push(token);
+        Ok(())
+    }
+}
+
+#[test]
+fn path_display_and_parse() {
+    let path = Path::new().key("a").key("b.c").index(2).key("d");
+    assert_eq!(path.to_string(), r"a.b\.c[2].d");
+    assert_eq!(path, r"a.b\.c[2].d".parse().unwrap());
+    assert_eq!(Path::new(), "".parse().unwrap());
+    assert!("a[x]".parse::<Path>().is_err());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn index() {
+    let json_str = r#"{"a": [1, {"b": "x"}], "c": null}"#;
+    let reader = CountingReader::new(json_str.as_bytes());
+    let position = reader.position();
+    let mut de = serde_json::de::Deserializer::from_reader(reader);
+    let mut sink = IndexSink::new().with_position(position);
+
+    tokenize(&mut de, &mut sink).unwrap();
+    let index = sink.into_index();
+
+    let entry = index.get(&"a[1].b".parse().unwrap()).unwrap();
+    assert_eq!(entry.tokens, 6..7);
+    let bytes = entry.bytes.clone().unwrap();
+    assert!(json_str[bytes].contains(r#""x""#));
+
+    assert_eq!(index.get(&"a".parse().unwrap()).unwrap().tokens, 2..9);
+    assert_eq!(index.get(&Path::new()).unwrap().tokens, 0..12);
+    assert_eq!(index.len(), 6);
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut sink = IndexSink::new().only(vec!["c".parse().unwrap()]);
+    tokenize(&mut de, &mut sink).unwrap();
+    let index = sink.into_index();
+    assert_eq!(index.len(), 1);
+    assert_eq!(index.entries()[0].tokens, 10..11);
+}
+
+#[test]
+fn find_first_subtree() {
+    let json_str = r#"[{"id": 1}, {"id": 2, "tags": ["x"]}, {"id": 2}]"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut seen = 0;
+    let (path, tokens) = find_first(&mut de, |token, path| {
+        seen += 1;
+        path.to_string() == "[1].tags" && *token == Token::Seq { len: None }
+    })
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(path.to_string(), "[1].tags");
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Seq { len: None },
+            Token::Str("x".into()),
+            Token::SeqEnd
+        ]
+    );
+    assert_eq!(seen, 6);
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    assert_eq!(find_first(&mut de, |_, _| false).unwrap(), None);
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn flatten_complex() {
+    let json_str =
+        r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"], "a": {"b": null}}"#;
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, token_sink).unwrap();
+
+    let pairs = flatten_stream(token_stream).collect().wait().unwrap();
+    assert_eq!(
+        pairs,
+        vec![
+            ("name".to_string(), Token::Str("John Doe".into())),
+            ("phones[0]".to_string(), Token::Str("+44 1234567".into())),
+            ("phones[1]".to_string(), Token::Str("+44 2345678".into())),
+            ("a.b".to_string(), Token::Unit),
+        ]
+    );
+}
+
+#[test]
+fn unflatten_roundtrip() {
+    let json_str =
+        r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"], "a": {"b": null}}"#;
+    let pairs = flatten(tokens(json_str));
+    let unflattened = unflatten(pairs.clone()).unwrap();
+    assert_eq!(flatten(unflattened), pairs);
+
+    assert_eq!(
+        unflatten(vec![("[0]", Token::U8(0)), ("[1]", Token::U8(1))]).unwrap(),
+        vec![
+            Token::Seq { len: Some(2) },
+            Token::U8(0),
+            Token::U8(1),
+            Token::SeqEnd
+        ]
+    );
+    for pairs in [
+        vec![("[1]", Token::U8(1))],
+        vec![("a[4000000000]", Token::Unit)],
+        vec![("a", Token::Unit), ("a.b", Token::Unit)],
+        vec![("a.b", Token::Unit), ("a", Token::Unit)],
+        vec![("a[0]", Token::Unit), ("a[0]", Token::Unit)],
+    ] {
+        match unflatten(pairs) {
+            Err(Error::PathError(_)) => {}
+            result => panic!("expected a path error, found {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn flatten_containers() {
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Pair(u8, u8);
+
+    #[derive(Serialize)]
+    enum E {
+        Newtype(u8),
+        Tuple(u8, u8),
+        Struct { x: u8 },
+        Unit,
+    }
+
+    #[derive(Serialize)]
+    struct S {
+        tuple: (u8, bool),
+        pair: Pair,
+        variants: Vec<E>,
+        map: BTreeMap<u8, Option<u8>>,
+        empty: Vec<u8>,
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert(3, Some(4));
+    map.insert(5, None);
+    let value = S {
+        tuple: (1, true),
+        pair: Pair(2, 3),
+        variants: vec![E::Newtype(4), E::Tuple(5, 6), E::Struct { x: 7 }, E::Unit],
+        map,
+        empty: vec![],
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    value
+        .serialize(&mut Tokenizer::new(ChannelSink::new(tx)))
+        .unwrap();
+    let pairs: Vec<_> = flatten(rx.try_iter())
+        .into_iter()
+        .map(|(path, token)| (path, token.kind()))
+        .collect();
+    assert_eq!(
+        pairs,
+        [
+            ("tuple[0]", TokenKind::U8),
+            ("tuple[1]", TokenKind::Bool),
+            ("pair[0]", TokenKind::U8),
+            ("pair[1]", TokenKind::U8),
+            ("variants[0]", TokenKind::U8),
+            ("variants[1][0]", TokenKind::U8),
+            ("variants[1][1]", TokenKind::U8),
+            ("variants[2].x", TokenKind::U8),
+            ("variants[3]", TokenKind::UnitVariant),
+            ("map.3", TokenKind::U8),
+            ("map.5", TokenKind::None),
+        ]
+        .iter()
+        .map(|&(path, kind)| (path.to_string(), kind))
+        .collect::<Vec<_>>()
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn substitute_strict() {
+    let (token_sink, _token_stream) = mpsc::unbounded::<Token>();
+    let mut sink = Substitute::new(token_sink, |_: &str| None).strict();
+    match sink.start_send(Token::Str("${MISSING}".into())) {
+        Err(SubstituteError::Undefined(name)) => assert_eq!(name, "MISSING"),
+        _ => panic!("expected an undefined variable error"),
+    }
+
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut sink = Substitute::new(token_sink, |name: &str| Some(name.to_lowercase()));
+    sink.start_send(Token::Str("${A}${B".into())).unwrap();
+    sink.start_send(Token::Str("plain".into())).unwrap();
+    drop(sink);
+    assert_eq!(
+        token_stream.collect().wait().unwrap(),
+        vec![
+            Token::String("a${B".to_string()),
+            Token::Str("plain".into())
+        ]
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn substitute_not_ready() {
+    use futures::{Async, AsyncSink, Poll, StartSend};
+
+    // Rejects every other token, as a full sink would.
+    #[derive(Default)]
+    struct Flaky<'a>(Vec<Token<'a>>, bool);
+
+    impl<'a> Sink for Flaky<'a> {
+        type SinkItem = Token<'a>;
+        type SinkError = ();
+
+        fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, ()> {
+            self.1 = !self.1;
+            if self.1 {
+                return Ok(AsyncSink::NotReady(token));
+            }
+            self.0.push(token);
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    let mut sink = Substitute::new(Flaky::default(), |_: &str| Some("x".to_string()));
+    for token in [Token::Str("a$$b".into()), Token::Str("${A}$$".into())] {
+        let token = match sink.start_send(token).unwrap() {
+            AsyncSink::NotReady(token) => token,
+            AsyncSink::Ready => panic!("expected the first attempt to be rejected"),
+        };
+        assert!(sink.start_send(token).unwrap().is_ready());
+    }
+    assert_eq!(
+        sink.into_inner().0,
+        vec![
+            Token::String("a$b".to_string()),
+            Token::String("x$".to_string())
+        ]
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn migrate_documents() {
+    let json_str = r#"{"user": {"first": "John", "last": "Doe"}, "tags": "a,b", "v": 1}"#;
+    let migration = Migration::new()
+        .merge(
+            vec!["user.first".parse().unwrap(), "user.last".parse().unwrap()],
+            "user.name".parse().unwrap(),
+            |values| {
+                let names: Vec<String> = values
+                    .into_iter()
+                    .map(|value| match value.as_slice() {
+                        [Token::Str(s)] => s.to_string(),
+                        _ => String::new(),
+                    })
+                    .collect();
+                vec![Token::String(names.join(" "))]
+            },
+        )
+        .split(
+            "tags".parse().unwrap(),
+            vec![
+                "meta.tags[0]".parse().unwrap(),
+                "meta.tags[1]".parse().unwrap(),
+            ],
+            |value| match value.as_slice() {
+                [Token::Str(s)] => s
+                    .split(',')
+                    .map(|s| vec![Token::Str(s.to_owned().into())])
+                    .collect(),
+                _ => Vec::new(),
+            },
+        )
+        .move_path("v".parse().unwrap(), "version".parse().unwrap());
+
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, Migrate::new(token_sink, migration)).unwrap();
+
+    let pairs = flatten(token_stream.collect().wait().unwrap());
+    assert_eq!(
+        pairs,
+        vec![
+            (
+                "user.name".to_string(),
+                Token::String("John Doe".to_string())
+            ),
+            ("meta.tags[0]".to_string(), Token::Str("a".into())),
+            ("meta.tags[1]".to_string(), Token::Str("b".into())),
+            ("version".to_string(), Token::U64(1)),
+        ]
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn migrate_document_markers() {
+    let (mut token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut sink = Migrate::new(
+        &mut token_sink,
+        Migration::new().rename("a".parse().unwrap(), "b"),
+    );
+    for json_str in &[r#"{"a": 1}"#, r#"{"a": 2}"#] {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        Tokenizer::new(&mut sink)
+            .with_options(&TokenizeOptions::new().document_markers(true))
+            .tokenize(&mut de)
+            .unwrap();
+    }
+    drop(sink);
+    drop(token_sink);
+
+    let document = |n| {
+        vec![
+            Token::DocumentStart,
+            Token::Map { len: None },
+            Token::String("b".to_string()),
+            Token::U64(n),
+            Token::MapEnd,
+            Token::DocumentEnd,
+        ]
+    };
+    assert_eq!(
+        token_stream.collect().wait().unwrap(),
+        [document(1), document(2)].concat()
+    );
+}
+
+#[test]
+fn transform_pipeline() {
+    use crate::{Identity, Role, TokenTransform, TransformContext, TransformSink};
+
+    /// Holds back every scalar until the end of the stream.
+    struct Defer<'a>(Vec<Token<'a>>);
+
+    impl<'a> TokenTransform<'a> for Defer<'a> {
+        fn transform(
+            &mut self,
+            token: Token<'a>,
+            cx: TransformContext,
+            out: &mut Vec<Token<'a>>,
+        ) -> Result<(), Error> {
+            match cx.role() {
+                Role::Scalar => self.0.push(token),
+                _ => out.push(token),
+            }
+            Ok(())
+        }
+
+        fn finish(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+            out.append(&mut self.0);
+            Ok(())
+        }
+    }
+
+    let json_str = r#"{"a": [1, {"b": 2}], "c": 3}"#;
+    let mut paths = Vec::new();
+    let mut transform = Identity
+        .filter(|token, cx| {
+            !(cx.role() == Role::Scalar && cx.depth() > 1) || *token != Token::U64(1)
+        })
+        .map(|token, cx| {
+            paths.push((cx.path().to_string(), cx.depth()));
+            match token {
+                Token::U64(v) => Token::U64(v * 10),
+                token => token,
+            }
+        })
+        .then(Defer(Vec::new()));
+    let transformed = transform.apply(tokens(json_str)).unwrap();
+    drop(transform);
+    assert_eq!(
+        transformed,
+        [
+            Token::Map { len: None },
+            Token::Str("a".into()),
+            Token::Seq { len: None },
+            Token::Map { len: None },
+            Token::Str("b".into()),
+            Token::MapEnd,
+            Token::SeqEnd,
+            Token::Str("c".into()),
+            Token::MapEnd,
+            Token::U64(20),
+            Token::U64(30),
+        ]
+    );
+    // the filtered element no longer counts towards the index
+    assert_eq!(paths[4], ("a[0].b".to_string(), 3));
+    assert_eq!(paths[5], ("a[0].b".to_string(), 3));
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str(json_str);
+    let sink = TransformSink::new(ChannelSink::new(tx), Box::new(Defer(Vec::new())));
+    crate::tokenize_and_close(&mut de, sink).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(tokens.len(), 12);
+    assert_eq!(tokens[11], Token::U64(3));
+
+    let mut failing = Identity.map(|token, _| token).then(Fail);
+    assert!(failing.apply(vec![Token::Unit]).is_err());
+
+    struct Fail;
+
+    impl<'a> TokenTransform<'a> for Fail {
+        fn transform(
+            &mut self,
+            _: Token<'a>,
+            _: TransformContext,
+            _: &mut Vec<Token<'a>>,
+        ) -> Result<(), Error> {
+            Err(Error::TokenStreamError("rejected".to_string()))
+        }
+    }
+}
+
+#[test]
+fn prune_subtrees() {
+    use crate::{prune, Segment, TokenTransform};
+
+    let json_str = r#"{"a": {"secret": [1, {"x": 2}], "b": [3, [4], 5]}, "c": {"secret": null}}"#;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str(json_str);
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().map_markers(true))
+        .tokenize(&mut de)
+        .unwrap();
+    let input: Vec<_> = rx.try_iter().collect();
+
+    let mut checked = Vec::new();
+    let mut transform = prune(|path| {
+        checked.push(path.to_string());
+        path.segments().last() == Some(&Segment::Key("secret".to_string()))
+            || path.to_string() == "a.b[1]"
+    });
+    let pruned = transform.apply(input).unwrap();
+    drop(transform);
+    // each value is checked once, and never within a dropped subtree
+    assert_eq!(
+        checked,
+        ["", "a", "a.secret", "a.b", "a.b[0]", "a.b[1]", "a.b[2]", "c", "c.secret"]
+    );
+    let value: serde_json::Value = from_tokens(&pruned).unwrap();
+    assert_eq!(value, serde_json::json!({"a": {"b": [3, 5]}, "c": {}}));
+    assert_eq!(
+        pruned.iter().filter(|t| **t == Token::MapKey).count(),
+        pruned.iter().filter(|t| **t == Token::MapValue).count()
+    );
+
+    let mut everything = prune(|_| true);
+    let tokens = vec![
+        Token::DocumentStart,
+        Token::Some,
+        Token::U8(1),
+        Token::DocumentEnd,
+    ];
+    assert_eq!(
+        everything.apply(tokens).unwrap(),
+        [Token::DocumentStart, Token::DocumentEnd]
+    );
+}
+
+#[test]
+fn chunked_keys() {
+    use crate::{prune, TokenTransform};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str(r#"{"abcd": 1, "b": {"xyz": 2}}"#);
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().chunk_strings(2))
+        .tokenize(&mut de)
+        .unwrap();
+    let chunked: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        &chunked[..5],
+        [
+            Token::Map { len: None },
+            Token::StrChunk("ab".into()),
+            Token::StrChunk("cd".into()),
+            Token::StrEnd,
+            Token::U64(1),
+        ]
+    );
+
+    assert_eq!(
+        flatten(chunked.clone()),
+        vec![
+            ("abcd".to_string(), Token::U64(1)),
+            ("b.xyz".to_string(), Token::U64(2)),
+        ]
+    );
+
+    let pruned = prune(|path| path.to_string() == "abcd" || path.to_string() == "b.xyz")
+        .apply(chunked)
+        .unwrap();
+    let value: serde_json::Value = from_tokens(&pruned).unwrap();
+    assert_eq!(value, serde_json::json!({"b": {}}));
+}
+
+#[test]
+fn rename_keys() {
+    use crate::{Path, RenameKeys, TokenTransform};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Old {
+        id: u32,
+        tags: BTreeMap<String, String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct New {
+        user_id: u32,
+        labels: BTreeMap<String, String>,
+    }
+
+    let mut tags = BTreeMap::new();
+    tags.insert("id".to_string(), "x".to_string());
+    tags.insert("tags".to_string(), "y".to_string());
+    let old = Old { id: 7, tags };
+    let mut transform = RenameKeys::new()
+        .rename("id", "user_id")
+        .rename("tags", "labels")
+        .rename_at(Path::new().key("tags").key("id"), "ident")
+        .rename_at(Path::new().key("tags").key("tags"), "tags");
+
+    for field_tokens in [false, true] {
+        let (tx, rx) = std::sync::mpsc::channel();
+        old.serialize(
+            &mut Tokenizer::new(ChannelSink::new(tx))
+                .with_options(&TokenizeOptions::new().field_tokens(field_tokens)),
+        )
+        .unwrap();
+        let renamed = transform.apply(rx.try_iter()).unwrap();
+        let mut labels = BTreeMap::new();
+        labels.insert("ident".to_string(), "x".to_string());
+        labels.insert("tags".to_string(), "y".to_string());
+        assert_eq!(
+            from_tokens::<New>(&renamed).unwrap(),
+            New { user_id: 7, labels }
+        );
+    }
+
+    // values and keys without a rule are passed through untouched
+    let tokens = tokens(r#"{"a": "id", "b": {"id": 1}}"#);
+    assert_eq!(
+        transform.apply(tokens.clone()).unwrap(),
+        [
+            &tokens[..5],
+            &[Token::String("user_id".to_string())],
+            &tokens[6..]
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn transform_interned_keys() {
+    use crate::{prune, RenameKeys, TokenTransform};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str(r#"{"a": 1, "secret": {"b": 2}}"#);
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().intern_keys(true))
+        .tokenize(&mut de)
+        .unwrap();
+    let interned: Vec<_> = rx.try_iter().collect();
+    assert!(matches!(interned[1], Token::Interned(_)));
+
+    let pruned = prune(|path| path.to_string() == "secret")
+        .apply(interned.clone())
+        .unwrap();
+    let value: serde_json::Value = from_tokens(&pruned).unwrap();
+    assert_eq!(value, serde_json::json!({"a": 1}));
+
+    let renamed = RenameKeys::new()
+        .rename("a", "x")
+        .rename("b", "y")
+        .apply(interned)
+        .unwrap();
+    let value: serde_json::Value = from_tokens(&renamed).unwrap();
+    assert_eq!(value, serde_json::json!({"x": 1, "secret": {"y": 2}}));
+}
+
+#[test]
+fn transform_inline_keys() {
+    use crate::{prune, RenameKeys, TokenTransform};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let json = r#"{"a": 1, "secret": {"b": 2}}"#;
+    let mut de = serde_json::Deserializer::from_reader(json.as_bytes());
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().inline_strings(true))
+        .tokenize(&mut de)
+        .unwrap();
+    let inline: Vec<_> = rx.try_iter().collect();
+    assert!(matches!(inline[1], Token::Inline(_)));
+
+    let pruned = prune(|path| path.to_string() == "secret")
+        .apply(inline.clone())
+        .unwrap();
+    let value: serde_json::Value = from_tokens(&pruned).unwrap();
+    assert_eq!(value, serde_json::json!({"a": 1}));
+
+    let renamed = RenameKeys::new()
+        .rename("a", "x")
+        .rename("b", "y")
+        .apply(inline)
+        .unwrap();
+    let value: serde_json::Value = from_tokens(&renamed).unwrap();
+    assert_eq!(value, serde_json::json!({"x": 1, "secret": {"y": 2}}));
+}
+
+#[test]
+fn merge_documents() {
+    use crate::{merge, ArrayMerge};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Config {
+        name: String,
+        ports: Vec<u16>,
+        limits: Option<Limits>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Limits {
+        cpu: u8,
+        mem: u8,
+    }
+
+    let base = Config {
+        name: "base".to_string(),
+        ports: vec![80, 443],
+        limits: Some(Limits { cpu: 1, mem: 2 }),
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    base.serialize(&mut Tokenizer::new(ChannelSink::new(tx)))
+        .unwrap();
+    let base: Vec<_> = rx.try_iter().collect();
+    let overrides = tokens(r#"{"ports": [8080], "limits": {"mem": 4}}"#);
+
+    let merged = |arrays| merge(base.clone(), overrides.clone(), arrays).unwrap();
+    let expected = |ports: &[u16]| Config {
+        name: "base".to_string(),
+        ports: ports.to_vec(),
+        limits: Some(Limits { cpu: 1, mem: 4 }),
+    };
+    assert_eq!(
+        from_tokens::<Config>(&merged(ArrayMerge::Replace)).unwrap(),
+        expected(&[8080])
+    );
+    assert_eq!(
+        from_tokens::<Config>(&merged(ArrayMerge::Concat)).unwrap(),
+        expected(&[80, 443, 8080])
+    );
+    assert_eq!(
+        from_tokens::<Config>(&merged(ArrayMerge::ByIndex)).unwrap(),
+        expected(&[8080, 443])
+    );
+
+    // scalars and mismatched shapes are replaced outright
+    let merged = merge(
+        tokens(r#"{"a": {"b": 1}, "c": [1]}"#),
+        tokens(r#"{"a": null, "c": {"d": 2}}"#),
+        ArrayMerge::ByIndex,
+    )
+    .unwrap();
+    assert_eq!(
+        from_tokens::<serde_json::Value>(&merged).unwrap(),
+        serde_json::json!({"a": null, "c": {"d": 2}})
+    );
+
+    assert_eq!(
+        merge(Vec::new(), tokens("[1]"), ArrayMerge::Replace).unwrap(),
+        tokens("[1]")
+    );
+    assert!(merge(
+        tokens("1"),
+        [Token::U8(1), Token::U8(2)],
+        ArrayMerge::Replace
+    )
+    .is_err());
+    assert!(merge(tokens("1"), [Token::SeqEnd], ArrayMerge::Replace).is_err());
+}
+
+#[test]
+fn diff_documents() {
+    use crate::{diff, Change};
+
+    let a = tokens(r#"{"a": {"b": [1, 2, 3], "c": "x"}, "d": null, "e": [1]}"#);
+    let b = tokens(r#"{"a": {"b": [1, 5], "c": "x\u0079"}, "e": {"f": 1}, "g": [true]}"#);
+    let changes = diff(&a, &b).unwrap();
+    assert_eq!(
+        changes.to_string(),
+        concat!(
+            "~ a.b[1]: 2u64 -> 5u64\n",
+            "- a.b[2]: 3u64\n",
+            "~ a.c: \"x\" -> \"xy\"\n",
+            "- d: ()\n",
+            "~ e: [ 1u64 ] -> { \"f\" 1u64 }\n",
+            "+ g: [ true ]\n",
+        )
+    );
+    assert_eq!(
+        changes.changes()[1],
+        Change::Removed {
+            path: "a.b[2]".parse().unwrap(),
+            value: vec![Token::U64(3)],
+        }
+    );
+
+    // representations of equal strings and container lengths don't count as changes
+    let borrowed = [
+        Token::Map { len: None },
+        Token::Str("k".into()),
+        Token::Str("v".into()),
+        Token::MapEnd,
+    ];
+    let owned = [
+        Token::Map { len: Some(1) },
+        Token::String("k".to_string()),
+        Token::StrChunk("v".into()),
+        Token::StrEnd,
+        Token::MapEnd,
+    ];
+    assert!(diff(&borrowed, &owned).unwrap().is_empty());
+
+    assert_eq!(
+        diff(&[], &[Token::Unit]).unwrap().to_string(),
+        "+ <root>: ()\n"
+    );
+    assert!(diff(&[Token::Unit, Token::Unit], &[]).is_err());
+}
+
+#[test]
+fn patch_documents() {
+    use crate::{diff, patch, Change, TokenDiff};
+
+    let docs = [
+        r#"{"a": {"b": [1, 2, 3], "c": "x"}, "d": null, "e": [1]}"#,
+        r#"{"a": {"b": [1, 5], "c": "xy"}, "e": {"f": 1}, "g": [true]}"#,
+        r#"{"a": {"b": [], "c": "xy"}, "e": {"f": [1, {"g": 2}]}}"#,
+        r#"[{"a": 1}, {"a": 2}]"#,
+        r#"1"#,
+        r#"{"": 1, "a": {"": [2]}}"#,
+        r#"{"": 2, "a": {"": [3]}}"#,
+    ];
+    for a in &docs {
+        for b in &docs {
+            let (a, b) = (tokens(a), tokens(b));
+            let changes = diff(&a, &b).unwrap();
+            let shipped: TokenDiff =
+                serde_json::from_str(&serde_json::to_string(&changes).unwrap()).unwrap();
+            assert_eq!(shipped, changes);
+            let patched = patch(&a, &shipped).unwrap();
+            assert!(diff(&patched, &b).unwrap().is_empty());
+        }
+    }
+
+    // paths are shipped as segments, so an empty key isn't mistaken for the root
+    let changes = diff(&tokens(r#"{"": [1]}"#), &tokens(r#"{"": [2]}"#)).unwrap();
+    let json = serde_json::to_value(&changes).unwrap();
+    assert_eq!(json[0][1], serde_json::json!([{"Key": ""}, {"Index": 0}]));
+    let shipped: TokenDiff = serde_json::from_value(json).unwrap();
+    assert_eq!(shipped.changes()[0].path(), &Path::new().key("").index(0));
+
+    // changes within an `Option` are recorded beneath its `Some`
+    let a = [
+        Token::Some,
+        Token::Seq { len: Some(1) },
+        Token::U8(1),
+        Token::SeqEnd,
+    ];
+    let b = [
+        Token::Some,
+        Token::Seq { len: Some(1) },
+        Token::U8(2),
+        Token::SeqEnd,
+    ];
+    let changes = diff(&a, &b).unwrap();
+    assert_eq!(changes.to_string(), "~ [0]: 1u8 -> 2u8\n");
+    assert_eq!(patch(&a, &changes).unwrap(), b);
+    let changes = diff(&[Token::Some, Token::U8(1)], &[Token::Some, Token::U8(2)]).unwrap();
+    assert_eq!(
+        patch(&[Token::Some, Token::U8(1)], &changes).unwrap(),
+        [Token::Some, Token::U8(2)]
+    );
+
+    assert_eq!(patch(&[], &diff(&[], &a).unwrap()).unwrap(), a);
+    assert_eq!(patch(&a, &diff(&a, &[]).unwrap()).unwrap(), []);
+
+    // a diff only applies to the base it was made from
+    let base = tokens(r#"{"a": 1}"#);
+    let removed = TokenDiff::from(vec![Change::Removed {
+        path: "a".parse().unwrap(),
+        value: vec![Token::U64(2)],
+    }]);
+    assert!(patch(&base, &removed).is_err());
+    let added = TokenDiff::from(vec![Change::Added {
+        path: "b.c".parse().unwrap(),
+        value: vec![Token::Unit],
+    }]);
+    assert!(patch(&base, &added).is_err());
+    let changed = TokenDiff::from(vec![Change::Changed {
+        path: "b".parse().unwrap(),
+        from: vec![Token::U64(1)],
+        to: vec![Token::U64(2)],
+    }]);
+    assert!(patch(&base, &changed).is_err());
+    assert!(serde_json::from_str::<TokenDiff>(r#"[["*", "a", []]]"#).is_err());
+}
+
+#[test]
+fn tee_sinks() {
+    use crate::{TeePolicy, TeeSink};
+
+    let json_str = r#"{"a": [1, "b"]}"#;
+    let run = |tee| Tokenizer::new(tee).tokenize(&mut serde_json::Deserializer::from_str(json_str));
+    let (tx1, rx1) = std::sync::mpsc::channel();
+    let (tx2, rx2) = std::sync::mpsc::channel();
+    let (tx3, rx3) = std::sync::mpsc::channel();
+    let mut collected = Vec::new();
+    let tee = TeeSink::new()
+        .sink(ChannelSink::new(tx1))
+        .sink(Collect(&mut collected))
+        .sink(ChannelSink::new(tx2));
+    Tokenizer::new(tee)
+        .tokenize(&mut serde_json::Deserializer::from_str(json_str))
+        .unwrap();
+    let owned: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+    assert_eq!(rx1.try_iter().collect::<Vec<_>>(), owned);
+    assert_eq!(rx2.try_iter().collect::<Vec<_>>(), owned);
+    assert_eq!(collected, tokens(json_str));
+
+    // fail-fast stops at the first failing sink
+    drop(rx1);
+    let (tx1, _) = std::sync::mpsc::channel::<Token<'static>>();
+    let tee = TeeSink::new()
+        .sink(ChannelSink::new(tx1))
+        .sink(ChannelSink::new(tx3.clone()));
+    assert!(run(tee).is_err());
+    assert_eq!(rx3.try_iter().count(), 0);
+
+    // best-effort detaches failing sinks until none are left
+    let (tx1, _) = std::sync::mpsc::channel::<Token<'static>>();
+    let tee = TeeSink::new()
+        .sink(ChannelSink::new(tx1))
+        .sink(ChannelSink::new(tx3))
+        .policy(TeePolicy::BestEffort);
+    let tee = run(tee).unwrap();
+    assert_eq!(rx3.try_iter().count(), tokens(json_str).len());
+    assert_eq!(tee.errors().len(), 1);
+    assert_eq!(tee.errors()[0].0, 0);
+    drop(rx3);
+    assert!(run(tee).is_err());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn broadcast_tokens() {
+    use crate::broadcast;
+    use futures::sync::mpsc::unbounded;
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let (token_sink, token_stream) = unbounded::<Token<'static>>();
+    let consumers: Vec<_> = broadcast(token_stream, 3)
+        .into_iter()
+        .map(|stream| std::thread::spawn(move || stream.collect().wait().unwrap()))
+        .collect();
+
+    let mut de = serde_json::Deserializer::from_str(json_str);
+    for token in to_tokens(&mut de).unwrap() {
+        token_sink.unbounded_send(token.into_owned()).unwrap();
+        std::thread::yield_now();
+    }
+    drop(token_sink);
+
+    for consumer in consumers {
+        let tokens: Vec<_> = consumer.join().unwrap();
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(tokens[5], Token::String("+44 1234567".to_string()));
+    }
+
+    // a dropped stream no longer buffers, and the others still finish
+    let (token_sink, token_stream) = unbounded::<Token<'static>>();
+    let mut streams = broadcast(token_stream, 2);
+    let last = streams.pop().unwrap();
+    let first = streams.pop().unwrap();
+    let consumer = std::thread::spawn(move || last.collect().wait().unwrap());
+    drop(first);
+    token_sink.unbounded_send(Token::Unit).unwrap();
+    drop(token_sink);
+    assert_eq!(consumer.join().unwrap(), [Token::Unit]);
+}
+
+#[test]
+fn truncate_seqs() {
+    use crate::{Path, TokenTransform, TruncateSeqs};
+    use serde::Serialize;
+
+    let json_str = r#"[[1, 2, 3], {"a": [[4], [5, 6], {"b": [7]}], "c": ["x", "y"]}, [8], 9]"#;
+    let mut transform = TruncateSeqs::new()
+        .max_len(2)
+        .max_len_at_depth(2, 1)
+        .max_len_at("[1].c".parse().unwrap(), 0);
+    let truncated = transform.apply(tokens(json_str)).unwrap();
+    assert_eq!(
+        from_tokens::<serde_json::Value>(&truncated).unwrap(),
+        serde_json::json!([[1, 2], {"a": [[4]], "c": []}])
+    );
+
+    // known lengths are reduced, and markers (like map markers) are kept with their elements
+    let value = vec![vec![1u8, 2, 3], vec![4]];
+    let (tx, rx) = std::sync::mpsc::channel();
+    value
+        .serialize(&mut Tokenizer::new(ChannelSink::new(tx)))
+        .unwrap();
+    let mut transform = TruncateSeqs::new()
+        .max_len_at(Path::new().index(0), 1)
+        .markers(true);
+    let truncated = transform.apply(rx.try_iter()).unwrap();
+    assert_eq!(
+        truncated,
+        [
+            Token::Seq { len: Some(2) },
+            Token::Seq { len: Some(1) },
+            Token::U8(1),
+            Token::Truncated { len: 3 },
+            Token::SeqEnd,
+            Token::Seq { len: Some(1) },
+            Token::U8(4),
+            Token::SeqEnd,
+            Token::SeqEnd,
+        ]
+    );
+    assert_eq!(
+        from_tokens::<Vec<Vec<u8>>>(&truncated).unwrap(),
+        [vec![1], vec![4]]
+    );
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str(r#"[{"a": 1}, {"b": 2}]"#);
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().map_markers(true))
+        .tokenize(&mut de)
+        .unwrap();
+    let mut transform = TruncateSeqs::new().max_len(1);
+    let truncated = transform.apply(rx.try_iter()).unwrap();
+    assert_eq!(truncated.len(), 8);
+    assert_eq!(truncated[7], Token::SeqEnd);
+}
+
+#[test]
+fn wire_roundtrip() {
+    let json_str = r#"{"name": "John Doe", "age": -43, "ok": [true, 1.5, null]}"#;
+    let expected = tokens(json_str);
+
+    let mut encoder = wire::Encoder::default();
+    let mut buf = Vec::new();
+    encoder.encode_header(&mut buf);
+    encoder.encode_frame(&expected[..4], &mut buf);
+    encoder.encode_frame(&expected[4..], &mut buf);
+
+    let (mut decoder, mut pos) = wire::Decoder::from_header(&buf).unwrap().unwrap();
+    assert_eq!(decoder.decode_frame(&buf[pos..pos + 2]).unwrap(), None);
+
+    let mut actual = Vec::new();
+    while pos < buf.len() {
+        let (tokens, n) = decoder.decode_frame(&buf[pos..]).unwrap().unwrap();
+        actual.extend(tokens);
+        pos += n;
+    }
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn wire_token_stream() {
+    let json_str = r#"{"name": "John Doe", "age": -43, "ok": [true, 1.5, null]}"#;
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+
+    let many = std::iter::repeat_n(Token::Unit, 2500);
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens(json_str).into_iter().chain(many)).unwrap();
+
+    let actual = wire::read_token_stream(&buf[..]).unwrap();
+    assert_eq!(actual.len(), expected.len() + 2500);
+    assert_eq!(actual[..expected.len()], expected[..]);
+
+    assert!(wire::read_token_stream(&buf[..buf.len() - 1]).is_err());
+    assert!(wire::read_token_stream(&buf[..3]).is_err());
+}
+
+#[test]
+fn variant_index_roundtrip() {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    enum E {
+        A,
+        B(u8),
+        C(u8, u8),
+        D { x: u8 },
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokenize_value(
+        &[E::A, E::B(1), E::C(1, 2), E::D { x: 1 }],
+        ChannelSink::new(tx),
+    )
+    .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    let indices: Vec<_> = tokens
+        .iter()
+        .filter_map(|token| match *token {
+            Token::UnitVariant { variant_index, .. }
+            | Token::NewtypeVariant { variant_index, .. }
+            | Token::TupleVariant { variant_index, .. }
+            | Token::StructVariant { variant_index, .. } => Some(variant_index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(indices, vec![0, 1, 2, 3]);
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+}
+
+#[test]
+fn field_tokens() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum E {
+        D { x: u8 },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct S {
+        a: u8,
+        e: E,
+    }
+
+    let value = S {
+        a: 1,
+        e: E::D { x: 2 },
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    value
+        .serialize(
+            &mut Tokenizer::new(ChannelSink::new(tx))
+                .with_options(&TokenizeOptions::new().field_tokens(true)),
+        )
+        .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    let fields: Vec<_> = tokens
+        .iter()
+        .filter_map(|token| match *token {
+            Token::Field(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(fields, vec!["a", "e", "x"]);
+    assert_eq!(from_tokens::<S>(&tokens).unwrap(), value);
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+
+    let mut v1 = Vec::new();
+    let mut encoder = wire::Encoder::new(1).unwrap();
+    encoder.encode_header(&mut v1);
+    encoder.encode_frame(&tokens, &mut v1);
+    let downgraded = wire::read_token_stream(&v1[..]).unwrap();
+    assert_eq!(downgraded[1], Token::String("a".to_string()));
+    assert_eq!(from_tokens::<S>(&downgraded).unwrap(), value);
+}
+
+#[test]
+fn map_markers() {
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    let json = r#" {"a": {"b": 1}, "c": [2]} "#;
+    let (tx, rx) = std::sync::mpsc::channel();
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().map_markers(true))
+        .tokenize(&mut serde_json::Deserializer::from_str(json))
+        .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    let markers = tokens
+        .iter()
+        .filter(|token| matches!(token, Token::MapKey | Token::MapValue))
+        .count();
+    assert_eq!(markers, 6);
+    assert_eq!(tokens[1], Token::MapKey);
+    assert_eq!(tokens[3], Token::MapValue);
+
+    let value: serde_json::Value = from_tokens(&tokens).unwrap();
+    assert_eq!(
+        value,
+        serde_json::from_str::<serde_json::Value>(json).unwrap()
+    );
+    let unmarked: Vec<_> = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::MapKey | Token::MapValue))
+        .cloned()
+        .collect();
+    assert_eq!(flatten(tokens.clone()), flatten(unmarked.clone()));
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let map: BTreeMap<_, _> = vec![(1u8, true)].into_iter().collect();
+    map.serialize(
+        &mut Tokenizer::new(ChannelSink::new(tx))
+            .with_options(&TokenizeOptions::new().map_markers(true)),
+    )
+    .unwrap();
+    assert_eq!(
+        rx.try_iter().collect::<Vec<_>>(),
+        vec![
+            Token::Map { len: Some(1) },
+            Token::MapKey,
+            Token::U8(1),
+            Token::MapValue,
+            Token::Bool(true),
+            Token::MapEnd,
+        ]
+    );
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let mut v2 = Vec::new();
+    let mut encoder = wire::Encoder::new(2).unwrap();
+    encoder.encode_header(&mut v2);
+    encoder.encode_frame(&tokens, &mut v2);
+    assert_eq!(wire::read_token_stream(&v2[..]).unwrap(), unmarked);
+}
+
+#[test]
+fn none_tokens() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct S {
+        a: Option<u8>,
+        b: Vec<Option<u8>>,
+    }
+
+    let value = S {
+        a: None,
+        b: vec![None, Some(1)],
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokenize_value(&value, ChannelSink::new(tx)).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Struct { name: "S", len: 2 },
+            Token::String("a".to_string()),
+            Token::None,
+            Token::String("b".to_string()),
+            Token::Seq { len: Some(2) },
+            Token::None,
+            Token::Some,
+            Token::U8(1),
+            Token::SeqEnd,
+            Token::StructEnd,
+        ]
+    );
+    assert_eq!(from_tokens::<S>(&tokens).unwrap(), value);
+}
+
+#[test]
+fn number_tokens() {
+    use serde::de::value::{Error as ValueError, MapDeserializer};
+    use serde::ser::{Serialize, SerializeStruct, Serializer};
+    use std::collections::BTreeMap;
+
+    const NUMBER: &str = "$serde_json::private::Number";
+    let digits = "123456789012345678901234567890.5";
+
+    let de = MapDeserializer::<_, ValueError>::new(std::iter::once((NUMBER, digits)));
+    let tokens = to_tokens(de).unwrap();
+    assert_eq!(tokens, vec![Token::Number(digits.into())]);
+
+    let de = MapDeserializer::<_, ValueError>::new(std::iter::once(("a", digits)));
+    assert_eq!(
+        to_tokens(de).unwrap(),
+        vec![
+            Token::Map { len: Some(1) },
+            Token::Str("a".into()),
+            Token::Str(digits.into()),
+            Token::MapEnd,
+        ]
+    );
+    let de = MapDeserializer::<_, ValueError>::new(std::iter::empty::<(&str, &str)>());
+    assert_eq!(
+        to_tokens(de).unwrap(),
+        vec![Token::Map { len: Some(0) }, Token::MapEnd]
+    );
+
+    struct Big(&'static str);
+
+    impl Serialize for Big {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct(NUMBER, 1)?;
+            state.serialize_field(NUMBER, self.0)?;
+            state.end()
+        }
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokenize_value(&[Big(digits)], ChannelSink::new(tx)).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Tuple { len: 1 },
+            Token::Number(digits.into()),
+            Token::TupleEnd,
+        ]
+    );
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    detokenize(tokens.clone(), &mut Tokenizer::new(ChannelSink::new(tx))).unwrap();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), tokens);
+
+    let map: BTreeMap<String, String> = from_tokens(&tokens[1..2]).unwrap();
+    assert_eq!(map[NUMBER], digits);
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+}
+
+#[test]
+fn str_chunks() {
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    let json = r#" {"a": "héllo wörld", "b": "ok"} "#;
+    let mut de = serde_json::Deserializer::from_str(json);
+    let tokens = to_tokens(&mut de).unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().chunk_strings(4))
+        .tokenize(&mut serde_json::Deserializer::from_str(json))
+        .unwrap();
+    let chunked: Vec<_> = rx.try_iter().collect();
+    let chunks: Vec<_> = chunked
+        .iter()
+        .filter_map(|token| match token {
+            Token::StrChunk(chunk) => Some(chunk.as_ref()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(chunks, vec!["hél", "lo w", "örl", "d"]);
+    assert_eq!(chunked.iter().filter(|t| **t == Token::StrEnd).count(), 1);
+
+    let value: BTreeMap<String, String> = from_tokens(&chunked).unwrap();
+    assert_eq!(
+        value,
+        from_tokens::<BTreeMap<String, String>>(&tokens).unwrap()
+    );
+    assert_eq!(
+        flatten(chunked.clone())
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>(),
+        vec!["a".to_string(), "b".to_string()]
+    );
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    "añb"
+        .serialize(
+            &mut Tokenizer::new(ChannelSink::new(tx))
+                .with_options(&TokenizeOptions::new().chunk_strings(1)),
+        )
+        .unwrap();
+    assert_eq!(
+        rx.try_iter().collect::<Vec<_>>(),
+        vec![
+            Token::StrChunk("a".into()),
+            Token::StrChunk("ñ".into()),
+            Token::StrChunk("b".into()),
+            Token::StrEnd,
+        ]
+    );
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, chunked.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), chunked);
+}
+
+#[test]
+fn bytes_chunks() {
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+
+    #[derive(Debug, PartialEq)]
+    struct Blob(Vec<u8>);
+
+    impl Serialize for Blob {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Blob {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct BlobVisitor;
+
+            impl<'de> de::Visitor<'de> for BlobVisitor {
+                type Value = Blob;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("bytes")
+                }
+
+                fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Blob, E> {
+                    Ok(Blob(v.to_vec()))
+                }
+
+                fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Blob, E> {
+                    Ok(Blob(v))
+                }
+            }
+
+            deserializer.deserialize_bytes(BlobVisitor)
+        }
+    }
+
+    let value = vec![Blob(vec![1, 2, 3, 4, 5]), Blob(vec![6])];
+    let (tx, rx) = std::sync::mpsc::channel();
+    value
+        .serialize(
+            &mut Tokenizer::new(ChannelSink::new(tx))
+                .with_options(&TokenizeOptions::new().chunk_bytes(2)),
+        )
+        .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Seq { len: Some(2) },
+            Token::BytesChunk(vec![1, 2].into()),
+            Token::BytesChunk(vec![3, 4].into()),
+            Token::BytesChunk(vec![5].into()),
+            Token::BytesEnd,
+            Token::ByteBuf(vec![6]),
+            Token::SeqEnd,
+        ]
+    );
+    assert_eq!(from_tokens::<Vec<Blob>>(&tokens).unwrap(), value);
+    assert_eq!(
+        flatten(tokens.clone()),
+        vec![
+            ("[0]".to_string(), Token::ByteBuf(vec![1, 2, 3, 4, 5])),
+            ("[1]".to_string(), Token::ByteBuf(vec![6])),
+        ]
+    );
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    detokenize(tokens.clone(), &mut Tokenizer::new(ChannelSink::new(tx))).unwrap();
+    assert_eq!(
+        rx.try_iter().nth(1),
+        Some(Token::ByteBuf(vec![1, 2, 3, 4, 5]))
+    );
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+}
+
+#[test]
+fn extension_tokens() {
+    use serde::Serialize;
+
+    let value = vec![
+        Extension {
+            tag: 32,
+            value: "https://example.com".to_string(),
+        },
+        Extension {
+            tag: 1,
+            value: "2013-03-21T20:04:00Z".to_string(),
+        },
+    ];
+    let (tx, rx) = std::sync::mpsc::channel();
+    value
+        .serialize(&mut Tokenizer::new(ChannelSink::new(tx)))
+        .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(tokens[1], Token::Extension { tag: 32 });
+    assert_eq!(tokens.len(), 6);
+    assert_eq!(
+        from_tokens::<Vec<Extension<String>>>(&tokens).unwrap(),
+        value
+    );
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    detokenize(tokens.clone(), &mut Tokenizer::new(ChannelSink::new(tx))).unwrap();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), tokens);
+    let mut json = Vec::new();
+    detokenize(tokens.clone(), &mut serde_json::Serializer::new(&mut json)).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        r#"[[32,"https://example.com"],[1,"2013-03-21T20:04:00Z"]]"#
+    );
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let mut v6 = Vec::new();
+    let mut encoder = wire::Encoder::new(6).unwrap();
+    encoder.encode_header(&mut v6);
+    encoder.encode_frame(&tokens, &mut v6);
+    let downgraded = wire::read_token_stream(&v6[..]).unwrap();
+    assert_eq!(
+        from_tokens::<Vec<String>>(&downgraded).unwrap(),
+        vec!["https://example.com", "2013-03-21T20:04:00Z"]
+    );
+}
+
+#[test]
+fn enum_headers() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum E {
+        A,
+        B(u8),
+        C(u8, u8),
+        D { x: u8 },
+    }
+
+    let value = vec![E::A, E::B(1), E::C(2, 3), E::D { x: 4 }];
+    let (tx, rx) = std::sync::mpsc::channel();
+    value
+        .serialize(
+            &mut Tokenizer::new(ChannelSink::new(tx))
+                .with_options(&TokenizeOptions::new().enum_headers(true)),
+        )
+        .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        tokens
+            .iter()
+            .filter(|token| matches!(token, Token::Enum { name: "E" }))
+            .count(),
+        4
+    );
+    assert_eq!(tokens[1], Token::Enum { name: "E" });
+    assert_eq!(
+        tokens[2],
+        Token::UnitVariant {
+            name: "E",
+            variant_index: 0,
+            variant: "A",
+        }
+    );
+    assert_eq!(from_tokens::<Vec<E>>(&tokens).unwrap(), value);
+
+    let mut json = Vec::new();
+    detokenize(tokens, &mut serde_json::Serializer::new(&mut json)).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        r#"["A",{"B":1},{"C":[2,3]},{"D":{"x":4}}]"#
+    );
+}
+
+#[test]
+fn token_kinds() {
+    let json = r#" {"a": [1, "b", null, {"c": 1.5}]} "#;
+    let tokens = to_tokens(&mut serde_json::Deserializer::from_str(json)).unwrap();
+    let starts = tokens.iter().filter(|t| t.is_container_start()).count();
+    let ends = tokens.iter().filter(|t| t.is_container_end()).count();
+    let scalars = tokens.iter().filter(|t| t.is_scalar()).count();
+    assert_eq!((starts, ends, scalars), (3, 3, 6));
+    assert_eq!(tokens[0].kind(), TokenKind::Map);
+    assert_eq!(tokens[5].kind(), TokenKind::Unit);
+
+    assert!(!Token::Some.is_scalar());
+    assert!(!Token::MapKey.is_scalar());
+    assert!(!Token::StrChunk("a".into()).is_scalar());
+    assert!(Token::UnitVariant {
+        name: "E",
+        variant_index: 0,
+        variant: "A",
+    }
+    .is_scalar());
+}
+
+#[test]
+fn token_accessors() {
+    let json = r#" [true, -3, 18446744073709551615, 0.5, "s"] "#;
+    let tokens = to_tokens(&mut serde_json::Deserializer::from_str(json)).unwrap();
+    assert_eq!(tokens[1].as_bool(), Some(true));
+    assert_eq!(tokens[2].as_i64(), Some(-3));
+    assert_eq!(tokens[2].as_u64(), None);
+    assert_eq!(tokens[3].as_u64(), Some(u64::MAX));
+    assert_eq!(tokens[3].as_i64(), None);
+    assert_eq!(tokens[4].as_f64(), Some(0.5));
+    assert_eq!(tokens[4].as_i64(), None);
+    assert_eq!(tokens[5].as_str(), Some("s"));
+    assert_eq!(tokens[5].as_bytes(), None);
+    assert_eq!(Token::ByteBuf(vec![1]).as_bytes(), Some(&[1][..]));
+    assert_eq!(Token::I128(1 << 70).as_i64(), None);
+}
+
+#[test]
+fn token_constructors() {
+    let json = r#" [1, "a", {"b": [true, 0.5]}] "#;
+    let expected: Vec<Token> = vec![
+        Token::seq(None),
+        1u64.into(),
+        "a".into(),
+        Token::map(None),
+        "b".into(),
+        Token::seq(None),
+        true.into(),
+        0.5.into(),
+        Token::SeqEnd,
+        Token::MapEnd,
+        Token::SeqEnd,
+    ];
+    assert_eq!(tokens(json), expected);
+    assert_eq!(
+        Token::from(String::from("c")),
+        Token::String("c".to_string())
+    );
+    assert_eq!(Token::from(&b"d"[..]), Token::Bytes(b"d"[..].into()));
+    assert_eq!(Token::tuple(2), Token::Tuple { len: 2 });
+}
+
+#[test]
+fn display_token_stream() {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Point {
+        x: Option<i8>,
+        y: (f64, char),
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokenize_value(
+        &Point {
+            x: Some(-1),
+            y: (0.5, 'c'),
+        },
+        ChannelSink::new(tx),
+    )
+    .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        display_tokens(&tokens).to_string(),
+        "Point {\n  \"x\"\n  some\n  -1i8\n  \"y\"\n  (\n    0.5f64\n    'c'\n  )\n}\n"
+    );
+    assert_eq!(Token::Seq { len: Some(1) }.to_string(), "[");
+    assert_eq!(Token::Bytes(b"ab"[..].into()).to_string(), "bytes [97, 98]");
+}
+
+#[test]
+fn utf8_policies() {
+    let valid = Token::Bytes(b"caf\xc3\xa9"[..].into());
+    let invalid = Token::ByteBuf(b"caf\xe9".to_vec());
+
+    for &policy in &[
+        Utf8Policy::Strict,
+        Utf8Policy::Lossy,
+        Utf8Policy::PassThrough,
+    ] {
+        assert_eq!(
+            valid.clone().bytes_to_text(policy).unwrap(),
+            Token::Str("café".into())
+        );
+        assert_eq!(Token::U8(1).bytes_to_text(policy).unwrap(), Token::U8(1));
+    }
+    match invalid.clone().bytes_to_text(Utf8Policy::Strict) {
+        Err(Error::InvalidUtf8 { valid_up_to }) => assert_eq!(valid_up_to, 3),
+        result => panic!("unexpected {:?}", result),
+    }
+    assert_eq!(
+        invalid.clone().bytes_to_text(Utf8Policy::Lossy).unwrap(),
+        Token::String("caf\u{fffd}".to_string())
+    );
+    assert_eq!(
+        invalid
+            .clone()
+            .bytes_to_text(Utf8Policy::PassThrough)
+            .unwrap(),
+        invalid
+    );
+
+    let tokens = [Token::Seq { len: Some(2) }, valid, invalid, Token::SeqEnd];
+    assert_eq!(
+        display_tokens(&tokens)
+            .bytes_as_text(Utf8Policy::PassThrough)
+            .to_string(),
+        "[\n  \"café\"\n  bytes [99, 97, 102, 233]\n]\n"
+    );
+    let mut out = String::new();
+    let strict = display_tokens(&tokens).bytes_as_text(Utf8Policy::Strict);
+    assert!(std::fmt::write(&mut out, format_args!("{}", strict)).is_err());
+}
+
+#[test]
+fn non_finite_float_policies() {
+    use serde::Serialize;
+
+    let floats = vec![1.0, f64::NAN, f64::NEG_INFINITY];
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new().non_finite_floats(FloatPolicy::Null);
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx.clone())).with_options(&options);
+    floats.serialize(&mut tokenizer).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        from_tokens::<Vec<Option<f64>>>(&tokens).unwrap(),
+        vec![Some(1.0), None, None]
+    );
+
+    let options = options.non_finite_floats(FloatPolicy::Reject);
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    match floats.serialize(&mut tokenizer) {
+        Err(Error::NonFiniteFloat { value }) => assert!(value.is_nan()),
+        result => panic!("unexpected {:?}", result),
+    }
+
+    let tokens = [Token::F32(f32::NAN), Token::F64(2.5)];
+    assert_eq!(display_tokens(&tokens).to_string(), "NaNf32\n2.5f64\n");
+    assert_eq!(
+        display_tokens(&tokens)
+            .non_finite_floats(FloatPolicy::Null)
+            .to_string(),
+        "none\n2.5f64\n"
+    );
+    let mut out = String::new();
+    let strict = display_tokens(&tokens).non_finite_floats(FloatPolicy::Reject);
+    assert!(std::fmt::write(&mut out, format_args!("{}", strict)).is_err());
+}
+
+#[test]
+fn token_hash_eq() {
+    use std::collections::HashSet;
+
+    let json = r#" [1, "a", 1, {"a": 1.5}, "a", 1.5] "#;
+    let tokens = to_tokens(&mut serde_json::Deserializer::from_str(json)).unwrap();
+    let unique: HashSet<_> = tokens.iter().collect();
+    assert_eq!(unique.len(), 7);
+
+    let nan = Token::F32(f32::NAN);
+    assert_eq!(nan, nan.clone());
+    assert!(vec![nan.clone()]
+        .into_iter()
+        .collect::<HashSet<_>>()
+        .contains(&nan));
+    assert_ne!(Token::U8(1), Token::U16(1));
+    assert_ne!(
+        Token::Struct { name: "A", len: 1 },
+        Token::TupleStruct { name: "A", len: 1 }
+    );
+}
+
+#[test]
+fn token_ordering() {
+    let mut floats = vec![
+        Token::F64(f64::NAN),
+        Token::F64(1.0),
+        Token::F64(-0.0),
+        Token::F64(f64::NEG_INFINITY),
+        Token::F64(0.0),
+        Token::F64(-1.5),
+    ];
+    floats.sort();
+    assert_eq!(
+        floats,
+        vec![
+            Token::F64(f64::NEG_INFINITY),
+            Token::F64(-1.5),
+            Token::F64(-0.0),
+            Token::F64(0.0),
+            Token::F64(1.0),
+            Token::F64(f64::NAN),
+        ]
+    );
+    assert!(Token::F32(-2.0) < Token::F32(-1.0));
+    assert!(Token::Bool(true) < Token::I8(-1));
+    assert!(Token::Str("a".into()) < Token::Str("b".into()));
+    assert!(Token::Seq { len: None } < Token::Seq { len: Some(0) });
+
+    let json = r#" [{"b": [{"z": 1, "y": 2}], "a": null, "10": 1, "9": 2}] "#;
+    let sorted = sort_maps(tokens(json)).unwrap();
+    let mut out = Vec::new();
+    detokenize(sorted, &mut serde_json::Serializer::new(&mut out)).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        r#"[{"10":1,"9":2,"a":null,"b":[{"y":2,"z":1}]}]"#
+    );
+}
+
+#[cfg(feature = "serde_test")]
+#[test]
+fn serde_test_interop() {
+    use serde::{Deserialize, Serialize};
+    use std::convert::TryFrom;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum E {
+        A,
+        B { x: i8 },
+    }
+
+    let value = vec![E::B { x: -1 }, E::A];
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokenize_value(&value, ChannelSink::new(tx)).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    let expected: Vec<_> = tokens
+        .iter()
+        .map(serde_test::Token::try_from)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    serde_test::assert_ser_tokens(&value, &expected);
+
+    let converted: Vec<Token> = expected.into_iter().map(Token::from).collect();
+    assert_eq!(from_tokens::<Vec<E>>(&converted).unwrap(), value);
+    assert!(serde_test::Token::try_from(&Token::U128(1)).is_err());
+}
+
+#[test]
+fn many_documents() {
+    let docs = ["[1, 2]", r#""a""#, "{}"];
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut des: Vec<_> = docs
+        .iter()
+        .map(|doc| serde_json::Deserializer::from_str(doc))
+        .collect();
+    tokenize_many(des.iter_mut(), ChannelSink::new(tx)).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+
+    let stream = docs.join("\n");
+    let mut de = serde_json::Deserializer::from_str(&stream);
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().document_markers(true));
+    while de.end().is_err() {
+        tokenizer.tokenize_next(&mut de).unwrap();
+    }
+    drop(tokenizer);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), tokens);
+    assert_eq!(flatten(tokens.clone()).len(), 3);
+    assert_eq!(
+        tokens
+            .iter()
+            .filter(|token| **token == Token::DocumentStart)
+            .count(),
+        3
+    );
+
+    let mut de = serde_json::Deserializer::from_str(r#" "ab\\c" "de\\f" "#);
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().max_owned_bytes(6));
+    tokenizer.tokenize_next(&mut de).unwrap();
+    match tokenizer.tokenize_next(&mut de) {
+        Err(Error::MemoryBudgetExceeded { used: 8, budget: 6 }) => {}
+        other => panic!("expected the budget to span documents, found {:?}", other),
+    }
+
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let mut des = [
+        serde_json::Deserializer::from_str("1"),
+        serde_json::Deserializer::from_str("["),
+    ];
+    assert!(tokenize_many(des.iter_mut(), ChannelSink::new(tx)).is_err());
+}
+
+#[test]
+fn split_many_documents() {
+    let docs = [r#"{"a": [1, "x"]}"#, "null", r#""long string""#];
+    let values: Vec<serde_json::Value> = docs
+        .iter()
+        .map(|doc| serde_json::from_str(doc).unwrap())
+        .collect();
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokenize_many(&values, ChannelSink::new(tx)).unwrap();
+    let marked: Vec<_> = rx.try_iter().collect();
+
+    let documents: Vec<_> = split_documents(marked.clone()).collect();
+    assert_eq!(documents.len(), 3);
+    for (tokens, value) in documents.iter().zip(&values) {
+        assert_eq!(&from_tokens::<serde_json::Value>(tokens).unwrap(), value);
+    }
+
+    let mut unmarked = Vec::new();
+    for doc in &docs {
+        Tokenizer::new(Collect(&mut unmarked))
+            .with_options(&TokenizeOptions::new().chunk_strings(4))
+            .tokenize(&mut serde_json::Deserializer::from_str(doc))
+            .unwrap();
+    }
+    let split: Vec<_> = split_documents(unmarked).collect();
+    assert_eq!(split.len(), 3);
+    assert_eq!(split[1], [Token::Unit]);
+    assert!(matches!(split[2][0], Token::StrChunk(_)));
+
+    let mut cut = marked.clone();
+    cut.truncate(4);
+    assert_eq!(
+        split_documents(cut).collect::<Vec<_>>(),
+        [[
+            Token::Map { len: Some(1) },
+            Token::String("a".to_string()),
+            Token::Seq { len: Some(2) }
+        ]]
+    );
+
+    #[cfg(feature = "futures01")]
+    {
+        let stream = futures::stream::iter_ok::<_, ()>(marked);
+        let split = split_document_stream(stream).collect().wait().unwrap();
+        assert_eq!(split, documents);
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_push_parser() {
+    use crate::JsonPushParser;
+
+    let json = r#" {"name": "J\u00f6hn \"D\" \ud83d\ude00", "phones": ["+44 1234567", []],
+        "n": [0, -0, 1.5e3, -12, 18446744073709551615, 18446744073709551616, -9223372036854775809],
+        "ok": true, "none": null, "f": false, "e": {}} "#;
+    let expected: Vec<_> = {
+        let mut de = serde_json::Deserializer::from_str(json);
+        let (tx, rx) = std::sync::mpsc::channel();
+        Tokenizer::new(ChannelSink::new(tx))
+            .with_options(
+                &TokenizeOptions::new()
+                    .string_policy(StringPolicy::Owned)
+                    .map_markers(true),
+            )
+            .tokenize(&mut de)
+            .unwrap();
+        rx.try_iter().collect()
+    };
+    let options = TokenizeOptions::new().map_markers(true);
+
+    for chunk_size in [1, 2, 7, json.len()] {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut parser = JsonPushParser::new(ChannelSink::new(tx)).with_options(&options);
+        for chunk in json.as_bytes().chunks(chunk_size) {
+            parser.feed(chunk).unwrap();
+        }
+        parser.finish().unwrap();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            expected,
+            "{}",
+            chunk_size
+        );
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut parser = JsonPushParser::new(ChannelSink::new(tx));
+    parser.feed(b"1 [2]\n\"a\"3").unwrap();
+    assert_eq!(rx.try_iter().count(), 5);
+    parser.finish().unwrap();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [Token::U64(3)]);
+
+    for invalid in [
+        &b"[1,]"[..],
+        b"{1: 2}",
+        b"[1}",
+        b"01",
+        b"1.",
+        b"tru ",
+        b"\"\\x\"",
+        b"\"\\ud800\"",
+        b"\"\xff\"",
+        b"{\"a\" 1}",
+        b"truefalse",
+        b"1true",
+    ] {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let mut parser = JsonPushParser::new(ChannelSink::new(tx));
+        let result = parser
+            .feed(invalid)
+            .and_then(|()| parser.finish().map(drop));
+        assert!(result.is_err(), "{:?}", String::from_utf8_lossy(invalid));
+    }
+
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let mut parser = JsonPushParser::new(ChannelSink::new(tx));
+    parser.feed(b"[\"abc").unwrap();
+    match parser.finish() {
+        Err(Error::DeserializeError(msg)) => assert_eq!(msg, "EOF while parsing a value at byte 5"),
+        _ => panic!("expected an EOF error"),
+    }
+
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let mut parser = JsonPushParser::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().max_depth(2));
+    match parser.feed(b"[[[") {
+        Err(Error::DepthLimitExceeded { depth: 3 }) => {}
+        other => panic!("expected a depth error, found {:?}", other),
+    }
+
+    // invalid UTF-8 is reported at its offset in the whole input
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let mut parser = JsonPushParser::new(ChannelSink::new(tx));
+    parser.feed(b"[\"\xc3\xa9").unwrap();
+    match parser.feed(b"\xc3\"]") {
+        Err(Error::InvalidUtf8 { valid_up_to: 4 }) => {}
+        other => panic!("expected a UTF-8 error, found {:?}", other),
+    }
+
+    // partial strings and numbers are limited as they are buffered
+    let options = TokenizeOptions::new().max_payload_len(4);
+    for partial in [&b"\"abcde"[..], b"[12345"] {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let mut parser = JsonPushParser::new(ChannelSink::new(tx)).with_options(&options);
+        match parser.feed(partial) {
+            Err(Error::LengthLimitExceeded { len: 5 }) => {}
+            other => panic!("expected a length error, found {:?}", other),
+        }
+    }
+
+    // or truncated like the tokenizer truncates them
+    let json = r#" ["ab\u00e9d", "abc\u00e9", "\ud83d\ude00\ud83d\ude00"] "#;
+    let options = options.truncate_long_payloads(true);
+    let expected: Vec<_> = {
+        let mut de = serde_json::Deserializer::from_str(json);
+        let (tx, rx) = std::sync::mpsc::channel();
+        Tokenizer::new(ChannelSink::new(tx))
+            .with_options(&options.clone().string_policy(StringPolicy::Owned))
+            .tokenize(&mut de)
+            .unwrap();
+        rx.try_iter().collect()
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut parser = JsonPushParser::new(ChannelSink::new(tx)).with_options(&options);
+    parser.feed(json.as_bytes()).unwrap();
+    parser.finish().unwrap();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn document_markers() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str(r#" {"a": [1, {"b": null}]} "#);
+    Tokenizer::new(ChannelSink::new(tx.clone()))
+        .with_options(
+            &TokenizeOptions::new()
+                .document_markers(true)
+                .map_markers(true),
+        )
+        .tokenize(&mut de)
+        .unwrap();
+    Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().document_markers(true))
+        .tokenize(&mut serde_json::Deserializer::from_str("2"))
+        .unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(tokens[0], Token::DocumentStart);
+    assert_eq!(
+        tokens[tokens.len() - 4..],
+        [
+            Token::DocumentEnd,
+            Token::DocumentStart,
+            Token::U64(2),
+            Token::DocumentEnd,
+        ]
+    );
+    assert_eq!(
+        tokens
+            .iter()
+            .filter(|token| **token == Token::DocumentEnd)
+            .count(),
+        2
+    );
+
+    let value: serde_json::Value = from_tokens(&tokens[..tokens.len() - 3]).unwrap();
+    assert_eq!(value, serde_json::json!({"a": [1, {"b": null}]}));
+    assert_eq!(flatten(tokens.clone()).len(), 3);
+
+    let mut buf = Vec::new();
+    wire::write_token_stream(&mut buf, tokens.clone()).unwrap();
+    assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), tokens);
+
+    let mut v7 = Vec::new();
+    let mut encoder = wire::Encoder::new(7).unwrap();
+    encoder.encode_header(&mut v7);
+    encoder.encode_frame(&tokens, &mut v7);
+    let downgraded = wire::read_token_stream(&v7[..]).unwrap();
+    assert!(!downgraded.contains(&Token::DocumentStart));
+}
+
+#[test]
+fn tokenize_options() {
+    let options = TokenizeOptions::new().max_depth(2).field_tokens(true);
+    let tokenizer = Tokenizer::new(ChannelSink::new(std::sync::mpsc::channel().0));
+    assert_eq!(tokenizer.with_options(&options).options(), &options);
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::Deserializer::from_str("[[1]]");
+    tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options).unwrap();
+    assert_eq!(rx.try_iter().count(), 5);
+
+    let mut de = serde_json::Deserializer::from_str("[[[1]]]");
+    match tokenize_with_options(&mut de, ChannelSink::new(tx), &options) {
+        Err(Error::DepthLimitExceeded { depth }) => assert_eq!(depth, 3),
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+#[test]
+fn token_serde_roundtrip() {
+    let tokens = vec![
+        Token::Bool(true),
+        Token::I128(-1),
+        Token::F64(1.5),
+        Token::Char('x'),
+        Token::Str("a".into()),
+        Token::Interned("k".into()),
+        Token::Inline(InlineStr::new("i").unwrap()),
+        Token::Bytes(b"ab"[..].into()),
+        Token::None,
+        Token::UnitVariant {
+            name: "E",
+            variant_index: 0,
+            variant: "A",
+        },
+        Token::Seq { len: None },
+        Token::Seq { len: Some(2) },
+        Token::TupleStruct { name: "T", len: 2 },
+        Token::StructVariant {
+            name: "E",
+            variant_index: 1,
+            variant: "B",
+            len: 1,
+        },
+        Token::Enum { name: "E" },
+        Token::MapEnd,
+    ];
+    let expected: Vec<_> = tokens.iter().cloned().map(Token::into_owned).collect();
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    let actual: Vec<Token> = serde_json::from_str(&json).unwrap();
+    assert_eq!(actual, expected);
+
+    let err = serde_json::from_str::<Token>(r#"{"Struct": {"name": "S"}}"#).unwrap_err();
+    assert!(err.to_string().contains("missing field `len`"), "{}", err);
+
+    // a seed interns names in its own table, and deserializes the same tokens
+    let mut seed = TokenSeed::new();
+    let mut de = serde_json::Deserializer::from_str(&json);
+    let actual: Vec<Token> =
+        serde::Deserializer::deserialize_seq(&mut de, SeedVisitor(&mut seed)).unwrap();
+    assert_eq!(actual, expected);
+}
+
+struct SeedVisitor<'s>(&'s mut TokenSeed);
+
+impl<'de, 's> serde::de::Visitor<'de> for SeedVisitor<'s> {
+    type Value = Vec<Token<'static>>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a sequence of tokens")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut tokens = Vec::new();
+        while let Some(token) = seq.next_element_seed(&mut *self.0)? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+}
+
+#[test]
+fn wire_skips_unknown_tokens() {
+    // a frame of three tokens: `Bool(true)`, an unknown tag with a 2-byte payload, and `Unit`
+    let frame = [10, 3, 0, 1, 1, 200, 2, 0xAB, 0xCD, 20, 0];
+    let mut decoder = wire::Decoder::default();
+    let (tokens, n) = decoder.decode_frame(&frame).unwrap().unwrap();
+
+    assert_eq!(tokens, vec![Token::Bool(true), Token::Unit]);
+    assert_eq!(n, frame.len());
+    assert_eq!(decoder.skipped(), 1);
+}
+
+#[test]
+fn wire_negotiate() {
+    let local = wire::Hello::default();
+    let mut buf = Vec::new();
+    wire::Hello {
+        min_version: 1,
+        max_version: wire::VERSION + 1,
+    }
+    .encode(&mut buf);
+
+    let (remote, _) = wire::Hello::decode(&buf).unwrap().unwrap();
+    assert_eq!(wire::negotiate(&local, &remote).unwrap(), wire::VERSION);
+
+    let newer = wire::Hello {
+        min_version: wire::VERSION + 1,
+        max_version: wire::VERSION + 2,
+    };
+    assert!(wire::negotiate(&local, &newer).is_err());
+}
+
+#[test]
+fn wire_intern_limit() {
+    let mut interner = wire::Interner::new(8);
+    let name = interner.intern("abcd").unwrap();
+    // names already interned are shared, and don't count against the limit again
+    assert!(std::ptr::eq(name, interner.intern("abcd").unwrap()));
+    interner.intern("efgh").unwrap();
+    match interner.intern("i") {
+        Err(Error::WireError(msg)) => assert!(msg.contains("limit 8 bytes")),
+        other => panic!("expected a wire error, found {:?}", other),
+    }
+    assert_eq!(interner.intern("efgh").unwrap(), "efgh");
+
+    // each table has its own limit, but names are only leaked once
+    let mut other = wire::Interner::new(8);
+    assert!(std::ptr::eq(name, other.intern("abcd").unwrap()));
+    other.intern("ijkl").unwrap();
+}
+
+#[test]
+fn wire_downgrade_chunks() {
+    let tokens = [
+        Token::Map { len: None },
+        Token::StrChunk("a".into()),
+        Token::StrChunk("b".into()),
+        Token::StrEnd,
+        Token::U64(1),
+        Token::MapEnd,
+    ];
+    let mut v4 = Vec::new();
+    let mut encoder = wire::Encoder::new(4).unwrap();
+    encoder.encode_header(&mut v4);
+    // a string's chunks are joined even when they span frames
+    encoder.encode_frame(&tokens[..2], &mut v4);
+    encoder.encode_frame(&tokens[2..], &mut v4);
+    assert_eq!(
+        wire::read_token_stream(&v4[..]).unwrap(),
+        [
+            Token::Map { len: None },
+            Token::String("ab".to_string()),
+            Token::U64(1),
+            Token::MapEnd,
+        ]
+    );
+}
+
+#[test]
+fn wire_downgrade_bytes_chunks() {
+    let tokens = [
+        Token::Seq { len: None },
+        Token::BytesChunk(vec![1, 2].into()),
+        Token::BytesChunk(vec![3].into()),
+        Token::BytesEnd,
+        Token::SeqEnd,
+    ];
+    let mut v5 = Vec::new();
+    let mut encoder = wire::Encoder::new(5).unwrap();
+    encoder.encode_header(&mut v5);
+    encoder.encode_frame(&tokens[..2], &mut v5);
+    encoder.encode_frame(&tokens[2..], &mut v5);
+    assert_eq!(
+        wire::read_token_stream(&v5[..]).unwrap(),
+        [
+            Token::Seq { len: None },
+            Token::ByteBuf(vec![1, 2, 3]),
+            Token::SeqEnd,
+        ]
+    );
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn signed_frames() {
+    use crate::{SigningSink, VerifyingStream};
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let expected: Vec<Token> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+
+    let (frame_sink, frame_stream) = mpsc::unbounded::<Vec<u8>>();
+    let mut sink = SigningSink::new(frame_sink, b"secret", 3);
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, &mut sink).unwrap();
+    sink.close().unwrap();
+    drop(sink);
+
+    let frames = frame_stream.collect().wait().unwrap();
+    // the header, three batches and the terminal frame
+    assert_eq!(frames.len(), 1 + 3 + 1);
+
+    let verified =
+        VerifyingStream::new(futures::stream::iter_ok::<_, ()>(frames.clone()), b"secret");
+    assert_eq!(verified.collect().wait().unwrap(), expected);
+
+    let mut tampered = frames.clone();
+    tampered[1][3] ^= 1;
+    let verified = VerifyingStream::new(futures::stream::iter_ok::<_, ()>(tampered), b"secret");
+    assert!(verified.collect().wait().is_err());
+
+    let reordered = vec![frames[0].clone(), frames[2].clone(), frames[1].clone()];
+    let verified = VerifyingStream::new(futures::stream::iter_ok::<_, ()>(reordered), b"secret");
+    assert!(verified.collect().wait().is_err());
+
+    // every prefix of the frames, even one ending between batches, is rejected
+    for len in 0..frames.len() {
+        let truncated = frames[..len].to_vec();
+        let verified =
+            VerifyingStream::new(futures::stream::iter_ok::<_, ()>(truncated), b"secret");
+        assert!(verified.collect().wait().is_err());
+    }
+}
+
+#[test]
+fn infallible_matches_sink() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"], "n": [1, -2, 3.5]}"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut actual = std::collections::VecDeque::new();
+    tokenize_infallible(&mut de, &mut actual).unwrap();
+
+    assert_eq!(Vec::from(actual), tokens(json_str));
+}
+
+#[test]
+fn deep_nesting_buffered() {
+    use crate::{diff, merge, ArrayMerge};
+
+    let nested = |depth: usize| {
+        let mut tokens = vec![Token::Seq { len: None }; depth];
+        tokens.extend(vec![Token::SeqEnd; depth]);
+        tokens
+    };
+    let deep = nested(100_000);
+    let limited = |result: Result<_, Error>| match result {
+        Err(Error::DepthLimitExceeded { depth }) => assert_eq!(depth, 129),
+        Err(other) => panic!("expected a depth limit error, found {:?}", other),
+        Ok(_) => panic!("expected a depth limit error"),
+    };
+    limited(sort_maps(deep.clone()).map(drop));
+    limited(diff(&deep, &deep).map(drop));
+    limited(merge(deep.clone(), deep, ArrayMerge::Replace).map(drop));
+
+    let shallow = nested(128);
+    assert_eq!(sort_maps(shallow.clone()).unwrap(), shallow);
+    assert!(diff(&shallow, &shallow).unwrap().is_empty());
+}
+
+#[test]
+fn deep_nesting() {
+    let depth = if cfg!(feature = "stacker") {
+        100_000
+    } else {
+        200
+    };
+    let json_str = "[".repeat(depth) + &"]".repeat(depth);
+    let mut de = serde_json::de::Deserializer::from_str(&json_str);
+    de.disable_recursion_limit();
+    let mut actual = Vec::new();
+    let result = tokenize_infallible(&mut de, &mut actual);
+
+    if cfg!(feature = "stacker") {
+        result.unwrap();
+        assert_eq!(actual.len(), depth * 2);
+    } else {
+        match result {
+            Err(Error::DepthLimitExceeded { depth }) => assert_eq!(depth, 129),
+            _ => panic!("expected a depth limit error"),
+        }
+    }
+}
+
+#[test]
+fn deep_nesting_from_tokens() {
+    let depth = 100_000;
+    let mut tokens = vec![Token::Seq { len: None }; depth];
+    tokens.extend(vec![Token::SeqEnd; depth]);
+    let result = from_tokens::<serde::de::IgnoredAny>(&tokens);
+
+    if cfg!(feature = "stacker") {
+        result.unwrap();
+    } else {
+        match result {
+            Err(Error::DepthLimitExceeded { depth }) => assert_eq!(depth, 129),
+            _ => panic!("expected a depth limit error"),
+        }
+        let nested = vec![Token::Some; 128]
+            .into_iter()
+            .chain(Some(Token::Unit))
+            .collect::<Vec<_>>();
+        from_tokens::<serde::de::IgnoredAny>(&nested).unwrap();
+    }
+}
+
+#[test]
+fn configured_depth_limit() {
+    use serde::Serialize;
+
+    let json_str = "[".repeat(100_000) + &"]".repeat(100_000);
+    let mut de = serde_json::de::Deserializer::from_str(&json_str);
+    de.disable_recursion_limit();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new().max_depth(16);
+    match tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options) {
+        Err(Error::DepthLimitExceeded { depth }) => assert_eq!(depth, 17),
+        _ => panic!("expected a depth limit error"),
+    }
+    assert_eq!(rx.try_iter().count(), 16);
+
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    let value = serde_json::json!({"a": {"b": [[1]]}});
+    value.serialize(&mut tokenizer).unwrap();
+    let options = tokenizer.options().clone().max_depth(3);
+    assert!(serde_json::json!([[[[1]]]])
+        .serialize(&mut tokenizer.with_options(&options))
+        .is_err());
+}
+
+#[test]
+fn integer_normalization() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Sample {
+        small: u8,
+        negative: i16,
+    }
+
+    let sample = Sample {
+        small: 7,
+        negative: -300,
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new().normalize_integers(IntegerWidth::Bits64);
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx.clone())).with_options(&options);
+    sample.serialize(&mut tokenizer).unwrap();
+    let serialized: Vec<_> = rx.try_iter().collect();
+    assert_eq!(serialized[2], Token::U64(7));
+    assert_eq!(serialized[4], Token::I64(-300));
+    assert_eq!(from_tokens::<Sample>(&serialized).unwrap(), sample);
+
+    let mut de = serde_json::Deserializer::from_str(r#" {"small": 7, "negative": -300} "#);
+    tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options).unwrap();
+    let parsed: Vec<_> = rx.try_iter().collect();
+    assert_eq!(parsed[2], serialized[2]);
+    assert_eq!(parsed[4], serialized[4]);
+
+    let options = options.normalize_integers(IntegerWidth::Bits128);
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    sample.serialize(&mut tokenizer).unwrap();
+    let widest: Vec<_> = rx.try_iter().collect();
+    assert_eq!(widest[2], Token::U128(7));
+    assert_eq!(widest[4], Token::I128(-300));
+}
+
+#[test]
+fn structs_as_maps() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Outer {
+        name: String,
+        shape: Shape,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Shape {
+        Rect { w: u8, h: u8 },
+    }
+
+    let outer = Outer {
+        name: "a".to_string(),
+        shape: Shape::Rect { w: 1, h: 2 },
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new()
+        .structs_as_maps(true)
+        .field_tokens(true)
+        .map_markers(true)
+        .enum_headers(true);
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    outer.serialize(&mut tokenizer).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert!(tokens.iter().all(|token| !matches!(
+        token,
+        Token::Struct { .. }
+            | Token::StructEnd
+            | Token::StructVariant { .. }
+            | Token::StructVariantEnd
+            | Token::Field(_)
+            | Token::Enum { .. }
+    )));
+    assert_eq!(
+        &tokens[..5],
+        &[
+            Token::Map { len: Some(2) },
+            Token::MapKey,
+            Token::String("name".to_string()),
+            Token::MapValue,
+            Token::String("a".to_string()),
+        ]
+    );
+    assert_eq!(
+        &tokens[tokens.len() - 3..],
+        &[Token::MapEnd, Token::MapEnd, Token::MapEnd]
+    );
+    assert_eq!(from_tokens::<Outer>(&tokens).unwrap(), outer);
+}
+
+#[test]
+fn tuples_as_seqs() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Point(i8, i8);
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Move {
+        To(Point, (u8, bool)),
+    }
+
+    let value = Move::To(Point(-1, 1), (2, true));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new().tuples_as_seqs(true);
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    value.serialize(&mut tokenizer).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Map { len: Some(1) },
+            Token::String("To".to_string()),
+            Token::Seq { len: Some(2) },
+            Token::Seq { len: Some(2) },
+            Token::I8(-1),
+            Token::I8(1),
+            Token::SeqEnd,
+            Token::Seq { len: Some(2) },
+            Token::U8(2),
+            Token::Bool(true),
+            Token::SeqEnd,
+            Token::SeqEnd,
+            Token::MapEnd,
+        ]
+    );
+    assert_eq!(from_tokens::<Move>(&tokens).unwrap(), value);
+}
+
+#[test]
+fn duplicate_keys() {
+    let json = r#" {"a": {"a": 1, "b": [{"a": 2}]}, "b": 3, "a": 4} "#;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new().reject_duplicate_keys(true);
+    let mut de = serde_json::Deserializer::from_str(json);
+    match tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options) {
+        Err(Error::DuplicateKey { key }) => assert_eq!(key, r#""a""#),
+        result => panic!("unexpected {:?}", result),
+    }
+    assert_eq!(rx.try_iter().last(), Some(Token::U64(3)));
+
+    let mut de = serde_json::Deserializer::from_str(r#" [{"a": 1}, {"a": 2}] "#);
+    tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options).unwrap();
+
+    let mut duplicates = Vec::new();
+    let tokens = vec![
+        Token::Map { len: None },
+        Token::Seq { len: Some(1) },
+        Token::U8(1),
+        Token::SeqEnd,
+        Token::Unit,
+        Token::Seq { len: Some(1) },
+        Token::U8(1),
+        Token::SeqEnd,
+        Token::Unit,
+        Token::Field("x"),
+        Token::Unit,
+        Token::Str("x".into()),
+        Token::Unit,
+        Token::MapEnd,
+    ];
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().map_markers(true))
+        .on_duplicate_key(|key| duplicates.push(key.to_vec()));
+    detokenize(tokens, &mut tokenizer).unwrap();
+    drop(tokenizer);
+    assert_eq!(
+        duplicates,
+        vec![
+            vec![Token::Seq { len: Some(1) }, Token::U8(1), Token::SeqEnd],
+            vec![Token::String("x".to_string())],
+        ]
+    );
+    assert_eq!(rx.try_iter().filter(|t| *t == Token::MapKey).count(), 4);
+}
+
+#[test]
+fn sorted_map_keys() {
+    let a = r#" [{"b": {"d": 1, "c": [{"f": 2, "e": 3}]}, "a": "x"}, 4] "#;
+    let b = r#" [{"a": "x", "b": {"c": [{"e": 3, "f": 2}], "d": 1}}, 4] "#;
+    let sorted = |json: &str, options: &TokenizeOptions| {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut de = serde_json::Deserializer::from_str(json);
+        tokenize_with_options(&mut de, ChannelSink::new(tx), options).unwrap();
+        rx.try_iter().collect::<Vec<_>>()
+    };
+
+    let options = TokenizeOptions::new().sort_map_keys(true);
+    assert_ne!(
+        sorted(a, &TokenizeOptions::new()),
+        sorted(b, &TokenizeOptions::new())
+    );
+    assert_eq!(sorted(a, &options), sorted(b, &options));
+    assert_eq!(
+        sorted(a, &options),
+        sort_maps(sorted(a, &TokenizeOptions::new())).unwrap()
+    );
+
+    let options = options.map_markers(true);
+    let tokens = sorted(r#" {"b": 1, "a": 2} "#, &options);
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Map { len: None },
+            Token::MapKey,
+            Token::String("a".to_string()),
+            Token::MapValue,
+            Token::U64(2),
+            Token::MapKey,
+            Token::String("b".to_string()),
+            Token::MapValue,
+            Token::U64(1),
+            Token::MapEnd,
+        ]
+    );
+}
+
+#[test]
+fn interned_keys() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    struct Row {
+        id: u8,
+        name: String,
+    }
+
+    let rows = vec![
+        Row {
+            id: 1,
+            name: "name".to_string(),
+        },
+        Row {
+            id: 2,
+            name: "b".to_string(),
+        },
+    ];
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx))
+        .with_options(&TokenizeOptions::new().intern_keys(true));
+    rows.serialize(&mut tokenizer).unwrap();
+    drop(tokenizer);
+    let tokens = rx.try_iter().collect::<Vec<_>>();
+
+    let keys = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Interned(key) => Some(key.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(keys.len(), 4);
+    assert!(std::sync::Arc::ptr_eq(&keys[0], &keys[2]));
+    assert!(std::sync::Arc::ptr_eq(&keys[1], &keys[3]));
+    // values equal to a key are left as they are
+    assert_eq!(tokens[5], Token::String("name".to_string()));
+
+    let rows: Vec<Row> = from_tokens(&tokens).unwrap();
+    assert_eq!(rows[1].name, "b");
+
+    let mut encoder = wire::Encoder::default();
+    let mut buf = Vec::new();
+    encoder.encode_header(&mut buf);
+    encoder.encode_frame(&tokens, &mut buf);
+    let (mut decoder, pos) = wire::Decoder::from_header(&buf).unwrap().unwrap();
+    assert_eq!(
+        decoder.decode_frame(&buf[pos..]).unwrap().unwrap().0,
+        tokens
+    );
+}
+
+#[test]
+fn inline_strings() {
+    use serde::Serialize;
+
+    let long = "x".repeat(InlineStr::CAPACITY + 1);
+    let value = (String::from("short"), long.clone(), "é".repeat(12));
+    let tokenize = |options: &TokenizeOptions| {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(options);
+        value.serialize(&mut tokenizer).unwrap();
+        drop(tokenizer);
+        rx.try_iter().collect::<Vec<_>>()
+    };
+
+    let options = TokenizeOptions::new().inline_strings(true);
+    let tokens = tokenize(&options);
+    assert_eq!(tokens[1], Token::Inline(InlineStr::new("short").unwrap()));
+    assert_eq!(tokens[2], Token::String(long));
+    assert_eq!(tokens[3].kind(), TokenKind::Inline);
+    let actual: (String, String, String) = from_tokens(&tokens).unwrap();
+    assert_eq!(actual, value);
+
+    let truncated = tokenize(
+        &options
+            .clone()
+            .max_payload_len(3)
+            .truncate_long_payloads(true),
+    );
+    assert_eq!(truncated[2], Token::Inline(InlineStr::new("sho").unwrap()));
+    // truncated on a character boundary
+    assert_eq!(truncated[6], Token::Inline(InlineStr::new("é").unwrap()));
+
+    let chunked = tokenize(&options.chunk_strings(4));
+    assert_eq!(chunked[1], Token::StrChunk("shor".into()));
+    assert_eq!(chunked[2], Token::StrChunk("t".into()));
+    assert_eq!(chunked[3], Token::StrEnd);
+}
+
+#[cfg(feature = "bumpalo")]
+#[test]
+fn arena_payloads() {
+    use serde::Serialize;
+
+    let arena = bumpalo::Bump::new();
+    let mut tokens = Vec::new();
+    let json = br#" {"k\u0065y": ["value", "long value"]} "#;
+    let mut de = serde_json::Deserializer::from_reader(&json[..]);
+    Tokenizer::new(Collect(&mut tokens))
+        .arena(&arena)
+        .with_options(&TokenizeOptions::new().string_policy(StringPolicy::Owned))
+        .tokenize(&mut de)
+        .unwrap();
+    let mut buf = Vec::new();
+    let mut tokenizer = Tokenizer::new(Collect(&mut buf)).arena(&arena);
+    ("s", vec![1u8, 2]).serialize(&mut tokenizer).unwrap();
+    detokenize(vec![Token::ByteBuf(vec![3])], &mut tokenizer).unwrap();
+    drop(tokenizer);
+
+    assert_eq!(tokens[1].borrowed_str(), Some("key"));
+    assert_eq!(tokens[3].borrowed_str(), Some("value"));
+    assert_eq!(tokens[4].borrowed_str(), Some("long value"));
+    assert_eq!(buf[1].borrowed_str(), Some("s"));
+    assert_eq!(buf[7].borrowed_bytes(), Some(&[3][..]));
+    assert!(arena.allocated_bytes() > 0);
+}
+
+#[test]
+fn string_policies() {
+    use std::borrow::Cow;
+
+    let json = r#" {"a\u0062": [98, 99]} "#;
+    let tokenize = |options: &TokenizeOptions| {
+        let mut tokens = Vec::new();
+        let mut de = serde_json::Deserializer::from_str(json);
+        tokenize_with_options(&mut de, Collect(&mut tokens), options).unwrap();
+        tokens
+    };
+    let borrowed = tokenize(&TokenizeOptions::new().string_policy(StringPolicy::Borrowed));
+    assert_eq!(borrowed, tokenize(&TokenizeOptions::new()));
+    match borrowed[1] {
+        Token::Str(Cow::Owned(ref v)) => assert_eq!(v, "ab"),
+        ref token => panic!("unexpected {:?}", token),
+    }
+    let owned = tokenize(&TokenizeOptions::new().string_policy(StringPolicy::Owned));
+    assert_eq!(owned[1], Token::String("ab".to_string()));
+
+    let mut tokens = Vec::new();
+    let options = TokenizeOptions::new().string_policy(StringPolicy::Borrowed);
+    let mut tokenizer = Tokenizer::new(Collect(&mut tokens)).with_options(&options);
+    let value = vec![
+        Token::Tuple { len: 2 },
+        Token::String("a".to_string()),
+        Token::ByteBuf(b"b".to_vec()),
+        Token::TupleEnd,
+    ];
+    detokenize(value, &mut tokenizer).unwrap();
+    drop(tokenizer);
+    assert_eq!(
+        tokens[1..3],
+        [Token::Str("a".into()), Token::Bytes(b"b"[..].into())]
+    );
+}
+
+#[test]
+fn payload_length_limit() {
+    let json = r#" {"short": "ab", "long": "héllo wörld"} "#;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let options = TokenizeOptions::new().max_payload_len(5);
+    let mut de = serde_json::Deserializer::from_str(json);
+    match tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options) {
+        Err(Error::LengthLimitExceeded { len }) => assert_eq!(len, 13),
+        _ => panic!("expected a length limit error"),
+    }
+    rx.try_iter().for_each(drop);
+
+    let options = options.truncate_long_payloads(true);
+    let mut de = serde_json::Deserializer::from_str(json);
+    tokenize_with_options(&mut de, ChannelSink::new(tx.clone()), &options).unwrap();
+    let tokens: Vec<_> = rx.try_iter().collect();
+    assert_eq!(tokens[4], Token::Truncated { len: 13 });
+    assert_eq!(tokens[5], Token::String("héll".to_string()));
+    let value: serde_json::Value = from_tokens(&tokens).unwrap();
+    assert_eq!(value, serde_json::json!({"short": "ab", "long": "héll"}));
+
+    let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    detokenize(
+        vec![Token::Bytes(b"\x01\x02\x03\x04\x05\x06"[..].into())],
+        &mut tokenizer,
+    )
+    .unwrap();
+    assert_eq!(
+        rx.try_iter().collect::<Vec<_>>(),
+        vec![
+            Token::Truncated { len: 6 },
+            Token::ByteBuf(vec![1, 2, 3, 4, 5])
+        ]
+    );
+}
+
+#[test]
+fn memory_budget() {
+    let json = r#" {"key": "plain", "esc\\aped": "ab"} "#;
+    let tokenize = |options: &TokenizeOptions| {
+        let mut tokens = Vec::new();
+        let mut de = serde_json::Deserializer::from_str(json);
+        tokenize_with_options(&mut de, Collect(&mut tokens), options).map(|()| tokens)
+    };
+
+    // only the copied `esc\aped` key is owned
+    assert!(tokenize(&TokenizeOptions::new().max_owned_bytes(8)).is_ok());
+    match tokenize(&TokenizeOptions::new().max_owned_bytes(7)) {
+        Err(Error::MemoryBudgetExceeded { used, budget }) => assert_eq!((used, budget), (8, 7)),
+        result => panic!("unexpected {:?}", result),
+    }
+
+    let options = TokenizeOptions::new()
+        .max_owned_bytes(9)
+        .string_policy(StringPolicy::Owned);
+    assert!(tokenize(&options).is_err());
+    assert!(tokenize(&options.inline_strings(true)).is_ok());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn sample_sequences() {
+    let json_str = r#"{"xs": [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]], "t": [1, 2]}"#;
+    let sampled = |p: Option<f64>| {
+        let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+        let sink = match p {
+            Some(p) => Sample::new(token_sink, p, 7),
+            None => Sample::reservoir(token_sink, 2, 7),
+        };
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, sink).unwrap();
+        token_stream.collect().wait().unwrap()
+    };
+
+    let values: Vec<Token> = flatten(sampled(None))
+        .into_iter()
+        .filter(|(path, _)| path.starts_with("xs"))
+        .map(|(_, token)| token)
+        .collect();
+    assert_eq!(values.len(), 2 * 2);
+    assert!(values.windows(2).all(|w| match (&w[0], &w[1]) {
+        (Token::U64(a), Token::U64(b)) => a < b,
+        _ => false,
+    }));
+
+    assert_eq!(flatten(sampled(Some(1.0))), flatten(tokens(json_str)));
+    assert!(flatten(sampled(Some(0.0))).is_empty());
+
+    // markers are kept or dropped along with the element they precede
+    let marked = vec![
+        Token::Seq { len: None },
+        Token::Truncated { len: 6 },
+        Token::Str("abc".into()),
+        Token::U64(1),
+        Token::Truncated { len: 3 },
+        Token::SeqEnd,
+    ];
+    for (p, expected) in [
+        (
+            0.0,
+            vec![
+                Token::Seq { len: None },
+                Token::Truncated { len: 3 },
+                Token::SeqEnd,
+            ],
+        ),
+        (1.0, marked.clone()),
+    ] {
+        let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+        let mut sink = Sample::new(token_sink, p, 7);
+        for token in marked.clone() {
+            sink.start_send(token).unwrap();
+        }
+        drop(sink);
+        assert_eq!(token_stream.collect().wait().unwrap(), expected);
+    }
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn adaptive_promotion() {
+    let json_str = r#"{"a": "b\"c"}"#;
     let (token_sink, token_stream) = mpsc::unbounded::<Token>();
     let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize_adaptive(&mut de, token_sink).unwrap();
+    assert_eq!(
+        token_stream.collect().wait().unwrap(),
+        vec![
+            Token::Map { len: None },
+            Token::Str("a".into()),
+            Token::String("b\"c".to_string()),
+            Token::MapEnd,
+        ]
+    );
 
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut de = serde_json::de::Deserializer::from_reader(json_str.as_bytes());
+    tokenize_adaptive(&mut de, token_sink).unwrap();
+    assert_eq!(
+        token_stream.collect().wait().unwrap()[1],
+        Token::String("a".to_string())
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn pipeline_stages() {
+    let json_str = r#"{"host": "${HOST}", "xs": [1, 2, 3, 4]}"#;
+    let (token_sink, token_stream) = futures::sync::mpsc::unbounded::<Token<'static>>();
+    Pipeline::new(move |sink| {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, sink)
+    })
+    .capacity(2)
+    .transform(|sink| Substitute::new(sink, |_: &str| Some("localhost".to_string())))
+    .transform(|sink| Sample::reservoir(sink, 2, 7))
+    .sink(token_sink)
+    .join()
+    .unwrap();
+
+    let pairs = flatten(token_stream.collect().wait().unwrap());
+    assert_eq!(pairs.len(), 1 + 2);
+    assert_eq!(pairs[0].1, Token::String("localhost".to_string()));
+
+    let (token_sink, _token_stream) = futures::sync::mpsc::unbounded::<Token<'static>>();
+    let result = Pipeline::new(move |sink| {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, sink)
+    })
+    .transform(|sink| Substitute::new(sink, |_: &str| None).strict())
+    .transform(|sink| Sample::new(sink, 1.0, 7))
+    .sink(token_sink)
+    .join();
+    match result {
+        Err(PipelineError::Transform(0, _)) => {}
+        _ => panic!("expected the first transform to fail"),
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+#[allow(unsafe_code)]
+fn mmap_borrowed_tokens() {
+    use crate::MappedFile;
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let path = std::env::temp_dir().join("serde_token_mmap_test.json");
+    std::fs::write(&path, json_str).unwrap();
+
+    let map = unsafe { MappedFile::open(&path) }.unwrap();
+    let mut de = serde_json::de::Deserializer::from_slice(map.as_bytes());
+    let mut actual = Vec::new();
+    tokenize_infallible(&mut de, &mut actual).unwrap();
+    assert_eq!(actual, tokens(json_str));
+
+    match actual[2] {
+        Token::Str(std::borrow::Cow::Borrowed(s)) => {
+            assert!(map.as_bytes().as_ptr_range().contains(&s.as_ptr()))
+        }
+        _ => panic!("expected a borrowed string"),
+    }
+    drop(actual);
+    drop(map);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn token_writer() {
+    // a hand-written parser for `key=value` lines
+    let input = "name=John Doe\nage=43";
+    let mut writer = TokenWriter::new(IndexSink::new());
+    writer.start_map(None).unwrap();
+    for line in input.lines() {
+        let (key, value) = line.split_at(line.find('=').unwrap());
+        writer.field(key).unwrap();
+        match value[1..].parse() {
+            Ok(n) => writer.scalar(Token::U64(n)).unwrap(),
+            Err(_) => writer.scalar(Token::Str(value[1..].into())).unwrap(),
+        }
+    }
+    writer.end().unwrap();
+    assert!(writer.end().is_err());
+    assert!(writer.end_seq().is_err());
+
+    let index = writer.into_inner().into_index();
+    assert_eq!(index.get(&"age".parse().unwrap()).unwrap().tokens, 4..5);
+
+    // a mismatched end is rejected without closing the container
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut writer = TokenWriter::new(token_sink);
+    writer.start_seq(None).unwrap();
+    assert!(writer.end_map().is_err());
+    assert_eq!(writer.depth(), 1);
+    writer.end_seq().unwrap();
+    drop(writer);
+    assert_eq!(
+        token_stream.collect().wait().unwrap(),
+        vec![Token::Seq { len: None }, Token::SeqEnd]
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn serializer_sink() {
+    let json_str = r#"{"name":"John Doe","phones":["+44 1234567"],"ok":[true,1.5,null,{}]}"#;
+    let reserialize = |json_str: &str| {
+        let mut out = Vec::new();
+        let mut ser = serde_json::Serializer::new(&mut out);
+        let mut sink = SerializerSink::new(&mut ser);
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, &mut sink).unwrap();
+        assert!(sink.is_done());
+        sink.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    assert_eq!(reserialize(json_str), json_str);
+    assert_eq!(reserialize("[]"), "[]");
+    assert_eq!(reserialize(r#""x""#), r#""x""#);
+
+    let mut out = Vec::new();
+    let mut ser = serde_json::Serializer::new(&mut out);
+    let mut sink = SerializerSink::new(&mut ser);
+    sink.start_send(Token::Seq { len: None }).unwrap();
+    assert!(sink.start_send(Token::MapEnd).is_err());
+}
+
+#[test]
+fn contains_fragment() {
+    let haystack = tokens(r#"{"data": [{"id": 1, "tags": ["a"]}, {"id": 2, "tags": ["b", "c"]}]}"#);
+
+    let needle = tokens(r#"{"tags": ["b", "c"]}"#);
+    let path = contains(haystack.clone(), needle).unwrap().unwrap();
+    assert_eq!(path.to_string(), "data[1]");
+
+    let needle = vec![Token::U8(2)];
+    let path = contains(haystack.clone(), needle).unwrap().unwrap();
+    assert_eq!(path.to_string(), "data[1].id");
+
+    let needle = tokens(r#"{"tags": ["b"]}"#);
+    assert_eq!(contains(haystack.clone(), needle).unwrap(), None);
+    assert!(contains(haystack, Vec::new()).is_err());
+}
+
+#[test]
+fn token_deserializer() {
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Kind<'a> {
+        Home,
+        Work(&'a str),
+        Other { label: String },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Person<'a> {
+        name: &'a str,
+        age: Option<u8>,
+        phones: Vec<(Kind<'a>, String)>,
+    }
+
+    let json_str = r#"{
+        "name": "John Doe",
+        "age": null,
+        "phones": [["Home", "1"], [{"Work": "acme"}, "2"], [{"Other": {"label": "x"}}, "3"]]
+    }"#;
+    let tokens = tokens(json_str);
+    let mut de = TokenDeserializer::new(&tokens);
+    let person = Person::deserialize(&mut de).unwrap();
+    de.end().unwrap();
+
+    assert_eq!(
+        person,
+        Person {
+            name: "John Doe",
+            age: None,
+            phones: vec![
+                (Kind::Home, "1".to_string()),
+                (Kind::Work("acme"), "2".to_string()),
+                (
+                    Kind::Other {
+                        label: "x".to_string()
+                    },
+                    "3".to_string()
+                ),
+            ],
+        }
+    );
+
+    let tokens = vec![
+        Token::NewtypeVariant {
+            name: "Kind",
+            variant_index: 1,
+            variant: "Work",
+        },
+        Token::Str("acme".into()),
+        Token::U8(1),
+    ];
+    let mut de = TokenDeserializer::new(&tokens);
+    assert_eq!(Kind::deserialize(&mut de).unwrap(), Kind::Work("acme"));
+    assert!(de.end().is_err());
+    assert_eq!(de.remaining(), &[Token::U8(1)]);
+
+    let tokens = vec![Token::UnitVariant {
+        name: "Kind",
+        variant_index: 0,
+        variant: "Home",
+    }];
+    let mut de = TokenDeserializer::new(&tokens);
+    assert_eq!(Kind::deserialize(&mut de).unwrap(), Kind::Home);
+}
+
+#[test]
+fn from_tokens_roundtrip() {
+    use std::collections::BTreeMap;
+
+    let json_str = r#"{"a": [1, 2], "b": []}"#;
+    let tokens = tokens(json_str);
+    let value: BTreeMap<&str, Vec<u32>> = from_tokens(&tokens).unwrap();
+    assert_eq!(value["a"], vec![1, 2]);
+    assert!(value["b"].is_empty());
+
+    assert!(from_tokens::<u8>(&[Token::U8(1), Token::U8(2)]).is_err());
+    assert!(from_tokens::<Vec<u8>>(&tokens[..3]).is_err());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn detokenize_roundtrip() {
+    let json_str = r#"{"name":"John Doe","age":43,"phones":["+44 1234567","+44 2345678"]}"#;
+    let mut out = Vec::new();
+    detokenize(tokens(json_str), &mut serde_json::Serializer::new(&mut out)).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), json_str);
+
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
     tokenize(&mut de, token_sink).unwrap();
-    token_stream.collect().wait().unwrap()
+
+    let mut out = Vec::new();
+    let mut ser = serde_json::Serializer::new(&mut out);
+    let (_, sink) = token_stream
+        .map_err(|()| -> SerializerSinkError<serde_json::Error> { unreachable!() })
+        .forward(SerializerSink::new(&mut ser))
+        .wait()
+        .unwrap();
+    sink.finish().unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), json_str);
+
+    let mut out = Vec::new();
+    let tokens = vec![Token::Seq { len: None }, Token::U8(1)];
+    assert!(detokenize(tokens, &mut serde_json::Serializer::new(&mut out)).is_err());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn tokenize_value_struct() {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    enum Kind {
+        Work(String),
+    }
+
+    #[derive(Serialize)]
+    struct Person<'a> {
+        name: &'a str,
+        age: Option<u8>,
+        kind: Kind,
+    }
+
+    let person = Person {
+        name: "John Doe",
+        age: None,
+        kind: Kind::Work("+44 1234567".to_string()),
+    };
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    tokenize_value(&person, token_sink).unwrap();
+
+    let expected = vec![
+        Token::Struct {
+            name: "Person",
+            len: 3,
+        },
+        Token::Str("name".into()),
+        Token::String("John Doe".to_string()),
+        Token::Str("age".into()),
+        Token::None,
+        Token::Str("kind".into()),
+        Token::NewtypeVariant {
+            name: "Kind",
+            variant_index: 0,
+            variant: "Work",
+        },
+        Token::String("+44 1234567".to_string()),
+        Token::StructEnd,
+    ];
+    assert_eq!(token_stream.collect().wait().unwrap(), expected);
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn stream_deserializer() {
+    use futures::sync::mpsc::unbounded;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Phone {
+        kind: String,
+        number: String,
+    }
+
+    let (mut token_sink, token_stream) = unbounded::<Token>();
+    let mut values = StreamDeserializer::<_, Phone>::new(token_stream).wait();
+    for json_str in &[
+        r#"{"kind": "home", "number": "+44 1234567"}"#,
+        r#"{"kind": "work", "number": "+44 2345678"}"#,
+    ] {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, &mut token_sink).unwrap();
+    }
+    // a value is deserialized as soon as its tokens arrive, while the stream is still open
+    assert_eq!(values.next().unwrap().unwrap().kind, "home");
+    assert_eq!(values.next().unwrap().unwrap().number, "+44 2345678");
+
+    token_sink.unbounded_send(Token::Seq { len: None }).unwrap();
+    drop(token_sink);
+    match values.next() {
+        Some(Err(StreamDeserializerError::Deserialize(_))) => {}
+        other => panic!("expected a deserialize error, found {:?}", other),
+    }
+    assert!(values.next().is_none());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn stream_deserializer_documents() {
+    let tokens = vec![
+        Token::DocumentStart,
+        Token::U64(1),
+        Token::DocumentEnd,
+        Token::DocumentStart,
+        Token::U64(2),
+        Token::DocumentEnd,
+    ];
+    let values = StreamDeserializer::<_, u64>::new(futures::stream::iter_ok::<_, ()>(tokens));
+    assert_eq!(values.collect().wait().unwrap(), vec![1, 2]);
+
+    let (mut token_sink, token_stream) = futures::sync::mpsc::unbounded::<Token>();
+    for json_str in &[r#"{"a": [1]}"#, r#"{"a": [2, 3]}"#] {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        Tokenizer::new(&mut token_sink)
+            .with_options(&TokenizeOptions::new().document_markers(true))
+            .tokenize(&mut de)
+            .unwrap();
+    }
+    token_sink.unbounded_send(Token::DocumentStart).unwrap();
+    token_sink.unbounded_send(Token::U64(4)).unwrap();
+    drop(token_sink);
+
+    let mut values =
+        StreamDeserializer::<_, std::collections::BTreeMap<String, Vec<u8>>>::new(token_stream)
+            .wait();
+    assert_eq!(values.next().unwrap().unwrap()["a"], [1]);
+    assert_eq!(values.next().unwrap().unwrap()["a"], [2, 3]);
+    // a document cut off before its `DocumentEnd` is incomplete
+    match values.next() {
+        Some(Err(StreamDeserializerError::Deserialize(_))) => {}
+        other => panic!("expected a deserialize error, found {:?}", other),
+    }
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn pipeline_serializer() {
+    let json_str = r#"{"host":"${HOST}","xs":[1,2,3,4]}"#;
+    let mut out = Vec::new();
+    Pipeline::new(move |sink| {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, sink)
+    })
+    .capacity(2)
+    .transform(|sink| Substitute::new(sink, |_: &str| Some("localhost".to_string())))
+    .serialize(&mut serde_json::Serializer::new(&mut out))
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        r#"{"host":"localhost","xs":[1,2,3,4]}"#
+    );
+
+    let result = Pipeline::new(move |sink| {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, sink)
+    })
+    .transform(|sink| Substitute::new(sink, |_: &str| None).strict())
+    .serialize(&mut serde_json::Serializer::new(Vec::new()));
+    match result {
+        Err(PipelineError::Transform(0, _)) => {}
+        _ => panic!("expected the transform to fail"),
+    }
+}
+
+#[test]
+fn tokenize_iter_error() {
+    let json_str = r#"["a", 1, }"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut iter = tokenize_iter(&mut de);
+    assert_eq!(iter.size_hint(), (4, Some(4)));
+
+    assert_eq!(iter.next().unwrap().unwrap(), Token::Seq { len: None });
+    assert_eq!(iter.next().unwrap().unwrap(), Token::Str("a".into()));
+    assert_eq!(iter.next().unwrap().unwrap(), Token::U64(1));
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn lazy_token_stream() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let de = serde_json::de::Deserializer::from_reader(json_str.as_bytes());
+    let mut stream = TokenStream::with_capacity(de, 1).wait();
+    assert_eq!(stream.next().unwrap().unwrap(), Token::Map { len: None });
+    drop(stream);
+
+    let de = serde_json::de::Deserializer::from_reader(json_str.as_bytes());
+    let actual = TokenStream::new(de).collect().wait().unwrap();
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+    assert_eq!(actual, expected);
+
+    let de = serde_json::de::Deserializer::from_reader(r#"[1, }"#.as_bytes());
+    let mut stream = TokenStream::new(de).wait();
+    assert_eq!(stream.next().unwrap().unwrap(), Token::Seq { len: None });
+    assert_eq!(stream.next().unwrap().unwrap(), Token::U64(1));
+    assert!(stream.next().unwrap().is_err());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn spawn_tokenize_across_threads() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+
+    let input = json_str.as_bytes().to_vec();
+    let stream = spawn_tokenize(move |sink| {
+        tokenize(&mut serde_json::Deserializer::from_slice(&input), sink)
+    });
+    let consumer = std::thread::spawn(move || stream.collect().wait().unwrap());
+    assert_eq!(consumer.join().unwrap(), expected);
+
+    let input = br#"[1, }"#.to_vec();
+    let mut stream = spawn_tokenize(move |sink| {
+        tokenize_and_close(&mut serde_json::Deserializer::from_slice(&input), sink)
+    })
+    .wait();
+    assert_eq!(stream.next().unwrap().unwrap(), Token::Seq { len: None });
+    assert_eq!(stream.next().unwrap().unwrap(), Token::U64(1));
+    assert!(stream.next().unwrap().is_err());
+    assert!(stream.next().is_none());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn resumable_tokenization() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let de = serde_json::de::Deserializer::from_reader(json_str.as_bytes());
+    let mut tokenization = Tokenization::new(de, Vec::new());
+
+    let mut steps = 0;
+    while !tokenization.step(3).unwrap() {
+        steps += 1;
+        assert_eq!(tokenization.emitted(), steps * 3);
+    }
+    assert!(tokenization.is_done());
+    assert!(tokenization.step(3).unwrap());
+
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+    assert_eq!(tokenization.emitted(), expected.len());
+    assert_eq!(tokenization.into_inner(), expected);
+
+    let de = serde_json::de::Deserializer::from_reader(r#"[1, }"#.as_bytes());
+    let mut tokenization = Tokenization::new(de, Vec::new());
+    match tokenization.step(10) {
+        Err(TokenizationError::Tokenize(_)) => {}
+        other => panic!("expected a tokenize error, found {:?}", other),
+    }
+    assert_eq!(tokenization.into_inner().len(), 2);
+}
+
+#[cfg(feature = "futures03")]
+#[test]
+fn tokenize_async_backpressure() {
+    use crate::tokenize_async;
+    use futures03::{channel::mpsc, executor::block_on, future::join, StreamExt};
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let (token_sink, token_stream) = mpsc::channel::<Token>(0);
+    let (result, actual) = block_on(join(
+        tokenize_async(&mut de, token_sink),
+        token_stream.collect::<Vec<_>>(),
+    ));
+    result.unwrap();
+    assert_eq!(actual, tokens(json_str));
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let (token_sink, token_stream) = mpsc::channel::<Token>(0);
+    let result = block_on(join(
+        tokenize_async(&mut de, token_sink),
+        token_stream.take(2).collect::<Vec<_>>(),
+    ));
+    match result {
+        (Err(Error::TokenSinkError(_)), taken) => assert_eq!(taken.len(), 2),
+        _ => panic!("expected the sink to fail once the stream is dropped"),
+    }
+}
+
+#[cfg(feature = "futures03")]
+#[test]
+fn tokenize_async_yields() {
+    use crate::tokenize_async_yielding;
+    use futures03::{channel::mpsc, executor::block_on, task::noop_waker, StreamExt};
+    use std::{future::Future, task::Context};
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]}"#;
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    for every in [1, 3, expected.len()] {
+        let de = serde_json::de::Deserializer::from_reader(json_str.as_bytes());
+        let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+        let mut tokenization = Box::pin(tokenize_async_yielding(de, token_sink, every));
+        // pending at least at each yield, as well as whenever the worker falls behind
+        let mut pending = 0;
+        while tokenization.as_mut().poll(&mut cx).is_pending() {
+            pending += 1;
+        }
+        assert!(pending >= (expected.len() - 1) / every);
+        assert_eq!(block_on(token_stream.collect::<Vec<_>>()), expected);
+    }
+}
+
+#[cfg(all(feature = "futures03", feature = "json"))]
+#[test]
+fn async_read_tokens() {
+    use crate::{tokenize_async_read, JsonFormat};
+    use futures03::{executor::block_on, io::AsyncRead, StreamExt};
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// Reads a few bytes at a time, pending before each read.
+    struct Trickle<'a>(&'a [u8], bool);
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.1 = !self.1;
+            if self.1 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567", "+44 2345678"]} [1.5]"#;
+    let options = TokenizeOptions::new().document_markers(true);
+    let expected: Vec<_> = {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let values: Vec<serde_json::Value> = serde_json::Deserializer::from_str(json_str)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        tokenize_many(&values, ChannelSink::new(tx)).unwrap();
+        rx.try_iter()
+            .map(|token| match token {
+                Token::Map { .. } => Token::Map { len: None },
+                Token::Seq { .. } => Token::Seq { len: None },
+                token => token,
+            })
+            .collect()
+    };
+
+    let stream = tokenize_async_read(
+        Trickle(json_str.as_bytes(), false),
+        JsonFormat::new().with_options(&options),
+    );
+    let tokens: Result<Vec<_>, _> = block_on(stream.collect::<Vec<_>>()).into_iter().collect();
+    assert_eq!(tokens.unwrap(), expected);
+
+    let results = block_on(
+        tokenize_async_read(Trickle(b"[true, nul]", false), JsonFormat::new()).collect::<Vec<_>>(),
+    );
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[1].as_ref().unwrap(), &Token::Bool(true));
+    assert!(matches!(results[2], Err(Error::DeserializeError(_))));
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn tokenize_send_backpressure() {
+    let json_str: &'static str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let (token_sink, token_stream) = futures::sync::mpsc::channel::<Token<'static>>(0);
+    let consumer = std::thread::spawn(move || token_stream.collect().wait().unwrap());
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    drop(tokenize_send(&mut de, token_sink).wait().unwrap());
+    assert_eq!(consumer.join().unwrap(), tokens(json_str));
+
+    let (token_sink, token_stream) = futures::sync::mpsc::channel::<Token<'static>>(0);
+    let mut de = serde_json::de::Deserializer::from_str(r#"[1, }"#);
+    assert!(tokenize_send(&mut de, token_sink).wait().is_err());
+    assert!(token_stream.collect().wait().unwrap().is_empty());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn batch_sink() {
+    use futures::{Async, AsyncSink, Poll, StartSend};
+
+    // Accepts a single batch at a time, until it is drained.
+    #[derive(Default)]
+    struct Slot<'a>(Option<Vec<Token<'a>>>);
+
+    impl<'a> Sink for Slot<'a> {
+        type SinkItem = Vec<Token<'a>>;
+        type SinkError = ();
+
+        fn start_send(&mut self, batch: Vec<Token<'a>>) -> StartSend<Vec<Token<'a>>, ()> {
+            if self.0.is_some() {
+                return Ok(AsyncSink::NotReady(batch));
+            }
+            self.0 = Some(batch);
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    let json_str = r#"{"a": [1, 2, 3], "b": null}"#;
+    let (batch_sink, batch_stream) = mpsc::unbounded::<Vec<Token>>();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, BatchSink::new(batch_sink, 3)).unwrap();
+    let batches = batch_stream.collect().wait().unwrap();
+    assert_eq!(
+        batches.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![3, 3, 3, 1]
+    );
+    assert_eq!(batches.concat(), tokens(json_str));
+
+    let mut sink = BatchSink::new(Slot::default(), 2);
+    assert_eq!(sink.start_send(Token::U8(1)), Ok(AsyncSink::Ready));
+    assert_eq!(sink.start_send(Token::U8(2)), Ok(AsyncSink::Ready));
+    assert_eq!(sink.start_send(Token::U8(3)), Ok(AsyncSink::Ready));
+    assert_eq!(sink.start_send(Token::U8(4)), Ok(AsyncSink::Ready));
+    assert_eq!(
+        sink.start_send(Token::U8(5)),
+        Ok(AsyncSink::NotReady(Token::U8(5)))
+    );
+    assert_eq!(sink.buffered(), 2);
+    assert_eq!(
+        sink.get_mut().0.take(),
+        Some(vec![Token::U8(1), Token::U8(2)])
+    );
+    assert_eq!(sink.poll_complete(), Ok(Async::Ready(())));
+    assert_eq!(
+        sink.get_mut().0.take(),
+        Some(vec![Token::U8(3), Token::U8(4)])
+    );
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn overflow_sink() {
+    use futures::{Async, AsyncSink, Poll, StartSend};
+    use std::convert::Infallible;
+
+    // Accepts a single token at a time, until it is drained.
+    #[derive(Default)]
+    struct Slot<'a>(Option<Token<'a>>);
+
+    impl<'a> Sink for Slot<'a> {
+        type SinkItem = Token<'a>;
+        type SinkError = Infallible;
+
+        fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Infallible> {
+            if self.0.is_some() {
+                return Ok(AsyncSink::NotReady(token));
+            }
+            self.0 = Some(token);
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Infallible> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    let json_str = r#"[1, 2, 3]"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut sink = OverflowSink::new(Slot::default(), 4);
+    tokenize(&mut de, &mut sink).unwrap();
+    assert_eq!(sink.buffered(), 4);
+
+    let mut actual = Vec::new();
+    while let Some(token) = sink.get_mut().0.take() {
+        actual.push(token);
+        sink.send_buffered().unwrap();
+    }
+    assert_eq!(sink.buffered(), 0);
+    assert_eq!(actual, tokens(json_str));
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut sink = OverflowSink::new(Slot::default(), 3);
+    match tokenize(&mut de, &mut sink) {
+        Err(Error::TokenSinkNotReadyError) => {}
+        other => panic!("expected the full buffer to fail, found {:?}", other),
+    }
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn tokenize_flushes_and_closes() {
+    use futures::{Async, AsyncSink, Poll, StartSend};
+    use std::convert::Infallible;
+
+    // Only makes tokens visible once flushed, like a framed writer.
+    #[derive(Default)]
+    struct Buffered<'a> {
+        pending: Vec<Token<'a>>,
+        flushed: Vec<Token<'a>>,
+        closed: bool,
+    }
+
+    impl<'a> Sink for Buffered<'a> {
+        type SinkItem = Token<'a>;
+        type SinkError = Infallible;
+
+        fn start_send(&mut self, token: Token<'a>) -> StartSend<Token<'a>, Infallible> {
+            self.pending.push(token);
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Infallible> {
+            self.flushed.append(&mut self.pending);
+            Ok(Async::Ready(()))
+        }
+
+        fn close(&mut self) -> Poll<(), Infallible> {
+            self.closed = true;
+            self.poll_complete()
+        }
+    }
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut sink = Buffered::default();
+    tokenize(&mut de, &mut sink).unwrap();
+    assert_eq!(sink.flushed, tokens(json_str));
+    assert!(!sink.closed);
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut sink = Buffered::default();
+    tokenize_and_close(&mut de, &mut sink).unwrap();
+    assert_eq!(sink.flushed, tokens(json_str));
+    assert!(sink.closed);
+}
+
+#[cfg(feature = "erased-serde")]
+#[test]
+fn tokenize_dyn_sinks() {
+    use crate::tokenize_dyn;
+    use std::{error::Error as _, sync::mpsc::SendError};
+
+    let json_str = r#"{"a": [1, true]}"#;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize_dyn(
+        &mut <dyn erased_serde::Deserializer>::erase(&mut de),
+        Box::new(ChannelSink::new(tx)),
+    )
+    .unwrap();
+    let expected: Vec<Token> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), expected);
+
+    // the sink's errors are returned, not discarded
+    let (tx, rx) = std::sync::mpsc::channel();
+    drop(rx);
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let err = tokenize_dyn(
+        &mut <dyn erased_serde::Deserializer>::erase(&mut de),
+        Box::new(ChannelSink::new(tx)),
+    )
+    .unwrap_err();
+    assert!(err
+        .source()
+        .and_then(|source| source.downcast_ref::<SendError<Token<'static>>>())
+        .is_some());
+}
+
+#[cfg(feature = "futures01")]
+#[test]
+fn sink_error_source() {
+    use std::{error::Error as _, sync::mpsc::SendError};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    drop(rx);
+    let mut de = serde_json::de::Deserializer::from_str(r#"[1]"#);
+    let err = tokenize(&mut de, ChannelSink::new(tx)).unwrap_err();
+
+    match err {
+        Error::TokenSinkError(_) => {}
+        ref other => panic!("expected a sink error, found {:?}", other),
+    }
+    let source = err.source().unwrap();
+    match source.downcast_ref::<SendError<Token<'static>>>() {
+        Some(SendError(token)) => assert_eq!(*token, Token::Seq { len: None }),
+        None => panic!("expected a send error, found {:?}", source),
+    }
+    assert!(err.to_string().starts_with("token sink error: "));
+
+    // as are the errors of a `futures::Sink`
+    let (token_sink, token_stream) = mpsc::unbounded::<Token>();
+    drop(token_stream);
+    let mut de = serde_json::de::Deserializer::from_str(r#"[1]"#);
+    let err = tokenize(&mut de, token_sink).unwrap_err();
+    let source = err.source().unwrap();
+    assert!(
+        source.is::<mpsc::SendError<Token<'static>>>(),
+        "{:?}",
+        source
+    );
+}
+
+#[test]
+fn custom_token_sink() {
+    #[derive(Debug)]
+    struct Full;
+
+    impl From<Full> for Error {
+        fn from(err: Full) -> Self {
+            Error::TokenSinkError(Box::new(std::io::Error::other(format!("{:?}", err))))
+        }
+    }
+
+    // Accepts a limited number of tokens.
+    struct Limited<'t, 'de> {
+        tokens: &'t mut Vec<Token<'de>>,
+        limit: usize,
+    }
+
+    impl<'t, 'de> crate::TokenSink<'de> for Limited<'t, 'de> {
+        type Error = Full;
+
+        fn write(&mut self, token: Token<'de>) -> Result<(), Full> {
+            if self.tokens.len() == self.limit {
+                return Err(Full);
+            }
+            self.tokens.push(token);
+            Ok(())
+        }
+    }
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let mut actual = Vec::new();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let sink = Limited {
+        tokens: &mut actual,
+        limit: 10,
+    };
+    tokenize(&mut de, sink).unwrap();
+    assert_eq!(actual, tokens(json_str));
+
+    let mut actual = Vec::new();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let sink = Limited {
+        tokens: &mut actual,
+        limit: 3,
+    };
+    match tokenize(&mut de, sink) {
+        Err(Error::TokenSinkError(err)) => assert_eq!(err.to_string(), "Full"),
+        other => panic!("expected a sink error, found {:?}", other),
+    }
+    assert_eq!(actual.len(), 3);
+}
+
+#[test]
+fn transient_strings_are_copied() {
+    use std::borrow::Cow;
+
+    let json_str = r#"["plain", "esc\"aped"]"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let actual = to_tokens(&mut de).unwrap();
+    assert!(matches!(actual[1], Token::Str(Cow::Borrowed("plain"))));
+    match actual[2] {
+        Token::Str(Cow::Owned(ref s)) => assert_eq!(s, "esc\"aped"),
+        ref token => panic!("expected a copied string, found {:?}", token),
+    }
+
+    let mut de = serde_json::de::Deserializer::from_reader(json_str.as_bytes());
+    let actual = to_tokens(&mut de).unwrap();
+    assert!(matches!(actual[1], Token::Str(Cow::Owned(_))));
+    assert_eq!(actual, tokens(json_str));
+}
+
+#[test]
+fn owned_tokens() {
+    let json_str = r#"{"name": "John \"Doe\"", "phones": ["+44 1234567"]}"#;
+    let expected: Vec<OwnedToken> = tokens(json_str).iter().map(Token::to_static).collect();
+    assert!(expected
+        .iter()
+        .all(|token| !matches!(token, Token::Str(_) | Token::Bytes(_))));
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let consumer = std::thread::spawn(move || rx.iter().collect::<Vec<OwnedToken>>());
+    {
+        let json = json_str.to_string();
+        let mut de = serde_json::de::Deserializer::from_str(&json);
+        tokenize_owned(&mut de, ChannelSink::new(tx)).unwrap();
+    }
+    assert_eq!(consumer.join().unwrap(), expected);
+}
+
+#[test]
+fn std_channel_sink() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, ChannelSink::new(tx)).unwrap();
+    assert_eq!(rx.iter().collect::<Vec<_>>(), expected);
+
+    let (tx, rx) = std::sync::mpsc::sync_channel(0);
+    let consumer = std::thread::spawn(move || rx.iter().collect::<Vec<_>>());
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, ChannelSink::new(tx)).unwrap();
+    assert_eq!(consumer.join().unwrap(), expected);
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    drop(rx);
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    match tokenize(&mut de, ChannelSink::new(tx)) {
+        Err(Error::TokenSinkError(_)) => {}
+        other => panic!("expected a sink error, found {:?}", other),
+    }
+}
+
+#[test]
+fn parallel_documents() {
+    let lines: Vec<_> = (0..200)
+        .map(|i| match i % 50 {
+            7 => "{".to_string(),
+            _ => format!(r#"{{"id": {}, "tags": ["a", "b\\c"]}}"#, i),
+        })
+        .collect();
+    let tokenize_line = |line: String| -> Result<Vec<OwnedToken>, Error> {
+        let mut de = serde_json::Deserializer::from_str(&line);
+        tokenize_iter(&mut de)
+            .map(|token| token.map(Token::into_owned))
+            .collect()
+    };
+
+    let mut n = 0;
+    for (index, result) in tokenize_parallel(lines.clone(), 4, tokenize_line) {
+        assert_eq!(index, n);
+        n += 1;
+        match tokenize_line(lines[index].clone()) {
+            Ok(expected) => assert_eq!(result.unwrap(), expected),
+            Err(_) => assert!(result.is_err(), "{}", index),
+        }
+    }
+    assert_eq!(n, 200);
+
+    let mut docs = tokenize_parallel(lines, 2, tokenize_line);
+    assert_eq!(docs.next().unwrap().0, 0);
+    drop(docs);
+
+    // a slow first document stalls the workers once the window of 2 documents per worker is full
+    use std::sync::{atomic::AtomicUsize, atomic::Ordering, mpsc, Arc, Mutex};
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let (release, released) = mpsc::channel::<()>();
+    let released = Mutex::new(released);
+    let inputs = {
+        let pulled = pulled.clone();
+        (0..100).inspect(move |_| {
+            pulled.fetch_add(1, Ordering::SeqCst);
+        })
+    };
+    let docs = tokenize_parallel(inputs, 2, move |i| {
+        if i == 0 {
+            released.lock().unwrap().recv().unwrap();
+        }
+        Ok(Vec::new())
+    });
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(pulled.load(Ordering::SeqCst), 4);
+    release.send(()).unwrap();
+    assert_eq!(docs.count(), 100);
+}
+
+#[test]
+#[should_panic(expected = "bad document")]
+fn parallel_documents_panic() {
+    let docs = tokenize_parallel(0..10, 3, |i| {
+        assert!(i != 5, "bad document");
+        Ok(Vec::new())
+    });
+    docs.for_each(drop);
+}
+
+#[test]
+fn token_ring_sink() {
+    use crate::TokenSink;
+
+    let row = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let json = format!("[{}]", vec![row; 500].join(","));
+    let expected: Vec<_> = tokens(&json).into_iter().map(Token::into_owned).collect();
+
+    let (sink, stream) = token_ring(3);
+    assert_eq!(sink.capacity(), 3);
+    let consumer = std::thread::spawn(move || stream.collect::<Vec<_>>());
+    let mut de = serde_json::de::Deserializer::from_str(&json);
+    tokenize(&mut de, sink).unwrap();
+    assert_eq!(consumer.join().unwrap(), expected);
+
+    let (sink, mut stream) = token_ring(8);
+    assert_eq!(stream.try_recv(), None);
+    let mut de = serde_json::de::Deserializer::from_str(row);
+    tokenize(&mut de, sink).unwrap();
+    assert_eq!(stream.len(), 8);
+    assert_eq!(stream.try_recv(), Some(Token::Map { len: None }));
+
+    // writes fail once the stream is dropped, even with room in the ring
+    let (mut sink, stream) = token_ring(8);
+    drop(stream);
+    match sink.write(Token::Unit) {
+        Err(Error::TokenSinkError(_)) => {}
+        other => panic!("expected a sink error, found {:?}", other),
+    }
+
+    // a parked stream is woken by the sink
+    let (mut sink, mut stream) = token_ring(1);
+    let consumer = std::thread::spawn(move || (stream.recv(), stream.recv()));
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    sink.write(Token::Unit).unwrap();
+    drop(sink);
+    assert_eq!(consumer.join().unwrap(), (Some(Token::Unit), None));
+}
+
+#[test]
+fn extend_sink() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let mut actual = std::collections::VecDeque::new();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize_into(&mut de, &mut actual).unwrap();
+    assert_eq!(Vec::from(actual), tokens(json_str));
+}
+
+#[test]
+fn write_sink() {
+    // Writes each token's name, one per line.
+    struct Names;
+
+    impl TokenEncoder for Names {
+        fn encode(&mut self, token: &Token, buf: &mut Vec<u8>) -> Result<(), Error> {
+            let debug = format!("{:?}", token);
+            let name = debug.split(['(', ' ']).next().unwrap();
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(b'\n');
+            Ok(())
+        }
+    }
+
+    let json_str = r#"{"a": [1]}"#;
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let mut out = Vec::new();
+    tokenize(&mut de, WriteSink::with_encoder(&mut out, Names)).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Map\nStr\nSeq\nU64\nSeqEnd\nMapEnd\n"
+    );
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let full: &mut [u8] = &mut [0; 8];
+    match tokenize(&mut de, WriteSink::new(full)) {
+        Err(Error::TokenSinkError(_)) => {}
+        other => panic!("expected a sink error, found {:?}", other),
+    }
+}
+
+#[test]
+fn closure_sink() {
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let mut actual = Vec::new();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize_with(&mut de, |token| {
+        actual.push(token);
+        Ok::<_, std::convert::Infallible>(())
+    })
+    .unwrap();
+    assert_eq!(actual, tokens(json_str));
+
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    let err = tokenize_with(&mut de, |token| match token {
+        Token::Seq { .. } => Err(std::io::Error::other("no sequences")),
+        _ => Ok(()),
+    })
+    .unwrap_err();
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(source.to_string(), "no sequences");
+}
+
+#[cfg(feature = "codec")]
+#[test]
+fn token_codec() {
+    use crate::TokenCodec;
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let json_str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+
+    let mut encoded = BytesMut::new();
+    let mut codec = TokenCodec::new();
+    for token in tokens(json_str) {
+        codec.encode(token, &mut encoded).unwrap();
+    }
+
+    // Feeds the decoder one byte at a time.
+    let mut codec = TokenCodec::new();
+    let mut buf = BytesMut::new();
+    let mut actual = Vec::new();
+    for byte in encoded.iter() {
+        buf.extend_from_slice(&[*byte]);
+        while let Some(token) = codec.decode(&mut buf).unwrap() {
+            actual.push(token);
+        }
+    }
+    assert_eq!(actual, expected);
+    assert!(buf.is_empty());
+
+    // Decodes frames of several tokens, as written by a `wire::Encoder`.
+    let mut encoder = crate::wire::Encoder::default();
+    let mut bytes = Vec::new();
+    encoder.encode_header(&mut bytes);
+    encoder.encode_frame(&tokens(json_str), &mut bytes);
+    let mut buf = BytesMut::from(&bytes[..]);
+    let mut codec = TokenCodec::new();
+    let mut actual = Vec::new();
+    while let Some(token) = codec.decode(&mut buf).unwrap() {
+        actual.push(token);
+    }
+    assert_eq!(actual, expected);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_channel_sink() {
+    use crate::tokenize_tokio;
+    use tokio::sync::mpsc;
+
+    let json_str: &'static str = r#"{"name": "John Doe", "phones": ["+44 1234567"]}"#;
+    let expected: Vec<_> = tokens(json_str)
+        .into_iter()
+        .map(Token::into_owned)
+        .collect();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut de = serde_json::de::Deserializer::from_str(json_str);
+    tokenize(&mut de, ChannelSink::new(tx)).unwrap();
+    let mut actual = Vec::new();
+    while let Ok(token) = rx.try_recv() {
+        actual.push(token);
+    }
+    assert_eq!(actual, expected);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    // a bounded channel can be written to within a runtime, failing once it's full
+    let (tx, mut rx) = mpsc::channel(expected.len());
+    runtime.block_on(async {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize(&mut de, ChannelSink::new(tx.clone())).unwrap();
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        match tokenize(&mut de, ChannelSink::new(tx)) {
+            Err(Error::TokenSinkNotReadyError) => {}
+            other => panic!("expected the full channel to fail, found {:?}", other),
+        }
+    });
+    let mut actual = Vec::new();
+    while let Ok(token) = rx.try_recv() {
+        actual.push(token);
+    }
+    assert_eq!(actual, expected);
+
+    let (tx, mut rx) = mpsc::channel(1);
+    let consumer = runtime.spawn(async move {
+        let mut tokens = Vec::new();
+        while let Some(token) = rx.recv().await {
+            tokens.push(token);
+        }
+        tokens
+    });
+    runtime.block_on(async move {
+        let mut de = serde_json::de::Deserializer::from_str(json_str);
+        tokenize_tokio(&mut de, &tx).await.unwrap();
+    });
+    assert_eq!(runtime.block_on(consumer).unwrap(), expected);
 }
@@ -0,0 +1,650 @@
+use crate::{
+    wire::{self, Interner, MAX_INTERNED_BYTES},
+    InlineStr, Token,
+};
+use serde::{
+    de::{
+        self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess,
+        VariantAccess,
+    },
+    ser::{Serialize, SerializeStructVariant, Serializer},
+};
+use std::{borrow::Cow, fmt};
+
+const NAME: &str = "Token";
+
+/// The names of the variants of `Token`, in declaration order except for those added since, which are appended so that variant indices stay stable.
+const VARIANTS: &[&str] = &[
+    "Bool",
+    "I8",
+    "I16",
+    "I32",
+    "I64",
+    "I128",
+    "U8",
+    "U16",
+    "U32",
+    "U64",
+    "U128",
+    "F32",
+    "F64",
+    "Char",
+    "Str",
+    "String",
+    "Bytes",
+    "ByteBuf",
+    "None",
+    "Some",
+    "Unit",
+    "UnitStruct",
+    "UnitVariant",
+    "NewtypeStruct",
+    "NewtypeVariant",
+    "Seq",
+    "SeqEnd",
+    "Tuple",
+    "TupleEnd",
+    "TupleStruct",
+    "TupleStructEnd",
+    "TupleVariant",
+    "TupleVariantEnd",
+    "Map",
+    "MapEnd",
+    "Struct",
+    "StructEnd",
+    "StructVariant",
+    "StructVariantEnd",
+    "Enum",
+    "Field",
+    "MapKey",
+    "MapValue",
+    "Number",
+    "StrChunk",
+    "StrEnd",
+    "BytesChunk",
+    "BytesEnd",
+    "Extension",
+    "DocumentStart",
+    "DocumentEnd",
+    "Truncated",
+    "Interned",
+    "Inline",
+];
+
+const NAMED: &[&str] = &["name"];
+const VARIANT: &[&str] = &["name", "variant_index", "variant"];
+const LEN: &[&str] = &["len"];
+const NAMED_LEN: &[&str] = &["name", "len"];
+const VARIANT_LEN: &[&str] = &["name", "variant_index", "variant", "len"];
+
+/// Serializes a `Token` as an externally tagged enum named `Token`, with the same variants and fields.
+impl<'a> Serialize for Token<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Token::Bool(ref v) => serializer.serialize_newtype_variant(NAME, 0, "Bool", v),
+            Token::I8(ref v) => serializer.serialize_newtype_variant(NAME, 1, "I8", v),
+            Token::I16(ref v) => serializer.serialize_newtype_variant(NAME, 2, "I16", v),
+            Token::I32(ref v) => serializer.serialize_newtype_variant(NAME, 3, "I32", v),
+            Token::I64(ref v) => serializer.serialize_newtype_variant(NAME, 4, "I64", v),
+            Token::I128(ref v) => serializer.serialize_newtype_variant(NAME, 5, "I128", v),
+            Token::U8(ref v) => serializer.serialize_newtype_variant(NAME, 6, "U8", v),
+            Token::U16(ref v) => serializer.serialize_newtype_variant(NAME, 7, "U16", v),
+            Token::U32(ref v) => serializer.serialize_newtype_variant(NAME, 8, "U32", v),
+            Token::U64(ref v) => serializer.serialize_newtype_variant(NAME, 9, "U64", v),
+            Token::U128(ref v) => serializer.serialize_newtype_variant(NAME, 10, "U128", v),
+            Token::F32(ref v) => serializer.serialize_newtype_variant(NAME, 11, "F32", v),
+            Token::F64(ref v) => serializer.serialize_newtype_variant(NAME, 12, "F64", v),
+            Token::Char(ref v) => serializer.serialize_newtype_variant(NAME, 13, "Char", v),
+            Token::Str(ref v) => serializer.serialize_newtype_variant(NAME, 14, "Str", v),
+            Token::String(ref v) => serializer.serialize_newtype_variant(NAME, 15, "String", v),
+            Token::Interned(ref v) => {
+                serializer.serialize_newtype_variant(NAME, 52, "Interned", &**v)
+            }
+            Token::Inline(ref v) => serializer.serialize_newtype_variant(NAME, 53, "Inline", &**v),
+            Token::Bytes(ref v) => {
+                serializer.serialize_newtype_variant(NAME, 16, "Bytes", &Bytes(v))
+            }
+            Token::ByteBuf(ref v) => {
+                serializer.serialize_newtype_variant(NAME, 17, "ByteBuf", &Bytes(v))
+            }
+            Token::None => serializer.serialize_unit_variant(NAME, 18, "None"),
+            Token::Some => serializer.serialize_unit_variant(NAME, 19, "Some"),
+            Token::Unit => serializer.serialize_unit_variant(NAME, 20, "Unit"),
+            Token::UnitStruct { name } => {
+                header(serializer, 21, "UnitStruct", Some(name), None, None::<()>)
+            }
+            Token::UnitVariant {
+                name,
+                variant_index,
+                variant,
+            } => header(
+                serializer,
+                22,
+                "UnitVariant",
+                Some(name),
+                Some((variant_index, variant)),
+                None::<()>,
+            ),
+            Token::NewtypeStruct { name } => header(
+                serializer,
+                23,
+                "NewtypeStruct",
+                Some(name),
+                None,
+                None::<()>,
+            ),
+            Token::NewtypeVariant {
+                name,
+                variant_index,
+                variant,
+            } => header(
+                serializer,
+                24,
+                "NewtypeVariant",
+                Some(name),
+                Some((variant_index, variant)),
+                None::<()>,
+            ),
+            Token::Seq { len } => header(serializer, 25, "Seq", None, None, Some(len)),
+            Token::SeqEnd => serializer.serialize_unit_variant(NAME, 26, "SeqEnd"),
+            Token::Tuple { len } => header(serializer, 27, "Tuple", None, None, Some(len)),
+            Token::TupleEnd => serializer.serialize_unit_variant(NAME, 28, "TupleEnd"),
+            Token::TupleStruct { name, len } => {
+                header(serializer, 29, "TupleStruct", Some(name), None, Some(len))
+            }
+            Token::TupleStructEnd => serializer.serialize_unit_variant(NAME, 30, "TupleStructEnd"),
+            Token::TupleVariant {
+                name,
+                variant_index,
+                variant,
+                len,
+            } => header(
+                serializer,
+                31,
+                "TupleVariant",
+                Some(name),
+                Some((variant_index, variant)),
+                Some(len),
+            ),
+            Token::TupleVariantEnd => {
+                serializer.serialize_unit_variant(NAME, 32, "TupleVariantEnd")
+            }
+            Token::Map { len } => header(serializer, 33, "Map", None, None, Some(len)),
+            Token::MapEnd => serializer.serialize_unit_variant(NAME, 34, "MapEnd"),
+            Token::Struct { name, len } => {
+                header(serializer, 35, "Struct", Some(name), None, Some(len))
+            }
+            Token::StructEnd => serializer.serialize_unit_variant(NAME, 36, "StructEnd"),
+            Token::StructVariant {
+                name,
+                variant_index,
+                variant,
+                len,
+            } => header(
+                serializer,
+                37,
+                "StructVariant",
+                Some(name),
+                Some((variant_index, variant)),
+                Some(len),
+            ),
+            Token::StructVariantEnd => {
+                serializer.serialize_unit_variant(NAME, 38, "StructVariantEnd")
+            }
+            Token::Enum { name } => header(serializer, 39, "Enum", Some(name), None, None::<()>),
+            Token::Field(name) => serializer.serialize_newtype_variant(NAME, 40, "Field", name),
+            Token::MapKey => serializer.serialize_unit_variant(NAME, 41, "MapKey"),
+            Token::MapValue => serializer.serialize_unit_variant(NAME, 42, "MapValue"),
+            Token::Number(ref v) => serializer.serialize_newtype_variant(NAME, 43, "Number", v),
+            Token::StrChunk(ref v) => serializer.serialize_newtype_variant(NAME, 44, "StrChunk", v),
+            Token::StrEnd => serializer.serialize_unit_variant(NAME, 45, "StrEnd"),
+            Token::BytesChunk(ref v) => {
+                serializer.serialize_newtype_variant(NAME, 46, "BytesChunk", &Bytes(v))
+            }
+            Token::BytesEnd => serializer.serialize_unit_variant(NAME, 47, "BytesEnd"),
+            Token::Extension { ref tag } => {
+                serializer.serialize_newtype_variant(NAME, 48, "Extension", tag)
+            }
+            Token::DocumentStart => serializer.serialize_unit_variant(NAME, 49, "DocumentStart"),
+            Token::DocumentEnd => serializer.serialize_unit_variant(NAME, 50, "DocumentEnd"),
+            Token::Truncated { ref len } => {
+                serializer.serialize_newtype_variant(NAME, 51, "Truncated", len)
+            }
+        }
+    }
+}
+
+/// Serializes a header token as a struct variant of its (present) fields.
+fn header<S: Serializer, L: Serialize>(
+    serializer: S,
+    index: u32,
+    kind: &'static str,
+    name: Option<&'static str>,
+    variant: Option<(u32, &'static str)>,
+    len: Option<L>,
+) -> Result<S::Ok, S::Error> {
+    let count = name.is_some() as usize + 2 * variant.is_some() as usize + len.is_some() as usize;
+    let mut state = serializer.serialize_struct_variant(NAME, index, kind, count)?;
+    if let Some(name) = name {
+        state.serialize_field("name", name)?;
+    }
+    if let Some((variant_index, variant)) = variant {
+        state.serialize_field("variant_index", &variant_index)?;
+        state.serialize_field("variant", variant)?;
+    }
+    if let Some(len) = len {
+        state.serialize_field("len", &len)?;
+    }
+    state.end()
+}
+
+struct Bytes<'b>(&'b [u8]);
+
+impl<'b> Serialize for Bytes<'b> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Deserializes a `Token` as serialized by its `Serialize` impl.
+///
+/// Strings and bytes are always deserialized as owned `Token::String`s and `Token::ByteBuf`s, and type, field and variant names are interned, so the token need not borrow from the input.
+///
+/// Names are interned in a process-wide table that admits at most [`MAX_INTERNED_BYTES`] of distinct names, shared by every caller. Tokens from untrusted sources should be deserialized with a [`TokenSeed`] per source instead.
+///
+/// [`MAX_INTERNED_BYTES`]: wire/constant.MAX_INTERNED_BYTES.html
+/// [`TokenSeed`]: struct.TokenSeed.html
+impl<'de, 'a> Deserialize<'de> for Token<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum(NAME, VARIANTS, TokenVisitor { names: None })
+    }
+}
+
+/// Deserializes `Token`s like their `Deserialize` impl, but interns type, field and variant names in a table of its own.
+///
+/// Each seed admits at most [`MAX_INTERNED_BYTES`] of distinct names, after which deserializing a token with a new name fails, so a source given its own seed can't exhaust the names available to others.
+///
+/// # Example:
+///
+/// ```
+/// use serde::de::DeserializeSeed;
+/// use serde_token::{Token, TokenSeed};
+///
+/// let mut seed = TokenSeed::new();
+/// let mut de = serde_json::Deserializer::from_str(r#"{"Struct": {"name": "S", "len": 1}}"#);
+///
+/// assert_eq!(seed.deserialize(&mut de).unwrap(), Token::Struct { name: "S", len: 1 });
+/// ```
+///
+/// [`MAX_INTERNED_BYTES`]: wire/constant.MAX_INTERNED_BYTES.html
+#[derive(Clone, Debug)]
+pub struct TokenSeed {
+    names: Interner,
+}
+
+impl Default for TokenSeed {
+    fn default() -> Self {
+        TokenSeed {
+            names: Interner::new(MAX_INTERNED_BYTES),
+        }
+    }
+}
+
+impl TokenSeed {
+    /// Creates a seed with an empty name table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for &mut TokenSeed {
+    type Value = Token<'static>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Token<'static>, D::Error> {
+        let visitor = TokenVisitor {
+            names: Some(&mut self.names),
+        };
+        deserializer.deserialize_enum(NAME, VARIANTS, visitor)
+    }
+}
+
+// Interns a name in the given table, or the process-wide table if there is none.
+fn intern<E: de::Error>(names: &mut Option<&mut Interner>, name: &str) -> Result<&'static str, E> {
+    match names {
+        Some(names) => names.intern(name),
+        None => wire::intern(name),
+    }
+    .map_err(E::custom)
+}
+
+struct TokenVisitor<'n> {
+    names: Option<&'n mut Interner>,
+}
+
+impl<'de, 'n> de::Visitor<'de> for TokenVisitor<'n> {
+    type Value = Token<'static>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a token")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(mut self, data: A) -> Result<Token<'static>, A::Error> {
+        let (Kind(kind), access) = data.variant()?;
+        let names = &mut self.names;
+        Ok(match kind {
+            "Bool" => Token::Bool(access.newtype_variant()?),
+            "I8" => Token::I8(access.newtype_variant()?),
+            "I16" => Token::I16(access.newtype_variant()?),
+            "I32" => Token::I32(access.newtype_variant()?),
+            "I64" => Token::I64(access.newtype_variant()?),
+            "I128" => Token::I128(access.newtype_variant()?),
+            "U8" => Token::U8(access.newtype_variant()?),
+            "U16" => Token::U16(access.newtype_variant()?),
+            "U32" => Token::U32(access.newtype_variant()?),
+            "U64" => Token::U64(access.newtype_variant()?),
+            "U128" => Token::U128(access.newtype_variant()?),
+            "F32" => Token::F32(access.newtype_variant()?),
+            "F64" => Token::F64(access.newtype_variant()?),
+            "Char" => Token::Char(access.newtype_variant()?),
+            "Str" | "String" => Token::String(access.newtype_variant()?),
+            "Bytes" | "ByteBuf" => Token::ByteBuf(access.newtype_variant::<ByteBuf>()?.0),
+            "None" => unit(access, Token::None)?,
+            "Some" => unit(access, Token::Some)?,
+            "Unit" => unit(access, Token::Unit)?,
+            "UnitStruct" => {
+                let h = fields(access, NAMED, false, names)?;
+                Token::UnitStruct { name: h.name }
+            }
+            "UnitVariant" => {
+                let h = fields(access, VARIANT, false, names)?;
+                Token::UnitVariant {
+                    name: h.name,
+                    variant_index: h.variant_index,
+                    variant: h.variant,
+                }
+            }
+            "NewtypeStruct" => {
+                let h = fields(access, NAMED, false, names)?;
+                Token::NewtypeStruct { name: h.name }
+            }
+            "NewtypeVariant" => {
+                let h = fields(access, VARIANT, false, names)?;
+                Token::NewtypeVariant {
+                    name: h.name,
+                    variant_index: h.variant_index,
+                    variant: h.variant,
+                }
+            }
+            "Seq" => Token::Seq {
+                len: fields(access, LEN, true, names)?.len,
+            },
+            "SeqEnd" => unit(access, Token::SeqEnd)?,
+            "Tuple" => Token::Tuple {
+                len: fields(access, LEN, false, names)?.len.unwrap_or_default(),
+            },
+            "TupleEnd" => unit(access, Token::TupleEnd)?,
+            "TupleStruct" => {
+                let h = fields(access, NAMED_LEN, false, names)?;
+                Token::TupleStruct {
+                    name: h.name,
+                    len: h.len.unwrap_or_default(),
+                }
+            }
+            "TupleStructEnd" => unit(access, Token::TupleStructEnd)?,
+            "TupleVariant" => {
+                let h = fields(access, VARIANT_LEN, false, names)?;
+                Token::TupleVariant {
+                    name: h.name,
+                    variant_index: h.variant_index,
+                    variant: h.variant,
+                    len: h.len.unwrap_or_default(),
+                }
+            }
+            "TupleVariantEnd" => unit(access, Token::TupleVariantEnd)?,
+            "Map" => Token::Map {
+                len: fields(access, LEN, true, names)?.len,
+            },
+            "MapEnd" => unit(access, Token::MapEnd)?,
+            "Struct" => {
+                let h = fields(access, NAMED_LEN, false, names)?;
+                Token::Struct {
+                    name: h.name,
+                    len: h.len.unwrap_or_default(),
+                }
+            }
+            "StructEnd" => unit(access, Token::StructEnd)?,
+            "StructVariant" => {
+                let h = fields(access, VARIANT_LEN, false, names)?;
+                Token::StructVariant {
+                    name: h.name,
+                    variant_index: h.variant_index,
+                    variant: h.variant,
+                    len: h.len.unwrap_or_default(),
+                }
+            }
+            "StructVariantEnd" => unit(access, Token::StructVariantEnd)?,
+            "Field" => Token::Field(intern(names, &access.newtype_variant::<String>()?)?),
+            "MapKey" => unit(access, Token::MapKey)?,
+            "MapValue" => unit(access, Token::MapValue)?,
+            "Number" => Token::Number(Cow::Owned(access.newtype_variant()?)),
+            "StrChunk" => Token::StrChunk(Cow::Owned(access.newtype_variant()?)),
+            "StrEnd" => unit(access, Token::StrEnd)?,
+            "BytesChunk" => Token::BytesChunk(Cow::Owned(access.newtype_variant::<ByteBuf>()?.0)),
+            "BytesEnd" => unit(access, Token::BytesEnd)?,
+            "Extension" => Token::Extension {
+                tag: access.newtype_variant()?,
+            },
+            "DocumentStart" => unit(access, Token::DocumentStart)?,
+            "DocumentEnd" => unit(access, Token::DocumentEnd)?,
+            "Truncated" => Token::Truncated {
+                len: access.newtype_variant()?,
+            },
+            "Interned" => Token::Interned(access.newtype_variant::<String>()?.into()),
+            "Inline" => {
+                let v = access.newtype_variant::<String>()?;
+                InlineStr::new(&v).map_or(Token::String(v), Token::Inline)
+            }
+            _ => {
+                let h = fields(access, NAMED, false, names)?;
+                Token::Enum { name: h.name }
+            }
+        })
+    }
+}
+
+fn unit<'de, A: VariantAccess<'de>>(
+    access: A,
+    token: Token<'static>,
+) -> Result<Token<'static>, A::Error> {
+    access.unit_variant().map(|()| token)
+}
+
+fn fields<'de, A: VariantAccess<'de>>(
+    access: A,
+    fields: &'static [&'static str],
+    optional_len: bool,
+    names: &mut Option<&mut Interner>,
+) -> Result<Header, A::Error> {
+    access.struct_variant(
+        fields,
+        HeaderVisitor {
+            fields,
+            optional_len,
+            names: names.as_deref_mut(),
+        },
+    )
+}
+
+/// The name of a `Token` variant.
+struct Kind(&'static str);
+
+impl<'de> Deserialize<'de> for Kind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_identifier(KindVisitor)
+    }
+}
+
+struct KindVisitor;
+
+impl<'de> de::Visitor<'de> for KindVisitor {
+    type Value = Kind;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a token variant")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Kind, E> {
+        VARIANTS
+            .get(v as usize)
+            .map(|kind| Kind(kind))
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Kind, E> {
+        VARIANTS
+            .iter()
+            .find(|kind| **kind == v)
+            .map(|kind| Kind(kind))
+            .ok_or_else(|| E::unknown_variant(v, VARIANTS))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Kind, E> {
+        match std::str::from_utf8(v) {
+            Ok(v) => self.visit_str(v),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(v), &self)),
+        }
+    }
+}
+
+/// The fields of a header token.
+struct Header {
+    name: &'static str,
+    variant_index: u32,
+    variant: &'static str,
+    len: Option<usize>,
+}
+
+struct HeaderVisitor<'n> {
+    fields: &'static [&'static str],
+    optional_len: bool,
+    names: Option<&'n mut Interner>,
+}
+
+impl<'n> HeaderVisitor<'n> {
+    fn field<'de, A: MapAccess<'de>>(
+        &mut self,
+        field: &'static str,
+        map: &mut A,
+        header: &mut Header,
+    ) -> Result<(), A::Error> {
+        match field {
+            "name" => header.name = intern(&mut self.names, &map.next_value::<String>()?)?,
+            "variant_index" => header.variant_index = map.next_value()?,
+            "variant" => header.variant = intern(&mut self.names, &map.next_value::<String>()?)?,
+            _ if self.optional_len => header.len = map.next_value()?,
+            _ => header.len = Some(map.next_value()?),
+        }
+        Ok(())
+    }
+}
+
+impl<'de, 'n> de::Visitor<'de> for HeaderVisitor<'n> {
+    type Value = Header;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a token with fields {:?}", self.fields)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<Header, A::Error> {
+        let mut header = Header {
+            name: "",
+            variant_index: 0,
+            variant: "",
+            len: None,
+        };
+        for (i, field) in self.fields.iter().enumerate() {
+            let missing = || de::Error::invalid_length(i, &self);
+            match *field {
+                "name" => {
+                    let name = seq.next_element::<String>()?.ok_or_else(missing)?;
+                    header.name = intern(&mut self.names, &name)?
+                }
+                "variant_index" => {
+                    header.variant_index = seq.next_element()?.ok_or_else(missing)?
+                }
+                "variant" => {
+                    let variant = seq.next_element::<String>()?.ok_or_else(missing)?;
+                    header.variant = intern(&mut self.names, &variant)?
+                }
+                _ if self.optional_len => {
+                    header.len = seq.next_element::<Option<usize>>()?.ok_or_else(missing)?
+                }
+                _ => header.len = Some(seq.next_element::<usize>()?.ok_or_else(missing)?),
+            }
+        }
+        Ok(header)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<Header, A::Error> {
+        let mut header = Header {
+            name: "",
+            variant_index: 0,
+            variant: "",
+            len: None,
+        };
+        let mut seen = Vec::with_capacity(self.fields.len());
+        while let Some(key) = map.next_key::<String>()? {
+            let field = match self.fields.iter().find(|field| **field == key) {
+                Some(field) => *field,
+                None => return Err(de::Error::unknown_field(&key, self.fields)),
+            };
+            if seen.contains(&field) {
+                return Err(de::Error::duplicate_field(field));
+            }
+            seen.push(field);
+            self.field(field, &mut map, &mut header)?;
+        }
+        match self.fields.iter().find(|field| !seen.contains(field)) {
+            Some(field) => Err(de::Error::missing_field(field)),
+            None => Ok(header),
+        }
+    }
+}
+
+/// A byte buffer, deserialized from either bytes or a sequence of `u8`s.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> de::Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("bytes")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(ByteBuf(bytes))
+    }
+}
@@ -1,43 +1,2196 @@
-use crate::{error::Error, Token};
-use futures::sink::Sink;
+#[cfg(feature = "futures01")]
+use crate::sink::Forward;
+use crate::{
+    error::Error,
+    transcode::{transcode, transcode_into},
+    InlineStr, Token, EXTENSION_TOKEN, NUMBER_TOKEN,
+};
+#[cfg(feature = "std")]
+use crate::{
+    keys::{KeyInterner, KeyTracker},
+    tree::Node,
+};
+use alloc::{
+    borrow::{Cow, ToOwned},
+    boxed::Box,
+    collections::VecDeque,
+    vec::{self, Vec},
+};
+#[cfg(feature = "bumpalo")]
+use bumpalo::Bump;
+use core::{convert::Infallible, marker::PhantomData};
+#[cfg(feature = "futures01")]
+use futures::{sink::Sink, try_ready, Async, AsyncSink, Future, Poll};
 use serde::{
     de,
     ser::{self, Serialize},
 };
-use serde_transcode::transcode;
 
-/// Transcodes a deserializer into a [`futures::Sink`] of `Token`s.
+/// Transcodes a deserializer into a [`TokenSink`], such as a [`futures::Sink`] of `Token`s, flushing the sink once every token has been sent.
+///
+/// If a [`futures::Sink`] can't finish flushing without blocking, the remainder is left to the caller (e.g. by waiting on `Sink::flush`).
 ///
-/// *TODO*: currenty uses `unsafe` twice: both for coercing a `'de` lifetime on the deserialized and borrowed `&[u8]` or `&str`. I believe this is safe because the `Tokenizer` is only used within this function, and is thus always paired with an accompanying [`Deserializer<'de>`].
+/// Input nested more than 128 levels deep fails with `Error::DepthLimitExceeded`, unless the `stacker` feature is enabled, in which case the stack is grown onto the heap as needed. A lower limit can be set with [`TokenizeOptions::max_depth`].
 ///
+/// Strings and bytes the deserializer lends for `'de` are written as borrowed `Token::Str`s and `Token::Bytes`, and those it only provides transiently (such as `serde_json` strings containing escapes, or anything read from an `io::Read`) as copied ones.
+///
+/// [`TokenSink`]: trait.TokenSink.html
 /// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
-/// [`Deserializer<'de>`]: https://docs.serde.rs/serde/trait.Deserializer.html
+/// [`TokenizeOptions::max_depth`]: struct.TokenizeOptions.html#method.max_depth
 pub fn tokenize<'de, D, S>(deserializer: D, sink: S) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    let mut sink = transcode(deserializer, Tokenizer::borrowing(sink), false)?;
+    sink.flush().map_err(Into::into)
+}
+
+/// Transcodes a deserializer into a [`TokenSink`] like [`tokenize`], then closes the sink.
+///
+/// If a [`futures::Sink`] can't finish closing without blocking, this fails with `Error::TokenSinkNotReadyError`, rather than leaving tokens behind in the sink.
+///
+/// # Example:
+///
+/// ```
+/// # #[cfg(feature = "futures01")] {
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize_and_close, Token};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize_and_close(&mut de, token_sink).unwrap();
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![Token::Seq { len: None }, Token::Bool(true), Token::SeqEnd]);
+/// # }
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`tokenize`]: fn.tokenize.html
+pub fn tokenize_and_close<'de, D, S>(deserializer: D, sink: S) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    let mut sink = transcode(deserializer, Tokenizer::borrowing(sink), false)?;
+    sink.finish().map_err(Into::into)
+}
+
+/// Transcodes a deserializer into a [`TokenSink`], borrowing only the strings and bytes the deserializer lends for `'de`.
+///
+/// Strings and bytes the deserializer only provides transiently (such as `serde_json` strings containing escapes, or anything read from an `io::Read`) are promoted to owned `Token::String`s and `Token::ByteBuf`s, rather than written as copied `Token::Str`s and `Token::Bytes` as [`tokenize`] does.
+///
+/// # Example:
+///
+/// ```
+/// # #[cfg(feature = "futures01")] {
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize_adaptive, Token};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" ["plain", "esc\\aped"] "#);
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize_adaptive(&mut de, token_sink).unwrap();
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Seq { len: None },
+///     Token::Str("plain".into()),
+///     Token::String("esc\\aped".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// # }
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`tokenize`]: fn.tokenize.html
+pub fn tokenize_adaptive<'de, D, S>(deserializer: D, sink: S) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    let mut sink = transcode(deserializer, Tokenizer::borrowing(sink), true)?;
+    sink.flush().map_err(Into::into)
+}
+
+/// Transcodes a deserializer into a [`TokenSink`] of [`OwnedToken`]s, such as a channel to another thread or task, converting every string and bytes payload into an owned `Token::String` or `Token::ByteBuf`.
+///
+/// # Example:
+///
+/// ```
+/// # #[cfg(feature = "futures01")] {
+/// use futures::{sync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize_owned, OwnedToken, Token};
+///
+/// let (token_sink, token_stream) = unbounded::<OwnedToken>();
+/// {
+///     let json = String::from(r#" ["a"] "#);
+///     let mut de = serde_json::Deserializer::from_str(&json);
+///     tokenize_owned(&mut de, token_sink).unwrap();
+/// }
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Seq { len: None },
+///     Token::String("a".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// # }
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`OwnedToken`]: type.OwnedToken.html
+pub fn tokenize_owned<'de, D, S>(deserializer: D, sink: S) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: TokenSink<'static>,
+{
+    let OwnedSink(mut sink) = transcode(deserializer, Tokenizer::borrowing(OwnedSink(sink)), true)?;
+    sink.flush().map_err(Into::into)
+}
+
+/// Tokenizes any `Serialize` value into a [`TokenSink`], without going through a deserializer.
+///
+/// This is shorthand for serializing the value into a [`Tokenizer`], so strings and bytes (other than struct field names) are written as owned `Token::String`s and `Token::ByteBuf`s.
+///
+/// # Example:
+///
+/// ```
+/// # #[cfg(feature = "futures01")] {
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize_value, Token};
+///
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize_value(&("a", Some(1u8)), token_sink).unwrap();
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Tuple { len: 2 },
+///     Token::String("a".to_string()),
+///     Token::Some,
+///     Token::U8(1),
+///     Token::TupleEnd,
+/// ]);
+/// # }
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`Tokenizer`]: struct.Tokenizer.html
+pub fn tokenize_value<T, S>(value: &T, sink: S) -> Result<(), Error>
+where
+    T: ?Sized + Serialize,
+    S: TokenSink<'static>,
+{
+    value.serialize(&mut Tokenizer::new(sink))
+}
+
+/// A type-erased [`TokenSink`] whose errors are `Error`s (such as any [`futures::Sink`] of `Token`s), for use with [`tokenize_dyn`].
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`tokenize_dyn`]: fn.tokenize_dyn.html
+#[cfg(feature = "erased-serde")]
+pub type DynTokenSink<'de> = dyn TokenSink<'de, Error = Error> + 'de;
+
+/// Transcodes a type-erased deserializer into a boxed, type-erased [`TokenSink`].
+///
+/// Every call shares a single instantiation of the tokenizer, so binaries that tokenize many combinations of deserializers and sinks on cold paths can use this to curb code size, at the cost of dynamic dispatch per token. The sink's errors are returned as they are.
+///
+/// *Requires the `erased-serde` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{tokenize_dyn, Token};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// let (token_sink, token_stream) = unbounded::<Token>();
+///
+/// tokenize_dyn(
+///     &mut <dyn erased_serde::Deserializer>::erase(&mut de),
+///     Box::new(token_sink),
+/// )
+/// .unwrap();
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![Token::Seq { len: None }, Token::Bool(true), Token::SeqEnd]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+#[cfg(feature = "erased-serde")]
+#[inline(never)]
+pub fn tokenize_dyn<'de>(
+    deserializer: &mut dyn erased_serde::Deserializer<'de>,
+    sink: Box<DynTokenSink<'de>>,
+) -> Result<(), Error> {
+    tokenize(deserializer, BoxedSink(sink))
+}
+
+/// Forwards tokens to a boxed [`DynTokenSink`], which isn't itself a `TokenSink`.
+///
+/// [`DynTokenSink`]: type.DynTokenSink.html
+#[cfg(feature = "erased-serde")]
+struct BoxedSink<'de>(Box<DynTokenSink<'de>>);
+
+#[cfg(feature = "erased-serde")]
+impl<'de> TokenSink<'de> for BoxedSink<'de> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        self.0.write(token)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.0.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.0.finish()
+    }
+}
+
+/// Transcodes a deserializer into an [`InfallibleTokenSink`], such as a `Vec<Token>`.
+///
+/// The sink itself can never fail or be not ready, so it needs no error type, but every token still passes through the same [`Tokenizer`] as with [`tokenize`] (and so may fail, e.g. when nested too deeply). This is a convenience for such sinks, not a faster path.
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::Deserializer;
+/// use serde_token::{tokenize_infallible, Token};
+///
+/// let mut de = Deserializer::from_str(r#" [1, "a"] "#);
+/// let mut tokens = Vec::new();
+///
+/// tokenize_infallible(&mut de, &mut tokens).unwrap();
+///
+/// assert_eq!(tokens, vec![
+///     Token::Seq { len: None },
+///     Token::U64(1),
+///     Token::Str("a".into()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`InfallibleTokenSink`]: trait.InfallibleTokenSink.html
+/// [`Tokenizer`]: struct.Tokenizer.html
+/// [`tokenize`]: fn.tokenize.html
+pub fn tokenize_infallible<'de, D, S>(deserializer: D, sink: S) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: InfallibleTokenSink<'de>,
+{
+    transcode(
+        deserializer,
+        Tokenizer::borrowing(InfallibleEmitter(sink)),
+        false,
+    )
+    .map(drop)
+}
+
+/// Transcodes a deserializer into any collection implementing `Extend`, such as a `Vec`, a `VecDeque` or an arena.
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::Deserializer;
+/// use serde_token::{tokenize_into, Token};
+///
+/// let mut de = Deserializer::from_str(r#" {"a": 1} "#);
+/// let mut tokens = vec![Token::Unit];
+///
+/// tokenize_into(&mut de, &mut tokens).unwrap();
+///
+/// assert_eq!(tokens, vec![
+///     Token::Unit,
+///     Token::Map { len: None },
+///     Token::Str("a".into()),
+///     Token::U64(1),
+///     Token::MapEnd,
+/// ]);
+/// ```
+pub fn tokenize_into<'de, D, C>(deserializer: D, collection: &mut C) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    C: Extend<Token<'de>> + ?Sized,
+{
+    tokenize_infallible(deserializer, ExtendSink::new(collection))
+}
+
+/// Transcodes a deserializer by calling the given closure with each `Token`, stopping at the first error it returns.
+///
+/// The closure's error is preserved in `Error::TokenSinkError`, and is available via `std::error::Error::source`.
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::Deserializer;
+/// use serde_token::{tokenize_with, Token};
+///
+/// let mut de = Deserializer::from_str(r#" [1, [2, 3]] "#);
+/// let mut scalars = 0;
+///
+/// tokenize_with(&mut de, |token| {
+///     if let Token::U64(_) = token {
+///         scalars += 1;
+///     }
+///     Ok::<_, std::convert::Infallible>(())
+/// })
+/// .unwrap();
+///
+/// assert_eq!(scalars, 3);
+/// ```
+pub fn tokenize_with<'de, D, F, E>(deserializer: D, f: F) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    F: FnMut(Token<'de>) -> Result<(), E>,
+    E: core::error::Error + Send + Sync + 'static,
+{
+    transcode(deserializer, Tokenizer::borrowing(FnSink(f)), false).map(drop)
+}
+
+/// Transcodes a deserializer into a [`futures::Sink`] of `Token`s, returning a future that waits for the sink to accept and complete each token, rather than failing with `Error::TokenSinkNotReadyError` as [`tokenize`] does.
+///
+/// Since a deserializer can't be suspended mid-value, it is first tokenized into a buffer; if that fails, the future fails without sending any tokens. The future resolves to the sink once every token has been sent and flushed.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{sync::mpsc::channel, Future, Stream};
+/// use serde_token::{tokenize_send, Token};
+/// use std::thread;
+///
+/// let (token_sink, token_stream) = channel::<Token<'static>>(0);
+/// let consumer = thread::spawn(move || token_stream.collect().wait().unwrap());
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// drop(tokenize_send(&mut de, token_sink).wait().unwrap());
+///
+/// assert_eq!(consumer.join().unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`tokenize`]: fn.tokenize.html
+#[cfg(feature = "futures01")]
+pub fn tokenize_send<'de, D, S>(deserializer: D, sink: S) -> TokenizeSend<S>
 where
     D: de::Deserializer<'de>,
     S: Sink<SinkItem = Token<'de>>,
+    S::SinkError: Into<Error>,
 {
-    let mut tokenizer = Tokenizer(sink);
-    transcode(deserializer, &mut tokenizer)
+    let mut forward = Forward::new(sink);
+    match to_tokens(deserializer) {
+        Ok(tokens) => {
+            forward.extend(tokens);
+            TokenizeSend {
+                sink: Some(forward),
+                error: None,
+            }
+        }
+        Err(err) => TokenizeSend {
+            sink: Some(forward),
+            error: Some(err),
+        },
+    }
 }
 
-#[derive(Clone, Debug)]
-struct Tokenizer<'a, S: Sink<SinkItem = Token<'a>>>(S);
+/// A future that sends the tokens of a deserializer to a sink, returned by [`tokenize_send`].
+///
+/// [`tokenize_send`]: fn.tokenize_send.html
+#[cfg(feature = "futures01")]
+pub struct TokenizeSend<S: Sink> {
+    sink: Option<Forward<S>>,
+    error: Option<Error>,
+}
+
+#[cfg(feature = "futures01")]
+impl<S> Future for TokenizeSend<S>
+where
+    S: Sink,
+    S::SinkError: Into<Error>,
+{
+    type Item = S;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<S, Error> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let sink = self
+            .sink
+            .as_mut()
+            .expect("polled TokenizeSend after completion");
+        try_ready!(sink.poll_complete().map_err(Into::into));
+        Ok(Async::Ready(self.sink.take().unwrap().into_inner()))
+    }
+}
 
-impl<'a, S: Sink<SinkItem = Token<'a>>> Tokenizer<'a, S> {
-    fn write_token(&mut self, token: Token<'a>) -> Result<(), Error> {
-        use futures::AsyncSink;
+/// Transcodes a deserializer into a `Vec` of `Token`s.
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::Deserializer;
+/// use serde_token::{to_tokens, Token};
+///
+/// let mut de = Deserializer::from_str(r#" {"a": true} "#);
+///
+/// assert_eq!(to_tokens(&mut de).unwrap(), vec![
+///     Token::Map { len: None },
+///     Token::Str("a".into()),
+///     Token::Bool(true),
+///     Token::MapEnd,
+/// ]);
+/// ```
+pub fn to_tokens<'de, D>(deserializer: D) -> Result<Vec<Token<'de>>, Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let mut tokens = Vec::new();
+    tokenize_infallible(deserializer, &mut tokens)?;
+    Ok(tokens)
+}
+
+/// Transcodes a deserializer into an iterator of `Token`s, for synchronous consumers.
+///
+/// The deserializer is tokenized into an internal buffer up front; if it fails, the tokens produced before the failure are yielded, followed by the error.
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::Deserializer;
+/// use serde_token::{tokenize_iter, Token};
+///
+/// let mut de = Deserializer::from_str(r#" [1, "a"] "#);
+/// let tokens: Result<Vec<Token>, _> = tokenize_iter(&mut de).collect();
+///
+/// assert_eq!(tokens.unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::U64(1),
+///     Token::Str("a".into()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+pub fn tokenize_iter<'de, D>(deserializer: D) -> TokenIter<'de>
+where
+    D: de::Deserializer<'de>,
+{
+    let mut tokens = Vec::new();
+    let error = tokenize_infallible(deserializer, &mut tokens).err();
+    TokenIter {
+        tokens: tokens.into_iter(),
+        error,
+    }
+}
+
+/// An iterator over the `Token`s of a deserializer, returned by [`tokenize_iter`].
+///
+/// [`tokenize_iter`]: fn.tokenize_iter.html
+#[derive(Debug)]
+pub struct TokenIter<'de> {
+    tokens: vec::IntoIter<Token<'de>>,
+    error: Option<Error>,
+}
+
+impl<'de> Iterator for TokenIter<'de> {
+    type Item = Result<Token<'de>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.tokens.next() {
+            Some(token) => Some(Ok(token)),
+            None => self.error.take().map(Err),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.tokens.len() + self.error.is_some() as usize;
+        (len, Some(len))
+    }
+}
+
+/// A sink of `Token`s that can never fail, such as a `Vec` or an arena-backed collector.
+pub trait InfallibleTokenSink<'de> {
+    /// Appends a token to the sink.
+    fn push_token(&mut self, token: Token<'de>);
+}
+
+impl<'de> InfallibleTokenSink<'de> for Vec<Token<'de>> {
+    #[inline]
+    fn push_token(&mut self, token: Token<'de>) {
+        self.push(token)
+    }
+}
+
+impl<'de> InfallibleTokenSink<'de> for VecDeque<Token<'de>> {
+    #[inline]
+    fn push_token(&mut self, token: Token<'de>) {
+        self.push_back(token)
+    }
+}
+
+impl<'de, S: InfallibleTokenSink<'de> + ?Sized> InfallibleTokenSink<'de> for &mut S {
+    #[inline]
+    fn push_token(&mut self, token: Token<'de>) {
+        (**self).push_token(token)
+    }
+}
+
+/// An [`InfallibleTokenSink`] that appends `Token`s to any collection implementing `Extend`, such as a `Vec`, a `VecDeque` or an arena.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize_infallible, ExtendSink, Token};
+/// use std::collections::LinkedList;
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+/// let mut tokens = LinkedList::new();
+/// tokenize_infallible(&mut de, ExtendSink::new(&mut tokens)).unwrap();
+///
+/// assert_eq!(tokens.into_iter().collect::<Vec<_>>(), vec![
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`InfallibleTokenSink`]: trait.InfallibleTokenSink.html
+#[derive(Debug)]
+pub struct ExtendSink<'c, C: ?Sized>(&'c mut C);
+
+impl<'c, C: ?Sized> ExtendSink<'c, C> {
+    /// Wraps the given collection.
+    pub fn new(collection: &'c mut C) -> Self {
+        ExtendSink(collection)
+    }
+
+    /// Consumes the `ExtendSink`, returning the wrapped collection.
+    pub fn into_inner(self) -> &'c mut C {
         self.0
-            .start_send(token)
-            .map_err(|_| Error::TokenSinkError)
-            .and_then(|sink| match sink {
-                AsyncSink::Ready => Ok(()),
-                AsyncSink::NotReady(_) => Err(Error::TokenSinkNotReadyError),
-            })
     }
 }
 
-impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Tokenizer<'a, S> {
+impl<'c, 'de, C: Extend<Token<'de>> + ?Sized> InfallibleTokenSink<'de> for ExtendSink<'c, C> {
+    #[inline]
+    fn push_token(&mut self, token: Token<'de>) {
+        self.0.extend(Some(token))
+    }
+}
+
+/// A destination for `Token`s, such as a [`futures::Sink`] or a channel.
+///
+/// With the `futures01` feature, this is implemented for every [`futures::Sink`] of `Token`s whose error converts `Into<Error>` (such as a channel's `SendError`, which is kept as the `source` of the returned error), where a sink that is not ready fails with `Error::TokenSinkNotReadyError`. Other sinks are passed by value, as only [`futures::Sink`]s can also be passed by `&mut` reference.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize, Error, Token, TokenSink};
+///
+/// struct Count<'c>(&'c mut usize);
+///
+/// impl<'c, 'de> TokenSink<'de> for Count<'c> {
+///     type Error = Error;
+///
+///     fn write(&mut self, _: Token<'de>) -> Result<(), Error> {
+///         *self.0 += 1;
+///         Ok(())
+///     }
+/// }
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" [1, 2] "#);
+/// let mut count = 0;
+/// tokenize(&mut de, Count(&mut count)).unwrap();
+///
+/// assert_eq!(count, 4);
+/// ```
+///
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+pub trait TokenSink<'de> {
+    /// The error returned when the sink fails.
+    type Error: Into<Error>;
+
+    /// Writes a token to the sink.
+    fn write(&mut self, token: Token<'de>) -> Result<(), Self::Error>;
+
+    /// Flushes any tokens buffered by the sink, called once a value has been completely written.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Flushes the sink and marks it as finished, such as by closing a [`futures::Sink`].
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<'de, S> TokenSink<'de> for S
+where
+    S: Sink<SinkItem = Token<'de>>,
+    S::SinkError: Into<Error>,
+{
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        match self.start_send(token) {
+            Ok(AsyncSink::Ready) => Ok(()),
+            Ok(AsyncSink::NotReady(_)) => Err(Error::TokenSinkNotReadyError),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Starts flushing the sink, failing only if the sink does.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.poll_complete().map(drop).map_err(Into::into)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        match self.close() {
+            Ok(Async::Ready(())) => Ok(()),
+            Ok(Async::NotReady) => Err(Error::TokenSinkNotReadyError),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+pub(crate) struct InfallibleEmitter<S>(S);
+
+impl<'a, S: InfallibleTokenSink<'a>> TokenSink<'a> for InfallibleEmitter<S> {
+    type Error = Infallible;
+
+    #[inline]
+    fn write(&mut self, token: Token<'a>) -> Result<(), Infallible> {
+        self.0.push_token(token);
+        Ok(())
+    }
+}
+
+/// Converts each token into an `OwnedToken` before writing it to the wrapped sink.
+struct OwnedSink<S>(S);
+
+impl<'a, S: TokenSink<'static>> TokenSink<'a> for OwnedSink<S> {
+    type Error = S::Error;
+
+    #[inline]
+    fn write(&mut self, token: Token<'a>) -> Result<(), S::Error> {
+        self.0.write(token.into_owned())
+    }
+
+    fn flush(&mut self) -> Result<(), S::Error> {
+        self.0.flush()
+    }
+
+    fn finish(&mut self) -> Result<(), S::Error> {
+        self.0.finish()
+    }
+}
+
+/// Transcodes a deserializer into a [`TokenSink`] like [`tokenize`], configuring the output with the given [`TokenizeOptions`].
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize_with_options, ChannelSink, Token, TokenizeOptions};
+/// use std::sync::mpsc::channel;
+///
+/// let options = TokenizeOptions::new().map_markers(true).document_markers(true);
+/// let mut de = serde_json::Deserializer::from_str(r#" {"a": 1} "#);
+/// let (tx, rx) = channel();
+/// tokenize_with_options(&mut de, ChannelSink::new(tx), &options).unwrap();
+///
+/// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+///     Token::DocumentStart,
+///     Token::Map { len: None },
+///     Token::MapKey,
+///     Token::String("a".to_string()),
+///     Token::MapValue,
+///     Token::U64(1),
+///     Token::MapEnd,
+///     Token::DocumentEnd,
+/// ]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`tokenize`]: fn.tokenize.html
+/// [`TokenizeOptions`]: struct.TokenizeOptions.html
+pub fn tokenize_with_options<'de, D, S>(
+    deserializer: D,
+    sink: S,
+    options: &TokenizeOptions,
+) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    let tokenizer = Tokenizer::borrowing(sink).with_options(options);
+    let mut sink = transcode(deserializer, tokenizer, false)?;
+    sink.flush().map_err(Into::into)
+}
+
+/// Transcodes each of an iterator of deserializers into a single [`TokenSink`], wrapping each document in `Token::DocumentStart` and `Token::DocumentEnd` markers, then flushes the sink.
+///
+/// Sources that read successive documents from one deserializer (such as `serde_json`'s whitespace-separated values) can instead be tokenized one document at a time with [`Tokenizer::tokenize_next`].
+///
+/// # Example:
+///
+/// ```
+/// use serde_json::json;
+/// use serde_token::{tokenize_many, ChannelSink, Token};
+/// use std::sync::mpsc::channel;
+///
+/// let docs = vec![json!([true]), json!("a")];
+/// let (tx, rx) = channel();
+/// tokenize_many(&docs, ChannelSink::new(tx)).unwrap();
+///
+/// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+///     Token::DocumentStart,
+///     Token::Seq { len: Some(1) },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+///     Token::DocumentEnd,
+///     Token::DocumentStart,
+///     Token::String("a".to_string()),
+///     Token::DocumentEnd,
+/// ]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`Tokenizer::tokenize_next`]: struct.Tokenizer.html#method.tokenize_next
+pub fn tokenize_many<'de, I, S>(deserializers: I, sink: S) -> Result<(), Error>
+where
+    I: IntoIterator,
+    I::Item: de::Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    let mut tokenizer =
+        Tokenizer::borrowing(sink).with_options(&TokenizeOptions::new().document_markers(true));
+    for deserializer in deserializers {
+        transcode_into(deserializer, &mut tokenizer, false)?;
+    }
+    tokenizer.into_inner().flush().map_err(Into::into)
+}
+
+/// Configuration for the tokens a [`Tokenizer`] (or [`tokenize_with_options`]) writes.
+///
+/// Every option defaults to the plain token stream written by [`tokenize`]. A [`Tokenizer`] is configured by passing the options to [`Tokenizer::with_options`], and its current options can be read back with [`Tokenizer::options`].
+///
+/// [`Tokenizer`]: struct.Tokenizer.html
+/// [`Tokenizer::with_options`]: struct.Tokenizer.html#method.with_options
+/// [`Tokenizer::options`]: struct.Tokenizer.html#method.options
+/// [`tokenize_with_options`]: fn.tokenize_with_options.html
+/// [`tokenize`]: fn.tokenize.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenizeOptions {
+    pub(crate) fields: bool,
+    pub(crate) markers: bool,
+    pub(crate) enums: bool,
+    pub(crate) documents: bool,
+    pub(crate) str_chunk: Option<usize>,
+    pub(crate) bytes_chunk: Option<usize>,
+    pub(crate) human_readable: bool,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_len: Option<usize>,
+    pub(crate) truncate: bool,
+    pub(crate) max_owned: Option<usize>,
+    pub(crate) integers: IntegerWidth,
+    pub(crate) struct_maps: bool,
+    pub(crate) tuple_seqs: bool,
+    pub(crate) non_finite: FloatPolicy,
+    pub(crate) strings: Option<StringPolicy>,
+    pub(crate) inline_strings: bool,
+    pub(crate) borrowed_only: bool,
+    #[cfg(feature = "std")]
+    pub(crate) unique_keys: bool,
+    #[cfg(feature = "std")]
+    pub(crate) sorted_maps: bool,
+    #[cfg(feature = "std")]
+    pub(crate) interned_keys: bool,
+}
+
+impl Default for TokenizeOptions {
+    fn default() -> Self {
+        TokenizeOptions {
+            fields: false,
+            markers: false,
+            enums: false,
+            documents: false,
+            str_chunk: None,
+            bytes_chunk: None,
+            human_readable: true,
+            max_depth: None,
+            max_len: None,
+            truncate: false,
+            max_owned: None,
+            integers: IntegerWidth::Native,
+            struct_maps: false,
+            tuple_seqs: false,
+            non_finite: FloatPolicy::PassThrough,
+            strings: None,
+            inline_strings: false,
+            borrowed_only: false,
+            #[cfg(feature = "std")]
+            unique_keys: false,
+            #[cfg(feature = "std")]
+            sorted_maps: false,
+            #[cfg(feature = "std")]
+            interned_keys: false,
+        }
+    }
+}
+
+impl TokenizeOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether struct field names are written as `Token::Field`s rather than `Token::Str`s, so that consumers can tell them apart from map keys.
+    ///
+    /// Defaults to `false`, for compatibility with consumers expecting `Str` keys.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Point {
+    ///     x: u8,
+    /// }
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().field_tokens(true);
+    /// Point { x: 1 }.serialize(&mut Tokenizer::new(ChannelSink::new(tx)).with_options(&options)).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Struct { name: "Point", len: 1 },
+    ///     Token::Field("x"),
+    ///     Token::U8(1),
+    ///     Token::StructEnd,
+    /// ]);
+    /// ```
+    pub fn field_tokens(mut self, enabled: bool) -> Self {
+        self.fields = enabled;
+        self
+    }
+
+    /// Sets whether each map key and value is preceded by a `Token::MapKey` or `Token::MapValue` marker, so that consumers can tell keys from values without tracking each map's state themselves.
+    ///
+    /// Defaults to `false`. Struct fields are not marked (unless written as maps with [`structs_as_maps`]), as their names are always single `Str` or `Field` tokens.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" {"a": [true]} "#);
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().map_markers(true);
+    /// Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Map { len: None },
+    ///     Token::MapKey,
+    ///     Token::String("a".to_string()),
+    ///     Token::MapValue,
+    ///     Token::Seq { len: None },
+    ///     Token::Bool(true),
+    ///     Token::SeqEnd,
+    ///     Token::MapEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`structs_as_maps`]: #method.structs_as_maps
+    pub fn map_markers(mut self, enabled: bool) -> Self {
+        self.markers = enabled;
+        self
+    }
+
+    /// Sets whether each enum variant is preceded by a `Token::Enum` header naming its enum, so that consumers can recognise enum values before reading their variant.
+    ///
+    /// Defaults to `false`, for compatibility with consumers expecting variant tokens alone.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// #[derive(Serialize)]
+    /// enum Shape {
+    ///     Circle(u8),
+    /// }
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().enum_headers(true);
+    /// Shape::Circle(1).serialize(&mut Tokenizer::new(ChannelSink::new(tx)).with_options(&options)).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Enum { name: "Shape" },
+    ///     Token::NewtypeVariant { name: "Shape", variant_index: 0, variant: "Circle" },
+    ///     Token::U8(1),
+    /// ]);
+    /// ```
+    pub fn enum_headers(mut self, enabled: bool) -> Self {
+        self.enums = enabled;
+        self
+    }
+
+    /// Sets whether each top-level value is wrapped in `Token::DocumentStart` and `Token::DocumentEnd` markers, so that consumers of a long-lived sink of many values can tell where each one begins and ends without tracking nesting themselves.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().document_markers(true);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    /// Some(1u8).serialize(&mut tokenizer).unwrap();
+    /// vec![true].serialize(&mut tokenizer).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::DocumentStart,
+    ///     Token::Some,
+    ///     Token::U8(1),
+    ///     Token::DocumentEnd,
+    ///     Token::DocumentStart,
+    ///     Token::Seq { len: Some(1) },
+    ///     Token::Bool(true),
+    ///     Token::SeqEnd,
+    ///     Token::DocumentEnd,
+    /// ]);
+    /// ```
+    pub fn document_markers(mut self, enabled: bool) -> Self {
+        self.documents = enabled;
+        self
+    }
+
+    /// Splits each string longer than `chunk_size` bytes into a run of `Token::StrChunk`s of at most `chunk_size` bytes (or a single character, if longer), followed by a `Token::StrEnd`, so that consumers can process huge strings incrementally.
+    ///
+    /// Chunks of borrowed strings are borrowed too. Strings are not chunked by default.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" ["abcde", "ab"] "#);
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().chunk_strings(2);
+    /// Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Seq { len: None },
+    ///     Token::StrChunk("ab".into()),
+    ///     Token::StrChunk("cd".into()),
+    ///     Token::StrChunk("e".into()),
+    ///     Token::StrEnd,
+    ///     Token::String("ab".to_string()),
+    ///     Token::SeqEnd,
+    /// ]);
+    /// ```
+    pub fn chunk_strings(mut self, chunk_size: usize) -> Self {
+        self.str_chunk = Some(chunk_size);
+        self
+    }
+
+    /// Splits each byte array longer than `chunk_size` bytes into a run of `Token::BytesChunk`s of at most `chunk_size` bytes, followed by a `Token::BytesEnd`, so that large binary payloads need not be held in a single buffer.
+    ///
+    /// Chunks of borrowed byte arrays are borrowed too. Byte arrays are not chunked by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn chunk_bytes(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        self.bytes_chunk = Some(chunk_size);
+        self
+    }
+
+    /// Sets whether the tokenizer reports itself as human-readable to `Serialize` impls (via `Serializer::is_human_readable`), which some types use to choose between a readable and a compact representation.
+    ///
+    /// Defaults to `true`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::{net::Ipv4Addr, sync::mpsc::channel};
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().human_readable(false);
+    /// Ipv4Addr::LOCALHOST
+    ///     .serialize(&mut Tokenizer::new(ChannelSink::new(tx)).with_options(&options))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().next(), Some(Token::Tuple { len: 4 }));
+    /// ```
+    pub fn human_readable(mut self, enabled: bool) -> Self {
+        self.human_readable = enabled;
+        self
+    }
+
+    /// Sets the nesting depth beyond which tokenization fails with `Error::DepthLimitExceeded`, to bound the resources spent on untrusted input.
+    ///
+    /// When transcoding a deserializer, `Some`s and newtypes count towards the depth as well as containers. Without the `stacker` feature, depths beyond the default limit of 128 are still rejected, to avoid overflowing the stack. Values serialized into the tokenizer are only limited when this is set.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Error, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let (tx, _rx) = channel();
+    /// let options = TokenizeOptions::new().max_depth(2);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    ///
+    /// assert!(vec![vec![1]].serialize(&mut tokenizer).is_ok());
+    /// match vec![vec![vec![1]]].serialize(&mut tokenizer) {
+    ///     Err(Error::DepthLimitExceeded { depth }) => assert_eq!(depth, 3),
+    ///     result => panic!("unexpected {:?}", result),
+    /// }
+    /// ```
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets the length in bytes beyond which a `Str`, `String`, `Bytes` or `ByteBuf` payload fails tokenization with `Error::LengthLimitExceeded`, so that a single huge string can't exhaust memory.
+    ///
+    /// Payloads are not limited by default. See [`truncate_long_payloads`] to truncate them instead.
+    ///
+    /// [`truncate_long_payloads`]: #method.truncate_long_payloads
+    pub fn max_payload_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Sets whether strings and byte arrays longer than the [`max_payload_len`] are truncated to it (strings on a character boundary) and preceded by a `Token::Truncated` marker of their original length, rather than failing tokenization.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" ["abcdef", "ab"] "#);
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().max_payload_len(4).truncate_long_payloads(true);
+    /// Tokenizer::new(ChannelSink::new(tx))
+    ///     .with_options(&options)
+    ///     .tokenize(&mut de)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Seq { len: None },
+    ///     Token::Truncated { len: 6 },
+    ///     Token::String("abcd".to_string()),
+    ///     Token::String("ab".to_string()),
+    ///     Token::SeqEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`max_payload_len`]: #method.max_payload_len
+    pub fn truncate_long_payloads(mut self, enabled: bool) -> Self {
+        self.truncate = enabled;
+        self
+    }
+
+    /// Sets a budget for the total length in bytes of the owned (or copied) strings, byte arrays and numbers the tokenizer writes, beyond which tokenization fails with `Error::MemoryBudgetExceeded`, so that one input can't claim more than its share of memory, such as in a multi-tenant service.
+    ///
+    /// This complements [`max_payload_len`] and [`max_depth`], which limit single payloads and nesting rather than the total. The cost is approximate: it counts payload bytes, not allocator overhead or the tokens themselves, and payloads borrowed from the input (or an [`arena`]) or stored inline are free. Payloads are not budgeted by default.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Error, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let json = format!(r#" ["{}", "{}"] "#, "a".repeat(60), "b".repeat(60));
+    /// let mut de = serde_json::Deserializer::from_reader(json.as_bytes());
+    /// let (tx, _rx) = channel();
+    ///
+    /// let options = TokenizeOptions::new().max_owned_bytes(100);
+    /// match Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de) {
+    ///     Err(Error::MemoryBudgetExceeded { used, budget }) => assert_eq!((used, budget), (120, 100)),
+    ///     _ => panic!("expected the memory budget to be exceeded"),
+    /// }
+    /// ```
+    ///
+    /// [`max_payload_len`]: #method.max_payload_len
+    /// [`max_depth`]: #method.max_depth
+    /// [`arena`]: struct.Tokenizer.html#method.arena
+    pub fn max_owned_bytes(mut self, budget: usize) -> Self {
+        self.max_owned = Some(budget);
+        self
+    }
+
+    /// Sets the width that integer tokens are widened to, so that token streams from formats that report the same number with different widths (e.g. `U8` and `U64`) compare equal.
+    ///
+    /// Signedness is preserved, so a `U8` is widened to a `U64` (or `U128`) and an `I8` to an `I64` (or `I128`). Defaults to [`IntegerWidth::Native`], which leaves integers as they are.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, IntegerWidth, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().normalize_integers(IntegerWidth::Bits64);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    /// (1u8, -2i16, 3u128).serialize(&mut tokenizer).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Tuple { len: 3 },
+    ///     Token::U64(1),
+    ///     Token::I64(-2),
+    ///     Token::U128(3),
+    ///     Token::TupleEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`IntegerWidth::Native`]: enum.IntegerWidth.html#variant.Native
+    pub fn normalize_integers(mut self, width: IntegerWidth) -> Self {
+        self.integers = width;
+        self
+    }
+
+    /// Sets whether structs are written as `Token::Map`s keyed by their field names (ending with `Token::MapEnd`), for consumers that only understand maps, sequences and scalars.
+    ///
+    /// Struct variants are written as a single-entry map from the variant's name to a map of its fields, as in serde's externally tagged representation, and so aren't preceded by an [`enum_headers`] header. Field names are written as strings even with [`field_tokens`], and are preceded by markers with [`map_markers`]. Either shape is accepted by [`from_tokens`]. Defaults to `false`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// #[derive(Serialize)]
+    /// enum Shape {
+    ///     Circle { r: u8 },
+    /// }
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().structs_as_maps(true);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    /// Shape::Circle { r: 1 }.serialize(&mut tokenizer).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Map { len: Some(1) },
+    ///     Token::String("Circle".to_string()),
+    ///     Token::Map { len: Some(1) },
+    ///     Token::String("r".to_string()),
+    ///     Token::U8(1),
+    ///     Token::MapEnd,
+    ///     Token::MapEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`enum_headers`]: #method.enum_headers
+    /// [`field_tokens`]: #method.field_tokens
+    /// [`map_markers`]: #method.map_markers
+    /// [`from_tokens`]: fn.from_tokens.html
+    pub fn structs_as_maps(mut self, enabled: bool) -> Self {
+        self.struct_maps = enabled;
+        self
+    }
+
+    /// Sets whether tuples and tuple structs are written as `Token::Seq`s with their length (ending with `Token::SeqEnd`), for consumers that treat every sequence-like value positionally.
+    ///
+    /// Tuple variants are written as a single-entry map from the variant's name to a sequence of its fields, as with [`structs_as_maps`], and so aren't preceded by an [`enum_headers`] header. Either shape is accepted by [`from_tokens`]. Defaults to `false`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Pair(u8, bool);
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().tuples_as_seqs(true);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    /// Pair(1, true).serialize(&mut tokenizer).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Seq { len: Some(2) },
+    ///     Token::U8(1),
+    ///     Token::Bool(true),
+    ///     Token::SeqEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`structs_as_maps`]: #method.structs_as_maps
+    /// [`enum_headers`]: #method.enum_headers
+    /// [`from_tokens`]: fn.from_tokens.html
+    pub fn tuples_as_seqs(mut self, enabled: bool) -> Self {
+        self.tuple_seqs = enabled;
+        self
+    }
+
+    /// Sets how `NaN` and infinite `F32` and `F64` tokens are handled, for consumers (such as JSON renderers or canonical hashes) that can't represent them.
+    ///
+    /// Defaults to [`FloatPolicy::PassThrough`], which writes them as they are.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_token::{ChannelSink, Error, FloatPolicy, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().non_finite_floats(FloatPolicy::Null);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    /// [1.5, f64::NAN].serialize(&mut tokenizer).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Tuple { len: 2 },
+    ///     Token::F64(1.5),
+    ///     Token::None,
+    ///     Token::TupleEnd,
+    /// ]);
+    ///
+    /// let options = tokenizer.options().clone().non_finite_floats(FloatPolicy::Reject);
+    /// let mut tokenizer = tokenizer.with_options(&options);
+    /// match f32::INFINITY.serialize(&mut tokenizer) {
+    ///     Err(Error::NonFiniteFloat { value }) => assert_eq!(value, f64::INFINITY),
+    ///     result => panic!("unexpected {:?}", result),
+    /// }
+    /// ```
+    ///
+    /// [`FloatPolicy::PassThrough`]: enum.FloatPolicy.html#variant.PassThrough
+    pub fn non_finite_floats(mut self, policy: FloatPolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// Sets whether string and bytes payloads are written as `Token::Str`s and `Token::Bytes` (which can borrow from the input, but are bound to its lifetime) or as `Token::String`s and `Token::ByteBuf`s (which can be retained or sent anywhere).
+    ///
+    /// By default, this depends on how the tokenizer is used: values serialized into a `Tokenizer::new` are written as owned tokens, [`tokenize`] writes `Str`s and `Bytes` (copying those the deserializer doesn't lend), and [`tokenize_adaptive`] and [`Tokenizer::tokenize`](struct.Tokenizer.html#method.tokenize) only borrow what the deserializer lends.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// # #[cfg(feature = "futures01")] {
+    /// use futures::{unsync::mpsc::unbounded, Future, Stream};
+    /// use serde_token::{StringPolicy, Token, TokenizeOptions, Tokenizer};
+    ///
+    /// let json = r#" ["plain", "esc\\aped"] "#;
+    /// let tokens = |policy| {
+    ///     let (token_sink, token_stream) = unbounded::<Token>();
+    ///     let mut de = serde_json::Deserializer::from_str(json);
+    ///     let options = TokenizeOptions::new().string_policy(policy);
+    ///     Tokenizer::new(token_sink).with_options(&options).tokenize(&mut de).unwrap();
+    ///     token_stream.collect().wait().unwrap()
+    /// };
+    ///
+    /// assert_eq!(tokens(StringPolicy::Borrowed)[1..3], [
+    ///     Token::Str("plain".into()),
+    ///     Token::Str("esc\\aped".into()),
+    /// ]);
+    /// assert_eq!(tokens(StringPolicy::Adaptive)[1..3], [
+    ///     Token::Str("plain".into()),
+    ///     Token::String("esc\\aped".to_string()),
+    /// ]);
+    /// assert_eq!(tokens(StringPolicy::Owned)[1..3], [
+    ///     Token::String("plain".to_string()),
+    ///     Token::String("esc\\aped".to_string()),
+    /// ]);
+    /// # }
+    /// ```
+    ///
+    /// [`tokenize`]: fn.tokenize.html
+    /// [`tokenize_adaptive`]: fn.tokenize_adaptive.html
+    pub fn string_policy(mut self, policy: StringPolicy) -> Self {
+        self.strings = Some(policy);
+        self
+    }
+
+    /// Sets whether strings that would otherwise be copied into a `Token::String` (or a copied `Token::Str`) are written as `Token::Inline`s if they are at most [`InlineStr::CAPACITY`] bytes long, storing them within the token rather than allocating each one on the heap.
+    ///
+    /// Most map keys and many values are this short, so this saves an allocation per string when tokenizing into owned tokens. Borrowed strings are left as they are, unless [`string_policy`] copies them. Defaults to `false`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, InlineStr, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let long = "long".repeat(10);
+    /// let json = format!(r#" ["id", "{}"] "#, long);
+    /// let mut de = serde_json::Deserializer::from_reader(json.as_bytes());
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().inline_strings(true);
+    /// Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Seq { len: None },
+    ///     Token::Inline(InlineStr::new("id").unwrap()),
+    ///     Token::String(long),
+    ///     Token::SeqEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`InlineStr::CAPACITY`]: struct.InlineStr.html#associatedconstant.CAPACITY
+    /// [`string_policy`]: #method.string_policy
+    pub fn inline_strings(mut self, enabled: bool) -> Self {
+        self.inline_strings = enabled;
+        self
+    }
+
+    /// Sets whether a string or byte array that would have to be copied onto the heap, as the input only lends it transiently, fails tokenization with `Error::TransientPayload`, so that tokenization performs no heap allocation per token.
+    ///
+    /// Payloads borrowed from the input are written as they are, as are short strings with [`inline_strings`], payloads copied into an [`arena`] and `String`s the input gives away. The guarantee holds as long as the sink doesn't allocate, and no option that buffers tokens ([`sort_map_keys`]), tracks keys ([`reject_duplicate_keys`], [`on_duplicate_key`] and [`intern_keys`]) or copies payloads (chunking owned strings or bytes, or a [`string_policy`] of `Owned`) is enabled. Errors may allocate. Defaults to `false`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{tokenize_with_options, Error, TokenizeOptions};
+    /// # struct Count;
+    /// # impl<'de> serde_token::TokenSink<'de> for Count {
+    /// #     type Error = Error;
+    /// #     fn write(&mut self, _: serde_token::Token<'de>) -> Result<(), Error> { Ok(()) }
+    /// # }
+    ///
+    /// let options = TokenizeOptions::new().borrowed_only(true);
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" ["plain"] "#);
+    /// assert!(tokenize_with_options(&mut de, Count, &options).is_ok());
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" ["esc\\aped"] "#);
+    /// match tokenize_with_options(&mut de, Count, &options) {
+    ///     Err(Error::TransientPayload { len }) => assert_eq!(len, 8),
+    ///     _ => panic!("expected a transient payload error"),
+    /// }
+    /// ```
+    ///
+    /// [`inline_strings`]: #method.inline_strings
+    /// [`arena`]: struct.Tokenizer.html#method.arena
+    /// [`sort_map_keys`]: #method.sort_map_keys
+    /// [`reject_duplicate_keys`]: #method.reject_duplicate_keys
+    /// [`on_duplicate_key`]: struct.Tokenizer.html#method.on_duplicate_key
+    /// [`intern_keys`]: #method.intern_keys
+    /// [`string_policy`]: #method.string_policy
+    pub fn borrowed_only(mut self, enabled: bool) -> Self {
+        self.borrowed_only = enabled;
+        self
+    }
+
+    /// Sets whether a key that repeats an earlier key of the same map (or field of the same struct) fails tokenization with `Error::DuplicateKey`, rather than being passed on for the consumer to resolve, as duplicate keys can be used to smuggle a value past a validator that reads a different occurrence of the key than the consumer.
+    ///
+    /// Keys are compared by their tokens, so `1u8` and `1u64` are different keys, but borrowed and owned strings (and `Field`s) of the same name are the same key. The tokens preceding the duplicate key will have been written. Defaults to `false`. See [`on_duplicate_key`] to be notified of duplicates instead.
+    ///
+    /// *Requires the `std` feature.*
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Error, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" {"role": "user", "role": "admin"} "#);
+    /// let (tx, _rx) = channel();
+    ///
+    /// let options = TokenizeOptions::new().reject_duplicate_keys(true);
+    /// match Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de) {
+    ///     Err(Error::DuplicateKey { key }) => assert_eq!(key, r#""role""#),
+    ///     _ => panic!("expected a duplicate key error"),
+    /// }
+    /// ```
+    ///
+    /// [`on_duplicate_key`]: struct.Tokenizer.html#method.on_duplicate_key
+    #[cfg(feature = "std")]
+    pub fn reject_duplicate_keys(mut self, enabled: bool) -> Self {
+        self.unique_keys = enabled;
+        self
+    }
+
+    /// Sets whether the entries of each map are written sorted by their keys' tokens (as ordered by [`Token`'s `Ord` implementation]), so that equal maps are always written identically, such as for content addressing.
+    ///
+    /// Each outermost map is buffered until it ends, then written with its entries (and those of any maps nested within it) sorted, as by [`sort_maps`]; tokens outside of maps are written as they arrive. Struct fields are left in their declared order. Defaults to `false`.
+    ///
+    /// *Requires the `std` feature.*
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" {"b": 1, "a": 2} "#);
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().sort_map_keys(true);
+    /// Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de).unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::Map { len: None },
+    ///     Token::String("a".to_string()),
+    ///     Token::U64(2),
+    ///     Token::String("b".to_string()),
+    ///     Token::U64(1),
+    ///     Token::MapEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`Token`'s `Ord` implementation]: enum.Token.html#impl-Ord
+    /// [`sort_maps`]: fn.sort_maps.html
+    #[cfg(feature = "std")]
+    pub fn sort_map_keys(mut self, enabled: bool) -> Self {
+        self.sorted_maps = enabled;
+        self
+    }
+
+    /// Sets whether map keys and struct field names are written as `Token::Interned`s, so that every occurrence of the same key shares a single allocation rather than each being copied, such as when tokenizing a long array of objects into owned tokens.
+    ///
+    /// The tokenizer keeps each distinct key for as long as it lives, so memory use grows with the number of distinct keys rather than the number of occurrences. Strings within compound keys are interned too, and keys long enough to be chunked by [`chunk_strings`] are chunked as usual. Defaults to `false`.
+    ///
+    /// *Requires the `std` feature.*
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, TokenizeOptions, Tokenizer};
+    /// use std::sync::{mpsc::channel, Arc};
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" [{"id": 1}, {"id": 2}] "#);
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().intern_keys(true);
+    /// Tokenizer::new(ChannelSink::new(tx)).with_options(&options).tokenize(&mut de).unwrap();
+    ///
+    /// let keys = rx
+    ///     .try_iter()
+    ///     .filter_map(|token| match token {
+    ///         Token::Interned(key) => Some(key),
+    ///         _ => None,
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(&*keys[0], "id");
+    /// assert!(Arc::ptr_eq(&keys[0], &keys[1]));
+    /// ```
+    ///
+    /// [`chunk_strings`]: #method.chunk_strings
+    #[cfg(feature = "std")]
+    pub fn intern_keys(mut self, enabled: bool) -> Self {
+        self.interned_keys = enabled;
+        self
+    }
+}
+
+/// The width that a [`Tokenizer`] widens integer tokens to, set with [`TokenizeOptions::normalize_integers`].
+///
+/// [`Tokenizer`]: struct.Tokenizer.html
+/// [`TokenizeOptions::normalize_integers`]: struct.TokenizeOptions.html#method.normalize_integers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IntegerWidth {
+    /// Integers are written with the width they were serialized or deserialized with.
+    #[default]
+    Native,
+    /// Signed integers of up to 64 bits are written as `Token::I64`s, and unsigned ones as `Token::U64`s. 128-bit integers are left as they are.
+    Bits64,
+    /// All signed integers are written as `Token::I128`s, and all unsigned ones as `Token::U128`s.
+    ///
+    /// Note that serde's implementations of `Deserialize` for narrower integers don't accept 128-bit integers, so such tokens can only be deserialized into 128-bit integers (or types that otherwise accept them).
+    Bits128,
+}
+
+/// How `NaN` and infinite floats are handled, set with [`TokenizeOptions::non_finite_floats`] and [`DisplayTokens::non_finite_floats`].
+///
+/// [`TokenizeOptions::non_finite_floats`]: struct.TokenizeOptions.html#method.non_finite_floats
+/// [`DisplayTokens::non_finite_floats`]: struct.DisplayTokens.html#method.non_finite_floats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FloatPolicy {
+    /// Non-finite floats are an error (`Error::NonFiniteFloat`).
+    Reject,
+    /// Non-finite floats are replaced with `Token::None`, which formats such as JSON write as `null`.
+    Null,
+    /// Non-finite floats are left as they are.
+    #[default]
+    PassThrough,
+}
+
+impl FloatPolicy {
+    /// Applies the policy to a token, leaving finite floats and any other token as they are.
+    pub(crate) fn apply<'a>(self, token: Token<'a>) -> Result<Token<'a>, Error> {
+        let value = match token {
+            Token::F32(v) if !v.is_finite() => f64::from(v),
+            Token::F64(v) if !v.is_finite() => v,
+            token => return Ok(token),
+        };
+        match self {
+            FloatPolicy::Reject => Err(Error::NonFiniteFloat { value }),
+            FloatPolicy::Null => Ok(Token::None),
+            FloatPolicy::PassThrough => Ok(token),
+        }
+    }
+}
+
+/// Whether a [`Tokenizer`] writes string and bytes payloads as borrowed or owned tokens, set with [`TokenizeOptions::string_policy`].
+///
+/// [`Tokenizer`]: struct.Tokenizer.html
+/// [`TokenizeOptions::string_policy`]: struct.TokenizeOptions.html#method.string_policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StringPolicy {
+    /// Payloads are always written as `Token::Str`s and `Token::Bytes`, borrowing them where possible and copying them otherwise.
+    Borrowed,
+    /// Payloads lent for the tokenizer's lifetime are written as borrowed `Token::Str`s and `Token::Bytes`, and any others as `Token::String`s and `Token::ByteBuf`s.
+    Adaptive,
+    /// Payloads are always written as `Token::String`s and `Token::ByteBuf`s.
+    Owned,
+}
+
+impl StringPolicy {
+    /// Converts a string or bytes token into the kind of token the policy prefers.
+    pub(crate) fn apply(self, token: Token) -> Token {
+        match (self, token) {
+            (StringPolicy::Borrowed, Token::String(v)) => Token::Str(Cow::Owned(v)),
+            (StringPolicy::Borrowed, Token::ByteBuf(v)) => Token::Bytes(Cow::Owned(v)),
+            (StringPolicy::Adaptive, Token::Str(Cow::Owned(v))) => Token::String(v),
+            (StringPolicy::Adaptive, Token::Bytes(Cow::Owned(v))) => Token::ByteBuf(v),
+            (StringPolicy::Owned, Token::Str(v)) => Token::String(v.into_owned()),
+            (StringPolicy::Owned, Token::Bytes(v)) => Token::ByteBuf(v.into_owned()),
+            (_, token) => token,
+        }
+    }
+}
+
+struct FnSink<F>(F);
+
+impl<'a, F, E> TokenSink<'a> for FnSink<F>
+where
+    F: FnMut(Token<'a>) -> Result<(), E>,
+    E: core::error::Error + Send + Sync + 'static,
+{
+    type Error = Error;
+
+    #[inline]
+    fn write(&mut self, token: Token<'a>) -> Result<(), Error> {
+        (self.0)(token).map_err(|err| Error::TokenSinkError(Box::new(err)))
+    }
+}
+
+/// A callback for the keys that repeat an earlier key of the same map.
+#[cfg(feature = "std")]
+type DuplicateKeyFn<'a> = Box<dyn FnMut(&[Token<'static>]) + Send + 'a>;
+
+/// A [`serde::Serializer`] that writes the serialized value as `Token`s into a [`TokenSink`], such as a [`futures::Sink`].
+///
+/// Since a `Serialize` impl only lends its strings and bytes for the duration of each call, they are written as owned `Token::String`s and `Token::ByteBuf`s. Struct field names are `'static`, and so are written as borrowed `Token::Str`s, or as `Token::Field`s with [`field_tokens`].
+///
+/// # Example:
+///
+/// ```
+/// # #[cfg(feature = "futures01")] {
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde::Serialize;
+/// use serde_token::{Token, Tokenizer};
+///
+/// let (token_sink, token_stream) = unbounded::<Token>();
+/// let mut tokenizer = Tokenizer::new(token_sink);
+///
+/// vec![1u8, 2].serialize(&mut tokenizer).unwrap();
+/// Some('a').serialize(&mut tokenizer).unwrap();
+/// drop(tokenizer);
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![
+///     Token::Seq { len: Some(2) },
+///     Token::U8(1),
+///     Token::U8(2),
+///     Token::SeqEnd,
+///     Token::Some,
+///     Token::Char('a'),
+/// ]);
+/// # }
+/// ```
+///
+/// [`serde::Serializer`]: https://docs.serde.rs/serde/trait.Serializer.html
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`field_tokens`]: struct.TokenizeOptions.html#method.field_tokens
+pub struct Tokenizer<'a, S> {
+    sink: S,
+    owned: bool,
+    options: TokenizeOptions,
+    depth: Option<usize>,
+    nesting: usize,
+    owned_bytes: usize,
+    number: bool,
+    extension: bool,
+    #[cfg(feature = "std")]
+    keys: KeyTracker,
+    #[cfg(feature = "std")]
+    on_duplicate: Option<DuplicateKeyFn<'a>>,
+    #[cfg(feature = "std")]
+    unsorted: Vec<Token<'a>>,
+    #[cfg(feature = "std")]
+    unsorted_depth: usize,
+    #[cfg(feature = "std")]
+    interner: KeyInterner,
+    #[cfg(feature = "bumpalo")]
+    pub(crate) arena: Option<&'a Bump>,
+    _marker: PhantomData<Token<'a>>,
+}
+
+impl<'a, S: TokenSink<'a>> Tokenizer<'a, S> {
+    /// Creates a new `Tokenizer` that writes into the given sink.
+    pub fn new(sink: S) -> Self {
+        Tokenizer {
+            sink,
+            owned: true,
+            options: TokenizeOptions::default(),
+            depth: None,
+            nesting: 0,
+            owned_bytes: 0,
+            number: false,
+            extension: false,
+            #[cfg(feature = "std")]
+            keys: KeyTracker::default(),
+            #[cfg(feature = "std")]
+            on_duplicate: None,
+            #[cfg(feature = "std")]
+            unsorted: Vec::new(),
+            #[cfg(feature = "std")]
+            unsorted_depth: 0,
+            #[cfg(feature = "std")]
+            interner: KeyInterner::default(),
+            #[cfg(feature = "bumpalo")]
+            arena: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, S> Tokenizer<'a, S> {
+    /// Creates a `Tokenizer` for use while driven by a `Deserializer<'a>`, which writes its `'a`-borrowed strings and bytes directly, and transient ones as copied `Str` and `Bytes` tokens.
+    pub(crate) fn borrowing(sink: S) -> Self {
+        Tokenizer {
+            sink,
+            owned: false,
+            options: TokenizeOptions::default(),
+            depth: None,
+            nesting: 0,
+            owned_bytes: 0,
+            number: false,
+            extension: false,
+            #[cfg(feature = "std")]
+            keys: KeyTracker::default(),
+            #[cfg(feature = "std")]
+            on_duplicate: None,
+            #[cfg(feature = "std")]
+            unsorted: Vec::new(),
+            #[cfg(feature = "std")]
+            unsorted_depth: 0,
+            #[cfg(feature = "std")]
+            interner: KeyInterner::default(),
+            #[cfg(feature = "bumpalo")]
+            arena: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replaces the tokenizer's configuration with the given options.
+    pub fn with_options(mut self, options: &TokenizeOptions) -> Self {
+        self.options = options.clone();
+        self
+    }
+
+    /// Gets the tokenizer's configuration.
+    pub fn options(&self) -> &TokenizeOptions {
+        &self.options
+    }
+
+    /// Copies every string and byte array that would otherwise be written as an owned (or copied) token into the given bump arena, writing it as a `Token::Str` or `Token::Bytes` borrowed from the arena instead.
+    ///
+    /// Allocating from an arena is much cheaper than allocating each payload on the heap, and everything tokenized into it is freed at once when the arena is reset or dropped, so a transcoding service can reuse one arena per request. Takes precedence over [`string_policy`] and [`inline_strings`].
+    ///
+    /// *Requires the `bumpalo` feature.*
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use bumpalo::Bump;
+    /// use serde_token::{Error, Token, TokenSink, Tokenizer};
+    ///
+    /// struct Collect<'c, 'de>(&'c mut Vec<Token<'de>>);
+    ///
+    /// impl<'c, 'de> TokenSink<'de> for Collect<'c, 'de> {
+    ///     type Error = Error;
+    ///
+    ///     fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+    ///         self.0.push(token);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let arena = Bump::new();
+    /// let mut de = serde_json::Deserializer::from_reader(&br#" ["a", "b"] "#[..]);
+    /// let mut tokens = Vec::new();
+    /// Tokenizer::new(Collect(&mut tokens)).arena(&arena).tokenize(&mut de).unwrap();
+    ///
+    /// assert_eq!(tokens[1].borrowed_str(), Some("a"));
+    /// assert!(arena.allocated_bytes() > 0);
+    /// ```
+    ///
+    /// [`string_policy`]: struct.TokenizeOptions.html#method.string_policy
+    /// [`inline_strings`]: struct.TokenizeOptions.html#method.inline_strings
+    #[cfg(feature = "bumpalo")]
+    pub fn arena(mut self, arena: &'a Bump) -> Self {
+        self.arena = Some(arena);
+        self
+    }
+
+    /// Calls the given function with the tokens of each key that repeats an earlier key of the same map (or field of the same struct), before the key is written, as with [`reject_duplicate_keys`] but without failing tokenization (unless that is enabled too).
+    ///
+    /// *Requires the `std` feature.*
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, Tokenizer};
+    /// use std::sync::{mpsc::channel, Arc, Mutex};
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" [{"a": 1, "a": 2}, {"a": 3}] "#);
+    /// let (tx, _rx) = channel();
+    /// let duplicates = Arc::new(Mutex::new(Vec::new()));
+    /// let seen = duplicates.clone();
+    ///
+    /// Tokenizer::new(ChannelSink::new(tx))
+    ///     .on_duplicate_key(move |key| seen.lock().unwrap().push(key.to_vec()))
+    ///     .tokenize(&mut de)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(*duplicates.lock().unwrap(), vec![vec![Token::String("a".to_string())]]);
+    /// ```
+    ///
+    /// [`reject_duplicate_keys`]: struct.TokenizeOptions.html#method.reject_duplicate_keys
+    #[cfg(feature = "std")]
+    pub fn on_duplicate_key<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&[Token<'static>]) + Send + 'a,
+    {
+        self.on_duplicate = Some(Box::new(f));
+        self
+    }
+
+    /// Gets a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Gets a mutable reference to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Consumes the `Tokenizer`, returning the underlying sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<'a, S: TokenSink<'a>> Tokenizer<'a, S> {
+    /// Transcodes a deserializer into the tokenizer's sink with the tokenizer's configuration, returning the flushed sink.
+    ///
+    /// As with [`tokenize_adaptive`], strings and bytes the deserializer lends for `'a` are borrowed, while those it only provides transiently are written as owned `Token::String`s and `Token::ByteBuf`s.
+    ///
+    /// [`tokenize_adaptive`]: fn.tokenize_adaptive.html
+    pub fn tokenize<D: de::Deserializer<'a>>(self, deserializer: D) -> Result<S, Error> {
+        let owned = self.owned;
+        let mut sink = transcode(deserializer, self, owned)?;
+        sink.flush().map_err(Into::into)?;
+        Ok(sink)
+    }
+
+    /// Transcodes one of several deserializers (or one of several successive documents of the same deserializer) into the tokenizer's sink, leaving the tokenizer ready for the next.
+    ///
+    /// Combined with [`document_markers`], this writes a stream of many documents into one sink, with any state (such as the [`max_owned_bytes`] budget) carried across them. Strings and bytes are borrowed or promoted as with [`tokenize`](#method.tokenize). The sink is not flushed.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use serde_token::{ChannelSink, Token, TokenSink, TokenizeOptions, Tokenizer};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(r#" 1 [] "#);
+    /// let (tx, rx) = channel();
+    /// let options = TokenizeOptions::new().document_markers(true);
+    /// let mut tokenizer = Tokenizer::new(ChannelSink::new(tx)).with_options(&options);
+    /// while de.end().is_err() {
+    ///     tokenizer.tokenize_next(&mut de).unwrap();
+    /// }
+    /// tokenizer.get_mut().flush().unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+    ///     Token::DocumentStart,
+    ///     Token::U64(1),
+    ///     Token::DocumentEnd,
+    ///     Token::DocumentStart,
+    ///     Token::Seq { len: None },
+    ///     Token::SeqEnd,
+    ///     Token::DocumentEnd,
+    /// ]);
+    /// ```
+    ///
+    /// [`document_markers`]: struct.TokenizeOptions.html#method.document_markers
+    /// [`max_owned_bytes`]: struct.TokenizeOptions.html#method.max_owned_bytes
+    pub fn tokenize_next<D: de::Deserializer<'a>>(&mut self, deserializer: D) -> Result<(), Error> {
+        let owned = self.owned;
+        transcode_into(deserializer, self, owned)
+    }
+
+    #[inline]
+    pub(crate) fn write_token(&mut self, mut token: Token<'a>) -> Result<(), Error> {
+        if let Some(max_depth) = self.options.max_depth {
+            self.check_depth(&token, max_depth)?;
+        }
+        if self.options.integers != IntegerWidth::Native {
+            token = widen(token, self.options.integers);
+        }
+        if self.options.non_finite != FloatPolicy::PassThrough {
+            token = self.options.non_finite.apply(token)?;
+        }
+        if let Some(policy) = self.options.strings {
+            token = policy.apply(token);
+        }
+        #[cfg(feature = "bumpalo")]
+        {
+            if let Some(arena) = self.arena {
+                token = in_arena(token, arena);
+            }
+        }
+        if self.options.inline_strings {
+            token = inline(token);
+        }
+        if let Some(budget) = self.options.max_owned {
+            self.check_budget(&token, budget)?;
+        }
+        #[cfg(feature = "std")]
+        {
+            if self.options.sorted_maps {
+                return self.write_sorted(token);
+            }
+        }
+        self.write_limited(token)
+    }
+
+    // Buffers each outermost map until it ends, then writes it with its entries sorted by their keys.
+    #[cfg(feature = "std")]
+    fn write_sorted(&mut self, token: Token<'a>) -> Result<(), Error> {
+        if self.unsorted.is_empty() && !matches!(token, Token::Map { .. }) {
+            return self.write_limited(token);
+        }
+        if token.is_container_start() {
+            self.unsorted_depth += 1;
+        } else if token.is_container_end() {
+            self.unsorted_depth -= 1;
+        }
+        self.unsorted.push(token);
+        if self.unsorted_depth > 0 {
+            return Ok(());
+        }
+        let mut sorted = Vec::new();
+        for mut node in Node::parse_all(core::mem::take(&mut self.unsorted))? {
+            node.sort_maps();
+            node.emit_marked(&mut sorted, self.options.markers);
+        }
+        sorted
+            .into_iter()
+            .try_for_each(|token| self.write_limited(token))
+    }
+
+    // Writes a token, truncating or rejecting it if its payload is too long.
+    fn write_limited(&mut self, mut token: Token<'a>) -> Result<(), Error> {
+        if let Some(max_len) = self.options.max_len {
+            let len = match token {
+                Token::Str(ref v) => v.len(),
+                Token::String(ref v) => v.len(),
+                Token::Inline(ref v) => v.len(),
+                Token::Bytes(ref v) => v.len(),
+                Token::ByteBuf(ref v) => v.len(),
+                _ => 0,
+            };
+            if len > max_len {
+                if !self.options.truncate {
+                    return Err(Error::LengthLimitExceeded { len });
+                }
+                self.write_unlimited(Token::Truncated { len })?;
+                token = truncate(token, max_len);
+            }
+        }
+        self.write_unlimited(token)
+    }
+
+    // Writes a token that is within the configured limits.
+    fn write_unlimited(&mut self, token: Token<'a>) -> Result<(), Error> {
+        #[cfg(feature = "std")]
+        {
+            if self.options.unique_keys || self.on_duplicate.is_some() {
+                self.check_key(&token)?;
+            }
+        }
+        #[cfg(feature = "std")]
+        let token = if self.options.interned_keys {
+            self.interner.intern(token)
+        } else {
+            token
+        };
+        if self.options.documents {
+            return self.write_document_token(token);
+        }
+        self.write_chunked(token)
+    }
+
+    // Reports a key that repeats an earlier key of its map, failing if duplicates are rejected.
+    #[cfg(feature = "std")]
+    fn check_key(&mut self, token: &Token<'a>) -> Result<(), Error> {
+        let key = match self.keys.observe(token) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        if let Some(f) = self.on_duplicate.as_mut() {
+            f(&key);
+        }
+        if self.options.unique_keys {
+            let key = key
+                .iter()
+                .map(|token| token.to_string())
+                .collect::<Vec<_>>();
+            return Err(Error::DuplicateKey { key: key.join(" ") });
+        }
+        Ok(())
+    }
+
+    // Adds the length of a token's owned payload to the running total, failing if it exceeds the budget.
+    fn check_budget(&mut self, token: &Token<'a>, budget: usize) -> Result<(), Error> {
+        self.owned_bytes += owned_len(token);
+        if self.owned_bytes > budget {
+            let used = self.owned_bytes;
+            return Err(Error::MemoryBudgetExceeded { used, budget });
+        }
+        Ok(())
+    }
+
+    // Tracks the nesting of containers, failing if a container would open beyond the maximum depth.
+    fn check_depth(&mut self, token: &Token<'a>, max_depth: usize) -> Result<(), Error> {
+        if token.is_container_start() {
+            if self.nesting == max_depth {
+                let depth = self.nesting + 1;
+                return Err(Error::DepthLimitExceeded { depth });
+            }
+            self.nesting += 1;
+        } else if token.is_container_end() {
+            self.nesting = self.nesting.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    // Writes a token between `DocumentStart` and `DocumentEnd` markers, tracking the nesting depth of the current document to find where it ends.
+    fn write_document_token(&mut self, token: Token<'a>) -> Result<(), Error> {
+        let depth = match self.depth {
+            Some(depth) => depth,
+            None => {
+                self.sink.write(Token::DocumentStart).map_err(Into::into)?;
+                0
+            }
+        };
+        let depth = if token.is_container_start() {
+            depth + 1
+        } else if token.is_container_end() {
+            depth.saturating_sub(1)
+        } else {
+            depth
+        };
+        let prefix = matches!(
+            token,
+            Token::Some
+                | Token::NewtypeStruct { .. }
+                | Token::NewtypeVariant { .. }
+                | Token::Enum { .. }
+                | Token::Extension { .. }
+                | Token::Truncated { .. }
+        );
+        self.write_chunked(token)?;
+        if depth == 0 && !prefix {
+            self.depth = None;
+            return self.sink.write(Token::DocumentEnd).map_err(Into::into);
+        }
+        self.depth = Some(depth);
+        Ok(())
+    }
+
+    fn write_chunked(&mut self, token: Token<'a>) -> Result<(), Error> {
+        if let Some(chunk_size) = self.options.str_chunk {
+            if let Token::Str(ref v) = token {
+                if v.len() > chunk_size {
+                    return self.write_str_chunks(token, chunk_size);
+                }
+            }
+            if let Token::String(ref v) = token {
+                if v.len() > chunk_size {
+                    return self.write_str_chunks(token, chunk_size);
+                }
+            }
+            if let Token::Interned(ref v) = token {
+                if v.len() > chunk_size {
+                    return self.write_str_chunks(token, chunk_size);
+                }
+            }
+            if let Token::Inline(ref v) = token {
+                if v.len() > chunk_size {
+                    return self.write_str_chunks(token, chunk_size);
+                }
+            }
+        }
+        if let Some(chunk_size) = self.options.bytes_chunk {
+            if let Token::Bytes(ref v) = token {
+                if v.len() > chunk_size {
+                    return self.write_bytes_chunks(token, chunk_size);
+                }
+            }
+            if let Token::ByteBuf(ref v) = token {
+                if v.len() > chunk_size {
+                    return self.write_bytes_chunks(token, chunk_size);
+                }
+            }
+        }
+        self.sink.write(token).map_err(Into::into)
+    }
+
+    fn write_bytes_chunks(&mut self, token: Token<'a>, chunk_size: usize) -> Result<(), Error> {
+        match token {
+            Token::Bytes(Cow::Borrowed(v)) => {
+                for chunk in v.chunks(chunk_size) {
+                    self.sink
+                        .write(Token::BytesChunk(Cow::Borrowed(chunk)))
+                        .map_err(Into::into)?;
+                }
+            }
+            Token::Bytes(Cow::Owned(ref v)) | Token::ByteBuf(ref v) => {
+                for chunk in v.chunks(chunk_size) {
+                    self.sink
+                        .write(Token::BytesChunk(Cow::Owned(chunk.to_vec())))
+                        .map_err(Into::into)?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        self.sink.write(Token::BytesEnd).map_err(Into::into)
+    }
+
+    fn write_str_chunks(&mut self, token: Token<'a>, chunk_size: usize) -> Result<(), Error> {
+        match token {
+            Token::Str(Cow::Borrowed(v)) => {
+                for chunk in str_chunks(v, chunk_size) {
+                    self.sink
+                        .write(Token::StrChunk(Cow::Borrowed(chunk)))
+                        .map_err(Into::into)?;
+                }
+            }
+            Token::Str(Cow::Owned(ref v)) | Token::String(ref v) => {
+                for chunk in str_chunks(v, chunk_size) {
+                    self.sink
+                        .write(Token::StrChunk(Cow::Owned(chunk.to_owned())))
+                        .map_err(Into::into)?;
+                }
+            }
+            Token::Inline(ref v) => {
+                for chunk in str_chunks(v, chunk_size) {
+                    self.sink
+                        .write(Token::StrChunk(Cow::Owned(chunk.to_owned())))
+                        .map_err(Into::into)?;
+                }
+            }
+            Token::Interned(ref v) => {
+                for chunk in str_chunks(v, chunk_size) {
+                    self.sink
+                        .write(Token::StrChunk(Cow::Owned(chunk.to_owned())))
+                        .map_err(Into::into)?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        self.sink.write(Token::StrEnd).map_err(Into::into)
+    }
+
+    /// Fails if payloads must be borrowed, as a transient payload of the given length would have to be copied.
+    #[inline]
+    pub(crate) fn check_borrowed(&self, len: usize) -> Result<(), Error> {
+        if self.options.borrowed_only {
+            return Err(Error::TransientPayload { len });
+        }
+        Ok(())
+    }
+
+    /// Writes a `MapKey` or `MapValue` marker, if enabled.
+    #[inline]
+    pub(crate) fn write_marker(&mut self, marker: Token<'a>) -> Result<(), Error> {
+        if self.options.markers {
+            return self.write_token(marker);
+        }
+        Ok(())
+    }
+
+    /// Writes an `Enum` header, if enabled.
+    #[inline]
+    fn write_enum(&mut self, name: &'static str) -> Result<(), Error> {
+        if self.options.enums {
+            return self.write_token(Token::Enum { name });
+        }
+        Ok(())
+    }
+
+    /// Opens the single-entry map that wraps a lowered variant, writing its key.
+    fn write_variant_key(&mut self, variant: &'static str) -> Result<(), Error> {
+        self.write_token(Token::Map { len: Some(1) })?;
+        self.write_marker(Token::MapKey)?;
+        self.write_token(Token::Str(variant.into()))?;
+        self.write_marker(Token::MapValue)
+    }
+
+    fn write_field(&mut self, key: &'static str) -> Result<(), Error> {
+        if self.options.struct_maps {
+            self.write_marker(Token::MapKey)?;
+            self.write_token(Token::Str(key.into()))?;
+            return self.write_marker(Token::MapValue);
+        }
+        if self.options.fields {
+            return self.write_token(Token::Field(key));
+        }
+        self.write_token(Token::Str(key.into()))
+    }
+}
+
+impl<'s, 'a: 's, S: TokenSink<'a>> ser::Serializer for &'s mut Tokenizer<'a, S> {
     type Ok = ();
     type Error = Error;
 
@@ -105,6 +2258,10 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
 
     #[inline]
     fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        if self.extension {
+            self.extension = false;
+            return self.write_token(Token::Extension { tag: v });
+        }
         self.write_token(Token::U64(v))?;
         Ok(())
     }
@@ -135,16 +2292,41 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
 
     #[inline]
     fn serialize_str(self, v: &str) -> Result<(), Error> {
-        let new_v = unsafe { std::mem::transmute::<&str, &'a str>(v) };
-        self.write_token(Token::Str(new_v))?;
-        Ok(())
+        if self.number {
+            self.check_borrowed(v.len())?;
+            return self.write_token(Token::Number(Cow::Owned(v.to_owned())));
+        }
+        #[cfg(feature = "bumpalo")]
+        {
+            if let Some(arena) = self.arena {
+                return self.write_token(Token::Str(Cow::Borrowed(arena.alloc_str(v))));
+            }
+        }
+        if self.options.inline_strings {
+            if let Some(v) = InlineStr::new(v) {
+                return self.write_token(Token::Inline(v));
+            }
+        }
+        self.check_borrowed(v.len())?;
+        if self.owned {
+            return self.write_token(Token::String(v.to_owned()));
+        }
+        self.write_token(Token::Str(Cow::Owned(v.to_owned())))
     }
 
     #[inline]
     fn serialize_bytes(self, v: &[u8]) -> Result<(), Self::Error> {
-        let new_v = unsafe { std::mem::transmute::<&[u8], &'a [u8]>(v) };
-        self.write_token(Token::Bytes(new_v))?;
-        Ok(())
+        #[cfg(feature = "bumpalo")]
+        {
+            if let Some(arena) = self.arena {
+                return self.write_token(Token::Bytes(Cow::Borrowed(arena.alloc_slice_copy(v))));
+            }
+        }
+        self.check_borrowed(v.len())?;
+        if self.owned {
+            return self.write_token(Token::ByteBuf(v.to_owned()));
+        }
+        self.write_token(Token::Bytes(Cow::Owned(v.to_owned())))
     }
 
     #[inline]
@@ -163,46 +2345,56 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
     fn serialize_unit_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
     ) -> Result<(), Error> {
-        self.write_token(Token::UnitVariant { name, variant })?;
+        self.write_enum(name)?;
+        self.write_token(Token::UnitVariant {
+            name,
+            variant_index,
+            variant,
+        })?;
         Ok(())
     }
 
     #[inline]
-    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<(), Error>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.write_token(Token::NewtypeStruct { name })?;
         value.serialize(self)
     }
 
     #[inline]
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         value: &T,
     ) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.write_token(Token::NewtypeVariant { name, variant })?;
+        self.write_enum(name)?;
+        self.write_token(Token::NewtypeVariant {
+            name,
+            variant_index,
+            variant,
+        })?;
         value.serialize(self)
     }
 
     #[inline]
     fn serialize_none(self) -> Result<(), Error> {
-        Ok(())
+        self.write_token(Token::None)
     }
 
     #[inline]
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<(), Error>
+    fn serialize_some<T>(self, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.write_token(Token::Some)?;
         value.serialize(self)
@@ -213,16 +2405,21 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
         self.write_token(Token::Seq { len })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::SeqEnd,
+            end: Some(Token::SeqEnd),
+            outer: None,
         })
     }
 
     #[inline]
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Error> {
+        if self.options.tuple_seqs {
+            return self.serialize_seq(Some(len));
+        }
         self.write_token(Token::Tuple { len })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::TupleEnd,
+            end: Some(Token::TupleEnd),
+            outer: None,
         })
     }
 
@@ -232,10 +2429,23 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, Error> {
+        // An `Extension` is serialized as a tuple struct of its tag and value.
+        if name == EXTENSION_TOKEN {
+            self.extension = true;
+            return Ok(CompoundTokenizer {
+                ser: self,
+                end: None,
+                outer: None,
+            });
+        }
+        if self.options.tuple_seqs {
+            return self.serialize_seq(Some(len));
+        }
         self.write_token(Token::TupleStruct { name, len })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::TupleStructEnd,
+            end: Some(Token::TupleStructEnd),
+            outer: None,
         })
     }
 
@@ -243,14 +2453,30 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
     fn serialize_tuple_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Error> {
-        self.write_token(Token::TupleVariant { name, variant, len })?;
+        if self.options.tuple_seqs {
+            self.write_variant_key(variant)?;
+            self.write_token(Token::Seq { len: Some(len) })?;
+            return Ok(CompoundTokenizer {
+                ser: self,
+                end: Some(Token::SeqEnd),
+                outer: Some(Token::MapEnd),
+            });
+        }
+        self.write_enum(name)?;
+        self.write_token(Token::TupleVariant {
+            name,
+            variant_index,
+            variant,
+            len,
+        })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::TupleVariantEnd,
+            end: Some(Token::TupleVariantEnd),
+            outer: None,
         })
     }
 
@@ -259,7 +2485,8 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
         self.write_token(Token::Map { len })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::MapEnd,
+            end: Some(Token::MapEnd),
+            outer: None,
         })
     }
 
@@ -269,10 +2496,22 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Error> {
+        // `serde_json`'s arbitrary-precision numbers are serialized as a struct with a single private field.
+        if name == NUMBER_TOKEN {
+            return Ok(CompoundTokenizer {
+                ser: self,
+                end: None,
+                outer: None,
+            });
+        }
+        if self.options.struct_maps {
+            return self.serialize_map(Some(len));
+        }
         self.write_token(Token::Struct { name, len })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::StructEnd,
+            end: Some(Token::StructEnd),
+            outer: None,
         })
     }
 
@@ -280,51 +2519,77 @@ impl<'s, 'a: 's, S: Sink<SinkItem = Token<'a>>> ser::Serializer for &'s mut Toke
     fn serialize_struct_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Error> {
-        self.write_token(Token::StructVariant { name, variant, len })?;
+        if self.options.struct_maps {
+            self.write_variant_key(variant)?;
+            self.write_token(Token::Map { len: Some(len) })?;
+            return Ok(CompoundTokenizer {
+                ser: self,
+                end: Some(Token::MapEnd),
+                outer: Some(Token::MapEnd),
+            });
+        }
+        self.write_enum(name)?;
+        self.write_token(Token::StructVariant {
+            name,
+            variant_index,
+            variant,
+            len,
+        })?;
         Ok(CompoundTokenizer {
             ser: self,
-            end: Token::StructVariantEnd,
+            end: Some(Token::StructVariantEnd),
+            outer: None,
         })
     }
 
     #[inline]
     fn is_human_readable(&self) -> bool {
-        true
+        self.options.human_readable
     }
 }
 
-struct CompoundTokenizer<'a, 's, S>
+/// The [`Tokenizer`]'s implementation of serde's compound serializer traits, writing the container's end token once finished.
+///
+/// [`Tokenizer`]: struct.Tokenizer.html
+pub struct CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     ser: &'s mut Tokenizer<'a, S>,
-    end: Token<'a>,
+    end: Option<Token<'a>>,
+    // The end of the map wrapping a variant that has been lowered into a plain container.
+    outer: Option<Token<'a>>,
 }
 
 impl<'s, 'a: 's, S> CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     fn do_end(self) -> Result<(), Error> {
-        self.ser.write_token(self.end)?;
+        if let Some(end) = self.end {
+            self.ser.write_token(end)?;
+        }
+        if let Some(outer) = self.outer {
+            self.ser.write_token(outer)?;
+        }
         Ok(())
     }
 }
 
 impl<'a, 's, S> ser::SerializeSeq for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut *self.ser)
     }
@@ -336,14 +2601,14 @@ where
 
 impl<'a, 's, S> ser::SerializeTuple for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut *self.ser)
     }
@@ -355,16 +2620,18 @@ where
 
 impl<'a, 's, S> ser::SerializeTupleStruct for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(&mut *self.ser)
+        let result = value.serialize(&mut *self.ser);
+        self.ser.extension = false;
+        result
     }
 
     fn end(self) -> Result<(), Error> {
@@ -374,14 +2641,14 @@ where
 
 impl<'a, 's, S> ser::SerializeTupleVariant for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut *self.ser)
     }
@@ -393,22 +2660,24 @@ where
 
 impl<'a, 's, S> ser::SerializeMap for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        self.ser.write_marker(Token::MapKey)?;
         key.serialize(&mut *self.ser)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        self.ser.write_marker(Token::MapValue)?;
         value.serialize(&mut *self.ser)
     }
 
@@ -419,20 +2688,22 @@ where
 
 impl<'a, 's, S> ser::SerializeStruct for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        key.serialize(&mut *self.ser)?;
+        if self.end.is_none() {
+            self.ser.number = true;
+            let result = value.serialize(&mut *self.ser);
+            self.ser.number = false;
+            return result;
+        }
+        self.ser.write_field(key)?;
         value.serialize(&mut *self.ser)
     }
 
@@ -443,20 +2714,16 @@ where
 
 impl<'a, 's, S> ser::SerializeStructVariant for CompoundTokenizer<'a, 's, S>
 where
-    S: Sink<SinkItem = Token<'a>>,
+    S: TokenSink<'a>,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        key.serialize(&mut *self.ser)?;
+        self.ser.write_field(key)?;
         value.serialize(&mut *self.ser)
     }
 
@@ -464,3 +2731,113 @@ where
         self.do_end()
     }
 }
+
+/// Widens an integer token to the given width, preserving its signedness.
+fn widen(token: Token, width: IntegerWidth) -> Token {
+    match (width, token) {
+        (IntegerWidth::Bits64, Token::I8(v)) => Token::I64(v.into()),
+        (IntegerWidth::Bits64, Token::I16(v)) => Token::I64(v.into()),
+        (IntegerWidth::Bits64, Token::I32(v)) => Token::I64(v.into()),
+        (IntegerWidth::Bits64, Token::U8(v)) => Token::U64(v.into()),
+        (IntegerWidth::Bits64, Token::U16(v)) => Token::U64(v.into()),
+        (IntegerWidth::Bits64, Token::U32(v)) => Token::U64(v.into()),
+        (IntegerWidth::Bits128, Token::I8(v)) => Token::I128(v.into()),
+        (IntegerWidth::Bits128, Token::I16(v)) => Token::I128(v.into()),
+        (IntegerWidth::Bits128, Token::I32(v)) => Token::I128(v.into()),
+        (IntegerWidth::Bits128, Token::I64(v)) => Token::I128(v.into()),
+        (IntegerWidth::Bits128, Token::U8(v)) => Token::U128(v.into()),
+        (IntegerWidth::Bits128, Token::U16(v)) => Token::U128(v.into()),
+        (IntegerWidth::Bits128, Token::U32(v)) => Token::U128(v.into()),
+        (IntegerWidth::Bits128, Token::U64(v)) => Token::U128(v.into()),
+        (_, token) => token,
+    }
+}
+
+/// Moves an owned or copied string or byte array into the arena, borrowing it from there.
+#[cfg(feature = "bumpalo")]
+fn in_arena<'a>(token: Token<'a>, arena: &'a Bump) -> Token<'a> {
+    match token {
+        Token::Str(Cow::Owned(ref v)) | Token::String(ref v) => {
+            Token::Str(Cow::Borrowed(arena.alloc_str(v)))
+        }
+        Token::Bytes(Cow::Owned(ref v)) | Token::ByteBuf(ref v) => {
+            Token::Bytes(Cow::Borrowed(arena.alloc_slice_copy(v)))
+        }
+        token => token,
+    }
+}
+
+/// The length of a token's payload, if it is owned (or copied) rather than borrowed or stored inline.
+fn owned_len(token: &Token) -> usize {
+    match token {
+        Token::Str(Cow::Owned(v))
+        | Token::Number(Cow::Owned(v))
+        | Token::StrChunk(Cow::Owned(v)) => v.len(),
+        Token::String(v) => v.len(),
+        Token::Interned(v) => v.len(),
+        Token::Bytes(Cow::Owned(v)) | Token::BytesChunk(Cow::Owned(v)) => v.len(),
+        Token::ByteBuf(v) => v.len(),
+        _ => 0,
+    }
+}
+
+/// Moves a copied string short enough to be stored inline into a `Token::Inline`.
+fn inline(token: Token) -> Token {
+    let v = match token {
+        Token::Str(Cow::Owned(ref v)) | Token::String(ref v) => v,
+        token => return token,
+    };
+    match InlineStr::new(v) {
+        Some(v) => Token::Inline(v),
+        None => token,
+    }
+}
+
+/// Truncates a string (on a character boundary) or byte array to at most `max_len` bytes.
+fn truncate(token: Token, max_len: usize) -> Token {
+    let boundary = |s: &str| (0..=max_len).rev().find(|&i| s.is_char_boundary(i));
+    match token {
+        Token::Str(Cow::Borrowed(v)) => Token::Str(Cow::Borrowed(&v[..boundary(v).unwrap_or(0)])),
+        Token::Str(Cow::Owned(mut v)) => {
+            v.truncate(boundary(&v).unwrap_or(0));
+            Token::Str(Cow::Owned(v))
+        }
+        Token::String(mut v) => {
+            v.truncate(boundary(&v).unwrap_or(0));
+            Token::String(v)
+        }
+        Token::Inline(v) => {
+            let truncated = InlineStr::new(&v[..boundary(&v).unwrap_or(0)]);
+            Token::Inline(truncated.unwrap_or(v))
+        }
+        Token::Bytes(Cow::Borrowed(v)) => Token::Bytes(Cow::Borrowed(&v[..max_len])),
+        Token::Bytes(Cow::Owned(mut v)) => {
+            v.truncate(max_len);
+            Token::Bytes(Cow::Owned(v))
+        }
+        Token::ByteBuf(mut v) => {
+            v.truncate(max_len);
+            Token::ByteBuf(v)
+        }
+        token => token,
+    }
+}
+
+/// Splits a string into pieces of at most `chunk_size` bytes, or of a single character if it is longer.
+fn str_chunks(mut s: &str, chunk_size: usize) -> impl Iterator<Item = &str> {
+    core::iter::from_fn(move || {
+        if s.is_empty() {
+            return None;
+        }
+        let mut end = chunk_size.min(s.len());
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = s.chars().next().map_or(s.len(), char::len_utf8);
+        }
+        let (chunk, rest) = s.split_at(end);
+        s = rest;
+        Some(chunk)
+    })
+}
@@ -0,0 +1,82 @@
+use crate::{to_tokens, ChannelSink, Error, Token, TokenSink};
+use serde::de;
+use tokio::sync::mpsc::{
+    error::{SendError, TrySendError},
+    Sender, UnboundedSender,
+};
+
+/// Transcodes a deserializer into a bounded `tokio` channel of owned `Token`s, awaiting the channel's capacity before sending each token.
+///
+/// Since a deserializer can't be suspended mid-value, it is first tokenized into a buffer.
+///
+/// *Requires the `tokio` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize_tokio, Token};
+/// use tokio::sync::mpsc::channel;
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// let (tx, mut rx) = channel::<Token<'static>>(1);
+///
+/// let consumer = runtime.spawn(async move {
+///     let mut tokens = Vec::new();
+///     while let Some(token) = rx.recv().await {
+///         tokens.push(token);
+///     }
+///     tokens
+/// });
+/// runtime.block_on(async move {
+///     let mut de = serde_json::Deserializer::from_str(r#" [true] "#);
+///     tokenize_tokio(&mut de, &tx).await.unwrap();
+/// });
+///
+/// assert_eq!(runtime.block_on(consumer).unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::Bool(true),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+pub async fn tokenize_tokio<'de, D>(
+    deserializer: D,
+    sender: &Sender<Token<'static>>,
+) -> Result<(), Error>
+where
+    D: de::Deserializer<'de>,
+{
+    for token in to_tokens(deserializer)? {
+        sender.send(token.into_owned()).await.map_err(Error::sink)?;
+    }
+    Ok(())
+}
+
+/// Sends tokens over an unbounded `tokio` channel.
+///
+/// *Requires the `tokio` feature.*
+impl<'de> TokenSink<'de> for ChannelSink<UnboundedSender<Token<'static>>> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        self.get_ref().send(token.into_owned()).map_err(Error::sink)
+    }
+}
+
+/// Sends tokens over a bounded `tokio` channel without waiting for its capacity, so it can be used both within and outside an asynchronous runtime.
+///
+/// A token written while the channel is full fails with `Error::TokenSinkNotReadyError`; use [`tokenize_tokio`] to await the channel's capacity instead.
+///
+/// *Requires the `tokio` feature.*
+///
+/// [`tokenize_tokio`]: fn.tokenize_tokio.html
+impl<'de> TokenSink<'de> for ChannelSink<Sender<Token<'static>>> {
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'de>) -> Result<(), Error> {
+        match self.get_ref().try_send(token.into_owned()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(Error::TokenSinkNotReadyError),
+            Err(TrySendError::Closed(token)) => Err(Error::sink(SendError(token))),
+        }
+    }
+}
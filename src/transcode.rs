@@ -0,0 +1,396 @@
+use crate::{
+    error::Error,
+    tokenize::{TokenSink, Tokenizer},
+    InlineStr, Token, NUMBER_TOKEN,
+};
+use alloc::{
+    borrow::{Cow, ToOwned},
+    string::String,
+    vec::Vec,
+};
+use core::fmt;
+use serde::{
+    de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess},
+    ser::{self, Serializer},
+};
+
+/// The default nesting limit, beyond which recursive walks of a value fail with `Error::DepthLimitExceeded` rather than overflowing the stack.
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// The deepest nesting the driver will descend into before failing with `Error::DepthLimitExceeded`, unless the tokenizer's options lower it.
+///
+/// With the `stacker` feature, the call stack is grown onto the heap as needed, so nesting is unlimited.
+#[cfg(not(feature = "stacker"))]
+pub(crate) const MAX_DEPTH: usize = DEFAULT_MAX_DEPTH;
+#[cfg(feature = "stacker")]
+pub(crate) const MAX_DEPTH: usize = usize::MAX;
+
+/// The remaining stack below which a new stack segment is allocated.
+#[cfg(feature = "stacker")]
+pub(crate) const RED_ZONE: usize = 64 * 1024;
+
+/// The size of each newly allocated stack segment.
+#[cfg(feature = "stacker")]
+pub(crate) const STACK_SIZE: usize = 1024 * 1024;
+
+/// Drives a deserializer with `deserialize_any`, writing each visited value to the tokenizer.
+///
+/// Unlike `serde_transcode`, this tracks the nesting depth so that deeply nested input fails with a dedicated error (or, with the `stacker` feature, grows the stack) rather than overflowing the stack, and preserves the tokenizer's original error.
+///
+/// If `promote` is set, transient (i.e. not `'de`-borrowed) strings and bytes are written as owned tokens. Returns the tokenizer's sink once the value has been written.
+pub(crate) fn transcode<'de, D, S>(
+    deserializer: D,
+    mut tokenizer: Tokenizer<'de, S>,
+    promote: bool,
+) -> Result<S, Error>
+where
+    D: Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    transcode_into(deserializer, &mut tokenizer, promote)?;
+    Ok(tokenizer.into_inner())
+}
+
+/// Drives a deserializer like [`transcode`], leaving the tokenizer ready for another value.
+pub(crate) fn transcode_into<'de, D, S>(
+    deserializer: D,
+    tokenizer: &mut Tokenizer<'de, S>,
+    promote: bool,
+) -> Result<(), Error>
+where
+    D: Deserializer<'de>,
+    S: TokenSink<'de>,
+{
+    let max_depth = match tokenizer.options().max_depth {
+        Some(max_depth) if max_depth < MAX_DEPTH => max_depth,
+        _ => MAX_DEPTH,
+    };
+    let mut driver = Driver {
+        tokenizer,
+        promote,
+        depth: 0,
+        max_depth,
+        pending: None,
+        number: false,
+        error: None,
+    };
+    match deserializer.deserialize_any(Visitor(&mut driver)) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(driver
+            .error
+            .take()
+            .unwrap_or_else(|| ser::Error::custom(err))),
+    }
+}
+
+struct Driver<'t, 'de, S: TokenSink<'de>> {
+    tokenizer: &'t mut Tokenizer<'de, S>,
+    promote: bool,
+    depth: usize,
+    max_depth: usize,
+    pending: Option<Token<'de>>,
+    number: bool,
+    error: Option<Error>,
+}
+
+impl<'t, 'de, S: TokenSink<'de>> Driver<'t, 'de, S> {
+    #[inline]
+    fn write<E: de::Error>(&mut self, token: Token<'de>) -> Result<(), E> {
+        if self.pending.is_some() && self.start(is_number_key(&token))? {
+            return Ok(());
+        }
+        let result = self.tokenizer.write_token(token);
+        self.check(result)
+    }
+
+    // Writes the deferred header of a map before its first key, unless the key marks the map as an arbitrary-precision number, returning whether it did.
+    fn start<E: de::Error>(&mut self, number: bool) -> Result<bool, E> {
+        let header = match self.pending.take() {
+            Some(header) => header,
+            None => return Ok(false),
+        };
+        if number {
+            self.number = true;
+            return Ok(true);
+        }
+        let result = self
+            .tokenizer
+            .write_token(header)
+            .and_then(|()| self.tokenizer.write_marker(Token::MapKey));
+        self.check(result).map(|()| false)
+    }
+
+    // Stashes the tokenizer's error, so it can be returned in place of the deserializer's.
+    #[inline]
+    fn check<E: de::Error>(&mut self, result: Result<(), Error>) -> Result<(), E> {
+        result.map_err(|err| {
+            let de_err = E::custom(&err);
+            self.error = Some(err);
+            de_err
+        })
+    }
+
+    // Descends one level of nesting, failing if too deep.
+    fn nested<E, F>(&mut self, f: F) -> Result<(), E>
+    where
+        E: de::Error,
+        F: FnOnce(&mut Self) -> Result<(), E>,
+    {
+        if self.depth == self.max_depth {
+            let depth = self.depth + 1;
+            return self.check(Err(Error::DepthLimitExceeded { depth }));
+        }
+
+        self.depth += 1;
+        #[cfg(feature = "stacker")]
+        let result = stacker::maybe_grow(RED_ZONE, STACK_SIZE, || f(self));
+        #[cfg(not(feature = "stacker"))]
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+struct Visitor<'a, 't, 'de, S: TokenSink<'de>>(&'a mut Driver<'t, 'de, S>);
+
+impl<'a, 't, 'de, S: TokenSink<'de>> de::Visitor<'de> for Visitor<'a, 't, 'de, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<(), E> {
+        self.0.write(Token::Bool(v))
+    }
+
+    fn visit_i8<E: de::Error>(self, v: i8) -> Result<(), E> {
+        self.0.write(Token::I8(v))
+    }
+
+    fn visit_i16<E: de::Error>(self, v: i16) -> Result<(), E> {
+        self.0.write(Token::I16(v))
+    }
+
+    fn visit_i32<E: de::Error>(self, v: i32) -> Result<(), E> {
+        self.0.write(Token::I32(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<(), E> {
+        self.0.write(Token::I64(v))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<(), E> {
+        self.0.write(Token::I128(v))
+    }
+
+    fn visit_u8<E: de::Error>(self, v: u8) -> Result<(), E> {
+        self.0.write(Token::U8(v))
+    }
+
+    fn visit_u16<E: de::Error>(self, v: u16) -> Result<(), E> {
+        self.0.write(Token::U16(v))
+    }
+
+    fn visit_u32<E: de::Error>(self, v: u32) -> Result<(), E> {
+        self.0.write(Token::U32(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<(), E> {
+        self.0.write(Token::U64(v))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<(), E> {
+        self.0.write(Token::U128(v))
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<(), E> {
+        self.0.write(Token::F32(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<(), E> {
+        self.0.write(Token::F64(v))
+    }
+
+    fn visit_char<E: de::Error>(self, v: char) -> Result<(), E> {
+        self.0.write(Token::Char(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        if self.0.start(v == NUMBER_TOKEN)? {
+            return Ok(());
+        }
+        #[cfg(feature = "bumpalo")]
+        {
+            if let Some(arena) = self.0.tokenizer.arena {
+                return self.0.write(Token::Str(Cow::Borrowed(arena.alloc_str(v))));
+            }
+        }
+        if self.0.tokenizer.options().inline_strings {
+            if let Some(v) = InlineStr::new(v) {
+                return self.0.write(Token::Inline(v));
+            }
+        }
+        if self.0.promote {
+            let result = self.0.tokenizer.check_borrowed(v.len());
+            self.0.check(result)?;
+            return self.0.write(Token::String(v.to_owned()));
+        }
+        let result = self.0.tokenizer.serialize_str(v);
+        self.0.check(result)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<(), E> {
+        self.0.write(Token::Str(v.into()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<(), E> {
+        self.0.write(Token::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<(), E> {
+        self.0.start(false)?;
+        #[cfg(feature = "bumpalo")]
+        {
+            if let Some(arena) = self.0.tokenizer.arena {
+                return self
+                    .0
+                    .write(Token::Bytes(Cow::Borrowed(arena.alloc_slice_copy(v))));
+            }
+        }
+        if self.0.promote {
+            let result = self.0.tokenizer.check_borrowed(v.len());
+            self.0.check(result)?;
+            return self.0.write(Token::ByteBuf(v.to_owned()));
+        }
+        let result = self.0.tokenizer.serialize_bytes(v);
+        self.0.check(result)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<(), E> {
+        self.0.write(Token::Bytes(v.into()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<(), E> {
+        self.0.write(Token::ByteBuf(v))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        self.0.start(false)?;
+        let result = self.0.tokenizer.serialize_none();
+        self.0.check(result)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.write(Token::Some)?;
+        self.0
+            .nested(|driver| deserializer.deserialize_any(Visitor(driver)))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.0.write(Token::Unit)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.write(Token::NewtypeStruct { name: "<unknown>" })?;
+        self.0
+            .nested(|driver| deserializer.deserialize_any(Visitor(driver)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        self.0.write(Token::Seq {
+            len: seq.size_hint(),
+        })?;
+        self.0.nested(|driver| {
+            while let Some(()) = seq.next_element_seed(Seed(&mut *driver, None))? {}
+            Ok(())
+        })?;
+        self.0.write(Token::SeqEnd)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        self.0.start(false)?;
+        // Defers the header until the first key shows whether the map is one of `serde_json`'s arbitrary-precision numbers.
+        self.0.pending = Some(Token::Map {
+            len: map.size_hint(),
+        });
+        let mut number = false;
+        self.0.nested(|driver| {
+            if map.next_key_seed(Seed(&mut *driver, None))?.is_none() {
+                return match driver.pending.take() {
+                    Some(header) => driver.write(header),
+                    None => Ok(()),
+                };
+            }
+            if core::mem::take(&mut driver.number) {
+                number = true;
+                return map.next_value_seed(NumberSeed(driver));
+            }
+            map.next_value_seed(Seed(&mut *driver, Some(Token::MapValue)))?;
+            while let Some(()) = map.next_key_seed(Seed(&mut *driver, Some(Token::MapKey)))? {
+                map.next_value_seed(Seed(&mut *driver, Some(Token::MapValue)))?;
+            }
+            Ok(())
+        })?;
+        if number {
+            return Ok(());
+        }
+        self.0.write(Token::MapEnd)
+    }
+}
+
+/// Visits an element, map key or map value, preceded by the given marker (if the tokenizer writes markers).
+struct Seed<'a, 't, 'de, S: TokenSink<'de>>(&'a mut Driver<'t, 'de, S>, Option<Token<'de>>);
+
+impl<'a, 't, 'de, S: TokenSink<'de>> DeserializeSeed<'de> for Seed<'a, 't, 'de, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        if let Some(marker) = self.1 {
+            let result = self.0.tokenizer.write_marker(marker);
+            self.0.check(result)?;
+        }
+        deserializer.deserialize_any(Visitor(self.0))
+    }
+}
+
+/// Visits the textual value of an arbitrary-precision number.
+struct NumberSeed<'a, 't, 'de, S: TokenSink<'de>>(&'a mut Driver<'t, 'de, S>);
+
+impl<'a, 't, 'de, S: TokenSink<'de>> DeserializeSeed<'de> for NumberSeed<'a, 't, 'de, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'a, 't, 'de, S: TokenSink<'de>> de::Visitor<'de> for NumberSeed<'a, 't, 'de, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        let result = self.0.tokenizer.check_borrowed(v.len());
+        self.0.check(result)?;
+        self.0.write(Token::Number(Cow::Owned(v.to_owned())))
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<(), E> {
+        self.0.write(Token::Number(Cow::Borrowed(v)))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<(), E> {
+        self.0.write(Token::Number(Cow::Owned(v)))
+    }
+}
+
+fn is_number_key(token: &Token) -> bool {
+    match token {
+        Token::Str(v) => v == NUMBER_TOKEN,
+        Token::String(v) => v == NUMBER_TOKEN,
+        _ => false,
+    }
+}
@@ -0,0 +1,777 @@
+use crate::{Error, Path, PathTracker, Role, Step, Token, TokenSink};
+use std::{collections::HashMap, mem};
+
+/// The structural context of a token passed to a [`TokenTransform`]: its path, role and depth within the transform's input stream.
+///
+/// [`TokenTransform`]: trait.TokenTransform.html
+#[derive(Clone, Copy, Debug)]
+pub struct TransformContext<'t> {
+    path: &'t Path,
+    step: Step,
+    depth: usize,
+}
+
+impl<'t> TransformContext<'t> {
+    /// Observes the given token with the tracker, returning its context.
+    fn observe(tracker: &'t mut PathTracker, token: &Token) -> Self {
+        let step = tracker.observe(token);
+        TransformContext {
+            path: tracker.path(),
+            step,
+            depth: tracker.depth(),
+        }
+    }
+
+    /// The path of the value the token belongs to (or, for map keys, the path of the value the key leads to).
+    pub fn path(&self) -> &'t Path {
+        self.path
+    }
+
+    /// The structural role of the token.
+    pub fn role(&self) -> Role {
+        self.step.role
+    }
+
+    /// The token's role, and whether it starts or ends a value.
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    /// The number of containers enclosing the token (including any it opens, but not any it closes).
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// A stage of middleware that rewrites a token stream one token at a time, such as between a [`Tokenizer`] and its sink via a [`TransformSink`].
+///
+/// Each input token is passed along with its [`TransformContext`], and may be replaced by any number of output tokens (including none). Transforms are composed with [`then`], [`map`] and [`filter`], each later stage seeing the output (and the context within the output) of the stage before it.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{Error, Token, TokenTransform, TransformContext};
+///
+/// /// Repeats every number.
+/// struct Repeat;
+///
+/// impl<'a> TokenTransform<'a> for Repeat {
+///     fn transform(
+///         &mut self,
+///         token: Token<'a>,
+///         _: TransformContext,
+///         out: &mut Vec<Token<'a>>,
+///     ) -> Result<(), Error> {
+///         if let Token::U64(_) = token {
+///             out.push(token.clone());
+///         }
+///         out.push(token);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut transform = Repeat.map(|token, _| match token {
+///     Token::U64(v) => Token::U64(v * 10),
+///     token => token,
+/// });
+/// let tokens = vec![Token::Seq { len: None }, Token::U64(1), Token::U64(2), Token::SeqEnd];
+///
+/// assert_eq!(transform.apply(tokens).unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::U64(10),
+///     Token::U64(10),
+///     Token::U64(20),
+///     Token::U64(20),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+///
+/// [`Tokenizer`]: struct.Tokenizer.html
+/// [`TransformSink`]: struct.TransformSink.html
+/// [`TransformContext`]: struct.TransformContext.html
+/// [`then`]: #method.then
+/// [`map`]: #method.map
+/// [`filter`]: #method.filter
+pub trait TokenTransform<'a> {
+    /// Transforms a single token, pushing its replacements onto `out`.
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error>;
+
+    /// Pushes any tokens still held by the transform once its input has ended.
+    fn finish(&mut self, _out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Feeds the output of this transform into another.
+    fn then<T: TokenTransform<'a>>(self, next: T) -> Then<'a, Self, T>
+    where
+        Self: Sized,
+    {
+        Then {
+            first: self,
+            second: next,
+            tracker: PathTracker::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Replaces each token this transform outputs with the result of `f`.
+    fn map<F>(self, f: F) -> Then<'a, Self, MapTokens<F>>
+    where
+        Self: Sized,
+        F: FnMut(Token<'a>, TransformContext) -> Token<'a>,
+    {
+        self.then(MapTokens(f))
+    }
+
+    /// Drops each token this transform outputs for which `f` returns `false`.
+    ///
+    /// Dropping only some of a value's tokens (such as a container's start but not its end) leaves the stream malformed.
+    fn filter<F>(self, f: F) -> Then<'a, Self, FilterTokens<F>>
+    where
+        Self: Sized,
+        F: FnMut(&Token<'a>, TransformContext) -> bool,
+    {
+        self.then(FilterTokens(f))
+    }
+
+    /// Transforms each of the given tokens, then finishes the transform, returning the output.
+    fn apply<I>(&mut self, tokens: I) -> Result<Vec<Token<'a>>, Error>
+    where
+        Self: Sized,
+        I: IntoIterator<Item = Token<'a>>,
+    {
+        let mut tracker = PathTracker::new();
+        let mut out = Vec::new();
+        for token in tokens {
+            let cx = TransformContext::observe(&mut tracker, &token);
+            self.transform(token, cx, &mut out)?;
+        }
+        self.finish(&mut out)?;
+        Ok(out)
+    }
+}
+
+impl<'a, T: TokenTransform<'a> + ?Sized> TokenTransform<'a> for Box<T> {
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        (**self).transform(token, cx, out)
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+        (**self).finish(out)
+    }
+}
+
+/// A transform that passes every token through unchanged, for starting a chain of combinators.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{Identity, Token, TokenTransform};
+///
+/// let mut transform = Identity.filter(|token, _| *token != Token::Unit);
+/// let tokens = vec![Token::Bool(true), Token::Unit];
+///
+/// assert_eq!(transform.apply(tokens).unwrap(), vec![Token::Bool(true)]);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Identity;
+
+impl<'a> TokenTransform<'a> for Identity {
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        _: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        out.push(token);
+        Ok(())
+    }
+}
+
+/// Two transforms applied in sequence, created by [`TokenTransform::then`].
+///
+/// [`TokenTransform::then`]: trait.TokenTransform.html#method.then
+#[derive(Debug)]
+pub struct Then<'a, A, B> {
+    first: A,
+    second: B,
+    tracker: PathTracker,
+    buf: Vec<Token<'a>>,
+}
+
+impl<'a, A, B> Then<'a, A, B>
+where
+    A: TokenTransform<'a>,
+    B: TokenTransform<'a>,
+{
+    // Passes the tokens output by the first transform through the second.
+    fn forward(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+        for token in self.buf.drain(..) {
+            let cx = TransformContext::observe(&mut self.tracker, &token);
+            self.second.transform(token, cx, out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, A, B> TokenTransform<'a> for Then<'a, A, B>
+where
+    A: TokenTransform<'a>,
+    B: TokenTransform<'a>,
+{
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        self.first.transform(token, cx, &mut self.buf)?;
+        self.forward(out)
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+        self.first.finish(&mut self.buf)?;
+        self.forward(out)?;
+        self.second.finish(out)
+    }
+}
+
+/// A transform that replaces each token with the result of a closure, created by [`TokenTransform::map`].
+///
+/// [`TokenTransform::map`]: trait.TokenTransform.html#method.map
+#[derive(Debug)]
+pub struct MapTokens<F>(F);
+
+impl<'a, F> TokenTransform<'a> for MapTokens<F>
+where
+    F: FnMut(Token<'a>, TransformContext) -> Token<'a>,
+{
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        out.push((self.0)(token, cx));
+        Ok(())
+    }
+}
+
+/// A transform that drops each token for which a closure returns `false`, created by [`TokenTransform::filter`].
+///
+/// [`TokenTransform::filter`]: trait.TokenTransform.html#method.filter
+#[derive(Debug)]
+pub struct FilterTokens<F>(F);
+
+impl<'a, F> TokenTransform<'a> for FilterTokens<F>
+where
+    F: FnMut(&Token<'a>, TransformContext) -> bool,
+{
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        if (self.0)(&token, cx) {
+            out.push(token);
+        }
+        Ok(())
+    }
+}
+
+/// A [`TokenSink`] adapter that passes each token through a [`TokenTransform`] before writing its output to the wrapped sink, so that middleware can be inserted between a [`Tokenizer`] and its sink.
+///
+/// The transform is finished when the sink is.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{tokenize, ChannelSink, Identity, Role, Token, TokenTransform, TransformSink};
+/// use std::sync::mpsc::channel;
+///
+/// let (tx, rx) = channel();
+/// let transform = Identity.map(|token, cx| match token {
+///     Token::Str(s) if cx.role() == Role::Scalar && cx.path().to_string() == "name" => {
+///         Token::String(s.to_uppercase())
+///     }
+///     token => token,
+/// });
+/// let mut de = serde_json::Deserializer::from_str(r#" {"name": "a", "id": "b"} "#);
+/// tokenize(&mut de, TransformSink::new(ChannelSink::new(tx), transform)).unwrap();
+///
+/// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+///     Token::Map { len: None },
+///     Token::String("name".to_string()),
+///     Token::String("A".to_string()),
+///     Token::String("id".to_string()),
+///     Token::String("b".to_string()),
+///     Token::MapEnd,
+/// ]);
+/// ```
+///
+/// [`TokenSink`]: trait.TokenSink.html
+/// [`TokenTransform`]: trait.TokenTransform.html
+/// [`Tokenizer`]: struct.Tokenizer.html
+#[derive(Debug)]
+pub struct TransformSink<'a, S, T> {
+    sink: S,
+    transform: T,
+    tracker: PathTracker,
+    buf: Vec<Token<'a>>,
+}
+
+impl<'a, S, T> TransformSink<'a, S, T>
+where
+    S: TokenSink<'a>,
+    T: TokenTransform<'a>,
+{
+    /// Wraps a sink, transforming each token written to it with the given transform.
+    pub fn new(sink: S, transform: T) -> Self {
+        TransformSink {
+            sink,
+            transform,
+            tracker: PathTracker::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Gets a reference to the wrapped sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Consumes the adapter, returning the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    fn write_buf(&mut self) -> Result<(), Error> {
+        for token in self.buf.drain(..) {
+            self.sink.write(token).map_err(Into::into)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, S, T> TokenSink<'a> for TransformSink<'a, S, T>
+where
+    S: TokenSink<'a>,
+    T: TokenTransform<'a>,
+{
+    type Error = Error;
+
+    fn write(&mut self, token: Token<'a>) -> Result<(), Error> {
+        let cx = TransformContext::observe(&mut self.tracker, &token);
+        self.transform.transform(token, cx, &mut self.buf)?;
+        self.write_buf()
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.sink.flush().map_err(Into::into)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.transform.finish(&mut self.buf)?;
+        self.write_buf()?;
+        self.sink.finish().map_err(Into::into)
+    }
+}
+
+/// Creates a transform that drops every value whose path matches `predicate`, along with the map key (and any `MapKey`, `MapValue` or `Truncated` markers) that leads to it, so the remaining stream stays well-formed.
+///
+/// The predicate is called once per value (and per scalar or chunked map key, with the path of the value the key leads to). A matching map value is dropped along with its key, and a matching sequence element is dropped outright, shifting the indices of the elements after it. A matching top-level value drops the whole document, but not its `DocumentStart` and `DocumentEnd` markers.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{prune, Segment, Token, TokenTransform};
+///
+/// let tokens = vec![
+///     Token::Map { len: None },
+///     Token::Str("secret".into()),
+///     Token::Seq { len: None },
+///     Token::U64(1),
+///     Token::SeqEnd,
+///     Token::Str("ids".into()),
+///     Token::Seq { len: None },
+///     Token::U64(1),
+///     Token::U64(2),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ];
+/// let mut transform = prune(|path| {
+///     path.to_string() == "secret" || path.segments().last() == Some(&Segment::Index(0))
+/// });
+///
+/// assert_eq!(transform.apply(tokens).unwrap(), vec![
+///     Token::Map { len: None },
+///     Token::Str("ids".into()),
+///     Token::Seq { len: None },
+///     Token::U64(2),
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ]);
+/// ```
+pub fn prune<'a, F>(predicate: F) -> Prune<'a, F>
+where
+    F: FnMut(&Path) -> bool,
+{
+    Prune {
+        predicate,
+        pending: Vec::new(),
+        skip: Skip::None,
+        checked: false,
+    }
+}
+
+/// A transform that drops whole values by their path, created by [`prune`].
+///
+/// [`prune`]: fn.prune.html
+#[derive(Debug)]
+pub struct Prune<'a, F> {
+    predicate: F,
+    pending: Vec<Token<'a>>,
+    skip: Skip,
+    // Whether the upcoming map value's path was already checked by way of its key.
+    checked: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Skip {
+    None,
+    // The key of the next value was dropped, so its markers and the value are dropped too.
+    Next,
+    // A value is being dropped, until it ends at the given depth.
+    Value { depth: usize },
+}
+
+impl<'a, F> TokenTransform<'a> for Prune<'a, F>
+where
+    F: FnMut(&Path) -> bool,
+{
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        if let Skip::Value { depth } = self.skip {
+            if cx.step().ends_value && cx.depth() == depth {
+                self.skip = Skip::None;
+            }
+            return Ok(());
+        }
+
+        let step = cx.step();
+        match step.role {
+            Role::Marker if token != Token::DocumentStart && token != Token::DocumentEnd => {
+                // held until the token it precedes is kept
+                if let Skip::None = self.skip {
+                    self.pending.push(token);
+                }
+                return Ok(());
+            }
+            Role::Key if matches!(token, Token::StrChunk(_) | Token::BytesChunk(_)) => {
+                // held until the end of the chunked key decides it
+                self.pending.push(token);
+                return Ok(());
+            }
+            Role::Key if token.is_scalar() || matches!(token, Token::StrEnd | Token::BytesEnd) => {
+                if (self.predicate)(cx.path()) {
+                    self.pending.clear();
+                    self.skip = Skip::Next;
+                    return Ok(());
+                }
+                self.checked = true;
+            }
+            _ if step.starts_value => {
+                let checked = mem::replace(&mut self.checked, false);
+                if let Skip::Next = self.skip {
+                } else if checked || !(self.predicate)(cx.path()) {
+                    out.append(&mut self.pending);
+                    out.push(token);
+                    return Ok(());
+                }
+                self.pending.clear();
+                self.skip = match step.ends_value {
+                    true => Skip::None,
+                    false => Skip::Value {
+                        depth: cx.depth() - usize::from(step.role == Role::Open),
+                    },
+                };
+                return Ok(());
+            }
+            _ => {}
+        }
+        out.append(&mut self.pending);
+        out.push(token);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+        out.append(&mut self.pending);
+        Ok(())
+    }
+}
+
+/// A transform that renames map keys and struct fields according to a table of exact names and per-path rules, such as for migrating documents between schemas as they're transcoded.
+///
+/// A rule for the full path of a key (in the input, before any renaming) takes precedence over a rule for its name alone. Renamed keys are written as owned `Token::String`s, and keys without a rule are passed through as is. Only scalar keys are renamed, so a key split into `StrChunk`s is left alone.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{Path, RenameKeys, Token, TokenTransform};
+///
+/// let tokens = vec![
+///     Token::Struct { name: "User", len: 2 },
+///     Token::Field("name"),
+///     Token::Str("John".into()),
+///     Token::Field("address"),
+///     Token::Map { len: None },
+///     Token::Str("name".into()),
+///     Token::Str("Home".into()),
+///     Token::MapEnd,
+///     Token::StructEnd,
+/// ];
+/// let mut transform = RenameKeys::new()
+///     .rename("name", "full_name")
+///     .rename("address", "addr")
+///     .rename_at(Path::new().key("address").key("name"), "label");
+///
+/// assert_eq!(transform.apply(tokens).unwrap(), vec![
+///     Token::Struct { name: "User", len: 2 },
+///     Token::String("full_name".to_string()),
+///     Token::Str("John".into()),
+///     Token::String("addr".to_string()),
+///     Token::Map { len: None },
+///     Token::String("label".to_string()),
+///     Token::Str("Home".into()),
+///     Token::MapEnd,
+///     Token::StructEnd,
+/// ]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RenameKeys {
+    names: HashMap<String, String>,
+    paths: HashMap<Path, String>,
+}
+
+impl RenameKeys {
+    /// Creates a transform without any rules, which passes every key through.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renames every key named `from`, wherever it appears, to `to`.
+    pub fn rename<F, T>(mut self, from: F, to: T) -> Self
+    where
+        F: Into<String>,
+        T: Into<String>,
+    {
+        self.names.insert(from.into(), to.into());
+        self
+    }
+
+    /// Renames the key at `path` (the path of the value it leads to) to `to`.
+    pub fn rename_at<T: Into<String>>(mut self, path: Path, to: T) -> Self {
+        self.paths.insert(path, to.into());
+        self
+    }
+
+    fn renamed(&self, path: &Path, key: &str) -> Option<&str> {
+        self.paths
+            .get(path)
+            .or_else(|| self.names.get(key))
+            .map(String::as_str)
+    }
+}
+
+impl<'a> TokenTransform<'a> for RenameKeys {
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        if cx.role() == Role::Key && token.is_scalar() {
+            if let Some(key) = token.as_str().and_then(|key| self.renamed(cx.path(), key)) {
+                out.push(Token::String(key.to_string()));
+                return Ok(());
+            }
+        }
+        out.push(token);
+        Ok(())
+    }
+}
+
+/// A transform that passes through only the first elements of each `Seq`, dropping the rest, such as for previewing giant documents.
+///
+/// Limits can be set for every sequence, for the sequences at a given depth (the number of containers around them, so a top-level sequence is at depth 0) and for the sequence at a given path, in increasing order of precedence; sequences without a limit are passed through whole. Tuples are never truncated. A truncated sequence's known length is reduced to its limit, and with [`markers`], a `Token::Truncated` marker of its original number of elements is written before its `SeqEnd`.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{to_tokens, Path, Token, TokenTransform, TruncateSeqs};
+///
+/// let mut de = serde_json::Deserializer::from_str(r#" {"a": [1, 2, 3], "b": [[4, 5], [6]]} "#);
+/// let tokens = to_tokens(&mut de).unwrap();
+/// let mut transform = TruncateSeqs::new()
+///     .max_len(1)
+///     .max_len_at(Path::new().key("a"), 2)
+///     .markers(true);
+///
+/// assert_eq!(transform.apply(tokens).unwrap(), vec![
+///     Token::Map { len: None },
+///     Token::Str("a".into()),
+///     Token::Seq { len: None },
+///     Token::U64(1),
+///     Token::U64(2),
+///     Token::Truncated { len: 3 },
+///     Token::SeqEnd,
+///     Token::Str("b".into()),
+///     Token::Seq { len: None },
+///     Token::Seq { len: None },
+///     Token::U64(4),
+///     Token::Truncated { len: 2 },
+///     Token::SeqEnd,
+///     Token::Truncated { len: 2 },
+///     Token::SeqEnd,
+///     Token::MapEnd,
+/// ]);
+/// ```
+///
+/// [`markers`]: #method.markers
+#[derive(Clone, Debug, Default)]
+pub struct TruncateSeqs<'a> {
+    max_len: Option<usize>,
+    depths: HashMap<usize, usize>,
+    paths: HashMap<Path, usize>,
+    markers: bool,
+    seqs: Vec<SeqFrame>,
+    pending: Vec<Token<'a>>,
+    skip: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SeqFrame {
+    // the depth of the sequence's elements
+    depth: usize,
+    max_len: usize,
+    seen: usize,
+}
+
+impl<'a> TruncateSeqs<'a> {
+    /// Creates a transform without any limits, which passes every sequence through whole.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of elements kept of every sequence without a more specific limit.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Sets the number of elements kept of the sequences at `depth`, unless set for their paths.
+    pub fn max_len_at_depth(mut self, depth: usize, max_len: usize) -> Self {
+        self.depths.insert(depth, max_len);
+        self
+    }
+
+    /// Sets the number of elements kept of the sequence at `path`.
+    pub fn max_len_at(mut self, path: Path, max_len: usize) -> Self {
+        self.paths.insert(path, max_len);
+        self
+    }
+
+    /// Sets whether a `Token::Truncated` marker of a truncated sequence's original number of elements is written before its `SeqEnd`. Defaults to `false`.
+    pub fn markers(mut self, enabled: bool) -> Self {
+        self.markers = enabled;
+        self
+    }
+}
+
+impl<'a> TokenTransform<'a> for TruncateSeqs<'a> {
+    fn transform(
+        &mut self,
+        token: Token<'a>,
+        cx: TransformContext,
+        out: &mut Vec<Token<'a>>,
+    ) -> Result<(), Error> {
+        if let Some(depth) = self.skip {
+            if cx.step().ends_value && cx.depth() == depth {
+                self.skip = None;
+            }
+            return Ok(());
+        }
+
+        let step = cx.step();
+        if step.role == Role::Marker && token != Token::DocumentStart && token != Token::DocumentEnd
+        {
+            // held until the element it precedes is kept
+            self.pending.push(token);
+            return Ok(());
+        }
+        if step.starts_value && step.role != Role::Key {
+            let depth = cx.depth() - usize::from(step.role == Role::Open);
+            if let Some(seq) = self.seqs.last_mut().filter(|seq| seq.depth == depth) {
+                seq.seen += 1;
+                if seq.seen > seq.max_len {
+                    self.pending.clear();
+                    if !step.ends_value {
+                        self.skip = Some(depth);
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        out.append(&mut self.pending);
+
+        match token {
+            Token::Seq { len } => {
+                let max_len = self
+                    .paths
+                    .get(cx.path())
+                    .or_else(|| self.depths.get(&(cx.depth() - 1)))
+                    .or(self.max_len.as_ref())
+                    .copied()
+                    .unwrap_or(usize::MAX);
+                self.seqs.push(SeqFrame {
+                    depth: cx.depth(),
+                    max_len,
+                    seen: 0,
+                });
+                out.push(Token::Seq {
+                    len: len.map(|len| len.min(max_len)),
+                });
+            }
+            Token::SeqEnd => {
+                if let Some(seq) = self.seqs.pop() {
+                    if self.markers && seq.seen > seq.max_len {
+                        out.push(Token::Truncated { len: seq.seen });
+                    }
+                }
+                out.push(token);
+            }
+            token => out.push(token),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), Error> {
+        out.append(&mut self.pending);
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{
+    path::{key_string, role, Role},
+    transcode::DEFAULT_MAX_DEPTH,
+    ArrayMerge, Error, Path, Segment, Token,
+};
+use std::mem;
+
+/// A single value parsed from a token stream, used by the buffering transforms.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Node<'a> {
+    Scalar(Token<'a>),
+    Prefixed(Token<'a>, Box<Node<'a>>),
+    Seq {
+        open: Token<'a>,
+        items: Vec<Node<'a>>,
+        close: Token<'a>,
+    },
+    Map {
+        open: Token<'a>,
+        entries: Vec<(Node<'a>, Node<'a>)>,
+        close: Token<'a>,
+    },
+}
+
+impl<'a> Node<'a> {
+    /// Parses a single value from the front of the given tokens.
+    ///
+    /// Values nested more than `DEFAULT_MAX_DEPTH` levels deep (counting prefixes as well as containers) fail with `Error::DepthLimitExceeded`, as nodes are parsed, walked and dropped recursively.
+    pub(crate) fn parse<I>(tokens: &mut I) -> Result<Option<Self>, Error>
+    where
+        I: Iterator<Item = Token<'a>>,
+    {
+        Self::parse_at(tokens, 0)
+    }
+
+    fn parse_at<I>(tokens: &mut I, depth: usize) -> Result<Option<Self>, Error>
+    where
+        I: Iterator<Item = Token<'a>>,
+    {
+        match next_token(tokens) {
+            Some(token) => Self::parse_from(token, tokens, depth).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn parse_from<I>(token: Token<'a>, tokens: &mut I, depth: usize) -> Result<Self, Error>
+    where
+        I: Iterator<Item = Token<'a>>,
+    {
+        if let Token::StrChunk(chunk) = token {
+            let mut string = chunk.into_owned();
+            loop {
+                match next_token(tokens) {
+                    Some(Token::StrChunk(chunk)) => string.push_str(&chunk),
+                    Some(Token::StrEnd) => return Ok(Node::Scalar(Token::String(string))),
+                    _ => return Err(malformed("unterminated string chunks")),
+                }
+            }
+        }
+        if let Token::BytesChunk(chunk) = token {
+            let mut bytes = chunk.into_owned();
+            loop {
+                match next_token(tokens) {
+                    Some(Token::BytesChunk(chunk)) => bytes.extend_from_slice(&chunk),
+                    Some(Token::BytesEnd) => return Ok(Node::Scalar(Token::ByteBuf(bytes))),
+                    _ => return Err(malformed("unterminated bytes chunks")),
+                }
+            }
+        }
+        let role = role(&token);
+        if (role == Role::Prefix || role == Role::Open) && depth == DEFAULT_MAX_DEPTH {
+            return Err(Error::DepthLimitExceeded { depth: depth + 1 });
+        }
+        let depth = depth + 1;
+        Ok(match role {
+            Role::Prefix => Node::Prefixed(token, Box::new(Self::parse_value(tokens, depth)?)),
+            Role::Open if is_map(&token) => {
+                let mut entries = Vec::new();
+                loop {
+                    match Self::parse_item(tokens, depth)? {
+                        Ok(key) => entries.push((key, Self::parse_value(tokens, depth)?)),
+                        Err(close) => {
+                            break Node::Map {
+                                open: token,
+                                entries,
+                                close,
+                            }
+                        }
+                    }
+                }
+            }
+            Role::Open => {
+                let mut items = Vec::new();
+                loop {
+                    match Self::parse_item(tokens, depth)? {
+                        Ok(item) => items.push(item),
+                        Err(close) => {
+                            break Node::Seq {
+                                open: token,
+                                items,
+                                close,
+                            }
+                        }
+                    }
+                }
+            }
+            Role::Close => return Err(malformed("unexpected end of container")),
+            _ => Node::Scalar(token),
+        })
+    }
+
+    /// Parses every value in the given tokens.
+    pub(crate) fn parse_all<I>(tokens: I) -> Result<Vec<Self>, Error>
+    where
+        I: IntoIterator<Item = Token<'a>>,
+    {
+        let mut tokens = tokens.into_iter();
+        let mut nodes = Vec::new();
+        while let Some(node) = Self::parse(&mut tokens)? {
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+
+    /// Parses the only value in the given tokens, if any, failing if there is more than one.
+    pub(crate) fn parse_single<I>(tokens: I) -> Result<Option<Self>, Error>
+    where
+        I: IntoIterator<Item = Token<'a>>,
+    {
+        let mut tokens = tokens.into_iter();
+        let node = Self::parse(&mut tokens)?;
+        if Self::parse(&mut tokens)?.is_some() {
+            return Err(malformed("expected a single value"));
+        }
+        Ok(node)
+    }
+
+    fn parse_value<I>(tokens: &mut I, depth: usize) -> Result<Self, Error>
+    where
+        I: Iterator<Item = Token<'a>>,
+    {
+        Self::parse_at(tokens, depth)?.ok_or_else(|| malformed("unexpected end of tokens"))
+    }
+
+    // Parses the next key or element of a container, or returns the container's end token.
+    fn parse_item<I>(tokens: &mut I, depth: usize) -> Result<Result<Self, Token<'a>>, Error>
+    where
+        I: Iterator<Item = Token<'a>>,
+    {
+        let token = next_token(tokens).ok_or_else(|| malformed("unclosed container"))?;
+        if role(&token) == Role::Close {
+            return Ok(Err(token));
+        }
+        Self::parse_from(token, tokens, depth).map(Ok)
+    }
+
+    /// Appends this value's tokens to the given buffer, recomputing any known container lengths.
+    pub(crate) fn emit(self, tokens: &mut Vec<Token<'a>>) {
+        self.emit_marked(tokens, false)
+    }
+
+    /// Appends this value's tokens to the given buffer like `emit`, preceding each map key and value with a `MapKey` or `MapValue` marker if `markers` is set.
+    pub(crate) fn emit_marked(self, tokens: &mut Vec<Token<'a>>, markers: bool) {
+        match self {
+            Node::Scalar(token) => tokens.push(token),
+            Node::Prefixed(prefix, node) => {
+                tokens.push(prefix);
+                node.emit_marked(tokens, markers);
+            }
+            Node::Seq { open, items, close } => {
+                tokens.push(with_len(open, items.len()));
+                for item in items {
+                    item.emit_marked(tokens, markers);
+                }
+                tokens.push(close);
+            }
+            Node::Map {
+                open,
+                entries,
+                close,
+            } => {
+                let marked = markers && matches!(open, Token::Map { .. });
+                tokens.push(with_len(open, entries.len()));
+                for (key, value) in entries {
+                    if marked {
+                        tokens.push(Token::MapKey);
+                    }
+                    key.emit_marked(tokens, markers);
+                    if marked {
+                        tokens.push(Token::MapValue);
+                    }
+                    value.emit_marked(tokens, markers);
+                }
+                tokens.push(close);
+            }
+        }
+    }
+
+    /// Returns this value's tokens.
+    pub(crate) fn into_tokens(self) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        self.emit(&mut tokens);
+        tokens
+    }
+
+    /// Creates an empty map.
+    pub(crate) fn map() -> Self {
+        Node::Map {
+            open: Token::Map { len: Some(0) },
+            entries: Vec::new(),
+            close: Token::MapEnd,
+        }
+    }
+
+    /// Creates an empty sequence.
+    pub(crate) fn seq() -> Self {
+        Node::Seq {
+            open: Token::Seq { len: Some(0) },
+            items: Vec::new(),
+            close: Token::SeqEnd,
+        }
+    }
+
+    /// Strips any `Some`, newtype and enum header prefixes.
+    pub(crate) fn unwrap_prefixes(&self) -> &Self {
+        match self {
+            Node::Prefixed(_, node) => node.unwrap_prefixes(),
+            node => node,
+        }
+    }
+
+    fn unwrap_prefixes_mut(&mut self) -> &mut Self {
+        match self {
+            Node::Prefixed(_, node) => node.unwrap_prefixes_mut(),
+            node => node,
+        }
+    }
+
+    fn into_unprefixed(self) -> Self {
+        match self {
+            Node::Prefixed(_, node) => node.into_unprefixed(),
+            node => node,
+        }
+    }
+
+    /// Deeply merges `right` into this value, as described by `merge`.
+    pub(crate) fn merge(self, mut right: Self, arrays: ArrayMerge) -> Self {
+        match (self.into_unprefixed(), right.unwrap_prefixes_mut()) {
+            (Node::Map { entries: left, .. }, Node::Map { entries, .. }) => {
+                let mut right_entries = mem::take(entries);
+                for (key, value) in left {
+                    match right_entries.iter().position(|(k, _)| k.same_key(&key)) {
+                        Some(i) => {
+                            let (key, right_value) = right_entries.remove(i);
+                            entries.push((key, value.merge(right_value, arrays)));
+                        }
+                        None => entries.push((key, value)),
+                    }
+                }
+                entries.append(&mut right_entries);
+            }
+            (Node::Seq { items: left, .. }, Node::Seq { items, .. }) => match arrays {
+                ArrayMerge::Replace => {}
+                ArrayMerge::Concat => {
+                    let right_items = mem::replace(items, left);
+                    items.extend(right_items);
+                }
+                ArrayMerge::ByIndex => {
+                    let mut right_items = mem::take(items).into_iter();
+                    for item in left {
+                        items.push(match right_items.next() {
+                            Some(right_item) => item.merge(right_item, arrays),
+                            None => item,
+                        });
+                    }
+                    items.extend(right_items);
+                }
+            },
+            _ => {}
+        }
+        right
+    }
+
+    /// Whether two map keys are the same, comparing scalars by their rendering.
+    pub(crate) fn same_key(&self, other: &Self) -> bool {
+        match (self.key_string(), other.key_string()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self == other,
+        }
+    }
+
+    /// Recursively sorts the entries of each map by their keys' tokens.
+    pub(crate) fn sort_maps(&mut self) {
+        match self {
+            Node::Scalar(_) => {}
+            Node::Prefixed(_, node) => node.sort_maps(),
+            Node::Seq { items, .. } => items.iter_mut().for_each(Node::sort_maps),
+            Node::Map { open, entries, .. } => {
+                for (key, value) in entries.iter_mut() {
+                    key.sort_maps();
+                    value.sort_maps();
+                }
+                if let Token::Map { .. } = open {
+                    entries.sort_by_cached_key(|(key, _)| key.clone().into_tokens());
+                }
+            }
+        }
+    }
+
+    /// Renders this value as a path key, if it is a scalar.
+    pub(crate) fn key_string(&self) -> Option<String> {
+        match self.unwrap_prefixes() {
+            Node::Scalar(token) => Some(key_string(token)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value at the given path, relative to this value.
+    pub(crate) fn get(&self, segments: &[Segment]) -> Option<&Self> {
+        let (segment, rest) = match segments.split_first() {
+            None => return Some(self),
+            Some(split) => split,
+        };
+        match (self.unwrap_prefixes(), segment) {
+            (Node::Map { entries, .. }, Segment::Key(key)) => entries
+                .iter()
+                .find(|(k, _)| k.key_string().as_ref() == Some(key))
+                .and_then(|(_, value)| value.get(rest)),
+            (Node::Seq { items, .. }, Segment::Index(index)) => {
+                items.get(*index).and_then(|item| item.get(rest))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value at the given path, relative to this value.
+    pub(crate) fn get_mut(&mut self, segments: &[Segment]) -> Option<&mut Self> {
+        let (segment, rest) = match segments.split_first() {
+            None => return Some(self),
+            Some(split) => split,
+        };
+        match (self.unwrap_prefixes_mut(), segment) {
+            (Node::Map { entries, .. }, Segment::Key(key)) => entries
+                .iter_mut()
+                .find(|(k, _)| k.key_string().as_ref() == Some(key))
+                .and_then(|(_, value)| value.get_mut(rest)),
+            (Node::Seq { items, .. }, Segment::Index(index)) => {
+                items.get_mut(*index).and_then(|item| item.get_mut(rest))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the map entry at the given path, relative to this value.
+    pub(crate) fn entry_mut(&mut self, path: &Path) -> Option<&mut (Self, Self)> {
+        let (last, parent) = path.segments().split_last()?;
+        let key = match last {
+            Segment::Key(key) => key,
+            Segment::Index(_) => return None,
+        };
+        match self.get_mut(parent)?.unwrap_prefixes_mut() {
+            Node::Map { entries, .. } => entries
+                .iter_mut()
+                .find(|(k, _)| k.key_string().as_ref() == Some(key)),
+            _ => None,
+        }
+    }
+
+    /// Removes and returns the value at the given path, relative to this value.
+    pub(crate) fn remove(&mut self, path: &Path) -> Option<Self> {
+        let (last, parent) = path.segments().split_last()?;
+        match (self.get_mut(parent)?.unwrap_prefixes_mut(), last) {
+            (Node::Map { entries, .. }, Segment::Key(key)) => {
+                let i = entries
+                    .iter()
+                    .position(|(k, _)| k.key_string().as_ref() == Some(key))?;
+                Some(entries.remove(i).1)
+            }
+            (Node::Seq { items, .. }, Segment::Index(index)) if *index < items.len() => {
+                Some(items.remove(*index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Inserts (or replaces) the value at the given path, relative to this value, creating any missing intermediate maps or sequences.
+    pub(crate) fn insert(&mut self, path: &Path, value: Self) -> Result<(), Error> {
+        let (last, parent) = match path.segments().split_last() {
+            Some(split) => split,
+            None => {
+                *self = value;
+                return Ok(());
+            }
+        };
+
+        let mut node = self;
+        for (i, segment) in parent.iter().enumerate() {
+            node = match (node.unwrap_prefixes_mut(), segment) {
+                (Node::Map { entries, .. }, Segment::Key(key)) => {
+                    let i = match entries
+                        .iter()
+                        .position(|(k, _)| k.key_string().as_ref() == Some(key))
+                    {
+                        Some(i) => i,
+                        None => {
+                            let child = match parent.get(i + 1).unwrap_or(last) {
+                                Segment::Key(_) => Node::map(),
+                                Segment::Index(_) => Node::seq(),
+                            };
+                            entries.push((Node::Scalar(Token::String(key.clone())), child));
+                            entries.len() - 1
+                        }
+                    };
+                    &mut entries[i].1
+                }
+                (Node::Seq { items, .. }, Segment::Index(index)) if *index < items.len() => {
+                    &mut items[*index]
+                }
+                _ => return Err(conflict(path)),
+            };
+        }
+
+        match (node.unwrap_prefixes_mut(), last) {
+            (Node::Map { entries, .. }, Segment::Key(key)) => {
+                match entries
+                    .iter_mut()
+                    .find(|(k, _)| k.key_string().as_ref() == Some(key))
+                {
+                    Some(entry) => entry.1 = value,
+                    None => entries.push((Node::Scalar(Token::String(key.clone())), value)),
+                }
+                Ok(())
+            }
+            (Node::Seq { items, .. }, Segment::Index(index)) if *index <= items.len() => {
+                if *index == items.len() {
+                    items.push(value);
+                } else {
+                    items[*index] = value;
+                }
+                Ok(())
+            }
+            _ => Err(conflict(path)),
+        }
+    }
+}
+
+fn is_map(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Map { .. } | Token::Struct { .. } | Token::StructVariant { .. }
+    )
+}
+
+pub(crate) fn with_len(token: Token, n: usize) -> Token {
+    match token {
+        Token::Seq { len: Some(_) } => Token::Seq { len: Some(n) },
+        Token::Map { len: Some(_) } => Token::Map { len: Some(n) },
+        Token::Tuple { .. } => Token::Tuple { len: n },
+        Token::TupleStruct { name, .. } => Token::TupleStruct { name, len: n },
+        Token::TupleVariant {
+            name,
+            variant_index,
+            variant,
+            ..
+        } => Token::TupleVariant {
+            name,
+            variant_index,
+            variant,
+            len: n,
+        },
+        Token::Struct { name, .. } => Token::Struct { name, len: n },
+        Token::StructVariant {
+            name,
+            variant_index,
+            variant,
+            ..
+        } => Token::StructVariant {
+            name,
+            variant_index,
+            variant,
+            len: n,
+        },
+        token => token,
+    }
+}
+
+pub(crate) fn malformed(msg: &str) -> Error {
+    Error::TokenStreamError(msg.to_string())
+}
+
+fn conflict(path: &Path) -> Error {
+    Error::PathError(format!("conflicting path: {}", path))
+}
+
+// Takes the next token, skipping any markers.
+fn next_token<'a, I: Iterator<Item = Token<'a>>>(tokens: &mut I) -> Option<Token<'a>> {
+    tokens.find(|token| role(token) != Role::Marker)
+}
@@ -0,0 +1,847 @@
+//! A versioned, framed binary encoding of `Token`s, for shipping token streams between processes.
+//!
+//! # Format
+//!
+//! A stream begins with a header: the magic bytes `STOK` followed by the protocol version as a little-endian `u16`. The rest of the stream is a sequence of frames, each a varint byte length followed by a varint token count and that many token records.
+//!
+//! Each token record is a one-byte tag, a varint payload length and the payload itself. Because every record carries its length, a decoder can skip tokens with tags it does not recognize (e.g. those introduced by a newer protocol version), allowing producers and consumers to be upgraded independently.
+//!
+//! Long-lived connections should exchange [`Hello`] messages and [`negotiate`] a version both ends support, with the producer's [`Encoder`] downgrading any tokens that the negotiated version cannot represent.
+//!
+//! [`Hello`]: struct.Hello.html
+//! [`negotiate`]: fn.negotiate.html
+//! [`Encoder`]: struct.Encoder.html
+
+use crate::{Error, InlineStr, Token};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    convert::TryInto,
+    io::{Read, Write},
+    sync::{Mutex, OnceLock},
+};
+
+/// The magic bytes that begin an encoded token stream or handshake.
+pub const MAGIC: &[u8; 4] = b"STOK";
+
+/// The newest protocol version this crate can encode and decode.
+pub const VERSION: u16 = 11;
+
+/// The oldest protocol version this crate can encode and decode.
+pub const MIN_VERSION: u16 = 1;
+
+/// A handshake message advertising the range of protocol versions an endpoint supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    /// The oldest supported protocol version.
+    pub min_version: u16,
+
+    /// The newest supported protocol version.
+    pub max_version: u16,
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Hello {
+            min_version: MIN_VERSION,
+            max_version: VERSION,
+        }
+    }
+}
+
+impl Hello {
+    /// Appends the encoded handshake to the given buffer.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&self.min_version.to_le_bytes());
+        buf.extend_from_slice(&self.max_version.to_le_bytes());
+    }
+
+    /// Decodes a handshake from the front of the given bytes, returning it and the number of bytes read, or `None` if more bytes are needed.
+    pub fn decode(input: &[u8]) -> Result<Option<(Hello, usize)>, Error> {
+        if input.len() < 8 {
+            return Ok(None);
+        }
+        check_magic(input)?;
+        let hello = Hello {
+            min_version: u16::from_le_bytes([input[4], input[5]]),
+            max_version: u16::from_le_bytes([input[6], input[7]]),
+        };
+        Ok(Some((hello, 8)))
+    }
+}
+
+/// Chooses the newest protocol version supported by both endpoints.
+pub fn negotiate(local: &Hello, remote: &Hello) -> Result<u16, Error> {
+    let version = local.max_version.min(remote.max_version);
+    if version < local.min_version.max(remote.min_version) {
+        return Err(wire_error(format!(
+            "no common protocol version: {}..={} and {}..={}",
+            local.min_version, local.max_version, remote.min_version, remote.max_version
+        )));
+    }
+    Ok(version)
+}
+
+/// Encodes tokens into frames for a given protocol version.
+#[derive(Clone, Debug)]
+pub struct Encoder {
+    version: u16,
+    // the chunks of a string being joined for a version without `StrChunk`s
+    chunks: Option<String>,
+    // the chunks of a byte array being joined for a version without `BytesChunk`s
+    bytes_chunks: Option<Vec<u8>>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Encoder {
+            version: VERSION,
+            chunks: None,
+            bytes_chunks: None,
+        }
+    }
+}
+
+impl Encoder {
+    /// Creates an encoder for the given (e.g. negotiated) protocol version.
+    pub fn new(version: u16) -> Result<Self, Error> {
+        check_version(version)?;
+        Ok(Encoder {
+            version,
+            chunks: None,
+            bytes_chunks: None,
+        })
+    }
+
+    /// The protocol version being encoded.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Appends the stream header to the given buffer.
+    pub fn encode_header(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&self.version.to_le_bytes());
+    }
+
+    /// Appends a frame containing the given tokens to the given buffer.
+    ///
+    /// Tokens that cannot be represented in the encoder's protocol version are downgraded to an older equivalent, or dropped if there is none. The chunks of a string (or byte array) are joined into a single `Str` (or `Bytes`) for versions without `StrChunk`s (or `BytesChunk`s), and are held by the encoder until the frame containing their `StrEnd` (or `BytesEnd`).
+    pub fn encode_frame(&mut self, tokens: &[Token], buf: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        let mut count = 0;
+        for token in tokens {
+            match token {
+                Token::StrChunk(chunk) if self.version < 5 => {
+                    self.chunks.get_or_insert_with(String::new).push_str(chunk);
+                    continue;
+                }
+                Token::StrEnd if self.version < 5 => {
+                    let string = self.chunks.take().unwrap_or_default();
+                    encode_token(&Token::Str(string.into()), &mut body);
+                    count += 1;
+                    continue;
+                }
+                Token::BytesChunk(chunk) if self.version < 6 => {
+                    self.bytes_chunks
+                        .get_or_insert_with(Vec::new)
+                        .extend_from_slice(chunk);
+                    continue;
+                }
+                Token::BytesEnd if self.version < 6 => {
+                    let bytes = self.bytes_chunks.take().unwrap_or_default();
+                    encode_token(&Token::Bytes(bytes.into()), &mut body);
+                    count += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(token) = downgrade(token, self.version) {
+                encode_token(&token, &mut body);
+                count += 1;
+            }
+        }
+
+        let mut prefix = Vec::new();
+        write_varint(count, &mut prefix);
+        write_varint((prefix.len() + body.len()) as u64, buf);
+        buf.extend_from_slice(&prefix);
+        buf.extend_from_slice(&body);
+    }
+}
+
+/// Decodes frames of tokens encoded with a given protocol version.
+///
+/// Type, field and variant names are given a `'static` lifetime by leaking a copy of each distinct name. Each decoder leaks at most [`MAX_INTERNED_BYTES`] of names, after which decoding a token with a new name fails, so a stream can't exhaust the names available to other streams.
+///
+/// [`MAX_INTERNED_BYTES`]: constant.MAX_INTERNED_BYTES.html
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    version: u16,
+    skipped: usize,
+    names: Interner,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            version: VERSION,
+            skipped: 0,
+            names: Interner::new(MAX_INTERNED_BYTES),
+        }
+    }
+}
+
+impl Decoder {
+    /// Creates a decoder for the given (e.g. negotiated) protocol version.
+    pub fn new(version: u16) -> Result<Self, Error> {
+        check_version(version)?;
+        Ok(Decoder {
+            version,
+            skipped: 0,
+            names: Interner::new(MAX_INTERNED_BYTES),
+        })
+    }
+
+    /// Decodes a stream header from the front of the given bytes, returning a decoder for the stream's version and the number of bytes read, or `None` if more bytes are needed.
+    pub fn from_header(input: &[u8]) -> Result<Option<(Self, usize)>, Error> {
+        if input.len() < 6 {
+            return Ok(None);
+        }
+        check_magic(input)?;
+        let version = u16::from_le_bytes([input[4], input[5]]);
+        Ok(Some((Self::new(version)?, 6)))
+    }
+
+    /// The protocol version being decoded.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// The number of tokens with unrecognized tags that have been skipped.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Decodes a frame from the front of the given bytes, returning its tokens and the number of bytes read, or `None` if more bytes are needed.
+    ///
+    /// Decoded `Str` and `Bytes` tokens borrow from the input.
+    pub fn decode_frame<'a>(
+        &mut self,
+        input: &'a [u8],
+    ) -> Result<Option<(Vec<Token<'a>>, usize)>, Error> {
+        let mut reader = Reader { input, pos: 0 };
+        let len = match reader.try_varint()? {
+            Some(len) => len as usize,
+            None => return Ok(None),
+        };
+        if input.len() - reader.pos < len {
+            return Ok(None);
+        }
+
+        let end = reader.pos + len;
+        let mut frame = Reader {
+            input: &input[..end],
+            pos: reader.pos,
+        };
+        let count = frame.varint()?;
+        let mut tokens = Vec::with_capacity(count.min(len as u64) as usize);
+        for _ in 0..count {
+            let tag = frame.byte()?;
+            let payload_len = frame.varint()? as usize;
+            let payload = frame.take(payload_len)?;
+            match decode_token(tag, payload, &mut self.names)? {
+                Some(token) => tokens.push(token),
+                None => self.skipped += 1,
+            }
+        }
+        if frame.pos != end {
+            return Err(wire_error("trailing bytes in frame".to_string()));
+        }
+        Ok(Some((tokens, end)))
+    }
+}
+
+/// The number of tokens [`write_token_stream`] encodes into each frame.
+///
+/// [`write_token_stream`]: fn.write_token_stream.html
+const STREAM_FRAME_LEN: usize = 1024;
+
+/// Writes a complete stream of the given tokens (a header, followed by frames of up to 1024 tokens) with the newest protocol version.
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{wire, Token};
+///
+/// let mut buf = Vec::new();
+/// wire::write_token_stream(&mut buf, vec![Token::Seq { len: None }, Token::Str("a".into()), Token::SeqEnd]).unwrap();
+///
+/// assert_eq!(wire::read_token_stream(&buf[..]).unwrap(), vec![
+///     Token::Seq { len: None },
+///     Token::String("a".to_string()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+pub fn write_token_stream<'a, W, I>(mut writer: W, tokens: I) -> Result<(), Error>
+where
+    W: Write,
+    I: IntoIterator<Item = Token<'a>>,
+{
+    let mut encoder = Encoder::default();
+    let mut buf = Vec::new();
+    encoder.encode_header(&mut buf);
+
+    let mut frame = Vec::with_capacity(STREAM_FRAME_LEN);
+    for token in tokens {
+        frame.push(token);
+        if frame.len() == STREAM_FRAME_LEN {
+            encoder.encode_frame(&frame, &mut buf);
+            writer.write_all(&buf)?;
+            frame.clear();
+            buf.clear();
+        }
+    }
+    if !frame.is_empty() {
+        encoder.encode_frame(&frame, &mut buf);
+    }
+    writer.write_all(&buf)?;
+    writer.flush().map_err(Into::into)
+}
+
+/// Reads a complete stream of tokens, as written by [`write_token_stream`] or an [`Encoder`] of any supported protocol version.
+///
+/// Fails if the stream ends partway through its header or a frame.
+///
+/// [`write_token_stream`]: fn.write_token_stream.html
+/// [`Encoder`]: struct.Encoder.html
+pub fn read_token_stream<R: Read>(mut reader: R) -> Result<Vec<Token<'static>>, Error> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+
+    let (mut decoder, mut pos) = Decoder::from_header(&input)?
+        .ok_or_else(|| wire_error("truncated stream header".to_string()))?;
+    let mut tokens = Vec::new();
+    while pos < input.len() {
+        let (frame, len) = decoder
+            .decode_frame(&input[pos..])?
+            .ok_or_else(|| wire_error("truncated frame".to_string()))?;
+        tokens.extend(frame.into_iter().map(Token::into_owned));
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+/// Maps a token to its equivalent in an older protocol version, or `None` if it has none.
+///
+/// `Field` tokens were added in protocol version 2, and are downgraded to the `Str` tokens that preceded them. `MapKey` and `MapValue` markers were added in version 3, and carry no data, so are dropped. `Number` tokens were added in version 4, and are downgraded to `Str` tokens of their text. `StrChunk` and `StrEnd` tokens were added in version 5, and are joined into a `Str` token by the `Encoder`. `BytesChunk` and `BytesEnd` tokens were added in version 6, and are joined into a `Bytes` token by the `Encoder`. `Extension` headers were added in version 7, and are dropped, leaving the value they wrap. `DocumentStart` and `DocumentEnd` markers were added in version 8 (and `Truncated` markers in version 9), and are dropped. `Interned` strings were added in version 10 (and `Inline` strings in version 11), and are downgraded to `String` tokens.
+fn downgrade<'t, 'a>(token: &'t Token<'a>, version: u16) -> Option<Cow<'t, Token<'a>>> {
+    match *token {
+        Token::Field(name) if version < 2 => Some(Cow::Owned(Token::Str(name.into()))),
+        Token::MapKey | Token::MapValue if version < 3 => None,
+        Token::Number(ref v) if version < 4 => Some(Cow::Owned(Token::Str(v.clone()))),
+        Token::Extension { .. } if version < 7 => None,
+        Token::DocumentStart | Token::DocumentEnd if version < 8 => None,
+        Token::Truncated { .. } if version < 9 => None,
+        Token::Interned(ref v) if version < 10 => Some(Cow::Owned(Token::String(v.to_string()))),
+        Token::Inline(ref v) if version < 11 => Some(Cow::Owned(Token::String(v.to_string()))),
+        _ => Some(Cow::Borrowed(token)),
+    }
+}
+
+fn encode_token(token: &Token, buf: &mut Vec<u8>) {
+    let mut payload = Vec::new();
+    let tag = match token {
+        Token::Bool(v) => {
+            payload.push(*v as u8);
+            0
+        }
+        Token::I8(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            1
+        }
+        Token::I16(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            2
+        }
+        Token::I32(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            3
+        }
+        Token::I64(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            4
+        }
+        Token::I128(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            5
+        }
+        Token::U8(v) => {
+            payload.push(*v);
+            6
+        }
+        Token::U16(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            7
+        }
+        Token::U32(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            8
+        }
+        Token::U64(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            9
+        }
+        Token::U128(v) => {
+            payload.extend_from_slice(&v.to_le_bytes());
+            10
+        }
+        Token::F32(v) => {
+            payload.extend_from_slice(&v.to_bits().to_le_bytes());
+            11
+        }
+        Token::F64(v) => {
+            payload.extend_from_slice(&v.to_bits().to_le_bytes());
+            12
+        }
+        Token::Char(v) => {
+            payload.extend_from_slice(&(*v as u32).to_le_bytes());
+            13
+        }
+        Token::Str(v) => {
+            payload.extend_from_slice(v.as_bytes());
+            14
+        }
+        Token::String(v) => {
+            payload.extend_from_slice(v.as_bytes());
+            15
+        }
+        Token::Bytes(v) => {
+            payload.extend_from_slice(v);
+            16
+        }
+        Token::ByteBuf(v) => {
+            payload.extend_from_slice(v);
+            17
+        }
+        Token::None => 18,
+        Token::Some => 19,
+        Token::Unit => 20,
+        Token::UnitStruct { name } => {
+            write_str(name, &mut payload);
+            21
+        }
+        Token::UnitVariant {
+            name,
+            variant_index,
+            variant,
+        } => {
+            write_str(name, &mut payload);
+            write_str(variant, &mut payload);
+            write_varint(u64::from(*variant_index), &mut payload);
+            22
+        }
+        Token::NewtypeStruct { name } => {
+            write_str(name, &mut payload);
+            23
+        }
+        Token::NewtypeVariant {
+            name,
+            variant_index,
+            variant,
+        } => {
+            write_str(name, &mut payload);
+            write_str(variant, &mut payload);
+            write_varint(u64::from(*variant_index), &mut payload);
+            24
+        }
+        Token::Seq { len } => {
+            write_len(*len, &mut payload);
+            25
+        }
+        Token::SeqEnd => 26,
+        Token::Tuple { len } => {
+            write_varint(*len as u64, &mut payload);
+            27
+        }
+        Token::TupleEnd => 28,
+        Token::TupleStruct { name, len } => {
+            write_str(name, &mut payload);
+            write_varint(*len as u64, &mut payload);
+            29
+        }
+        Token::TupleStructEnd => 30,
+        Token::TupleVariant {
+            name,
+            variant_index,
+            variant,
+            len,
+        } => {
+            write_str(name, &mut payload);
+            write_str(variant, &mut payload);
+            write_varint(*len as u64, &mut payload);
+            write_varint(u64::from(*variant_index), &mut payload);
+            31
+        }
+        Token::TupleVariantEnd => 32,
+        Token::Map { len } => {
+            write_len(*len, &mut payload);
+            33
+        }
+        Token::MapEnd => 34,
+        Token::MapKey => 41,
+        Token::MapValue => 42,
+        Token::Number(v) => {
+            payload.extend_from_slice(v.as_bytes());
+            43
+        }
+        Token::StrChunk(v) => {
+            payload.extend_from_slice(v.as_bytes());
+            44
+        }
+        Token::StrEnd => 45,
+        Token::BytesChunk(v) => {
+            payload.extend_from_slice(v);
+            46
+        }
+        Token::BytesEnd => 47,
+        Token::Extension { tag } => {
+            write_varint(*tag, &mut payload);
+            48
+        }
+        Token::DocumentStart => 49,
+        Token::DocumentEnd => 50,
+        Token::Truncated { len } => {
+            write_varint(*len as u64, &mut payload);
+            51
+        }
+        Token::Interned(v) => {
+            payload.extend_from_slice(v.as_bytes());
+            52
+        }
+        Token::Inline(v) => {
+            payload.extend_from_slice(v.as_bytes());
+            53
+        }
+        Token::Struct { name, len } => {
+            write_str(name, &mut payload);
+            write_varint(*len as u64, &mut payload);
+            35
+        }
+        Token::Field(name) => {
+            payload.extend_from_slice(name.as_bytes());
+            40
+        }
+        Token::StructEnd => 36,
+        Token::StructVariant {
+            name,
+            variant_index,
+            variant,
+            len,
+        } => {
+            write_str(name, &mut payload);
+            write_str(variant, &mut payload);
+            write_varint(*len as u64, &mut payload);
+            write_varint(u64::from(*variant_index), &mut payload);
+            37
+        }
+        Token::StructVariantEnd => 38,
+        Token::Enum { name } => {
+            write_str(name, &mut payload);
+            39
+        }
+    };
+
+    buf.push(tag);
+    write_varint(payload.len() as u64, buf);
+    buf.extend_from_slice(&payload);
+}
+
+fn decode_token<'a>(
+    tag: u8,
+    payload: &'a [u8],
+    names: &mut Interner,
+) -> Result<Option<Token<'a>>, Error> {
+    let mut r = Reader {
+        input: payload,
+        pos: 0,
+    };
+    let token = match tag {
+        0 => Token::Bool(r.array::<1>()?[0] != 0),
+        1 => Token::I8(i8::from_le_bytes(r.array()?)),
+        2 => Token::I16(i16::from_le_bytes(r.array()?)),
+        3 => Token::I32(i32::from_le_bytes(r.array()?)),
+        4 => Token::I64(i64::from_le_bytes(r.array()?)),
+        5 => Token::I128(i128::from_le_bytes(r.array()?)),
+        6 => Token::U8(r.array::<1>()?[0]),
+        7 => Token::U16(u16::from_le_bytes(r.array()?)),
+        8 => Token::U32(u32::from_le_bytes(r.array()?)),
+        9 => Token::U64(u64::from_le_bytes(r.array()?)),
+        10 => Token::U128(u128::from_le_bytes(r.array()?)),
+        11 => Token::F32(f32::from_bits(u32::from_le_bytes(r.array()?))),
+        12 => Token::F64(f64::from_bits(u64::from_le_bytes(r.array()?))),
+        13 => Token::Char(
+            std::char::from_u32(u32::from_le_bytes(r.array()?))
+                .ok_or_else(|| wire_error("invalid char".to_string()))?,
+        ),
+        14 => Token::Str(r.rest_str()?.into()),
+        15 => Token::String(r.rest_str()?.to_string()),
+        16 => Token::Bytes(r.take(payload.len())?.into()),
+        17 => Token::ByteBuf(r.take(payload.len())?.to_vec()),
+        18 => Token::None,
+        19 => Token::Some,
+        20 => Token::Unit,
+        21 => Token::UnitStruct {
+            name: r.name(names)?,
+        },
+        22 => Token::UnitVariant {
+            name: r.name(names)?,
+            variant: r.name(names)?,
+            variant_index: r.variant_index()?,
+        },
+        23 => Token::NewtypeStruct {
+            name: r.name(names)?,
+        },
+        24 => Token::NewtypeVariant {
+            name: r.name(names)?,
+            variant: r.name(names)?,
+            variant_index: r.variant_index()?,
+        },
+        25 => Token::Seq { len: r.len()? },
+        26 => Token::SeqEnd,
+        27 => Token::Tuple {
+            len: r.varint()? as usize,
+        },
+        28 => Token::TupleEnd,
+        29 => Token::TupleStruct {
+            name: r.name(names)?,
+            len: r.varint()? as usize,
+        },
+        30 => Token::TupleStructEnd,
+        31 => Token::TupleVariant {
+            name: r.name(names)?,
+            variant: r.name(names)?,
+            len: r.varint()? as usize,
+            variant_index: r.variant_index()?,
+        },
+        32 => Token::TupleVariantEnd,
+        33 => Token::Map { len: r.len()? },
+        34 => Token::MapEnd,
+        35 => Token::Struct {
+            name: r.name(names)?,
+            len: r.varint()? as usize,
+        },
+        36 => Token::StructEnd,
+        37 => Token::StructVariant {
+            name: r.name(names)?,
+            variant: r.name(names)?,
+            len: r.varint()? as usize,
+            variant_index: r.variant_index()?,
+        },
+        38 => Token::StructVariantEnd,
+        39 => Token::Enum {
+            name: r.name(names)?,
+        },
+        40 => Token::Field(names.intern(r.rest_str()?)?),
+        41 => Token::MapKey,
+        42 => Token::MapValue,
+        43 => Token::Number(r.rest_str()?.into()),
+        44 => Token::StrChunk(r.rest_str()?.into()),
+        45 => Token::StrEnd,
+        46 => Token::BytesChunk(r.take(payload.len())?.into()),
+        47 => Token::BytesEnd,
+        48 => Token::Extension { tag: r.varint()? },
+        49 => Token::DocumentStart,
+        50 => Token::DocumentEnd,
+        51 => Token::Truncated {
+            len: r.varint()? as usize,
+        },
+        52 => Token::Interned(r.rest_str()?.into()),
+        53 => {
+            let v = r.rest_str()?;
+            InlineStr::new(v).map_or_else(|| Token::String(v.to_string()), Token::Inline)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(token))
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.input.len() - self.pos < n {
+            return Err(wire_error("unexpected end of input".to_string()));
+        }
+        let bytes = &self.input[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn try_varint(&mut self) -> Result<Option<u64>, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = match self.input.get(self.pos) {
+                Some(&byte) => byte,
+                None => return Ok(None),
+            };
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(Some(value));
+            }
+        }
+        Err(wire_error("varint overflow".to_string()))
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        self.try_varint()?
+            .ok_or_else(|| wire_error("unexpected end of input".to_string()))
+    }
+
+    fn len(&mut self) -> Result<Option<usize>, Error> {
+        Ok(match self.varint()? {
+            0 => None,
+            n => Some(n as usize - 1),
+        })
+    }
+
+    fn rest_str(&mut self) -> Result<&'a str, Error> {
+        let bytes = self.take(self.input.len() - self.pos)?;
+        std::str::from_utf8(bytes).map_err(|err| wire_error(err.to_string()))
+    }
+
+    /// Reads the variant index that trails a variant token's payload, which is absent (and so `0`) in tokens encoded before it was added.
+    fn variant_index(&mut self) -> Result<u32, Error> {
+        let index = self.try_varint()?.unwrap_or_default();
+        index
+            .try_into()
+            .map_err(|_| wire_error("variant index overflow".to_string()))
+    }
+
+    fn name(&mut self, names: &mut Interner) -> Result<&'static str, Error> {
+        let len = self.varint()? as usize;
+        let bytes = self.take(len)?;
+        let name = std::str::from_utf8(bytes).map_err(|err| wire_error(err.to_string()))?;
+        names.intern(name)
+    }
+}
+
+fn write_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_len(len: Option<usize>, buf: &mut Vec<u8>) {
+    write_varint(len.map_or(0, |len| len as u64 + 1), buf);
+}
+
+fn write_str(s: &str, buf: &mut Vec<u8>) {
+    write_varint(s.len() as u64, buf);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// The total length in bytes of the distinct type, field and variant names that a single [`Decoder`] may give a `'static` lifetime (by leaking a copy of each) as it decodes tokens, after which decoding a token with a new name fails.
+///
+/// [`Decoder`]: struct.Decoder.html
+pub const MAX_INTERNED_BYTES: usize = 1 << 20;
+
+/// A table of `'static` copies of type, field and variant names, admitting at most `limit` bytes of distinct names.
+///
+/// Names are leaked through a process-wide table, so each distinct name is leaked at most once however many tables admit it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Interner {
+    names: HashSet<&'static str>,
+    bytes: usize,
+    limit: usize,
+}
+
+impl Interner {
+    pub(crate) fn new(limit: usize) -> Self {
+        Interner {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn intern(&mut self, name: &str) -> Result<&'static str, Error> {
+        if let Some(&name) = self.names.get(name) {
+            return Ok(name);
+        }
+        if self.bytes + name.len() > self.limit {
+            return Err(wire_error(format!(
+                "too many distinct names to intern (limit {} bytes)",
+                self.limit
+            )));
+        }
+        let name = leak(name);
+        self.names.insert(name);
+        self.bytes += name.len();
+        Ok(name)
+    }
+}
+
+// Returns the process-wide `'static` copy of a name, leaking one if there is none yet.
+fn leak(name: &str) -> &'static str {
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut names = NAMES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    match names.get(name) {
+        Some(&name) => name,
+        None => {
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            names.insert(name);
+            name
+        }
+    }
+}
+
+/// Returns a `'static` copy of a type, field or variant name from a process-wide table, which admits at most [`MAX_INTERNED_BYTES`] of names.
+///
+/// [`MAX_INTERNED_BYTES`]: constant.MAX_INTERNED_BYTES.html
+pub(crate) fn intern(name: &str) -> Result<&'static str, Error> {
+    static NAMES: OnceLock<Mutex<Interner>> = OnceLock::new();
+    NAMES
+        .get_or_init(|| Mutex::new(Interner::new(MAX_INTERNED_BYTES)))
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .intern(name)
+}
+
+fn check_magic(input: &[u8]) -> Result<(), Error> {
+    if &input[..4] != MAGIC {
+        return Err(wire_error("invalid magic bytes".to_string()));
+    }
+    Ok(())
+}
+
+fn check_version(version: u16) -> Result<(), Error> {
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(wire_error(format!(
+            "unsupported protocol version: {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+fn wire_error(msg: String) -> Error {
+    Error::WireError(msg)
+}
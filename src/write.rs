@@ -0,0 +1,204 @@
+use crate::{error::Error, tokenize::InfallibleTokenSink, Token};
+#[cfg(feature = "futures01")]
+use crate::{
+    path::{role, Role},
+    tree::malformed,
+};
+#[cfg(feature = "futures01")]
+use futures::{sink::Sink, AsyncSink};
+
+/// A low-level interface for emitting `Token`s, for hand-written parsers of formats without a serde `Deserializer`.
+///
+/// Only `write_token` must be implemented; the rest are conveniences for writing the tokens of each part of the [Serde data model]. It is implemented for every [`InfallibleTokenSink`] (such as a `Vec<Token>`), and for any [`futures::Sink`] via a [`TokenWriter`].
+///
+/// # Example:
+///
+/// ```
+/// use serde_token::{Token, TokenWrite};
+///
+/// let mut tokens = Vec::new();
+/// tokens.start_map(None).unwrap();
+/// tokens.field("a").unwrap();
+/// tokens.scalar(Token::Bool(true)).unwrap();
+/// tokens.end_map().unwrap();
+///
+/// assert_eq!(tokens, vec![
+///     Token::Map { len: None },
+///     Token::Str("a".into()),
+///     Token::Bool(true),
+///     Token::MapEnd,
+/// ]);
+/// ```
+///
+/// [Serde data model]: https://serde.rs/data-model.html
+/// [`InfallibleTokenSink`]: trait.InfallibleTokenSink.html
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+/// [`TokenWriter`]: struct.TokenWriter.html
+pub trait TokenWrite<'a> {
+    /// Writes a single token.
+    fn write_token(&mut self, token: Token<'a>) -> Result<(), Error>;
+
+    /// Writes a scalar value, such as a `Bool`, `U64` or `Str`.
+    fn scalar(&mut self, token: Token<'a>) -> Result<(), Error> {
+        self.write_token(token)
+    }
+
+    /// Writes the prefix of an optional value, which must be followed by the value itself.
+    fn some(&mut self) -> Result<(), Error> {
+        self.write_token(Token::Some)
+    }
+
+    /// Starts a sequence, with an optional length.
+    fn start_seq(&mut self, len: Option<usize>) -> Result<(), Error> {
+        self.write_token(Token::Seq { len })
+    }
+
+    /// Ends a sequence.
+    fn end_seq(&mut self) -> Result<(), Error> {
+        self.write_token(Token::SeqEnd)
+    }
+
+    /// Starts a map, with an optional length.
+    fn start_map(&mut self, len: Option<usize>) -> Result<(), Error> {
+        self.write_token(Token::Map { len })
+    }
+
+    /// Ends a map.
+    fn end_map(&mut self) -> Result<(), Error> {
+        self.write_token(Token::MapEnd)
+    }
+
+    /// Starts a struct with the given name and number of fields.
+    fn start_struct(&mut self, name: &'static str, len: usize) -> Result<(), Error> {
+        self.write_token(Token::Struct { name, len })
+    }
+
+    /// Ends a struct.
+    fn end_struct(&mut self) -> Result<(), Error> {
+        self.write_token(Token::StructEnd)
+    }
+
+    /// Writes the key of a map entry or struct field, which must be followed by its value.
+    fn field(&mut self, key: &'a str) -> Result<(), Error> {
+        self.write_token(Token::Str(key.into()))
+    }
+}
+
+impl<'a, S: InfallibleTokenSink<'a>> TokenWrite<'a> for S {
+    #[inline]
+    fn write_token(&mut self, token: Token<'a>) -> Result<(), Error> {
+        self.push_token(token);
+        Ok(())
+    }
+}
+
+/// A [`TokenWrite`]r that sends its tokens to a [`futures::Sink`], tracking open containers so each can be closed with `end`.
+///
+/// *Requires the `futures01` feature.*
+///
+/// # Example:
+///
+/// ```
+/// use futures::{unsync::mpsc::unbounded, Future, Stream};
+/// use serde_token::{Token, TokenWrite, TokenWriter};
+///
+/// let (token_sink, token_stream) = unbounded::<Token>();
+/// let mut writer = TokenWriter::new(token_sink);
+/// writer.start_seq(None).unwrap();
+/// writer.scalar(Token::U8(1)).unwrap();
+/// writer.end().unwrap();
+/// drop(writer);
+///
+/// let expected = token_stream.collect().wait().unwrap();
+/// assert_eq!(expected, vec![Token::Seq { len: None }, Token::U8(1), Token::SeqEnd]);
+/// ```
+///
+/// [`TokenWrite`]: trait.TokenWrite.html
+/// [`futures::Sink`]: https://docs.rs/futures/0.1.27/futures/sink/trait.Sink.html
+#[cfg(feature = "futures01")]
+#[derive(Debug)]
+pub struct TokenWriter<S> {
+    sink: S,
+    open: Vec<Token<'static>>,
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S> TokenWriter<S>
+where
+    S: Sink<SinkItem = Token<'a>>,
+    S::SinkError: Into<Error>,
+{
+    /// Wraps the given sink.
+    pub fn new(sink: S) -> Self {
+        TokenWriter {
+            sink,
+            open: Vec::new(),
+        }
+    }
+
+    /// Ends the innermost open container.
+    pub fn end(&mut self) -> Result<(), Error> {
+        let end = self
+            .open
+            .last()
+            .cloned()
+            .ok_or_else(|| malformed("no open container to end"))?;
+        self.write_token(end)
+    }
+
+    /// The number of open containers.
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    /// Consumes the writer, returning the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+#[cfg(feature = "futures01")]
+impl<'a, S> TokenWrite<'a> for TokenWriter<S>
+where
+    S: Sink<SinkItem = Token<'a>>,
+    S::SinkError: Into<Error>,
+{
+    fn write_token(&mut self, token: Token<'a>) -> Result<(), Error> {
+        let role = role(&token);
+        let end = match role {
+            Role::Open => Some(end_of(&token)),
+            Role::Close if self.open.last() != Some(&token) => {
+                return Err(malformed("unexpected end of container"))
+            }
+            _ => None,
+        };
+
+        // Only track the token once the sink has accepted it, so a rejected token leaves the writer unchanged.
+        match self.sink.start_send(token) {
+            Ok(AsyncSink::Ready) => {}
+            Ok(AsyncSink::NotReady(_)) => return Err(Error::TokenSinkNotReadyError),
+            Err(err) => return Err(err.into()),
+        }
+        match end {
+            Some(end) => self.open.push(end),
+            None if role == Role::Close => {
+                self.open.pop();
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "futures01")]
+fn end_of(token: &Token) -> Token<'static> {
+    match token {
+        Token::Seq { .. } => Token::SeqEnd,
+        Token::Tuple { .. } => Token::TupleEnd,
+        Token::TupleStruct { .. } => Token::TupleStructEnd,
+        Token::TupleVariant { .. } => Token::TupleVariantEnd,
+        Token::Map { .. } => Token::MapEnd,
+        Token::Struct { .. } => Token::StructEnd,
+        _ => Token::StructVariantEnd,
+    }
+}
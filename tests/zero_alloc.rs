@@ -0,0 +1,94 @@
+//! Verifies that tokenizing with `borrowed_only` performs no heap allocation, by counting the allocations made by the current thread.
+
+use serde_token::{tokenize_with_options, Error, IntegerWidth, Token, TokenSink, TokenizeOptions};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+/// Returns the number of allocations the current thread made while running `f`.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Counts tokens without allocating.
+struct Count<'c>(&'c mut usize);
+
+impl<'c, 'de> TokenSink<'de> for Count<'c> {
+    type Error = Error;
+
+    fn write(&mut self, _: Token<'de>) -> Result<(), Error> {
+        *self.0 += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn borrowed_only_does_not_allocate() {
+    let row =
+        r#"{"id": 1, "name": "row", "tags": ["a", "b"], "ok": true, "score": -1.5, "none": null}"#;
+    let json = format!("[{}]", vec![row; 1000].join(","));
+    let options = TokenizeOptions::new()
+        .borrowed_only(true)
+        .map_markers(true)
+        .document_markers(true)
+        .max_depth(8)
+        .max_payload_len(16)
+        .normalize_integers(IntegerWidth::Bits64);
+
+    let mut count = 0;
+    let n = allocations(|| {
+        let mut de = serde_json::Deserializer::from_str(&json);
+        tokenize_with_options(&mut de, Count(&mut count), &options).unwrap();
+    });
+
+    assert!(count > 20_000, "{}", count);
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn counts_allocations() {
+    let n = allocations(|| {
+        let mut count = 0;
+        let mut de = serde_json::Deserializer::from_str(r#" ["esc\\aped"] "#);
+        tokenize_with_options(&mut de, Count(&mut count), &TokenizeOptions::new()).unwrap();
+    });
+
+    assert!(n > 0);
+}